@@ -0,0 +1,58 @@
+//! PLOC build and refit on the standard benchmark scenes, for each scheduler.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use pool_racing::{
+    bvh::Bvh2,
+    par::Scheduler,
+    ploc::{set_ploc_scheduler, PlocBuilder},
+    radix::set_radix_scheduler,
+    test_util::scenes::{BenchScene, SceneSize, DEFAULT_SEED},
+    Aabb,
+};
+
+const SIZES: [SceneSize; 2] = [SceneSize::Small, SceneSize::Medium];
+
+fn scene_aabbs(size: SceneSize) -> Vec<Aabb> {
+    BenchScene::UniformSoup
+        .generate(size, DEFAULT_SEED)
+        .iter()
+        .map(|t| t.aabb())
+        .collect()
+}
+
+fn ploc_build(c: &mut Criterion) {
+    for size in SIZES {
+        let aabbs = scene_aabbs(size);
+        let mut group = c.benchmark_group(format!("ploc_build/{}", size.name()));
+        group.throughput(Throughput::Elements(aabbs.len() as u64));
+        let mut builder = PlocBuilder::with_capacity(aabbs.len());
+        let mut bvh = Bvh2::default();
+        builder.rebuild_ploc(&aabbs, &mut bvh);
+        print!("ploc_build/{}: {}", size.name(), bvh.stats());
+        for scheduler in Scheduler::ALL {
+            scheduler.init();
+            set_ploc_scheduler(scheduler);
+            set_radix_scheduler(scheduler);
+            group.bench_function(BenchmarkId::from_parameter(scheduler.name()), |b| {
+                b.iter(|| builder.rebuild_ploc(&aabbs, &mut bvh))
+            });
+        }
+        group.finish();
+    }
+}
+
+fn refit(c: &mut Criterion) {
+    let mut group = c.benchmark_group("refit");
+    for size in SIZES {
+        let aabbs = scene_aabbs(size);
+        let mut bvh = PlocBuilder::with_capacity(aabbs.len()).build_ploc(&aabbs);
+        group.throughput(Throughput::Elements(aabbs.len() as u64));
+        group.bench_function(BenchmarkId::from_parameter(size.name()), |b| {
+            b.iter(|| bvh.refit(&aabbs))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, ploc_build, refit);
+criterion_main!(benches);
@@ -0,0 +1,32 @@
+//! Radix sort of random u64 keys at several sizes, for each scheduler.
+
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use pool_racing::{par::Scheduler, radix::sorter::Sorter, test_util::reference::Rng};
+
+fn radix_sort(c: &mut Criterion) {
+    let mut rng = Rng::new(1);
+    for len in [10_000, 100_000, 1_000_000] {
+        let keys: Vec<u64> = (0..len).map(|_| rng.next_u64()).collect();
+        let mut group = c.benchmark_group(format!("radix_sort/{len}"));
+        group.throughput(Throughput::Elements(len as u64));
+        let mut sorter = Sorter::<u64>::new();
+        print!(
+            "radix_sort/{len}: {}",
+            sorter.sort_with_stats(&mut keys.clone())
+        );
+        for scheduler in Scheduler::ALL {
+            scheduler.init();
+            group.bench_function(BenchmarkId::from_parameter(scheduler.name()), |b| {
+                b.iter_batched_ref(
+                    || keys.clone(),
+                    |data| sorter.sort_with(scheduler, data),
+                    BatchSize::LargeInput,
+                )
+            });
+        }
+        group.finish();
+    }
+}
+
+criterion_group!(benches, radix_sort);
+criterion_main!(benches);
@@ -0,0 +1,112 @@
+//! Batches of closest hit camera rays through the standard benchmark scenes, for each scheduler, and
+//! with full precision against quantized triangles.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use pool_racing::{
+    glam::{Mat4, Vec3},
+    par::Scheduler,
+    ploc::PlocBuilder,
+    quantized::QuantizedTriangles,
+    test_util::{
+        rays::camera_rays,
+        scenes::{BenchScene, SceneSize, DEFAULT_SEED},
+    },
+    Ray,
+};
+
+const WIDTH: usize = 256;
+const HEIGHT: usize = 256;
+
+fn bench_rays() -> Vec<Ray> {
+    // Looking into the unit cube the generated scenes fill
+    let proj_inv = Mat4::perspective_infinite_reverse_rh(60f32.to_radians(), 1.0, 0.01).inverse();
+    let view_inv = Mat4::look_at_rh(Vec3::new(0.5, 0.5, 2.5), Vec3::splat(0.5), Vec3::Y).inverse();
+    camera_rays(WIDTH, HEIGHT, &view_inv, &proj_inv)
+}
+
+fn batch_traversal(c: &mut Criterion) {
+    let rays = bench_rays();
+    let mut hit_ids = vec![u32::MAX; rays.len()];
+
+    for scene in [
+        BenchScene::UniformSoup,
+        BenchScene::HairBall,
+        BenchScene::CityGrid,
+    ] {
+        for size in [SceneSize::Small, SceneSize::Medium] {
+            let triangles = scene.generate(size, DEFAULT_SEED);
+            let aabbs: Vec<_> = triangles.iter().map(|t| t.aabb()).collect();
+            let bvh = PlocBuilder::with_capacity(aabbs.len()).build_ploc(&aabbs);
+
+            let mut group = c.benchmark_group(format!("traverse/{}/{}", scene.name(), size.name()));
+            group.throughput(Throughput::Elements(rays.len() as u64));
+            for scheduler in Scheduler::ALL {
+                scheduler.init();
+                let chunks = scheduler.current_num_threads() as u32 * 4;
+                group.bench_function(BenchmarkId::from_parameter(scheduler.name()), |b| {
+                    b.iter(|| {
+                        scheduler.par_map(
+                            &mut hit_ids,
+                            &|i, hit_id| {
+                                let mut ray = rays[i];
+                                *hit_id = u32::MAX;
+                                bvh.traverse(&mut ray, hit_id, |ray, id| {
+                                    triangles[id].intersect(ray)
+                                });
+                            },
+                            chunks,
+                        )
+                    })
+                });
+            }
+            group.finish();
+        }
+    }
+}
+
+fn quantized_traversal(c: &mut Criterion) {
+    let rays = bench_rays();
+    let mut hit_ids = vec![u32::MAX; rays.len()];
+    let scheduler = Scheduler::Forte;
+    scheduler.init();
+    let chunks = scheduler.current_num_threads() as u32 * 4;
+
+    for size in [SceneSize::Medium, SceneSize::Large] {
+        let triangles = BenchScene::UniformSoup.generate(size, DEFAULT_SEED);
+        let quantized = QuantizedTriangles::new(&triangles);
+        let bvh = PlocBuilder::with_capacity(triangles.len()).build_ploc(&quantized.aabbs());
+
+        let mut group = c.benchmark_group(format!("traverse_quantized/{}", size.name()));
+        group.throughput(Throughput::Elements(rays.len() as u64));
+        group.bench_function("triangle", |b| {
+            b.iter(|| {
+                scheduler.par_map(
+                    &mut hit_ids,
+                    &|i, hit_id| {
+                        let mut ray = rays[i];
+                        *hit_id = u32::MAX;
+                        bvh.traverse(&mut ray, hit_id, |ray, id| triangles[id].intersect(ray));
+                    },
+                    chunks,
+                )
+            })
+        });
+        group.bench_function("quantized", |b| {
+            b.iter(|| {
+                scheduler.par_map(
+                    &mut hit_ids,
+                    &|i, hit_id| {
+                        let mut ray = rays[i];
+                        *hit_id = u32::MAX;
+                        bvh.traverse(&mut ray, hit_id, |ray, id| quantized.intersect(ray, id));
+                    },
+                    chunks,
+                )
+            })
+        });
+        group.finish();
+    }
+}
+
+criterion_group!(benches, batch_traversal, quantized_traversal);
+criterion_main!(benches);
@@ -1,12 +1,10 @@
 use glam::*;
-use obvhs::{
-    ray::Ray,
-    test_util::geometry::{icosphere, PLANE},
-    triangle::Triangle,
-};
-use pool_racing::ploc::PlocBuilder;
+use obvhs::test_util::geometry::{icosphere, PLANE};
+use pool_racing::{ploc::PlocBuilder, Args, Ray, Triangle};
 
 fn main() {
+    Args::from_env().apply();
+
     // Build a scene with an icosphere and a plane
     // BVH primitives do not need to be triangles, the BVH builder is only concerned with AABBs.
     let mut tris: Vec<Triangle> = Vec::new();
@@ -4,17 +4,17 @@ use std::{f32::consts::PI, thread};
 use glam::*;
 use image::{ImageBuffer, Rgba};
 
-#[path = "./helpers/debug.rs"]
-mod debug;
 use obvhs::{
-    ray::Ray,
     test_util::geometry::{CUBE, PLANE},
     triangle::Triangle,
     Transformable,
 };
-use pool_racing::ploc::PlocBuilder;
-
-use crate::debug::{simple_debug_window, AtomicColorBuffer};
+use pool_racing::{
+    debug_vis::{simple_debug_window, AtomicColorBuffer},
+    ploc::PlocBuilder,
+    test_util::rays::camera_ray,
+    Args,
+};
 
 // Generate triangles for cornell box
 fn generate_cornell_box() -> Vec<Triangle> {
@@ -60,6 +60,7 @@ fn generate_cornell_box() -> Vec<Triangle> {
 }
 
 fn main() {
+    Args::from_env().apply();
     let tris = generate_cornell_box();
     let aabbs = tris.iter().map(|t| t.aabb()).collect::<Vec<_>>();
     // Build cwbvh (Change this to build_bvh2_from_tris to try with Bvh2)
@@ -92,16 +93,7 @@ fn main() {
 
             // For each pixel trace ray into scene and write normal as color to image buffer
             pixels.chunks_mut(4).enumerate().for_each(|(i, chunk)| {
-                let frag_coord = uvec2((i % width) as u32, (i / width) as u32);
-                let mut screen_uv = frag_coord.as_vec2() / target_size;
-                screen_uv.y = 1.0 - screen_uv.y;
-                let ndc = screen_uv * 2.0 - Vec2::ONE;
-                let clip_pos = vec4(ndc.x, ndc.y, 1.0, 1.0);
-
-                let mut vs_pos = proj_inv * clip_pos;
-                vs_pos /= vs_pos.w;
-                let direction = (Vec3A::from((view_inv * vs_pos).xyz()) - eye).normalize();
-                let mut ray = Ray::new(eye, direction, 0.0, f32::MAX);
+                let mut ray = camera_ray(i, width, height, &view_inv, &proj_inv);
 
                 let mut hit_id = u32::MAX;
 
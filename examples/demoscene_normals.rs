@@ -2,17 +2,16 @@ use core::f32;
 use std::thread;
 
 use glam::*;
-use image::{ImageBuffer, Rgba};
-
-#[path = "./helpers/debug.rs"]
-mod debug;
-use debug::simple_debug_window;
-use obvhs::{ray::Ray, test_util::geometry::demoscene};
-use pool_racing::ploc::{init_ploc_scheduler, ploc_scheduler, PlocBuilder};
-
-use crate::debug::AtomicColorBuffer;
+use obvhs::test_util::geometry::demoscene;
+use pool_racing::{
+    debug_vis::{accumulating_debug_window, AtomicColorBuffer, Tonemap},
+    ploc::{init_ploc_scheduler, ploc_scheduler, PlocBuilder},
+    test_util::rays::camera_ray,
+    Args,
+};
 
 fn main() {
+    Args::from_env().apply();
     init_ploc_scheduler();
 
     let tris = demoscene(1280, 570);
@@ -22,6 +21,7 @@ fn main() {
     let mut bvh = ploc_alloc.build_ploc(&aabbs); // Warm
     println!("-- warming done --");
     ploc_alloc.rebuild_ploc(&aabbs, &mut bvh);
+    print!("{}", bvh.stats());
 
     // Setup render target and camera
     let width = 1280;
@@ -50,16 +50,7 @@ fn main() {
             // For each pixel trace ray into scene and write normal as color
             let trace_fn = |i: usize, fragment: &mut Vec3A| {
                 pool_racing::scope!("trace ray");
-                let frag_coord = uvec2((i % width) as u32, (i / width) as u32);
-                let mut screen_uv = frag_coord.as_vec2() / target_size;
-                screen_uv.y = 1.0 - screen_uv.y;
-                let ndc = screen_uv * 2.0 - Vec2::ONE;
-                let clip_pos = vec4(ndc.x, ndc.y, 1.0, 1.0);
-
-                let mut vs_pos = proj_inv * clip_pos;
-                vs_pos /= vs_pos.w;
-                let direction = (Vec3A::from((view_inv * vs_pos).xyz()) - eye).normalize();
-                let mut ray = Ray::new(eye, direction, 0.0, f32::MAX);
+                let mut ray = camera_ray(i, width, height, &view_inv, &proj_inv);
 
                 let mut hit_id = u32::MAX;
                 bvh.traverse(&mut ray, &mut hit_id, |ray, id| tris[id].intersect(ray));
@@ -69,28 +60,19 @@ fn main() {
                     *fragment = normal;
                 }
 
-                let accum_color = window_buffer.get(i as usize) + fragment.extend(1.0);
-                window_buffer.set(i as usize, accum_color);
+                window_buffer.accumulate(i, (*fragment).into());
             };
 
             ploc_scheduler().par_map(&mut fragments, &trace_fn, fragments_count as u32);
-            fragments
         })
     };
 
-    simple_debug_window(width, height, window_buffer); // Wait for window to close.
-
-    let fragments = render_thread.join().unwrap();
-
-    // Init image buffer
-    let mut img: ImageBuffer<Rgba<u8>, Vec<u8>> = ImageBuffer::new(width as u32, height as u32);
-    let pixels = img.as_mut();
+    // Wait for window to close.
+    accumulating_debug_window(width, height, window_buffer.clone(), Tonemap::None);
 
-    pixels.chunks_mut(4).enumerate().for_each(|(i, chunk)| {
-        let c = (fragments[i].clamp(Vec3A::ZERO, Vec3A::ONE) * 255.0).as_uvec3();
-        chunk.copy_from_slice(&[c.x as u8, c.y as u8, c.z as u8, 255]);
-    });
+    render_thread.join().unwrap();
 
-    img.save("basic_cornell_box_rend.png")
+    window_buffer
+        .save_png("basic_cornell_box_rend.png", Tonemap::None)
         .expect("Failed to save image");
 }
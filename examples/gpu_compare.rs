@@ -0,0 +1,67 @@
+// Compare the CPU traversal of a bvh built with the configured scheduler against the wgpu reference.
+// cargo run --release --example gpu_compare --features cli,wgpu,test_util -- --ploc-sch rayon
+
+use glam::*;
+use obvhs::{
+    ray::Ray,
+    test_util::geometry::{icosphere, PLANE},
+    triangle::Triangle,
+};
+use pool_racing::{bvh::wgpu_traversal::GpuTraversal, ploc::PlocBuilder, Args, Timer};
+
+fn main() {
+    Args::from_env().apply();
+    let mut tris: Vec<Triangle> = Vec::new();
+    tris.extend(icosphere(5));
+    tris.extend(PLANE);
+    let aabbs = tris.iter().map(|t| t.aabb()).collect::<Vec<_>>();
+    let bvh = PlocBuilder::preallocate_builder(aabbs.len()).build_ploc(&aabbs);
+
+    let size = 512;
+    let rays: Vec<Ray> = (0..size * size)
+        .map(|i| {
+            let uv = vec2((i % size) as f32, (i / size) as f32) / size as f32 * 2.0 - 1.0;
+            Ray::new_inf(vec3a(uv.x * 1.5, uv.y * 1.5, 4.0), vec3a(0.0, 0.0, -1.0))
+        })
+        .collect();
+
+    let cpu_hits: Vec<(u32, f32)> = {
+        let _t = Timer::new("cpu traversal");
+        rays.iter()
+            .map(|ray| {
+                let mut ray = *ray;
+                let mut hit_id = u32::MAX;
+                bvh.traverse(&mut ray, &mut hit_id, |ray, id| {
+                    tris[id as usize].intersect(ray)
+                });
+                (hit_id, ray.tmax)
+            })
+            .collect()
+    };
+
+    let Some(gpu) = GpuTraversal::new() else {
+        println!("No wgpu adapter available");
+        return;
+    };
+    let gpu_hits = {
+        let _t = Timer::new("gpu upload, traversal and readback");
+        match gpu.cast_rays(&bvh, &tris, &rays) {
+            Ok(hits) => hits,
+            Err(err) => {
+                println!("Can't cast rays on the gpu: {err}");
+                return;
+            }
+        }
+    };
+
+    // Rays grazing shared edges can hit either triangle, so only count mismatches in distance
+    let mismatches = cpu_hits
+        .iter()
+        .zip(&gpu_hits)
+        .filter(|((cpu_id, cpu_t), gpu)| {
+            (*cpu_id == u32::MAX) != !gpu.is_hit()
+                || (gpu.is_hit() && (cpu_t - gpu.t).abs() > 1e-4 * cpu_t.max(1.0))
+        })
+        .count();
+    println!("{mismatches} of {} rays differ", rays.len());
+}
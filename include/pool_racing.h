@@ -0,0 +1,90 @@
+#ifndef POOL_RACING_H
+#define POOL_RACING_H
+
+/* Generated with cbindgen --config cbindgen.toml --features ffi -o include/pool_racing.h */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/*
+ Opaque bvh handle, free with `pool_racing_bvh_free`.
+ */
+typedef struct PoolRacingBvh PoolRacingBvh;
+
+typedef struct PoolRacingRay {
+  float origin[3];
+  float tmin;
+  float direction[3];
+  float tmax;
+} PoolRacingRay;
+
+/*
+ `primitive_id` is `UINT32_MAX` and `t` is the ray tmax if nothing was hit.
+ */
+typedef struct PoolRacingHit {
+  float t;
+  uint32_t primitive_id;
+} PoolRacingHit;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/*
+ Select the scheduler used for building, by its index in `Scheduler`: 0 seq_opt, 1 seq, 2 forte,
+ 3 chili, 4 rayon, 5 rayon_join, 6 raw, 7 bevy. Returns false for an unknown index.
+ */
+bool pool_racing_set_scheduler(uint32_t scheduler);
+
+/*
+ Build a bvh over `count` aabbs, each 6 floats: min x, y, z then max x, y, z. The aabbs are copied, so
+ rays cast against the bvh are intersected with them. Returns null if `aabbs` is null and `count`
+ isn't 0, or if building fails.
+
+ # Safety
+ `aabbs` must point to `count * 6` readable floats.
+ */
+PoolRacingBvh *pool_racing_bvh_build_aabbs(const float *aabbs, size_t count);
+
+/*
+ Build a bvh over `count` triangles, each 9 floats: x, y, z of each vertex. The triangles are copied,
+ so rays cast against the bvh are intersected with them. Returns null if `vertices` is null and
+ `count` isn't 0, or if building fails.
+
+ # Safety
+ `vertices` must point to `count * 9` readable floats.
+ */
+PoolRacingBvh *pool_racing_bvh_build_triangles(const float *vertices, size_t count);
+
+/*
+ # Safety
+ `bvh` must be null or a handle returned by one of the build functions that hasn't been freed yet.
+ */
+void pool_racing_bvh_free(PoolRacingBvh *bvh);
+
+/*
+ # Safety
+ `bvh` must be a valid handle.
+ */
+size_t pool_racing_bvh_node_count(const PoolRacingBvh *bvh);
+
+/*
+ Cast `count` rays, writing the closest hit of each to `hits`. Can be called from multiple threads
+ at once on the same bvh. Returns false if casting fails, `hits` may then be partially written.
+
+ # Safety
+ `bvh` must be a valid handle, `rays` must point to `count` readable rays and `hits` to `count`
+ writable hits.
+ */
+bool pool_racing_bvh_cast_rays(const PoolRacingBvh *bvh,
+                               const PoolRacingRay *rays,
+                               PoolRacingHit *hits,
+                               size_t count);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* POOL_RACING_H */
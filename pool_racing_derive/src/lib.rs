@@ -0,0 +1,146 @@
+//! `#[derive(RadixKey)]` for pool_racing. Use through the `derive` feature of pool_racing.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{parse_macro_input, parse_quote, Data, DeriveInput, Fields, Index, Member};
+
+/// Derive `RadixKey` for a struct by composing its fields lexicographically. The first field is the
+/// most significant. Fields marked `#[radix_key(skip)]` are carried along but not sorted on.
+///
+/// Mark the struct itself `#[radix_key(ord)]` when its `Ord` impl agrees with the radix order, to sort
+/// small buckets with `sort_unstable` instead of comparing level by level.
+#[proc_macro_derive(RadixKey, attributes(radix_key))]
+pub fn derive_radix_key(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match expand(input) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+fn expand(mut input: DeriveInput) -> syn::Result<TokenStream2> {
+    let ord = is_ord(&input.attrs)?;
+    let fields = match &input.data {
+        Data::Struct(data) => &data.fields,
+        _ => {
+            return Err(syn::Error::new_spanned(
+                &input.ident,
+                "RadixKey can only be derived for structs",
+            ))
+        }
+    };
+
+    let mut keys = Vec::new();
+    let field_list: Vec<_> = match fields {
+        Fields::Named(named) => named.named.iter().collect(),
+        Fields::Unnamed(unnamed) => unnamed.unnamed.iter().collect(),
+        Fields::Unit => Vec::new(),
+    };
+    for (i, field) in field_list.into_iter().enumerate() {
+        if is_skipped(field)? {
+            continue;
+        }
+        let member = match &field.ident {
+            Some(ident) => Member::Named(ident.clone()),
+            None => Member::Unnamed(Index::from(i)),
+        };
+        keys.push((member, field.ty.clone()));
+    }
+
+    if keys.is_empty() {
+        return Err(syn::Error::new_spanned(
+            &input.ident,
+            "RadixKey needs at least one field that isn't skipped",
+        ));
+    }
+
+    let radix_key = quote!(::pool_racing::radix::radix_key::RadixKey);
+    let where_clause = input.generics.make_where_clause();
+    for (_, ty) in &keys {
+        where_clause.predicates.push(parse_quote!(#ty: #radix_key));
+    }
+    if ord {
+        where_clause
+            .predicates
+            .push(parse_quote!(Self: ::core::cmp::Ord));
+    }
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let levels = keys
+        .iter()
+        .map(|(_, ty)| quote!(<#ty as #radix_key>::LEVELS));
+    // The last field holds the lowest levels
+    let lookups = keys.iter().rev().map(|(member, ty)| {
+        quote! {
+            if level < <#ty as #radix_key>::LEVELS {
+                return #radix_key::get_level(&self.#member, level);
+            }
+            level -= <#ty as #radix_key>::LEVELS;
+        }
+    });
+
+    let sort_small = if ord {
+        quote! {
+            #[inline]
+            fn sort_small(bucket: &mut [Self], _: usize) {
+                bucket.sort_unstable();
+            }
+        }
+    } else {
+        quote!()
+    };
+
+    Ok(quote! {
+        impl #impl_generics #radix_key for #name #ty_generics #where_clause {
+            const LEVELS: usize = 0 #(+ #levels)*;
+
+            #sort_small
+
+            #[inline]
+            #[allow(unused_assignments)]
+            fn get_level(&self, level: usize) -> u8 {
+                let mut level = level;
+                #(#lookups)*
+                panic!("level {} out of range", level)
+            }
+        }
+    })
+}
+
+fn is_skipped(field: &syn::Field) -> syn::Result<bool> {
+    let mut skip = false;
+    for attr in &field.attrs {
+        if !attr.path().is_ident("radix_key") {
+            continue;
+        }
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("skip") {
+                skip = true;
+                Ok(())
+            } else {
+                Err(meta.error("unknown radix_key attribute, expected `skip`"))
+            }
+        })?;
+    }
+    Ok(skip)
+}
+
+fn is_ord(attrs: &[syn::Attribute]) -> syn::Result<bool> {
+    let mut ord = false;
+    for attr in attrs {
+        if !attr.path().is_ident("radix_key") {
+            continue;
+        }
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("ord") {
+                ord = true;
+                Ok(())
+            } else {
+                Err(meta.error("unknown radix_key attribute, expected `ord`"))
+            }
+        })?;
+    }
+    Ok(ord)
+}
@@ -0,0 +1,208 @@
+//! Python bindings for pool_racing, build with `maturin build --release` from this directory.
+//!
+//! ```python
+//! import numpy as np, pool_racing
+//! bvh = pool_racing.Bvh.from_triangles(vertices)  # float32 (n, 9) or (n, 3, 3)
+//! primitive_ids, t = bvh.cast_rays(origins, directions)  # float32 (m, 3) each
+//! ```
+//!
+//! Schedulers come from `pool_racing.set_scheduler` or the `POOL_RACING_*SCHEDULER` environment
+//! variables, the Python process args are never parsed.
+
+use glam::Vec3A;
+use numpy::{IntoPyArray, PyArray1, PyReadonlyArray2, PyReadonlyArrayDyn};
+use obvhs::{aabb::Aabb, ray::Ray};
+use pool_racing::{
+    bindings::{self, BoundBvh, Primitives},
+    par::Scheduler,
+    ploc::ploc_scheduler,
+};
+use pyo3::{exceptions::PyValueError, prelude::*};
+
+/// Select the scheduler used for building and ray casting by name, like 'forte' or 'rayon'.
+#[pyfunction]
+fn set_scheduler(name: &str) -> PyResult<()> {
+    let scheduler: Scheduler = name.parse().map_err(PyValueError::new_err)?;
+    bindings::set_scheduler(scheduler);
+    Ok(())
+}
+
+#[pyclass(name = "Bvh", frozen)]
+pub struct PyBvh(BoundBvh);
+
+/// Read an array with `width` floats per row, in any shape where the trailing dimensions hold them.
+fn rows(array: &PyReadonlyArrayDyn<f32>, width: usize, what: &str) -> PyResult<Vec<f32>> {
+    let view = array.as_array();
+    let trailing: usize = view.shape().iter().skip(1).product();
+    if view.ndim() < 2 || trailing != width {
+        return Err(PyValueError::new_err(format!(
+            "{what} must have shape (n, {width}), got {:?}",
+            view.shape()
+        )));
+    }
+    Ok(view.iter().copied().collect())
+}
+
+fn vec3_rows(array: &PyReadonlyArray2<f32>, what: &str) -> PyResult<Vec<Vec3A>> {
+    let view = array.as_array();
+    if view.shape()[1] != 3 {
+        return Err(PyValueError::new_err(format!(
+            "{what} must have shape (n, 3), got {:?}",
+            view.shape()
+        )));
+    }
+    Ok(view
+        .rows()
+        .into_iter()
+        .map(|r| Vec3A::new(r[0], r[1], r[2]))
+        .collect())
+}
+
+fn build(py: Python<'_>, primitives: Primitives) -> PyBvh {
+    PyBvh(py.allow_threads(|| BoundBvh::build(primitives)))
+}
+
+#[pymethods]
+impl PyBvh {
+    /// Build from float32 aabbs of shape (n, 6): min x, y, z then max x, y, z, or (n, 2, 3).
+    #[staticmethod]
+    fn from_aabbs(py: Python<'_>, aabbs: PyReadonlyArrayDyn<f32>) -> PyResult<Self> {
+        let floats = rows(&aabbs, 6, "aabbs")?;
+        Ok(build(py, Primitives::from_aabb_floats(&floats)))
+    }
+
+    /// Build from float32 triangles of shape (n, 9) or (n, 3, 3), their vertices in order.
+    #[staticmethod]
+    fn from_triangles(py: Python<'_>, vertices: PyReadonlyArrayDyn<f32>) -> PyResult<Self> {
+        let floats = rows(&vertices, 9, "vertices")?;
+        Ok(build(py, Primitives::from_triangle_floats(&floats)))
+    }
+
+    #[getter]
+    fn node_count(&self) -> usize {
+        self.0.bvh.nodes.len()
+    }
+
+    /// Cast rays given as float32 (m, 3) origins and directions. Returns the closest hit primitive ids
+    /// (uint32, 0xffffffff on a miss) and distances (float32, tmax on a miss).
+    #[pyo3(signature = (origins, directions, tmin = 0.0, tmax = f32::INFINITY))]
+    fn cast_rays<'py>(
+        &self,
+        py: Python<'py>,
+        origins: PyReadonlyArray2<f32>,
+        directions: PyReadonlyArray2<f32>,
+        tmin: f32,
+        tmax: f32,
+    ) -> PyResult<(Bound<'py, PyArray1<u32>>, Bound<'py, PyArray1<f32>>)> {
+        let origins = vec3_rows(&origins, "origins")?;
+        let directions = vec3_rows(&directions, "directions")?;
+        if origins.len() != directions.len() {
+            return Err(PyValueError::new_err(
+                "origins and directions must have the same length",
+            ));
+        }
+
+        let mut hits = vec![(u32::MAX, tmax); origins.len()];
+        if !self.0.bvh.nodes.is_empty() {
+            const CHUNK_SIZE: usize = 4096;
+            py.allow_threads(|| {
+                ploc_scheduler().par_chunks_mut(
+                    &mut hits,
+                    &|chunk_index, chunk| {
+                        let start = chunk_index * CHUNK_SIZE;
+                        for (i, hit) in chunk.iter_mut().enumerate() {
+                            let mut ray =
+                                Ray::new(origins[start + i], directions[start + i], tmin, tmax);
+                            hit.0 = self.0.closest_hit(&mut ray);
+                            hit.1 = ray.tmax;
+                        }
+                    },
+                    CHUNK_SIZE,
+                )
+            });
+        }
+
+        let (ids, ts): (Vec<u32>, Vec<f32>) = hits.into_iter().unzip();
+        Ok((ids.into_pyarray(py), ts.into_pyarray(py)))
+    }
+
+    /// Primitive ids whose aabbs overlap the query box given by min and max corners.
+    fn query_aabb(&self, min: [f32; 3], max: [f32; 3]) -> Vec<u32> {
+        let query = Aabb::new(Vec3A::from_array(min), Vec3A::from_array(max));
+        let mut found = Vec::new();
+        let nodes = &self.0.bvh.nodes;
+        if nodes.is_empty() {
+            return found;
+        }
+        let mut stack = vec![0usize];
+        while let Some(index) = stack.pop() {
+            let node = &nodes[index];
+            if !node.aabb.intersect_aabb(&query) {
+                continue;
+            }
+            if node.index < 0 {
+                found.push(-(node.index + 1) as u32);
+            } else {
+                stack.push(node.index as usize);
+                stack.push(node.index as usize + 1);
+            }
+        }
+        found
+    }
+}
+
+#[pymodule]
+#[pyo3(name = "pool_racing")]
+fn pool_racing_module(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyBvh>()?;
+    m.add_function(wrap_pyfunction!(set_scheduler, m)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use numpy::{PyArray2, PyArrayMethods};
+
+    use super::*;
+
+    #[test]
+    fn test_python_build_and_cast() {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            set_scheduler("seq_opt").unwrap();
+            assert!(set_scheduler("nope").is_err());
+
+            let aabbs = PyArray2::from_vec2(
+                py,
+                &[
+                    vec![0.0, 0.0, 0.0, 1.0, 1.0, 1.0],
+                    vec![2.0, 0.0, 0.0, 3.0, 1.0, 1.0],
+                    vec![4.0, 0.0, 0.0, 5.0, 1.0, 1.0],
+                ],
+            )
+            .unwrap();
+            let bvh = PyBvh::from_aabbs(py, aabbs.to_dyn().readonly()).unwrap();
+            assert_eq!(bvh.node_count(), 5);
+
+            let origins =
+                PyArray2::from_vec2(py, &[vec![2.5, 0.5, 5.0], vec![1.5, 0.5, 5.0]]).unwrap();
+            let directions =
+                PyArray2::from_vec2(py, &[vec![0.0, 0.0, -1.0], vec![0.0, 0.0, -1.0]]).unwrap();
+            let (ids, ts) = bvh
+                .cast_rays(
+                    py,
+                    origins.readonly(),
+                    directions.readonly(),
+                    0.0,
+                    f32::INFINITY,
+                )
+                .unwrap();
+            assert_eq!(ids.to_vec().unwrap(), vec![1, u32::MAX]);
+            assert_eq!(ts.to_vec().unwrap()[0], 4.0);
+            assert_eq!(bvh.query_aabb([1.5, 0.0, 0.0], [4.5, 1.0, 1.0]).len(), 2);
+
+            let bad = PyArray2::from_vec2(py, &[vec![0.0f32; 4]]).unwrap();
+            assert!(PyBvh::from_aabbs(py, bad.to_dyn().readonly()).is_err());
+        });
+    }
+}
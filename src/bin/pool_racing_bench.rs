@@ -0,0 +1,138 @@
+//! Race the full pipeline, triangle aabbs, ploc build and ray casts, on a standard benchmark scene for
+//! each backend and print the results. The table format also prints the `BvhStats` of the built bvh.
+//!
+//! `cargo run --release --features cli,test_util --bin pool_racing_bench -- --scene hair_ball --size medium`
+
+use std::{io, str::FromStr};
+
+use argh::FromArgs;
+use glam::{Mat4, Vec3, Vec3A};
+use pool_racing::{
+    par::Scheduler,
+    ploc::PlocBuilder,
+    race::{write_race_csv, write_race_json, PipelineTable, Race},
+    test_util::{
+        rays::camera_rays,
+        scenes::{BenchScene, SceneSize, DEFAULT_SEED},
+    },
+};
+
+#[derive(FromArgs)]
+/// Race the pool_racing backends on a benchmark scene
+struct BenchArgs {
+    /// scene to build and trace. Scenes: 'uniform_soup', 'clustered_soup', 'hair_ball', 'city_grid',
+    /// 'demoscene'
+    #[argh(option, default = "BenchScene::UniformSoup")]
+    scene: BenchScene,
+
+    /// scene size. Sizes: 'small' (10k triangles), 'medium' (100k), 'large' (1M)
+    #[argh(option, default = "SceneSize::Small")]
+    size: SceneSize,
+
+    /// comma separated backends to race, like 'forte,rayon'. Defaults to all of them
+    #[argh(option)]
+    backends: Option<String>,
+
+    /// timed repetitions per backend
+    #[argh(option, default = "10")]
+    reps: usize,
+
+    /// untimed repetitions per backend before timing starts
+    #[argh(option, default = "1")]
+    warmup: usize,
+
+    /// width and height of the camera ray grid
+    #[argh(option, default = "512")]
+    resolution: usize,
+
+    /// seed for the scene generator
+    #[argh(option, default = "DEFAULT_SEED")]
+    seed: u64,
+
+    /// output format. Formats: 'table', 'csv', 'json'
+    #[argh(option, default = "Format::Table")]
+    format: Format,
+}
+
+#[derive(Clone, Copy)]
+enum Format {
+    Table,
+    Csv,
+    Json,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "table" => Ok(Self::Table),
+            "csv" => Ok(Self::Csv),
+            "json" => Ok(Self::Json),
+            _ => Err(format!(
+                "Unknown format: '{s}', valid formats: 'table', 'csv', 'json'"
+            )),
+        }
+    }
+}
+
+fn main() -> Result<(), String> {
+    let args: BenchArgs = argh::from_env();
+    let schedulers = match &args.backends {
+        Some(list) => list
+            .split(',')
+            .map(|name| name.trim().parse())
+            .collect::<Result<Vec<Scheduler>, _>>()?,
+        None => Scheduler::ALL.to_vec(),
+    };
+
+    let triangles = args.scene.generate(args.size, args.seed);
+
+    // Look at the scene from in front, far enough back to see all of it
+    let (min, max) = triangles.iter().fold(
+        (Vec3A::splat(f32::MAX), Vec3A::splat(f32::MIN)),
+        |(min, max), t| {
+            (
+                min.min(t.v0).min(t.v1).min(t.v2),
+                max.max(t.v0).max(t.v1).max(t.v2),
+            )
+        },
+    );
+    let center = Vec3::from((min + max) * 0.5);
+    let eye = center + Vec3::new(0.0, 0.0, (max - min).max_element() * 1.5);
+    let proj_inv = Mat4::perspective_infinite_reverse_rh(60f32.to_radians(), 1.0, 0.01).inverse();
+    let view_inv = Mat4::look_at_rh(eye, center, Vec3::Y).inverse();
+    let rays = camera_rays(args.resolution, args.resolution, &view_inv, &proj_inv);
+
+    let race = Race {
+        schedulers,
+        warmup: args.warmup,
+        reps: args.reps,
+    };
+    let results = race.run_pipeline(&triangles, &rays);
+    let records: Vec<_> = results.iter().flat_map(|r| r.records()).collect();
+    match args.format {
+        Format::Table => {
+            println!(
+                "{} {}: {} triangles, {} rays",
+                args.scene.name(),
+                args.size.name(),
+                triangles.len(),
+                rays.len()
+            );
+            print!("{}", PipelineTable(&results));
+            let aabbs: Vec<_> = triangles.iter().map(|t| t.aabb()).collect();
+            print!(
+                "{}",
+                PlocBuilder::preallocate_builder(aabbs.len())
+                    .build_ploc(&aabbs)
+                    .stats()
+            );
+        }
+        Format::Csv => write_race_csv(io::stdout().lock(), &records).map_err(|e| e.to_string())?,
+        Format::Json => {
+            write_race_json(io::stdout().lock(), &records).map_err(|e| e.to_string())?
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,143 @@
+//! Shared by the C API in `ffi` and the Python bindings in the `pool_racing_python` crate: scheduler
+//! setup that never parses the host process args, and a bvh that owns the primitives rays are cast
+//! against. Requires the `bindings` feature, which `ffi` enables.
+
+use std::{
+    borrow::Cow,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use glam::Vec3A;
+use obvhs::{aabb::Aabb, ray::Ray, triangle::Triangle};
+
+use crate::{
+    bvh::Bvh2,
+    par::{scheduler_from_env_or_default, Scheduler},
+    ploc::{set_ploc_scheduler, PlocBuilder},
+    radix::set_radix_scheduler,
+};
+
+static CONFIGURED: AtomicBool = AtomicBool::new(false);
+
+/// Select the schedulers from the `POOL_RACING_*SCHEDULER` environment variables or the default, unless
+/// `set_scheduler` was already called.
+pub fn configure() {
+    if !CONFIGURED.swap(true, Ordering::Relaxed) {
+        set_ploc_scheduler(scheduler_from_env_or_default("POOL_RACING_PLOC_SCHEDULER"));
+        set_radix_scheduler(scheduler_from_env_or_default("POOL_RACING_RADIX_SCHEDULER"));
+    }
+}
+
+/// Use `scheduler` for building and ray casting from now on.
+pub fn set_scheduler(scheduler: Scheduler) {
+    set_ploc_scheduler(scheduler);
+    set_radix_scheduler(scheduler);
+    CONFIGURED.store(true, Ordering::Relaxed);
+}
+
+/// The primitives rays are intersected with
+pub enum Primitives {
+    Aabbs(Vec<Aabb>),
+    Triangles(Vec<Triangle>),
+}
+
+impl Primitives {
+    /// Aabbs of 6 floats each: min x, y, z then max x, y, z. A trailing partial aabb is ignored.
+    pub fn from_aabb_floats(floats: &[f32]) -> Self {
+        Primitives::Aabbs(
+            floats
+                .chunks_exact(6)
+                .map(|a| Aabb::new(Vec3A::new(a[0], a[1], a[2]), Vec3A::new(a[3], a[4], a[5])))
+                .collect(),
+        )
+    }
+
+    /// Triangles of 9 floats each: x, y, z of each vertex. A trailing partial triangle is ignored.
+    pub fn from_triangle_floats(floats: &[f32]) -> Self {
+        Primitives::Triangles(
+            floats
+                .chunks_exact(9)
+                .map(|v| Triangle {
+                    v0: Vec3A::new(v[0], v[1], v[2]),
+                    v1: Vec3A::new(v[3], v[4], v[5]),
+                    v2: Vec3A::new(v[6], v[7], v[8]),
+                })
+                .collect(),
+        )
+    }
+
+    pub fn aabbs(&self) -> Cow<'_, [Aabb]> {
+        match self {
+            Primitives::Aabbs(aabbs) => Cow::Borrowed(aabbs),
+            Primitives::Triangles(triangles) => {
+                Cow::Owned(triangles.iter().map(|t| t.aabb()).collect())
+            }
+        }
+    }
+
+    #[inline(always)]
+    pub fn intersect(&self, ray: &Ray, id: usize) -> f32 {
+        match self {
+            Primitives::Aabbs(aabbs) => aabbs[id].intersect_ray(ray),
+            Primitives::Triangles(triangles) => triangles[id].intersect(ray),
+        }
+    }
+}
+
+/// A bvh together with the primitives it was built over.
+pub struct BoundBvh {
+    pub bvh: Bvh2,
+    pub primitives: Primitives,
+}
+
+impl BoundBvh {
+    /// Build with ploc on the configured schedulers, see `configure`.
+    pub fn build(primitives: Primitives) -> Self {
+        configure();
+        let bvh = {
+            let aabbs = primitives.aabbs();
+            if aabbs.is_empty() {
+                Bvh2::default()
+            } else {
+                PlocBuilder::preallocate_builder(aabbs.len()).build_ploc(&aabbs)
+            }
+        };
+        BoundBvh { bvh, primitives }
+    }
+
+    /// The closest primitive `ray` hits, or `u32::MAX` on a miss. `ray.tmax` is set to the hit distance.
+    #[inline]
+    pub fn closest_hit(&self, ray: &mut Ray) -> u32 {
+        let mut primitive_id = u32::MAX;
+        self.bvh.traverse(ray, &mut primitive_id, |ray, id| {
+            self.primitives.intersect(ray, id)
+        });
+        primitive_id
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bound_bvh_closest_hit() {
+        #[rustfmt::skip]
+        let vertices = [
+            0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0,
+            0.0, 0.0, -1.0, 1.0, 0.0, -1.0, 0.0, 1.0, -1.0,
+        ];
+        let bound = BoundBvh::build(Primitives::from_triangle_floats(&vertices));
+        assert_eq!(bound.bvh.validate(2), Ok(()));
+        let direction = Vec3A::new(0.0, 0.0, -1.0);
+        let mut ray = Ray::new(Vec3A::new(0.2, 0.2, 1.0), direction, 0.0, f32::INFINITY);
+        assert_eq!(bound.closest_hit(&mut ray), 0);
+        assert_eq!(ray.tmax, 1.0);
+        let mut ray = Ray::new(Vec3A::new(2.0, 2.0, 1.0), direction, 0.0, f32::INFINITY);
+        assert_eq!(bound.closest_hit(&mut ray), u32::MAX);
+
+        let empty = BoundBvh::build(Primitives::from_aabb_floats(&[]));
+        assert!(empty.bvh.nodes.is_empty());
+        assert_eq!(empty.closest_hit(&mut ray), u32::MAX);
+    }
+}
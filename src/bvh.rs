@@ -1,14 +1,41 @@
 use bytemuck::Zeroable;
 use obvhs::{aabb::Aabb, cwbvh::TraversalStack32, ray::Ray};
 
+#[cfg(feature = "bvh")]
+pub mod bvh_crate;
+pub mod cache;
+pub mod compact;
+pub mod cwbvh;
+pub mod debug;
+pub mod gpu;
+pub mod hash;
+pub mod heatmap;
+pub mod layout;
+pub mod lod;
+pub mod merge;
+pub mod metrics;
+pub mod motion;
+#[cfg(feature = "shaders")]
+pub mod shaders;
+pub mod shared_origin;
+pub mod stats;
+pub mod subtree;
+pub mod validate;
+pub mod visibility;
+#[cfg(feature = "wgpu")]
+pub mod wgpu_traversal;
+
 #[derive(Default, Clone, Copy, Debug, Zeroable)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(C)]
 pub struct Bvh2Node {
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_remote::AabbDef"))]
     pub aabb: Aabb,
     pub index: i32, // Negative for leaf (and offset down one to avoid collision at 0)
 }
 
 #[derive(Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Bvh2 {
     pub nodes: Vec<Bvh2Node>,
 }
@@ -19,10 +46,23 @@ impl Bvh2 {
         &self,
         ray: &mut Ray,
         closest_id: &mut u32,
-        mut intersection_fn: F,
+        intersection_fn: F,
     ) {
-        crate::scope!("traverse");
-        // TODO allow for a deeper stack
+        traverse_nodes(&self.nodes, ray, closest_id, intersection_fn);
+    }
+
+    /// Whether anything is hit before `ray.tmax`, stopping at the first hit found rather than the
+    /// closest.
+    #[inline(always)]
+    pub fn occluded<F: FnMut(&Ray, usize) -> f32>(
+        &self,
+        ray: &Ray,
+        mut intersection_fn: F,
+    ) -> bool {
+        crate::scope!("occluded");
+        if self.nodes.is_empty() {
+            return false;
+        }
         let mut stack = TraversalStack32::default();
         stack.clear();
         stack.push(0);
@@ -32,18 +72,70 @@ impl Bvh2 {
                 continue;
             }
             if node.index < 0 {
-                let primitive_id = -(node.index + 1) as u32;
-                let t = intersection_fn(ray, primitive_id as usize);
-                if t < ray.tmax {
-                    *closest_id = primitive_id;
-                    ray.tmax = t;
-                    continue;
+                let primitive_id = -(node.index + 1) as usize;
+                if intersection_fn(ray, primitive_id) < ray.tmax {
+                    return true;
                 }
             } else {
                 stack.push(node.index as u32);
                 stack.push(node.index as u32 + 1);
             }
         }
+        false
+    }
+
+    /// Update the node aabbs after primitives moved, keeping the tree structure. `aabbs` is indexed by
+    /// primitive id and must have the same primitives the bvh was built with. Cheaper than a rebuild, but
+    /// quality degrades as primitives move further from where they were at build time.
+    pub fn refit(&mut self, aabbs: &[Aabb]) {
+        self.refit_with(|primitive| aabbs[primitive]);
+    }
+
+    /// `refit` with leaf aabbs from `leaf_aabb`, called with each primitive id.
+    pub fn refit_with<F: Fn(usize) -> Aabb>(&mut self, leaf_aabb: F) {
+        crate::scope!("refit");
+        // Children always come after their parent, so they are updated first in reverse node order.
+        for i in (0..self.nodes.len()).rev() {
+            let index = self.nodes[i].index;
+            self.nodes[i].aabb = if index < 0 {
+                leaf_aabb(-(index + 1) as usize)
+            } else {
+                let child = index as usize;
+                self.nodes[child].aabb.union(&self.nodes[child + 1].aabb)
+            };
+        }
+    }
+
+    /// The depth of each node, the root is at depth 0.
+    pub fn node_depths(&self) -> Vec<u32> {
+        // Children always come after their parent, so depths can be filled in node order.
+        let mut depths = vec![0; self.nodes.len()];
+        for (i, node) in self.nodes.iter().enumerate() {
+            if node.index >= 0 {
+                depths[node.index as usize] = depths[i] + 1;
+                depths[node.index as usize + 1] = depths[i] + 1;
+            }
+        }
+        depths
+    }
+
+    /// The depth of the deepest leaf, the root is at depth 0. Doesn't depend on the node order.
+    pub fn depth(&self) -> u32 {
+        if self.nodes.is_empty() {
+            return 0;
+        }
+        let mut max_depth = 0;
+        let mut stack = vec![(0usize, 0u32)];
+        while let Some((i, depth)) = stack.pop() {
+            let index = self.nodes[i].index;
+            if index < 0 {
+                max_depth = max_depth.max(depth);
+            } else {
+                stack.push((index as usize, depth + 1));
+                stack.push((index as usize + 1, depth + 1));
+            }
+        }
+        max_depth
     }
 
     #[inline(always)]
@@ -51,3 +143,196 @@ impl Bvh2 {
         self.nodes.clear();
     }
 }
+
+/// `Bvh2::traverse` over nodes stored elsewhere, like ones built with `PlocBuilder::rebuild_ploc_into`.
+#[inline(always)]
+pub fn traverse_nodes<F: FnMut(&Ray, usize) -> f32>(
+    nodes: &[Bvh2Node],
+    ray: &mut Ray,
+    closest_id: &mut u32,
+    intersection_fn: F,
+) {
+    traverse_nodes_counted(nodes, ray, closest_id, intersection_fn, &mut 0);
+}
+
+/// `traverse_nodes`, adding the number of nodes visited (popped and not culled) to `visited`.
+#[inline(always)]
+fn traverse_nodes_counted<F: FnMut(&Ray, usize) -> f32>(
+    nodes: &[Bvh2Node],
+    ray: &mut Ray,
+    closest_id: &mut u32,
+    mut intersection_fn: F,
+    visited: &mut u32,
+) {
+    crate::scope!("traverse");
+    if nodes.is_empty() {
+        return;
+    }
+    let root_t = nodes[0].aabb.intersect_ray(ray);
+    if root_t >= ray.tmax {
+        return;
+    }
+    // Each entry keeps the t it was pushed with, so nodes are culled again on pop once a closer hit
+    // has shortened ray.tmax
+    let mut stack = NodeStack::default();
+    stack.push(0, root_t);
+    while let Some((current_node_index, entry_t)) = stack.pop() {
+        if entry_t >= ray.tmax {
+            continue;
+        }
+        *visited += 1;
+        let node = &nodes[current_node_index as usize];
+        if node.index < 0 {
+            let primitive_id = -(node.index + 1) as u32;
+            let t = intersection_fn(ray, primitive_id as usize);
+            if t < ray.tmax {
+                *closest_id = primitive_id;
+                ray.tmax = t;
+            }
+            continue;
+        }
+        // Both children are tested together, only hits are pushed and the nearer one is pushed last so
+        // it's visited first
+        let first = node.index as u32;
+        let [t0, t1] = intersect_children(
+            ray,
+            &nodes[first as usize].aabb,
+            &nodes[first as usize + 1].aabb,
+        );
+        let (near, far) = if t0 <= t1 {
+            ((first, t0), (first + 1, t1))
+        } else {
+            ((first + 1, t1), (first, t0))
+        };
+        if far.1 < ray.tmax {
+            stack.push(far.0, far.1);
+        }
+        if near.1 < ray.tmax {
+            stack.push(near.0, near.1);
+        }
+    }
+}
+
+/// Traversal stack of (node index, entry t). Fixed size for typical depths, spilling to the heap for
+/// deeper trees instead of overflowing.
+#[derive(Default)]
+struct NodeStack {
+    entries: [(u32, f32); 32],
+    len: usize,
+    spill: Vec<(u32, f32)>,
+}
+
+impl NodeStack {
+    #[inline(always)]
+    fn push(&mut self, node: u32, t: f32) {
+        if self.len < self.entries.len() {
+            self.entries[self.len] = (node, t);
+            self.len += 1;
+        } else {
+            self.spill.push((node, t));
+        }
+    }
+
+    #[inline(always)]
+    fn pop(&mut self) -> Option<(u32, f32)> {
+        if let Some(entry) = self.spill.pop() {
+            return Some(entry);
+        }
+        if self.len == 0 {
+            return None;
+        }
+        self.len -= 1;
+        Some(self.entries[self.len])
+    }
+}
+
+/// Entry distances of `ray` into a pair of sibling aabbs, at least `ray.tmax` on a miss. Uses the slab
+/// test from `simd` with the `simd` feature.
+#[inline(always)]
+fn intersect_children(ray: &Ray, left: &Aabb, right: &Aabb) -> [f32; 2] {
+    #[cfg(feature = "simd")]
+    return crate::simd::intersect_aabbs_xn(ray, &[*left, *right]);
+    #[cfg(not(feature = "simd"))]
+    [left.intersect_ray(ray), right.intersect_ray(ray)]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::reference::{brute_force_closest_hit, random_bvh, random_rays, Rng};
+    use glam::Vec3A;
+    use obvhs::triangle::Triangle;
+
+    #[test]
+    fn test_traverse_culls_after_closer_hit() {
+        // A far cluster behind a single near triangle, under a root with the near leaf as one child
+        let (mut triangles, far) = random_bvh(81, 100);
+        let offset = Vec3A::new(0.0, 0.0, -11.0);
+        for t in &mut triangles {
+            *t = Triangle {
+                v0: t.v0 + offset,
+                v1: t.v1 + offset,
+                v2: t.v2 + offset,
+            };
+        }
+        let mut far_nodes = far.nodes.clone();
+        for node in &mut far_nodes {
+            node.aabb.min += offset;
+            node.aabb.max += offset;
+            if node.index >= 0 {
+                node.index += 2;
+            }
+        }
+        let near = Triangle {
+            v0: Vec3A::new(-1.0, -1.0, 0.0),
+            v1: Vec3A::new(3.0, -1.0, 0.0),
+            v2: Vec3A::new(-1.0, 3.0, 0.0),
+        };
+        triangles.push(near);
+        let root_aabb = far_nodes[0].aabb.union(&near.aabb());
+        let mut nodes = vec![
+            Bvh2Node {
+                aabb: root_aabb,
+                index: 1,
+            },
+            Bvh2Node {
+                aabb: near.aabb(),
+                index: -101,
+            },
+        ];
+        nodes.extend(far_nodes);
+        let bvh = Bvh2 { nodes };
+        bvh.validate(triangles.len()).unwrap();
+
+        let mut ray = Ray::new_inf(Vec3A::new(0.5, 0.5, 5.0), Vec3A::new(0.0, 0.0, -1.0));
+        let mut id = u32::MAX;
+        let mut visited = 0;
+        let mut leaves = 0;
+        traverse_nodes_counted(
+            &bvh.nodes,
+            &mut ray,
+            &mut id,
+            |ray, i| {
+                leaves += 1;
+                triangles[i].intersect(ray)
+            },
+            &mut visited,
+        );
+        assert_eq!(id, 100);
+        assert!((ray.tmax - 5.0).abs() < 1e-4);
+        // The root and the near leaf, the far subtree is behind the hit
+        assert_eq!((visited, leaves), (2, 1));
+    }
+
+    #[test]
+    fn test_traverse_matches_brute_force() {
+        let (triangles, bvh) = random_bvh(82, 300);
+        for ray in random_rays(&mut Rng::new(83), 256) {
+            let mut traced = ray;
+            let mut id = u32::MAX;
+            bvh.traverse(&mut traced, &mut id, |ray, i| triangles[i].intersect(ray));
+            let expected = brute_force_closest_hit(&triangles, &ray);
+            assert_eq!((id != u32::MAX).then_some((id, traced.tmax)), expected);
+        }
+    }
+}
@@ -0,0 +1,199 @@
+//! Conversion to and from the `bvh` crate's `Bvh`, so users of that crate can build with the parallel
+//! builders here and keep querying through its API. Requires the `bvh` feature.
+//!
+//! Only the `bvh` crate is supported, there is no parry3d `Qbvh` conversion. `Qbvh` is 4 wide and has no
+//! public way to take prebuilt nodes, it always runs its own splitting, so a conversion couldn't keep the
+//! tree built here.
+
+use ::bvh::{
+    aabb::{Aabb as BvhAabb, Bounded},
+    bounding_hierarchy::BHShape,
+    bvh::{Bvh as BvhCrateBvh, BvhNode as BvhCrateNode},
+};
+use glam::Vec3A;
+use obvhs::aabb::Aabb;
+
+use super::{Bvh2, Bvh2Node};
+
+fn to_bvh_aabb(aabb: &Aabb) -> BvhAabb<f32, 3> {
+    BvhAabb::with_bounds(aabb.min.to_array().into(), aabb.max.to_array().into())
+}
+
+fn from_bvh_aabb(aabb: &BvhAabb<f32, 3>) -> Aabb {
+    Aabb {
+        min: Vec3A::new(aabb.min.x, aabb.min.y, aabb.min.z),
+        max: Vec3A::new(aabb.max.x, aabb.max.y, aabb.max.z),
+    }
+}
+
+impl Bvh2 {
+    /// Convert to a `bvh` crate `Bvh` with the same node indices. Like `Bvh::build`, this sets the node
+    /// index of each shape, the shapes must be the primitives the bvh was built from.
+    pub fn to_bvh_crate<S: BHShape<f32, 3>>(&self, shapes: &mut [S]) -> BvhCrateBvh<f32, 3> {
+        crate::scope!("to_bvh_crate");
+        let mut parents = vec![0usize; self.nodes.len()];
+        for (i, node) in self.nodes.iter().enumerate() {
+            if node.index >= 0 {
+                parents[node.index as usize] = i;
+                parents[node.index as usize + 1] = i;
+            }
+        }
+
+        let nodes = self
+            .nodes
+            .iter()
+            .enumerate()
+            .map(|(i, node)| {
+                if node.index < 0 {
+                    let shape_index = -(node.index + 1) as usize;
+                    shapes[shape_index].set_bh_node_index(i);
+                    BvhCrateNode::Leaf {
+                        parent_index: parents[i],
+                        shape_index,
+                    }
+                } else {
+                    let child = node.index as usize;
+                    BvhCrateNode::Node {
+                        parent_index: parents[i],
+                        child_l_index: child,
+                        child_l_aabb: to_bvh_aabb(&self.nodes[child].aabb),
+                        child_r_index: child + 1,
+                        child_r_aabb: to_bvh_aabb(&self.nodes[child + 1].aabb),
+                    }
+                }
+            })
+            .collect();
+        BvhCrateBvh { nodes }
+    }
+
+    /// Convert from a `bvh` crate `Bvh`. Its nodes are laid out again so siblings are adjacent. `shapes`
+    /// are only used for the bounds of a root that is a leaf, since the `bvh` crate stores child bounds
+    /// in the parent.
+    pub fn from_bvh_crate<S: Bounded<f32, 3>>(bvh: &BvhCrateBvh<f32, 3>, shapes: &[S]) -> Bvh2 {
+        crate::scope!("from_bvh_crate");
+        let Some(root) = bvh.nodes.first() else {
+            return Bvh2::default();
+        };
+
+        let root_aabb = match root {
+            BvhCrateNode::Leaf { shape_index, .. } => from_bvh_aabb(&shapes[*shape_index].aabb()),
+            BvhCrateNode::Node {
+                child_l_aabb,
+                child_r_aabb,
+                ..
+            } => from_bvh_aabb(&child_l_aabb.join(child_r_aabb)),
+        };
+
+        let mut nodes = Vec::with_capacity(bvh.nodes.len());
+        nodes.push(Bvh2Node {
+            aabb: root_aabb,
+            index: 0,
+        });
+        // (index in the bvh crate nodes, index in our nodes)
+        let mut stack = vec![(0usize, 0usize)];
+        while let Some((src, dst)) = stack.pop() {
+            match &bvh.nodes[src] {
+                BvhCrateNode::Leaf { shape_index, .. } => {
+                    nodes[dst].index = -(*shape_index as i32) - 1;
+                }
+                BvhCrateNode::Node {
+                    child_l_index,
+                    child_l_aabb,
+                    child_r_index,
+                    child_r_aabb,
+                    ..
+                } => {
+                    let child = nodes.len();
+                    nodes[dst].index = child as i32;
+                    for aabb in [child_l_aabb, child_r_aabb] {
+                        nodes.push(Bvh2Node {
+                            aabb: from_bvh_aabb(aabb),
+                            index: 0,
+                        });
+                    }
+                    stack.push((*child_l_index, child));
+                    stack.push((*child_r_index, child + 1));
+                }
+            }
+        }
+        Bvh2 { nodes }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Shape {
+        aabb: Aabb,
+        node_index: usize,
+    }
+
+    impl Bounded<f32, 3> for Shape {
+        fn aabb(&self) -> BvhAabb<f32, 3> {
+            to_bvh_aabb(&self.aabb)
+        }
+    }
+
+    impl BHShape<f32, 3> for Shape {
+        fn set_bh_node_index(&mut self, index: usize) {
+            self.node_index = index;
+        }
+
+        fn bh_node_index(&self) -> usize {
+            self.node_index
+        }
+    }
+
+    #[test]
+    fn test_bvh_crate_round_trip() {
+        let aabb = |min: f32, max: f32| Aabb {
+            min: Vec3A::splat(min),
+            max: Vec3A::splat(max),
+        };
+        let mut shapes: Vec<Shape> = [aabb(1.0, 2.0), aabb(2.0, 3.0), aabb(0.0, 1.0)]
+            .into_iter()
+            .map(|aabb| Shape {
+                aabb,
+                node_index: usize::MAX,
+            })
+            .collect();
+        let bvh = Bvh2 {
+            nodes: vec![
+                Bvh2Node {
+                    aabb: aabb(0.0, 3.0),
+                    index: 1,
+                },
+                Bvh2Node {
+                    aabb: aabb(0.0, 1.0),
+                    index: -3,
+                },
+                Bvh2Node {
+                    aabb: aabb(1.0, 3.0),
+                    index: 3,
+                },
+                Bvh2Node {
+                    aabb: aabb(1.0, 2.0),
+                    index: -1,
+                },
+                Bvh2Node {
+                    aabb: aabb(2.0, 3.0),
+                    index: -2,
+                },
+            ],
+        };
+
+        let converted = bvh.to_bvh_crate(&mut shapes);
+        assert_eq!(converted.nodes.len(), 5);
+        let node_indices: Vec<_> = shapes.iter().map(|s| s.node_index).collect();
+        assert_eq!(node_indices, vec![3, 4, 1]);
+
+        let back = Bvh2::from_bvh_crate(&converted, &shapes);
+        assert_eq!(back.nodes.len(), bvh.nodes.len());
+        for (a, b) in back.nodes.iter().zip(&bvh.nodes) {
+            assert_eq!(a.aabb.min, b.aabb.min);
+            assert_eq!(a.aabb.max, b.aabb.max);
+            assert_eq!(a.index, b.index);
+        }
+    }
+}
@@ -0,0 +1,507 @@
+//! Versioned binary cache for `Bvh2`, so large static scenes don't need to be rebuilt on every start.
+//!
+//! The layout is a fixed size `CacheHeader` followed by `node_count` `CachedNode`s, both written in
+//! native byte order. The header records that byte order, and loading rejects caches written with a
+//! different one instead of byte swapping them.
+//!
+//! `Bvh2::write_mappable_to` writes a second, larger layout with the nodes exactly as `Bvh2Node` holds
+//! them in memory, 16 byte aligned after the header. A `Bvh2View` borrows its nodes straight from those
+//! bytes, like a memory mapped file, without copying them. `Bvh2::read_from` loads either layout.
+
+use std::{
+    io::{self, Read, Write},
+    mem::{align_of, offset_of},
+};
+
+use bytemuck::{Pod, Zeroable};
+use glam::Vec3A;
+use obvhs::{aabb::Aabb, ray::Ray};
+
+use super::{traverse_nodes, Bvh2, Bvh2Node};
+
+pub const CACHE_MAGIC: [u8; 4] = *b"PRBV";
+/// Bump whenever the header or node layout changes.
+pub const CACHE_VERSION: u32 = 1;
+/// Version of the layout written by `Bvh2::write_mappable_to`.
+pub const CACHE_VERSION_MAPPABLE: u32 = 2;
+/// Where the nodes start in the mappable layout, the header padded out to the node alignment.
+const MAPPABLE_NODE_OFFSET: usize = 32;
+/// Reads back as `0x04030201` when the cache was written on a machine with the other byte order.
+const ENDIANNESS_MARKER: u32 = 0x01020304;
+
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+#[repr(C)]
+struct CacheHeader {
+    magic: [u8; 4],
+    version: u32,
+    endianness: u32,
+    node_stride: u32,
+    node_count: u64,
+}
+
+/// `Bvh2Node` without the padding of `Vec3A` and the trailing padding of the struct, so it can be Pod.
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+#[repr(C)]
+struct CachedNode {
+    min: [f32; 3],
+    max: [f32; 3],
+    index: i32,
+}
+
+impl From<&Bvh2Node> for CachedNode {
+    fn from(node: &Bvh2Node) -> Self {
+        CachedNode {
+            min: node.aabb.min.to_array(),
+            max: node.aabb.max.to_array(),
+            index: node.index,
+        }
+    }
+}
+
+impl From<&CachedNode> for Bvh2Node {
+    fn from(node: &CachedNode) -> Self {
+        Bvh2Node {
+            aabb: Aabb {
+                min: Vec3A::from_array(node.min),
+                max: Vec3A::from_array(node.max),
+            },
+            index: node.index,
+        }
+    }
+}
+
+/// `Bvh2Node` as it is laid out in memory, with its padding made explicit so it can be Pod.
+#[derive(Clone, Copy, Debug)]
+#[repr(C, align(16))]
+struct MappedNode {
+    min: [f32; 3],
+    _pad0: u32,
+    max: [f32; 3],
+    _pad1: u32,
+    index: i32,
+    _pad2: [u32; 3],
+}
+
+// SAFETY: all fields are Pod and they fill the struct with no implicit padding
+unsafe impl Zeroable for MappedNode {}
+unsafe impl Pod for MappedNode {}
+
+// `Bvh2View` reinterprets `MappedNode`s as `Bvh2Node`s, which needs the layouts to match exactly.
+const _: () = {
+    assert!(size_of::<MappedNode>() == size_of::<Bvh2Node>());
+    assert!(align_of::<MappedNode>() == align_of::<Bvh2Node>());
+    assert!(MAPPABLE_NODE_OFFSET % align_of::<MappedNode>() == 0);
+    assert!(size_of::<CacheHeader>() <= MAPPABLE_NODE_OFFSET);
+    assert!(offset_of!(Bvh2Node, aabb) == offset_of!(MappedNode, min));
+    assert!(offset_of!(Aabb, min) == 0);
+    assert!(offset_of!(Aabb, max) == offset_of!(MappedNode, max));
+    assert!(offset_of!(Bvh2Node, index) == offset_of!(MappedNode, index));
+};
+
+impl From<&Bvh2Node> for MappedNode {
+    fn from(node: &Bvh2Node) -> Self {
+        MappedNode {
+            min: node.aabb.min.to_array(),
+            _pad0: 0,
+            max: node.aabb.max.to_array(),
+            _pad1: 0,
+            index: node.index,
+            _pad2: [0; 3],
+        }
+    }
+}
+
+fn invalid_data(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// How many nodes `read_nodes` reads at a time.
+const READ_CHUNK_NODES: usize = 1 << 16;
+
+/// Read `count` nodes, growing the Vec as they arrive rather than trusting the count up front, so a
+/// corrupt or truncated cache fails with an error instead of a huge allocation.
+fn read_nodes<N: Pod, R: Read>(reader: &mut R, count: usize) -> io::Result<Vec<N>> {
+    let mut nodes: Vec<N> = Vec::new();
+    while nodes.len() < count {
+        let chunk = (count - nodes.len()).min(READ_CHUNK_NODES);
+        nodes.try_reserve_exact(chunk).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::OutOfMemory,
+                format!("bvh cache node count {count} could not be allocated: {e}"),
+            )
+        })?;
+        let start = nodes.len();
+        nodes.resize(start + chunk, N::zeroed());
+        reader.read_exact(bytemuck::cast_slice_mut(&mut nodes[start..]))?;
+    }
+    Ok(nodes)
+}
+
+impl Bvh2 {
+    /// Write the bvh in the cache format. Use `Bvh2::read_from` to load it again.
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        crate::scope!("bvh write_to");
+        let header = CacheHeader {
+            magic: CACHE_MAGIC,
+            version: CACHE_VERSION,
+            endianness: ENDIANNESS_MARKER,
+            node_stride: size_of::<CachedNode>() as u32,
+            node_count: self.nodes.len() as u64,
+        };
+        writer.write_all(bytemuck::bytes_of(&header))?;
+        let nodes: Vec<CachedNode> = self.nodes.iter().map(CachedNode::from).collect();
+        writer.write_all(bytemuck::cast_slice(&nodes))
+    }
+
+    /// Write the bvh in the mappable cache format, which `Bvh2View::from_bytes` can use without copying.
+    /// Larger than `write_to`'s output, as the nodes keep their in memory padding.
+    pub fn write_mappable_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        crate::scope!("bvh write_mappable_to");
+        let header = CacheHeader {
+            magic: CACHE_MAGIC,
+            version: CACHE_VERSION_MAPPABLE,
+            endianness: ENDIANNESS_MARKER,
+            node_stride: size_of::<MappedNode>() as u32,
+            node_count: self.nodes.len() as u64,
+        };
+        writer.write_all(bytemuck::bytes_of(&header))?;
+        writer.write_all(&[0; MAPPABLE_NODE_OFFSET - size_of::<CacheHeader>()])?;
+        let nodes: Vec<MappedNode> = self.nodes.iter().map(MappedNode::from).collect();
+        writer.write_all(bytemuck::cast_slice(&nodes))
+    }
+
+    /// Read a bvh written by `Bvh2::write_to` or `Bvh2::write_mappable_to`. Fails with
+    /// `io::ErrorKind::InvalidData` if the cache is from another version or byte order, or if its nodes
+    /// don't form a valid tree.
+    pub fn read_from<R: Read>(reader: &mut R) -> io::Result<Bvh2> {
+        crate::scope!("bvh read_from");
+        let mut header = CacheHeader::zeroed();
+        reader.read_exact(bytemuck::bytes_of_mut(&mut header))?;
+        let node_count = check_header(&header)?;
+
+        let nodes: Vec<Bvh2Node> = if header.version == CACHE_VERSION_MAPPABLE {
+            let mut padding = [0; MAPPABLE_NODE_OFFSET - size_of::<CacheHeader>()];
+            reader.read_exact(&mut padding)?;
+            let nodes: Vec<MappedNode> = read_nodes(reader, node_count)?;
+            cast_mapped(&nodes).to_vec()
+        } else {
+            let nodes: Vec<CachedNode> = read_nodes(reader, node_count)?;
+            nodes.iter().map(Bvh2Node::from).collect()
+        };
+        check_children(&nodes)?;
+        Ok(Bvh2 { nodes })
+    }
+
+    /// Borrow the nodes as a `Bvh2View`.
+    pub fn view(&self) -> Bvh2View<'_> {
+        Bvh2View { nodes: &self.nodes }
+    }
+}
+
+/// Check the header is one this build can read, returning the node count.
+fn check_header(header: &CacheHeader) -> io::Result<usize> {
+    if header.magic != CACHE_MAGIC {
+        return Err(invalid_data("not a bvh cache".to_string()));
+    }
+    let stride = match header.version {
+        CACHE_VERSION => size_of::<CachedNode>(),
+        CACHE_VERSION_MAPPABLE => size_of::<MappedNode>(),
+        version => {
+            return Err(invalid_data(format!(
+                "bvh cache version {version} is not supported, expected {CACHE_VERSION} or \
+                 {CACHE_VERSION_MAPPABLE}"
+            )))
+        }
+    };
+    if header.endianness != ENDIANNESS_MARKER {
+        return Err(invalid_data(
+            "bvh cache was written with a different byte order".to_string(),
+        ));
+    }
+    if header.node_stride as usize != stride {
+        return Err(invalid_data(format!(
+            "bvh cache node stride {} does not match {stride}",
+            header.node_stride
+        )));
+    }
+    // Node indices are i32, anything larger can't be a valid tree.
+    if header.node_count > i32::MAX as u64 {
+        return Err(invalid_data(format!(
+            "bvh cache node count {} is too large",
+            header.node_count
+        )));
+    }
+    Ok(header.node_count as usize)
+}
+
+fn check_children(nodes: &[Bvh2Node]) -> io::Result<()> {
+    for (i, node) in nodes.iter().enumerate() {
+        // Inner nodes point at a pair of children, which always come after the node itself.
+        if node.index >= 0 && (node.index as usize <= i || node.index as usize + 1 >= nodes.len()) {
+            return Err(invalid_data(format!(
+                "bvh cache node {i} has out of range child index {}",
+                node.index
+            )));
+        }
+    }
+    Ok(())
+}
+
+fn cast_mapped(nodes: &[MappedNode]) -> &[Bvh2Node] {
+    // SAFETY: the layouts match exactly, checked at compile time above, and any bit pattern is a valid
+    // `Bvh2Node` as it only holds f32s and an i32
+    unsafe { std::slice::from_raw_parts(nodes.as_ptr().cast::<Bvh2Node>(), nodes.len()) }
+}
+
+/// A bvh borrowing its nodes from elsewhere, like a memory mapped cache written with
+/// `Bvh2::write_mappable_to`.
+#[derive(Clone, Copy)]
+pub struct Bvh2View<'a> {
+    pub nodes: &'a [Bvh2Node],
+}
+
+impl<'a> Bvh2View<'a> {
+    /// View the nodes of a mappable cache in place. Fails with `io::ErrorKind::InvalidData` if the cache
+    /// isn't in the mappable format, is truncated, isn't a valid tree, or if the nodes in `bytes` aren't
+    /// 16 byte aligned. Memory maps are page aligned, so the nodes of a mapped file always are.
+    pub fn from_bytes(bytes: &'a [u8]) -> io::Result<Self> {
+        crate::scope!("bvh view from_bytes");
+        let header: CacheHeader = bytes
+            .get(..size_of::<CacheHeader>())
+            .map(bytemuck::pod_read_unaligned)
+            .ok_or_else(|| invalid_data("bvh cache is truncated".to_string()))?;
+        let node_count = check_header(&header)?;
+        if header.version != CACHE_VERSION_MAPPABLE {
+            return Err(invalid_data(
+                "bvh cache isn't in the mappable format".to_string(),
+            ));
+        }
+        let node_bytes = bytes
+            .get(MAPPABLE_NODE_OFFSET..)
+            .and_then(|nodes| nodes.get(..node_count.checked_mul(size_of::<MappedNode>())?))
+            .ok_or_else(|| invalid_data("bvh cache is truncated".to_string()))?;
+        let nodes: &[MappedNode] = bytemuck::try_cast_slice(node_bytes).map_err(|_| {
+            invalid_data("bvh cache nodes aren't aligned for reading in place".to_string())
+        })?;
+        let nodes = cast_mapped(nodes);
+        check_children(nodes)?;
+        Ok(Bvh2View { nodes })
+    }
+
+    /// Like `Bvh2::traverse`.
+    #[inline(always)]
+    pub fn traverse<F: FnMut(&Ray, usize) -> f32>(
+        &self,
+        ray: &mut Ray,
+        closest_id: &mut u32,
+        intersection_fn: F,
+    ) {
+        traverse_nodes(self.nodes, ray, closest_id, intersection_fn);
+    }
+
+    /// Copy the nodes into an owned `Bvh2`.
+    pub fn to_bvh(&self) -> Bvh2 {
+        Bvh2 {
+            nodes: self.nodes.to_vec(),
+        }
+    }
+}
+
+/// A mappable cache file mapped into memory. The mapping is only valid while the file isn't modified,
+/// which the OS doesn't enforce.
+#[cfg(feature = "mmap")]
+pub struct MappedBvh {
+    map: memmap2::Mmap,
+    node_count: usize,
+}
+
+#[cfg(feature = "mmap")]
+impl MappedBvh {
+    /// Map the cache at `path` and check it like `Bvh2View::from_bytes`.
+    ///
+    /// # Safety
+    /// The file must not be modified or truncated while the `MappedBvh` exists.
+    pub unsafe fn open<P: AsRef<std::path::Path>>(path: P) -> io::Result<Self> {
+        let file = std::fs::File::open(path)?;
+        // SAFETY: the caller guarantees the file isn't changed while mapped
+        let map = unsafe { memmap2::Mmap::map(&file)? };
+        let node_count = Bvh2View::from_bytes(&map)?.nodes.len();
+        Ok(MappedBvh { map, node_count })
+    }
+
+    pub fn view(&self) -> Bvh2View<'_> {
+        // Only the nodes checked when opened, the file may have trailing bytes after them.
+        let end = MAPPABLE_NODE_OFFSET + self.node_count * size_of::<MappedNode>();
+        let nodes: &[MappedNode] = bytemuck::cast_slice(&self.map[MAPPABLE_NODE_OFFSET..end]);
+        Bvh2View {
+            nodes: cast_mapped(nodes),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(min: f32, max: f32, index: i32) -> Bvh2Node {
+        Bvh2Node {
+            aabb: Aabb {
+                min: Vec3A::splat(min),
+                max: Vec3A::splat(max),
+            },
+            index,
+        }
+    }
+
+    #[test]
+    fn test_cache_round_trip() {
+        let bvh = Bvh2 {
+            nodes: vec![node(0.0, 2.0, 1), node(0.0, 1.0, -1), node(1.0, 2.0, -2)],
+        };
+        let mut bytes = Vec::new();
+        bvh.write_to(&mut bytes).unwrap();
+        assert_eq!(
+            bytes.len(),
+            size_of::<CacheHeader>() + 3 * size_of::<CachedNode>()
+        );
+
+        let loaded = Bvh2::read_from(&mut bytes.as_slice()).unwrap();
+        assert_eq!(loaded.nodes.len(), bvh.nodes.len());
+        for (a, b) in loaded.nodes.iter().zip(&bvh.nodes) {
+            assert_eq!(a.aabb.min, b.aabb.min);
+            assert_eq!(a.aabb.max, b.aabb.max);
+            assert_eq!(a.index, b.index);
+        }
+    }
+
+    #[test]
+    fn test_cache_rejects_invalid() {
+        let bvh = Bvh2 {
+            nodes: vec![node(0.0, 2.0, 1), node(0.0, 1.0, -1), node(1.0, 2.0, -2)],
+        };
+        let mut bytes = Vec::new();
+        bvh.write_to(&mut bytes).unwrap();
+
+        let mut wrong_version = bytes.clone();
+        wrong_version[4] = wrong_version[4].wrapping_add(1);
+        assert!(Bvh2::read_from(&mut wrong_version.as_slice()).is_err());
+
+        let truncated = &bytes[..bytes.len() - 1];
+        assert!(Bvh2::read_from(&mut &truncated[..]).is_err());
+
+        // A huge node count with no nodes behind it fails on the read instead of allocating them all.
+        let mut huge_count = bytes[..size_of::<CacheHeader>()].to_vec();
+        huge_count[16..24].copy_from_slice(&(i32::MAX as u64).to_ne_bytes());
+        let err = Bvh2::read_from(&mut huge_count.as_slice()).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+
+        let broken = Bvh2 {
+            nodes: vec![node(0.0, 2.0, 2), node(0.0, 1.0, -1), node(1.0, 2.0, -2)],
+        };
+        let mut broken_bytes = Vec::new();
+        broken.write_to(&mut broken_bytes).unwrap();
+        let err = Bvh2::read_from(&mut broken_bytes.as_slice()).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_cache_mappable_view() {
+        let bvh = Bvh2 {
+            nodes: vec![node(0.0, 2.0, 1), node(0.0, 1.0, -1), node(1.0, 2.0, -2)],
+        };
+        let mut bytes = Vec::new();
+        bvh.write_mappable_to(&mut bytes).unwrap();
+        assert_eq!(
+            bytes.len(),
+            MAPPABLE_NODE_OFFSET + 3 * size_of::<MappedNode>()
+        );
+
+        // Copy into 16 byte aligned storage, as a memory map would be.
+        let mut storage =
+            vec![MappedNode::zeroed(); bytes.len().div_ceil(size_of::<MappedNode>()) + 1];
+        let aligned: &mut [u8] = bytemuck::cast_slice_mut(&mut storage);
+        aligned[..bytes.len()].copy_from_slice(&bytes);
+        let aligned = &aligned[..];
+
+        let view = Bvh2View::from_bytes(&aligned[..bytes.len()]).unwrap();
+        assert_eq!(view.nodes.len(), bvh.nodes.len());
+        for (a, b) in view.nodes.iter().zip(&bvh.nodes) {
+            assert_eq!(a.aabb.min, b.aabb.min);
+            assert_eq!(a.aabb.max, b.aabb.max);
+            assert_eq!(a.index, b.index);
+        }
+
+        let mut ray = Ray::new_inf(Vec3A::new(1.5, 1.5, -1.0), Vec3A::Z);
+        let mut closest_id = u32::MAX;
+        view.traverse(&mut ray, &mut closest_id, |_, primitive_id| {
+            primitive_id as f32 + 1.0
+        });
+        assert_eq!(closest_id, 1);
+
+        // The same bytes one byte off can't be viewed in place, and the compact format never can.
+        let offset = &aligned[1..bytes.len() + 1];
+        assert!(Bvh2View::from_bytes(offset).is_err());
+        assert!(Bvh2View::from_bytes(&aligned[..bytes.len() - 1]).is_err());
+        let mut compact = Vec::new();
+        bvh.write_to(&mut compact).unwrap();
+        assert!(Bvh2View::from_bytes(&compact).is_err());
+
+        let mut huge_count = bytes[..MAPPABLE_NODE_OFFSET].to_vec();
+        huge_count[16..24].copy_from_slice(&(i32::MAX as u64).to_ne_bytes());
+        let err = Bvh2::read_from(&mut huge_count.as_slice()).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+
+        let loaded = Bvh2::read_from(&mut bytes.as_slice()).unwrap();
+        assert_eq!(loaded.nodes.len(), bvh.nodes.len());
+        for (a, b) in loaded.nodes.iter().zip(view.to_bvh().nodes.iter()) {
+            assert_eq!(a.aabb.min, b.aabb.min);
+            assert_eq!(a.index, b.index);
+        }
+    }
+
+    #[test]
+    fn test_cache_mappable_empty() {
+        let mut bytes = Vec::new();
+        Bvh2 { nodes: Vec::new() }
+            .write_mappable_to(&mut bytes)
+            .unwrap();
+        let mut storage = vec![MappedNode::zeroed(); bytes.len().div_ceil(size_of::<MappedNode>())];
+        let aligned: &mut [u8] = bytemuck::cast_slice_mut(&mut storage);
+        aligned[..bytes.len()].copy_from_slice(&bytes);
+
+        let view = Bvh2View::from_bytes(&aligned[..bytes.len()]).unwrap();
+        assert!(view.nodes.is_empty());
+        let mut ray = Ray::new_inf(Vec3A::ZERO, Vec3A::Z);
+        let mut closest_id = u32::MAX;
+        view.traverse(&mut ray, &mut closest_id, |_, _| 0.0);
+        assert_eq!(closest_id, u32::MAX);
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn test_mapped_bvh_trailing_bytes() {
+        let bvh = Bvh2 {
+            nodes: vec![node(0.0, 2.0, 1), node(0.0, 1.0, -1), node(1.0, 2.0, -2)],
+        };
+        let mut bytes = Vec::new();
+        bvh.write_mappable_to(&mut bytes).unwrap();
+        // Trailing data that isn't a whole node, and a whole extra node that was never checked.
+        bytes.extend_from_slice(&[0xff; 5]);
+        bytes.extend_from_slice(bytemuck::bytes_of(&MappedNode::from(&node(0.0, 1.0, 0))));
+
+        let path =
+            std::env::temp_dir().join(format!("pool_racing_mapped_bvh_{}.bin", std::process::id()));
+        std::fs::write(&path, &bytes).unwrap();
+        // SAFETY: the file is only removed after the mapping is dropped
+        let mapped = unsafe { MappedBvh::open(&path) }.unwrap();
+        let view = mapped.view();
+        assert_eq!(view.nodes.len(), bvh.nodes.len());
+        for (a, b) in view.nodes.iter().zip(&bvh.nodes) {
+            assert_eq!(a.aabb.min, b.aabb.min);
+            assert_eq!(a.aabb.max, b.aabb.max);
+            assert_eq!(a.index, b.index);
+        }
+        drop(mapped);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,165 @@
+//! Alternative node encoding with an explicit leaf flag in the high bit of a `u32` index, instead of
+//! negative, offset by one `i32` indices. Leaf checks become a bit test and primitive ids a mask. Every
+//! bit pattern decodes to a primitive id or child index below 2^31 without overflow, but nothing checks
+//! they are in range of the primitives or nodes, decoding doesn't validate. Convert to `Bvh2` and use
+//! `Bvh2::validate` for untrusted data.
+
+use bytemuck::Zeroable;
+use obvhs::{aabb::Aabb, cwbvh::TraversalStack32, ray::Ray};
+
+use super::{Bvh2, Bvh2Node};
+
+#[derive(Default, Clone, Copy, Debug, Zeroable)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(C)]
+pub struct CompactBvh2Node {
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_remote::AabbDef"))]
+    pub aabb: Aabb,
+    /// Primitive id with `LEAF_FLAG` set for leaves, index of the first child for inner nodes
+    pub index: u32,
+}
+
+impl CompactBvh2Node {
+    pub const LEAF_FLAG: u32 = 1 << 31;
+
+    #[inline(always)]
+    pub fn new_leaf(aabb: Aabb, primitive_id: u32) -> Self {
+        debug_assert!(primitive_id < Self::LEAF_FLAG);
+        Self {
+            aabb,
+            index: primitive_id | Self::LEAF_FLAG,
+        }
+    }
+
+    #[inline(always)]
+    pub fn new_inner(aabb: Aabb, first_child: u32) -> Self {
+        debug_assert!(first_child < Self::LEAF_FLAG);
+        Self {
+            aabb,
+            index: first_child,
+        }
+    }
+
+    #[inline(always)]
+    pub fn is_leaf(&self) -> bool {
+        self.index & Self::LEAF_FLAG != 0
+    }
+
+    /// Only meaningful for leaves.
+    #[inline(always)]
+    pub fn primitive_id(&self) -> u32 {
+        self.index & !Self::LEAF_FLAG
+    }
+}
+
+impl From<&Bvh2Node> for CompactBvh2Node {
+    fn from(node: &Bvh2Node) -> Self {
+        if node.index < 0 {
+            Self::new_leaf(node.aabb, -(node.index + 1) as u32)
+        } else {
+            Self::new_inner(node.aabb, node.index as u32)
+        }
+    }
+}
+
+impl From<&CompactBvh2Node> for Bvh2Node {
+    fn from(node: &CompactBvh2Node) -> Self {
+        Bvh2Node {
+            aabb: node.aabb,
+            index: if node.is_leaf() {
+                -(node.primitive_id() as i32) - 1
+            } else {
+                node.index as i32
+            },
+        }
+    }
+}
+
+/// `Bvh2` with `CompactBvh2Node`s. Node order and layout are otherwise the same.
+#[derive(Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CompactBvh2 {
+    pub nodes: Vec<CompactBvh2Node>,
+}
+
+impl From<&Bvh2> for CompactBvh2 {
+    fn from(bvh: &Bvh2) -> Self {
+        crate::scope!("to compact bvh2");
+        CompactBvh2 {
+            nodes: bvh.nodes.iter().map(CompactBvh2Node::from).collect(),
+        }
+    }
+}
+
+impl From<&CompactBvh2> for Bvh2 {
+    fn from(bvh: &CompactBvh2) -> Self {
+        crate::scope!("from compact bvh2");
+        Bvh2 {
+            nodes: bvh.nodes.iter().map(Bvh2Node::from).collect(),
+        }
+    }
+}
+
+impl CompactBvh2 {
+    /// Same as `Bvh2::traverse`.
+    #[inline(always)]
+    pub fn traverse<F: FnMut(&Ray, usize) -> f32>(
+        &self,
+        ray: &mut Ray,
+        closest_id: &mut u32,
+        mut intersection_fn: F,
+    ) {
+        crate::scope!("traverse compact");
+        let mut stack = TraversalStack32::default();
+        stack.clear();
+        stack.push(0);
+        while let Some(current_node_index) = stack.pop() {
+            let node = &self.nodes[*current_node_index as usize];
+            if node.aabb.intersect_ray(ray) >= ray.tmax {
+                continue;
+            }
+            if node.is_leaf() {
+                let primitive_id = node.primitive_id();
+                let t = intersection_fn(ray, primitive_id as usize);
+                if t < ray.tmax {
+                    *closest_id = primitive_id;
+                    ray.tmax = t;
+                }
+            } else {
+                stack.push(node.index);
+                stack.push(node.index + 1);
+            }
+        }
+    }
+
+    #[inline(always)]
+    pub fn clear(&mut self) {
+        self.nodes.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{
+        random_bvh,
+        reference::{bvh_closest_hit, random_rays, Rng},
+    };
+
+    #[test]
+    fn test_compact_traversal_matches() {
+        let (triangles, bvh) = random_bvh(3, 400);
+        let mut rng = Rng::new(4);
+        let compact = CompactBvh2::from(&bvh);
+        assert_eq!(Bvh2::from(&compact).content_hash(), bvh.content_hash());
+
+        for ray in random_rays(&mut rng, 256) {
+            let expected = bvh_closest_hit(&bvh, &triangles, &ray);
+            let mut ray = ray;
+            let mut id = u32::MAX;
+            compact.traverse(&mut ray, &mut id, |ray, i| triangles[i].intersect(ray));
+            let got = (id != u32::MAX).then_some((id, ray.tmax));
+            assert_eq!(got, expected);
+        }
+    }
+}
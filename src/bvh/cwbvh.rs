@@ -0,0 +1,125 @@
+//! Conversion to the obvhs `Bvh2` and compressed wide bvh (CWBVH) formats, so bvhs from the parallel
+//! builders here can be used with obvhs' CWBVH CPU and GPU traversal.
+
+use obvhs::{
+    bvh2::{Bvh2 as ObvhsBvh2, Bvh2Node as ObvhsBvh2Node},
+    cwbvh::{bvh2_to_cwbvh, CwBvh},
+};
+
+use super::Bvh2;
+
+impl Bvh2 {
+    /// Convert to an obvhs `Bvh2` with one primitive per leaf. Node indices are unchanged, so
+    /// `primitive_indices` lists the primitives in leaf node order.
+    pub fn to_obvhs_bvh2(&self) -> ObvhsBvh2 {
+        crate::scope!("to_obvhs_bvh2");
+        let mut primitive_indices = Vec::with_capacity(self.nodes.len().div_ceil(2));
+        let nodes = self
+            .nodes
+            .iter()
+            .map(|node| {
+                if node.index < 0 {
+                    primitive_indices.push(-(node.index + 1) as u32);
+                    ObvhsBvh2Node {
+                        aabb: node.aabb,
+                        prim_count: 1,
+                        first_index: primitive_indices.len() as u32 - 1,
+                    }
+                } else {
+                    ObvhsBvh2Node {
+                        aabb: node.aabb,
+                        prim_count: 0,
+                        first_index: node.index as u32,
+                    }
+                }
+            })
+            .collect();
+        let max_depth = self.node_depths().into_iter().max().unwrap_or(0) as usize;
+        ObvhsBvh2 {
+            nodes,
+            primitive_indices,
+            max_depth,
+            ..Default::default()
+        }
+    }
+
+    /// Convert to an obvhs `CwBvh`, collapsing leaves up to `max_prims_per_leaf` primitives (at most 3
+    /// for the CWBVH node format).
+    pub fn to_cwbvh(&self, max_prims_per_leaf: u32) -> CwBvh {
+        crate::scope!("to_cwbvh");
+        bvh2_to_cwbvh(&self.to_obvhs_bvh2(), max_prims_per_leaf, true, false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bvh::Bvh2Node,
+        test_util::{
+            random_bvh,
+            reference::{bvh_closest_hit, random_rays, Rng},
+        },
+    };
+    use glam::Vec3A;
+    use obvhs::{aabb::Aabb, ray::RayHit};
+
+    #[test]
+    fn test_to_obvhs_bvh2() {
+        let aabb = |min: f32, max: f32| Aabb {
+            min: Vec3A::splat(min),
+            max: Vec3A::splat(max),
+        };
+        let bvh = Bvh2 {
+            nodes: vec![
+                Bvh2Node {
+                    aabb: aabb(0.0, 3.0),
+                    index: 1,
+                },
+                Bvh2Node {
+                    aabb: aabb(0.0, 1.0),
+                    index: -3,
+                },
+                Bvh2Node {
+                    aabb: aabb(1.0, 3.0),
+                    index: 3,
+                },
+                Bvh2Node {
+                    aabb: aabb(1.0, 2.0),
+                    index: -1,
+                },
+                Bvh2Node {
+                    aabb: aabb(2.0, 3.0),
+                    index: -2,
+                },
+            ],
+        };
+        let converted = bvh.to_obvhs_bvh2();
+        assert_eq!(converted.primitive_indices, vec![2, 0, 1]);
+        assert_eq!(converted.max_depth, 2);
+        let counts: Vec<_> = converted.nodes.iter().map(|n| n.prim_count).collect();
+        assert_eq!(counts, vec![0, 1, 0, 1, 1]);
+        let firsts: Vec<_> = converted.nodes.iter().map(|n| n.first_index).collect();
+        assert_eq!(firsts, vec![1, 0, 3, 1, 2]);
+        assert_eq!(converted.nodes[2].aabb.min, Vec3A::splat(1.0));
+    }
+
+    #[test]
+    fn test_to_cwbvh_traversal_matches() {
+        let (triangles, bvh) = random_bvh(61, 500);
+        let rays = random_rays(&mut Rng::new(62), 256);
+        for max_prims_per_leaf in [1, 3] {
+            let cwbvh = bvh.to_cwbvh(max_prims_per_leaf);
+            let primitive = |id: usize| cwbvh.primitive_indices[id] as usize;
+            for ray in &rays {
+                let mut hit = RayHit::none();
+                let got = cwbvh
+                    .ray_traverse(*ray, &mut hit, |ray, id| {
+                        triangles[primitive(id)].intersect(ray)
+                    })
+                    .then(|| (primitive(hit.primitive_id as usize) as u32, hit.t));
+                assert_eq!(got, bvh_closest_hit(&bvh, &triangles, ray));
+            }
+        }
+    }
+}
@@ -0,0 +1,166 @@
+//! Visualization export for diagnosing bad clusters: nested aabb wireframes as OBJ, and the tree of node
+//! indices as Graphviz.
+
+use std::{
+    io::{self, Write},
+    ops::RangeInclusive,
+};
+
+use super::Bvh2;
+
+// Corners are numbered by their xyz bits, 1 is max and 0 is min
+const BOX_EDGES: [(u32, u32); 12] = [
+    (0, 1),
+    (2, 3),
+    (4, 5),
+    (6, 7),
+    (0, 2),
+    (1, 3),
+    (4, 6),
+    (5, 7),
+    (0, 4),
+    (1, 5),
+    (2, 6),
+    (3, 7),
+];
+
+impl Bvh2 {
+    /// Write the aabbs of the nodes with depths in `depths` as OBJ line wireframes, one group per depth.
+    /// Depths past the deepest leaf are ignored, so `0..=u32::MAX` writes every level.
+    pub fn write_obj_wireframe<W: Write>(
+        &self,
+        writer: &mut W,
+        depths: RangeInclusive<u32>,
+    ) -> io::Result<()> {
+        crate::scope!("write_obj_wireframe");
+        // One walk buckets the nodes by depth, without descending below the last depth written
+        let (first_depth, last_depth) = (*depths.start(), *depths.end());
+        let mut levels: Vec<Vec<usize>> = Vec::new();
+        let mut stack = if self.nodes.is_empty() {
+            Vec::new()
+        } else {
+            vec![(0usize, 0u32)]
+        };
+        while let Some((i, depth)) = stack.pop() {
+            if depth >= first_depth {
+                let level = (depth - first_depth) as usize;
+                if levels.len() <= level {
+                    levels.resize(level + 1, Vec::new());
+                }
+                levels[level].push(i);
+            }
+            let index = self.nodes[i].index;
+            if index >= 0 && depth < last_depth {
+                stack.push((index as usize, depth + 1));
+                stack.push((index as usize + 1, depth + 1));
+            }
+        }
+        let mut vertex_count = 0u32;
+        for (level, nodes) in levels.iter_mut().enumerate() {
+            if nodes.is_empty() {
+                continue;
+            }
+            // Nodes in storage order within a group
+            nodes.sort_unstable();
+            let depth = first_depth + level as u32;
+            writeln!(writer, "g depth_{depth}")?;
+            for &i in nodes.iter() {
+                let (min, max) = (self.nodes[i].aabb.min, self.nodes[i].aabb.max);
+                for corner in 0..8 {
+                    let x = if corner & 1 != 0 { max.x } else { min.x };
+                    let y = if corner & 2 != 0 { max.y } else { min.y };
+                    let z = if corner & 4 != 0 { max.z } else { min.z };
+                    writeln!(writer, "v {x} {y} {z}")?;
+                }
+                // OBJ indices are 1 based
+                for (a, b) in BOX_EDGES {
+                    writeln!(
+                        writer,
+                        "l {} {}",
+                        vertex_count + a + 1,
+                        vertex_count + b + 1
+                    )?;
+                }
+                vertex_count += 8;
+            }
+        }
+        Ok(())
+    }
+
+    /// Write the tree as a Graphviz digraph. Inner nodes are labeled with their node index, leaves with
+    /// their primitive index.
+    pub fn write_graphviz<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        crate::scope!("write_graphviz");
+        writeln!(writer, "digraph bvh {{")?;
+        for (i, node) in self.nodes.iter().enumerate() {
+            if node.index < 0 {
+                let primitive_id = -(node.index + 1);
+                writeln!(writer, "    n{i} [label=\"p{primitive_id}\", shape=box];")?;
+            } else {
+                writeln!(writer, "    n{i} [label=\"{i}\"];")?;
+                writeln!(writer, "    n{i} -> n{};", node.index)?;
+                writeln!(writer, "    n{i} -> n{};", node.index + 1)?;
+            }
+        }
+        writeln!(writer, "}}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bvh::Bvh2Node;
+    use glam::Vec3A;
+    use obvhs::aabb::Aabb;
+
+    fn test_bvh() -> Bvh2 {
+        let node = |min: f32, max: f32, index: i32| Bvh2Node {
+            aabb: Aabb {
+                min: Vec3A::splat(min),
+                max: Vec3A::splat(max),
+            },
+            index,
+        };
+        Bvh2 {
+            nodes: vec![
+                node(0.0, 3.0, 1),
+                node(0.0, 1.0, -3),
+                node(1.0, 3.0, 3),
+                node(1.0, 2.0, -1),
+                node(2.0, 3.0, -2),
+            ],
+        }
+    }
+
+    #[test]
+    fn test_write_obj_wireframe_depth_range() {
+        let mut out = Vec::new();
+        test_bvh().write_obj_wireframe(&mut out, 1..=1).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert_eq!(out.lines().filter(|l| l.starts_with("v ")).count(), 16);
+        assert_eq!(out.lines().filter(|l| l.starts_with("l ")).count(), 24);
+        assert!(out.contains("g depth_1"));
+        assert!(!out.contains("g depth_0"));
+        // The last edge of the second box
+        assert!(out.lines().any(|l| l == "l 12 16"));
+
+        // Depths past the deepest leaf are ignored rather than scanned
+        let mut all = Vec::new();
+        test_bvh()
+            .write_obj_wireframe(&mut all, 0..=u32::MAX)
+            .unwrap();
+        let all = String::from_utf8(all).unwrap();
+        assert_eq!(all.lines().filter(|l| l.starts_with("g ")).count(), 3);
+        assert_eq!(all.lines().filter(|l| l.starts_with("v ")).count(), 40);
+    }
+
+    #[test]
+    fn test_write_graphviz() {
+        let mut out = Vec::new();
+        test_bvh().write_graphviz(&mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.starts_with("digraph bvh {"));
+        assert!(out.contains("n2 -> n4;"));
+        assert!(out.contains("n1 [label=\"p2\", shape=box];"));
+    }
+}
@@ -0,0 +1,159 @@
+//! Flattened `Bvh2` buffers for uploading to GPU storage buffers.
+//!
+//! The layout is a stable contract, matching std430 (and WGSL storage buffer) rules:
+//!
+//! ```text
+//! struct GpuBvhNode {          // stride 32, align 16
+//!     min: vec3<f32>,          // offset 0
+//!     child_or_first: u32,     // offset 12
+//!     max: vec3<f32>,          // offset 16
+//!     primitive_count: u32,    // offset 28
+//! }
+//! primitive_indices: array<u32> // stride 4
+//! ```
+//!
+//! Inner nodes have `primitive_count == 0` and `child_or_first` is the index of the left child, the
+//! right child is always at `child_or_first + 1`. Leaves have `primitive_count > 0` and their primitives
+//! are `primitive_indices[child_or_first..child_or_first + primitive_count]`. The root is node 0.
+//!
+//! All values are in native byte order, which is little endian on every platform with a GPU API.
+
+use bytemuck::{Pod, Zeroable};
+
+use super::Bvh2;
+
+pub const GPU_NODE_STRIDE: usize = 32;
+pub const GPU_NODE_MIN_OFFSET: usize = 0;
+pub const GPU_NODE_CHILD_OR_FIRST_OFFSET: usize = 12;
+pub const GPU_NODE_MAX_OFFSET: usize = 16;
+pub const GPU_NODE_PRIMITIVE_COUNT_OFFSET: usize = 28;
+pub const GPU_PRIMITIVE_INDEX_STRIDE: usize = 4;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Pod, Zeroable)]
+#[repr(C, align(16))]
+pub struct GpuBvhNode {
+    pub min: [f32; 3],
+    pub child_or_first: u32,
+    pub max: [f32; 3],
+    pub primitive_count: u32,
+}
+
+const _: () = assert!(size_of::<GpuBvhNode>() == GPU_NODE_STRIDE);
+
+impl GpuBvhNode {
+    #[inline(always)]
+    pub fn is_leaf(&self) -> bool {
+        self.primitive_count > 0
+    }
+}
+
+/// Byte buffers ready to be copied into a pair of storage buffers, see the module docs for the layout.
+#[derive(Clone, Default, Debug)]
+pub struct GpuBvhBuffers {
+    pub nodes: Vec<u8>,
+    pub primitive_indices: Vec<u8>,
+    pub node_count: u32,
+    pub primitive_count: u32,
+}
+
+impl Bvh2 {
+    /// Flatten the bvh into typed gpu nodes and primitive indices.
+    pub fn gpu_nodes(&self) -> (Vec<GpuBvhNode>, Vec<u32>) {
+        crate::scope!("gpu_nodes");
+        let mut primitive_indices = Vec::with_capacity(self.nodes.len().div_ceil(2));
+        let nodes = self
+            .nodes
+            .iter()
+            .map(|node| {
+                let (child_or_first, primitive_count) = if node.index < 0 {
+                    primitive_indices.push(-(node.index + 1) as u32);
+                    (primitive_indices.len() as u32 - 1, 1)
+                } else {
+                    (node.index as u32, 0)
+                };
+                GpuBvhNode {
+                    min: node.aabb.min.to_array(),
+                    child_or_first,
+                    max: node.aabb.max.to_array(),
+                    primitive_count,
+                }
+            })
+            .collect();
+        (nodes, primitive_indices)
+    }
+
+    /// Flatten the bvh into byte buffers, see the module docs for the layout.
+    pub fn to_gpu_buffers(&self) -> GpuBvhBuffers {
+        let (nodes, primitive_indices) = self.gpu_nodes();
+        GpuBvhBuffers {
+            nodes: bytemuck::cast_slice(&nodes).to_vec(),
+            primitive_indices: bytemuck::cast_slice(&primitive_indices).to_vec(),
+            node_count: nodes.len() as u32,
+            primitive_count: primitive_indices.len() as u32,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bvh::Bvh2Node;
+    use glam::Vec3A;
+    use obvhs::aabb::Aabb;
+
+    #[test]
+    fn test_gpu_buffer_layout() {
+        let aabb = |min: f32, max: f32| Aabb {
+            min: Vec3A::splat(min),
+            max: Vec3A::splat(max),
+        };
+        let bvh = Bvh2 {
+            nodes: vec![
+                Bvh2Node {
+                    aabb: aabb(0.0, 2.0),
+                    index: 1,
+                },
+                Bvh2Node {
+                    aabb: aabb(0.0, 1.0),
+                    index: -6,
+                },
+                Bvh2Node {
+                    aabb: aabb(1.0, 2.0),
+                    index: -3,
+                },
+            ],
+        };
+        let buffers = bvh.to_gpu_buffers();
+        assert_eq!(buffers.node_count, 3);
+        assert_eq!(buffers.primitive_count, 2);
+        assert_eq!(buffers.nodes.len(), 3 * GPU_NODE_STRIDE);
+        assert_eq!(
+            buffers.primitive_indices.len(),
+            2 * GPU_PRIMITIVE_INDEX_STRIDE
+        );
+
+        let read_u32 = |bytes: &[u8], offset: usize| {
+            u32::from_ne_bytes(bytes[offset..offset + 4].try_into().unwrap())
+        };
+        let read_f32 = |bytes: &[u8], offset: usize| f32::from_bits(read_u32(bytes, offset));
+
+        let leaf = GPU_NODE_STRIDE * 2;
+        assert_eq!(read_f32(&buffers.nodes, leaf + GPU_NODE_MIN_OFFSET), 1.0);
+        assert_eq!(
+            read_f32(&buffers.nodes, leaf + GPU_NODE_MAX_OFFSET + 8),
+            2.0
+        );
+        assert_eq!(
+            read_u32(&buffers.nodes, leaf + GPU_NODE_CHILD_OR_FIRST_OFFSET),
+            1
+        );
+        assert_eq!(
+            read_u32(&buffers.nodes, leaf + GPU_NODE_PRIMITIVE_COUNT_OFFSET),
+            1
+        );
+        assert_eq!(read_u32(&buffers.nodes, GPU_NODE_CHILD_OR_FIRST_OFFSET), 1);
+        assert_eq!(read_u32(&buffers.nodes, GPU_NODE_PRIMITIVE_COUNT_OFFSET), 0);
+        assert_eq!(read_u32(&buffers.primitive_indices, 0), 5);
+        assert_eq!(read_u32(&buffers.primitive_indices, 4), 2);
+    }
+}
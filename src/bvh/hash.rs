@@ -0,0 +1,65 @@
+//! Content hashing of `Bvh2`, to check builds are reproducible across runs and schedulers.
+
+use super::Bvh2;
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
+impl Bvh2 {
+    /// FNV-1a hash of every node's aabb bits and index, in node order. Unlike `std::hash`, this is
+    /// stable across runs, platforms and Rust versions, so it can be stored and compared later.
+    pub fn content_hash(&self) -> u64 {
+        crate::scope!("content_hash");
+        let mut hash = FNV_OFFSET;
+        let mut write = |word: u32| {
+            for byte in word.to_le_bytes() {
+                hash ^= byte as u64;
+                hash = hash.wrapping_mul(FNV_PRIME);
+            }
+        };
+        write(self.nodes.len() as u32);
+        for node in &self.nodes {
+            for v in node
+                .aabb
+                .min
+                .to_array()
+                .into_iter()
+                .chain(node.aabb.max.to_array())
+            {
+                write(v.to_bits());
+            }
+            write(node.index as u32);
+        }
+        hash
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bvh::{Bvh2, Bvh2Node};
+    use glam::Vec3A;
+    use obvhs::aabb::Aabb;
+
+    #[test]
+    fn test_content_hash() {
+        let node = |min: f32, max: f32, index: i32| Bvh2Node {
+            aabb: Aabb {
+                min: Vec3A::splat(min),
+                max: Vec3A::splat(max),
+            },
+            index,
+        };
+        let mut bvh = Bvh2 {
+            nodes: vec![node(0.0, 2.0, 1), node(0.0, 1.0, -1), node(1.0, 2.0, -2)],
+        };
+        let hash = bvh.content_hash();
+        assert_eq!(hash, bvh.clone().content_hash());
+        assert_ne!(hash, Bvh2::default().content_hash());
+
+        bvh.nodes.swap(1, 2);
+        assert_ne!(hash, bvh.content_hash());
+        bvh.nodes.swap(1, 2);
+        bvh.nodes[2].aabb.max.x = 2.0001;
+        assert_ne!(hash, bvh.content_hash());
+    }
+}
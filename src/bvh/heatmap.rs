@@ -0,0 +1,210 @@
+//! Per-node diagnostics for seeing where a builder struggles on a particular asset: how much each inner
+//! node's children overlap and how many primitives each subtree holds, exported as CSV or rendered into
+//! a top-down heatmap.
+
+use std::io::{self, Write};
+
+use obvhs::aabb::Aabb;
+
+use super::Bvh2;
+
+/// Scores for each node, indexed like `Bvh2::nodes`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct NodeDiagnostics {
+    /// Surface area of the intersection of an inner node's two children, 0 for leaves
+    pub child_overlap: Vec<f32>,
+    /// `child_overlap` divided by the node's surface area
+    pub relative_overlap: Vec<f32>,
+    /// Primitives in the subtree. Bvh2 leaves always hold a single primitive.
+    pub primitive_count: Vec<u32>,
+    /// The root is at depth 0
+    pub depth: Vec<u32>,
+}
+
+fn intersection_area(a: &Aabb, b: &Aabb) -> f32 {
+    let min = a.min.max(b.min);
+    let max = a.max.min(b.max);
+    if max.cmplt(min).any() {
+        0.0
+    } else {
+        Aabb { min, max }.surface_area()
+    }
+}
+
+impl Bvh2 {
+    pub fn node_diagnostics(&self) -> NodeDiagnostics {
+        crate::scope!("node_diagnostics");
+        let mut diag = NodeDiagnostics {
+            child_overlap: vec![0.0; self.nodes.len()],
+            relative_overlap: vec![0.0; self.nodes.len()],
+            primitive_count: vec![0; self.nodes.len()],
+            depth: self.node_depths(),
+        };
+        // Children always come after their parent, so counts can be summed in reverse node order.
+        for (i, node) in self.nodes.iter().enumerate().rev() {
+            if node.index < 0 {
+                diag.primitive_count[i] = 1;
+            } else {
+                let child = node.index as usize;
+                diag.primitive_count[i] =
+                    diag.primitive_count[child] + diag.primitive_count[child + 1];
+                let overlap =
+                    intersection_area(&self.nodes[child].aabb, &self.nodes[child + 1].aabb);
+                diag.child_overlap[i] = overlap;
+                let area = node.aabb.surface_area();
+                if area > 0.0 {
+                    diag.relative_overlap[i] = overlap / area;
+                }
+            }
+        }
+        diag
+    }
+
+    /// Sum `values` (one per node) over the top-down (xz) footprint of each node, on a grid covering the
+    /// root's bounds. `z` increases down the image. A zero width or height gives an empty heatmap.
+    pub fn heatmap_top_down(&self, values: &[f32], width: usize, height: usize) -> Heatmap {
+        crate::scope!("heatmap_top_down");
+        assert_eq!(values.len(), self.nodes.len());
+        let mut heatmap = Heatmap {
+            width,
+            height,
+            values: vec![0.0; width * height],
+        };
+        let Some(root) = self.nodes.first() else {
+            return heatmap;
+        };
+        if width == 0 || height == 0 {
+            return heatmap;
+        }
+        let size = (root.aabb.max - root.aabb.min).max(glam::Vec3A::splat(f32::EPSILON));
+        let to_pixel = |v: f32, min: f32, size: f32, res: usize| {
+            (((v - min) / size * res as f32) as usize).min(res - 1)
+        };
+        for (node, &value) in self.nodes.iter().zip(values) {
+            if value == 0.0 {
+                continue;
+            }
+            let x0 = to_pixel(node.aabb.min.x, root.aabb.min.x, size.x, width);
+            let x1 = to_pixel(node.aabb.max.x, root.aabb.min.x, size.x, width);
+            let y0 = to_pixel(node.aabb.min.z, root.aabb.min.z, size.z, height);
+            let y1 = to_pixel(node.aabb.max.z, root.aabb.min.z, size.z, height);
+            for y in y0..=y1 {
+                for v in &mut heatmap.values[y * width + x0..=y * width + x1] {
+                    *v += value;
+                }
+            }
+        }
+        heatmap
+    }
+
+    /// Top-down heatmap of how many leaves cover each pixel.
+    pub fn leaf_heatmap(&self, width: usize, height: usize) -> Heatmap {
+        let values: Vec<f32> = self
+            .nodes
+            .iter()
+            .map(|node| if node.index < 0 { 1.0 } else { 0.0 })
+            .collect();
+        self.heatmap_top_down(&values, width, height)
+    }
+
+    /// Top-down heatmap of the relative child overlap of the inner nodes covering each pixel.
+    pub fn overlap_heatmap(&self, width: usize, height: usize) -> Heatmap {
+        self.heatmap_top_down(&self.node_diagnostics().relative_overlap, width, height)
+    }
+}
+
+impl NodeDiagnostics {
+    /// Write one `node,depth,primitive_count,child_overlap,relative_overlap` row per node, with a header.
+    pub fn write_csv<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writeln!(
+            writer,
+            "node,depth,primitive_count,child_overlap,relative_overlap"
+        )?;
+        for (i, depth) in self.depth.iter().enumerate() {
+            writeln!(
+                writer,
+                "{i},{depth},{},{},{}",
+                self.primitive_count[i], self.child_overlap[i], self.relative_overlap[i]
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// A grid of accumulated node scores, row by row from the top.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Heatmap {
+    pub width: usize,
+    pub height: usize,
+    pub values: Vec<f32>,
+}
+
+impl Heatmap {
+    pub fn max(&self) -> f32 {
+        self.values.iter().copied().fold(0.0, f32::max)
+    }
+
+    /// Values normalized by the max and mapped from black through red and yellow to white, as 8 bit rgb.
+    pub fn to_rgb8(&self) -> Vec<u8> {
+        let max = self.max();
+        let scale = if max > 0.0 { 1.0 / max } else { 0.0 };
+        self.values
+            .iter()
+            .flat_map(|v| {
+                let t = (v * scale * 3.0).clamp(0.0, 3.0);
+                [t, t - 1.0, t - 2.0].map(|c| (c.clamp(0.0, 1.0) * 255.0) as u8)
+            })
+            .collect()
+    }
+
+    /// Write `to_rgb8` as a binary PPM, which most image viewers open without any extra dependencies.
+    pub fn write_ppm<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        write!(writer, "P6\n{} {}\n255\n", self.width, self.height)?;
+        writer.write_all(&self.to_rgb8())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bvh::Bvh2Node;
+    use glam::{vec3a, Vec3A};
+
+    #[test]
+    fn test_node_diagnostics() {
+        let node = |min: Vec3A, max: Vec3A, index: i32| Bvh2Node {
+            aabb: Aabb { min, max },
+            index,
+        };
+        let bvh = Bvh2 {
+            nodes: vec![
+                node(Vec3A::ZERO, vec3a(4.0, 1.0, 4.0), 1),
+                node(Vec3A::ZERO, vec3a(2.0, 1.0, 4.0), -1),
+                node(vec3a(1.0, 0.0, 0.0), vec3a(4.0, 1.0, 2.0), -2),
+            ],
+        };
+        let diag = bvh.node_diagnostics();
+        assert_eq!(diag.primitive_count, vec![2, 1, 1]);
+        assert_eq!(diag.depth, vec![0, 1, 1]);
+        // Children overlap in a 1x1x2 box
+        assert_eq!(diag.child_overlap, vec![10.0, 0.0, 0.0]);
+        assert_eq!(diag.relative_overlap[0], 10.0 / 48.0);
+
+        let leaves = bvh.leaf_heatmap(4, 4);
+        assert_eq!(leaves.max(), 2.0);
+        // Top left is only covered by the first leaf, bottom right by none
+        assert_eq!(leaves.values[0], 1.0);
+        assert_eq!(leaves.values[4 * 4 - 1], 0.0);
+        assert!(bvh.leaf_heatmap(0, 4).values.is_empty());
+        assert!(bvh.leaf_heatmap(4, 0).values.is_empty());
+
+        let mut ppm = Vec::new();
+        leaves.write_ppm(&mut ppm).unwrap();
+        assert!(ppm.starts_with(b"P6\n4 4\n255\n"));
+        assert_eq!(ppm.len(), 11 + 4 * 4 * 3);
+
+        let mut csv = Vec::new();
+        diag.write_csv(&mut csv).unwrap();
+        assert!(String::from_utf8(csv).unwrap().contains("\n0,0,2,10,"));
+    }
+}
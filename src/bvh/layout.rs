@@ -0,0 +1,160 @@
+//! Rewriting the node array so it only holds nodes reachable from the root, laid out depth first. After
+//! nodes have been unlinked or spliced in, the array can be left with holes and subtrees scattered far
+//! from their parents, this restores the dense layout a fresh build has.
+
+use crate::{
+    bvh::{Bvh2, Bvh2Node},
+    ploc::{init_ploc_scheduler, ploc_scheduler},
+};
+
+impl Bvh2 {
+    /// Drop unreachable nodes and reorder the rest depth first, keeping each pair of siblings together
+    /// with the left subtree before the right. Children can be anywhere in the array, before or after
+    /// their parent, as after refits, rotations or incremental updates. The new positions are assigned in
+    /// one depth first walk, then nodes are copied and their child indices remapped in parallel on the
+    /// ploc scheduler.
+    pub fn compact(&mut self) {
+        crate::scope!("compact");
+        if self.nodes.is_empty() {
+            return;
+        }
+        const UNREACHABLE: u32 = u32::MAX;
+
+        // Each node's children go at the start of the space used by its descendants, with the left
+        // child's descendants next and the right child's after them. Popping the left child first
+        // finishes its whole subtree before the right child is placed.
+        let mut new_index = vec![UNREACHABLE; self.nodes.len()];
+        let mut old_index = vec![0u32];
+        new_index[0] = 0;
+        let mut stack = vec![0usize];
+        while let Some(i) = stack.pop() {
+            let index = self.nodes[i].index;
+            if index < 0 {
+                continue;
+            }
+            let (left, right) = (index as usize, index as usize + 1);
+            assert!(
+                new_index[left] == UNREACHABLE && new_index[right] == UNREACHABLE,
+                "node {i} links to children that are already in the tree"
+            );
+            new_index[left] = old_index.len() as u32;
+            new_index[right] = old_index.len() as u32 + 1;
+            old_index.push(left as u32);
+            old_index.push(right as u32);
+            stack.push(right);
+            stack.push(left);
+        }
+        let count = old_index.len();
+
+        init_ploc_scheduler();
+        let scheduler = ploc_scheduler();
+        let mut compacted = vec![Bvh2Node::default(); count];
+        let nodes = &self.nodes;
+        scheduler.par_map(
+            &mut compacted,
+            &|i, node| {
+                let old = nodes[old_index[i] as usize];
+                node.aabb = old.aabb;
+                node.index = if old.index < 0 {
+                    old.index
+                } else {
+                    new_index[old.index as usize] as i32
+                };
+            },
+            scheduler.current_num_threads() as u32 * 4,
+        );
+        self.nodes = compacted;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{
+        random_bvh,
+        reference::{bvh_closest_hit, random_rays, Rng},
+    };
+
+    #[test]
+    fn test_compact_removes_holes() {
+        let (triangles, bvh) = random_bvh(21, 300);
+        let mut rng = Rng::new(121);
+
+        // Spread the sibling pairs out with two unreachable pairs after each
+        assert!(bvh.nodes.iter().all(|n| n.index < 0 || n.index % 2 == 1));
+        let spread = |i: usize| {
+            if i == 0 {
+                0
+            } else {
+                (i - 1) / 2 * 6 + 1 + (i - 1) % 2
+            }
+        };
+        let mut holey = Bvh2 {
+            nodes: vec![Bvh2Node::default(); bvh.nodes.len() * 3],
+        };
+        for (i, node) in bvh.nodes.iter().enumerate() {
+            let mut node = *node;
+            if node.index >= 0 {
+                node.index = spread(node.index as usize) as i32;
+            }
+            holey.nodes[spread(i)] = node;
+        }
+        assert!(holey.validate(triangles.len()).is_err());
+
+        holey.compact();
+        assert_eq!(holey.nodes.len(), bvh.nodes.len());
+        holey.validate(triangles.len()).unwrap();
+        for ray in random_rays(&mut rng, 128) {
+            assert_eq!(
+                bvh_closest_hit(&holey, &triangles, &ray),
+                bvh_closest_hit(&bvh, &triangles, &ray)
+            );
+        }
+
+        // Compacting a dense bvh only reorders it
+        let mut dense = bvh.clone();
+        dense.compact();
+        assert_eq!(dense.nodes.len(), bvh.nodes.len());
+        dense.validate(triangles.len()).unwrap();
+    }
+
+    #[test]
+    fn test_compact_children_before_parent() {
+        let (triangles, bvh) = random_bvh(22, 300);
+        let mut rng = Rng::new(122);
+
+        // Reverse the order of the sibling pairs, so every pair ends up before its parent
+        let pairs = (bvh.nodes.len() - 1) / 2;
+        let reversed = |i: usize| {
+            if i == 0 {
+                0
+            } else {
+                (pairs - 1 - (i - 1) / 2) * 2 + 1 + (i - 1) % 2
+            }
+        };
+        let mut shuffled = Bvh2 {
+            nodes: vec![Bvh2Node::default(); bvh.nodes.len()],
+        };
+        for (i, node) in bvh.nodes.iter().enumerate() {
+            let mut node = *node;
+            if node.index >= 0 {
+                node.index = reversed(node.index as usize) as i32;
+            }
+            shuffled.nodes[reversed(i)] = node;
+        }
+        assert!(shuffled.nodes[1..]
+            .iter()
+            .enumerate()
+            .any(|(i, n)| n.index >= 0 && (n.index as usize) < i + 1));
+
+        shuffled.compact();
+        assert_eq!(shuffled.nodes.len(), bvh.nodes.len());
+        shuffled.validate(triangles.len()).unwrap();
+        for ray in random_rays(&mut rng, 128) {
+            assert_eq!(
+                bvh_closest_hit(&shuffled, &triangles, &ray),
+                bvh_closest_hit(&bvh, &triangles, &ray)
+            );
+        }
+    }
+}
@@ -0,0 +1,107 @@
+//! Traversal that treats nodes as opaque once they look small enough from the ray, for ray traced level of
+//! detail and filtered visibility. The ray is a cone with a spread angle, and a node whose angular size at
+//! its entry distance falls below a fraction of that spread is reported as the hit instead of descending
+//! into it.
+
+use obvhs::{cwbvh::TraversalStack32, ray::Ray};
+
+use super::Bvh2;
+
+/// What a level of detail traversal hit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LodHit {
+    /// A primitive, by id
+    Primitive(u32),
+    /// A node small enough to stop at, by index, hit where the ray enters its aabb
+    Node(u32),
+}
+
+impl Bvh2 {
+    /// Closest hit like `traverse`, but a node whose angular radius seen from the ray origin is below
+    /// `threshold * spread_angle` is hit at its aabb entry instead of being descended into. The angular
+    /// radius is half the aabb diagonal over the entry distance. `spread_angle` is the cone's angle in
+    /// radians, typically the pixel footprint. Larger thresholds stop earlier, zero never does and matches
+    /// `traverse`. `ray.tmax` is set to the hit distance.
+    pub fn traverse_lod<F: FnMut(&Ray, usize) -> f32>(
+        &self,
+        ray: &mut Ray,
+        spread_angle: f32,
+        threshold: f32,
+        mut intersection_fn: F,
+    ) -> Option<LodHit> {
+        crate::scope!("traverse_lod");
+        if self.nodes.is_empty() {
+            return None;
+        }
+        let cutoff = spread_angle * threshold;
+        let mut hit = None;
+        let mut stack = TraversalStack32::default();
+        stack.clear();
+        stack.push(0);
+        while let Some(current_node_index) = stack.pop() {
+            let node_index = *current_node_index;
+            let node = &self.nodes[node_index as usize];
+            let t = node.aabb.intersect_ray(ray);
+            if t >= ray.tmax {
+                continue;
+            }
+            if node.index < 0 {
+                let primitive_id = -(node.index + 1) as u32;
+                let t = intersection_fn(ray, primitive_id as usize);
+                if t < ray.tmax {
+                    hit = Some(LodHit::Primitive(primitive_id));
+                    ray.tmax = t;
+                }
+                continue;
+            }
+            let radius = (node.aabb.max - node.aabb.min).length() * 0.5;
+            if t > 0.0 && radius < cutoff * t {
+                hit = Some(LodHit::Node(node_index));
+                ray.tmax = t;
+                continue;
+            }
+            stack.push(node.index as u32);
+            stack.push(node.index as u32 + 1);
+        }
+        hit
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{
+        random_bvh,
+        reference::{bvh_closest_hit, random_rays, Rng},
+    };
+
+    #[test]
+    fn test_traverse_lod() {
+        let (triangles, bvh) = random_bvh(51, 400);
+        let mut rng = Rng::new(52);
+
+        let mut node_hits = 0;
+        for ray in random_rays(&mut rng, 128) {
+            // No cutoff is the same as a normal traversal
+            let mut exact = ray;
+            let hit = bvh.traverse_lod(&mut exact, 0.01, 0.0, |ray, i| triangles[i].intersect(ray));
+            let expected = bvh_closest_hit(&bvh, &triangles, &ray);
+            assert_eq!(hit.map(|_| exact.tmax), expected.map(|(_, t)| t));
+            if let (Some(hit), Some((id, _))) = (hit, expected) {
+                assert_eq!(hit, LodHit::Primitive(id));
+            }
+
+            // A wide cone stops at nodes, never further away than the exact hit
+            let mut coarse = ray;
+            if let Some(hit) =
+                bvh.traverse_lod(&mut coarse, 0.2, 1.0, |ray, i| triangles[i].intersect(ray))
+            {
+                node_hits += matches!(hit, LodHit::Node(_)) as usize;
+                if expected.is_some() {
+                    assert!(coarse.tmax <= exact.tmax);
+                }
+            }
+        }
+        assert!(node_hits > 0);
+    }
+}
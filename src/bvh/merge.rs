@@ -0,0 +1,163 @@
+//! Combining bvhs under a new root, so chunks of a scene that are streamed in can join an existing bvh
+//! without rebuilding it, and rebuilding just the top of a bvh after merges leave it unbalanced.
+
+use obvhs::aabb::Aabb;
+
+use crate::{
+    bvh::{Bvh2, Bvh2Node},
+    ploc::PlocBuilder,
+};
+
+impl Bvh2 {
+    /// A bvh with `self` and `other` as the two children of a new root. `other`'s primitive ids are
+    /// offset by `prim_offset`, usually the primitive count of `self`.
+    pub fn merge(&self, other: &Bvh2, prim_offset: u32) -> Bvh2 {
+        crate::scope!("merge");
+        let offset_leaf = |node: &Bvh2Node| Bvh2Node {
+            aabb: node.aabb,
+            index: if node.index < 0 {
+                node.index - prim_offset as i32
+            } else {
+                node.index
+            },
+        };
+        if self.nodes.is_empty() {
+            return Bvh2 {
+                nodes: other.nodes.iter().map(offset_leaf).collect(),
+            };
+        }
+        if other.nodes.is_empty() {
+            return self.clone();
+        }
+
+        // [new root, self root, other root, rest of self, rest of other], keeping sibling pairs together
+        let a_len = self.nodes.len();
+        let map_a = |i: i32| if i == 0 { 1 } else { i + 2 };
+        let map_b = |i: i32| if i == 0 { 2 } else { a_len as i32 + 1 + i };
+        let remap = |node: &Bvh2Node, map: &dyn Fn(i32) -> i32| Bvh2Node {
+            aabb: node.aabb,
+            index: if node.index < 0 {
+                node.index
+            } else {
+                map(node.index)
+            },
+        };
+        let mut nodes = Vec::with_capacity(a_len + other.nodes.len() + 1);
+        nodes.push(Bvh2Node {
+            aabb: self.nodes[0].aabb.union(&other.nodes[0].aabb),
+            index: 1,
+        });
+        nodes.push(remap(&self.nodes[0], &map_a));
+        nodes.push(remap(&offset_leaf(&other.nodes[0]), &map_b));
+        nodes.extend(self.nodes[1..].iter().map(|n| remap(n, &map_a)));
+        nodes.extend(
+            other.nodes[1..]
+                .iter()
+                .map(|n| remap(&offset_leaf(n), &map_b)),
+        );
+        Bvh2 { nodes }
+    }
+
+    /// `merge` followed by `rebuild_top`.
+    pub fn merge_optimized(&self, other: &Bvh2, prim_offset: u32, top_leaves: usize) -> Bvh2 {
+        let mut merged = self.merge(other, prim_offset);
+        merged.rebuild_top(top_leaves);
+        merged
+    }
+
+    /// Rebuild the top of the tree with ploc over up to `top_leaves` subtrees, found by splitting the
+    /// subtree with the most primitives until there are enough. The subtrees themselves are kept as they
+    /// are. Fixes the poor top levels left by merging bvhs that overlap, for much less than a full
+    /// rebuild.
+    pub fn rebuild_top(&mut self, top_leaves: usize) {
+        crate::scope!("rebuild top");
+        if self.nodes.is_empty() {
+            return;
+        }
+        let roots = self.split_roots(top_leaves);
+        if roots.len() < 2 {
+            return;
+        }
+        let aabbs: Vec<Aabb> = roots.iter().map(|&r| self.nodes[r].aabb).collect();
+        let top = PlocBuilder::preallocate_builder(aabbs.len()).build_ploc(&aabbs);
+
+        // Top nodes first with each leaf replaced by the root of its subtree, then the rest of each
+        // subtree appended in its original order
+        let mut nodes = top.nodes;
+        let mut new_index = vec![u32::MAX; self.nodes.len()];
+        let mut descendants = Vec::new();
+        for i in 0..nodes.len() {
+            if nodes[i].index >= 0 {
+                continue;
+            }
+            let root = roots[-(nodes[i].index + 1) as usize];
+            descendants.clear();
+            let mut stack = vec![root];
+            while let Some(n) = stack.pop() {
+                let index = self.nodes[n].index;
+                if index >= 0 {
+                    descendants.push(index as usize);
+                    descendants.push(index as usize + 1);
+                    stack.push(index as usize);
+                    stack.push(index as usize + 1);
+                }
+            }
+            descendants.sort_unstable();
+            for (rank, &old) in descendants.iter().enumerate() {
+                new_index[old] = (nodes.len() + rank) as u32;
+            }
+            let remap = |node: Bvh2Node| Bvh2Node {
+                aabb: node.aabb,
+                index: if node.index < 0 {
+                    node.index
+                } else {
+                    new_index[node.index as usize] as i32
+                },
+            };
+            nodes[i] = remap(self.nodes[root]);
+            let appended: Vec<Bvh2Node> = descendants
+                .iter()
+                .map(|&old| remap(self.nodes[old]))
+                .collect();
+            nodes.extend(appended);
+        }
+        self.nodes = nodes;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        par::Scheduler,
+        test_util::{
+            random_bvh,
+            reference::{bvh_closest_hit, random_rays, Rng},
+        },
+    };
+
+    #[test]
+    fn test_merge() {
+        let (a_tris, a) = random_bvh(41, 300);
+        let (b_tris, b) = random_bvh(42, 200);
+        let triangles: Vec<_> = a_tris.iter().chain(&b_tris).cloned().collect();
+        let mut rng = Rng::new(43);
+
+        let merged = a.merge(&b, a_tris.len() as u32);
+        merged.validate(triangles.len()).unwrap();
+        let optimized = a.merge_optimized(&b, a_tris.len() as u32, 16);
+        optimized.validate(triangles.len()).unwrap();
+        assert_eq!(optimized.nodes.len(), merged.nodes.len());
+
+        let aabbs: Vec<_> = triangles.iter().map(|t| t.aabb()).collect();
+        let full = PlocBuilder::with_scheduler(Scheduler::SequentialOptimized, aabbs.len())
+            .build_ploc(&aabbs);
+        for ray in random_rays(&mut rng, 128) {
+            let expected = bvh_closest_hit(&full, &triangles, &ray);
+            assert_eq!(bvh_closest_hit(&merged, &triangles, &ray), expected);
+            assert_eq!(bvh_closest_hit(&optimized, &triangles, &ray), expected);
+        }
+
+        assert_eq!(Bvh2::default().merge(&b, 5).nodes.len(), b.nodes.len());
+    }
+}
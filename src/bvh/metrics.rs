@@ -0,0 +1,221 @@
+//! Numerical quality metrics for comparing builders, less noisy than timing traversal.
+//!
+//! SAH is the classic surface area heuristic cost. EPO is the expected primitive overlap from
+//! "On Quality Metrics of Bounding Volume Hierarchies" (Aila et al. 2013), which also accounts for
+//! primitive surface inside a node that doesn't belong to its subtree. It correlates better with ray
+//! tracing performance, especially for builders that produce overlapping nodes.
+
+use glam::Vec3A;
+use obvhs::{aabb::Aabb, triangle::Triangle};
+
+use super::Bvh2;
+
+// A triangle clipped by the 6 planes of a box has at most 9 vertices
+const MAX_CLIPPED_VERTS: usize = 9;
+
+impl Bvh2 {
+    /// Surface area heuristic cost, normalized by the root's surface area. Each leaf holds one primitive.
+    pub fn sah_cost(&self, traverse_cost: f32, intersect_cost: f32) -> f32 {
+        crate::scope!("sah_cost");
+        let Some(root) = self.nodes.first() else {
+            return 0.0;
+        };
+        let cost: f32 = self
+            .nodes
+            .iter()
+            .map(|node| {
+                let cost = if node.index < 0 {
+                    intersect_cost
+                } else {
+                    traverse_cost
+                };
+                node.aabb.half_area() * cost
+            })
+            .sum();
+        cost / root.aabb.half_area()
+    }
+
+    /// Expected primitive overlap for the bvh built over `triangles`: the surface area of triangles
+    /// inside each node that are not in its subtree, weighted by the node's cost and normalized by the
+    /// total triangle area.
+    pub fn epo(&self, triangles: &[Triangle], traverse_cost: f32, intersect_cost: f32) -> f32 {
+        crate::scope!("epo");
+        if self.nodes.is_empty() {
+            return 0.0;
+        }
+        // Leaves of a subtree are contiguous in depth first order, so a primitive is in the subtree of a
+        // node if its leaf position is in the node's range.
+        let mut leaf_ranges = vec![(0u32, 0u32); self.nodes.len()];
+        let mut leaf_positions = vec![0u32; triangles.len()];
+        let mut next_leaf = 0;
+        let mut stack = vec![(0usize, false)];
+        while let Some((index, children_done)) = stack.pop() {
+            let node = &self.nodes[index];
+            if node.index < 0 {
+                leaf_positions[-(node.index + 1) as usize] = next_leaf;
+                leaf_ranges[index] = (next_leaf, next_leaf + 1);
+                next_leaf += 1;
+            } else if children_done {
+                let child = node.index as usize;
+                leaf_ranges[index] = (leaf_ranges[child].0, leaf_ranges[child + 1].1);
+            } else {
+                stack.push((index, true));
+                stack.push((node.index as usize + 1, false));
+                stack.push((node.index as usize, false));
+            }
+        }
+
+        let total_area: f32 = triangles.iter().map(|t| t.area()).sum();
+        if total_area <= 0.0 {
+            return 0.0;
+        }
+
+        let mut overlap = 0.0;
+        let mut stack = Vec::new();
+        for (primitive, tri) in triangles.iter().enumerate() {
+            let tri_aabb = tri.aabb();
+            let position = leaf_positions[primitive];
+            stack.clear();
+            stack.push(0usize);
+            while let Some(index) = stack.pop() {
+                let node = &self.nodes[index];
+                // Children are contained in their parent, so nothing below can overlap either
+                if !node.aabb.intersect_aabb(&tri_aabb) {
+                    continue;
+                }
+                let (start, end) = leaf_ranges[index];
+                let cost = if node.index < 0 {
+                    intersect_cost
+                } else {
+                    traverse_cost
+                };
+                if position < start || position >= end {
+                    overlap += cost * clipped_area(tri, &node.aabb);
+                }
+                if node.index >= 0 {
+                    stack.push(node.index as usize);
+                    stack.push(node.index as usize + 1);
+                }
+            }
+        }
+        overlap / total_area
+    }
+}
+
+/// Area of the part of `tri` inside `aabb`, by clipping it against each of the box's planes.
+fn clipped_area(tri: &Triangle, aabb: &Aabb) -> f32 {
+    let mut poly = [Vec3A::ZERO; MAX_CLIPPED_VERTS];
+    let mut next = [Vec3A::ZERO; MAX_CLIPPED_VERTS];
+    poly[..3].copy_from_slice(&[tri.v0, tri.v1, tri.v2]);
+    let mut len = 3;
+
+    for axis in 0..3 {
+        for (bound, keep_above) in [(aabb.min[axis], true), (aabb.max[axis], false)] {
+            let inside = |p: Vec3A| {
+                if keep_above {
+                    p[axis] >= bound
+                } else {
+                    p[axis] <= bound
+                }
+            };
+            let mut next_len = 0;
+            for i in 0..len {
+                let a = poly[i];
+                let b = poly[(i + 1) % len];
+                if inside(a) {
+                    next[next_len] = a;
+                    next_len += 1;
+                }
+                if inside(a) != inside(b) && next_len < MAX_CLIPPED_VERTS {
+                    let t = (bound - a[axis]) / (b[axis] - a[axis]);
+                    next[next_len] = a + (b - a) * t;
+                    next_len += 1;
+                }
+            }
+            poly = next;
+            len = next_len;
+            if len < 3 {
+                return 0.0;
+            }
+        }
+    }
+
+    let mut cross = Vec3A::ZERO;
+    for i in 1..len - 1 {
+        cross += (poly[i] - poly[0]).cross(poly[i + 1] - poly[0]);
+    }
+    cross.length() * 0.5
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bvh::Bvh2Node;
+
+    fn tri(x: f32, z: f32) -> Triangle {
+        Triangle {
+            v0: Vec3A::new(x, 0.0, z),
+            v1: Vec3A::new(x + 1.0, 0.0, z),
+            v2: Vec3A::new(x, 1.0, z),
+        }
+    }
+
+    fn bvh_over(tris: &[Triangle; 2]) -> Bvh2 {
+        let a = tris[0].aabb();
+        let b = tris[1].aabb();
+        Bvh2 {
+            nodes: vec![
+                Bvh2Node {
+                    aabb: a.union(&b),
+                    index: 1,
+                },
+                Bvh2Node { aabb: a, index: -1 },
+                Bvh2Node { aabb: b, index: -2 },
+            ],
+        }
+    }
+
+    #[test]
+    fn test_sah_cost() {
+        let b = Aabb::new(Vec3A::ZERO, Vec3A::ONE);
+        let half = Aabb::new(Vec3A::ZERO, Vec3A::new(0.5, 1.0, 1.0));
+        let bvh = Bvh2 {
+            nodes: vec![
+                Bvh2Node { aabb: b, index: 1 },
+                Bvh2Node {
+                    aabb: half,
+                    index: -1,
+                },
+                Bvh2Node {
+                    aabb: half,
+                    index: -2,
+                },
+            ],
+        };
+        // half area of the root is 3, of each half 2
+        let expected = (3.0 * 1.0 + 2.0 * 2.0 * 0.5) / 3.0;
+        assert!((bvh.sah_cost(1.0, 0.5) - expected).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_clipped_area() {
+        let t = tri(0.0, 0.5);
+        let all = Aabb::new(Vec3A::splat(-1.0), Vec3A::splat(2.0));
+        assert!((clipped_area(&t, &all) - 0.5).abs() < 1e-6);
+        let left = Aabb::new(Vec3A::splat(-1.0), Vec3A::new(0.5, 2.0, 2.0));
+        assert!((clipped_area(&t, &left) - 0.375).abs() < 1e-6);
+        let away = Aabb::new(Vec3A::splat(3.0), Vec3A::splat(4.0));
+        assert_eq!(clipped_area(&t, &away), 0.0);
+    }
+
+    #[test]
+    fn test_epo() {
+        let disjoint = [tri(0.0, 0.0), tri(2.0, 0.0)];
+        assert_eq!(bvh_over(&disjoint).epo(&disjoint, 1.0, 1.0), 0.0);
+
+        // Coplanar and overlapping, so each leaf contains part of the other triangle
+        let overlapping = [tri(0.0, 0.0), tri(0.5, 0.0)];
+        let epo = bvh_over(&overlapping).epo(&overlapping, 1.0, 1.0);
+        assert!(epo > 0.0);
+    }
+}
@@ -0,0 +1,95 @@
+//! Primitive aabbs covering a whole animation range, the union over every keyframe or morph target, so a
+//! single bvh conservatively bounds the primitives at any time in the range. Used for sampled motion blur
+//! and for short loops that would otherwise need a refit every frame.
+
+use glam::Vec3A;
+use obvhs::{aabb::Aabb, triangle::Triangle};
+
+use crate::ploc::{init_ploc_scheduler, ploc_scheduler};
+
+/// Aabb `i` of the result is the union of aabb `i` of every keyframe. Each keyframe holds the same
+/// primitives in the same order. Computed in parallel on the ploc scheduler.
+pub fn keyframe_aabbs(keyframes: &[&[Aabb]]) -> Vec<Aabb> {
+    keyframe_aabbs_with(keyframes, |aabb| *aabb)
+}
+
+/// `keyframe_aabbs` for keyframes of triangles.
+pub fn keyframe_triangle_aabbs(keyframes: &[&[Triangle]]) -> Vec<Aabb> {
+    keyframe_aabbs_with(keyframes, Triangle::aabb)
+}
+
+/// `keyframe_aabbs` for an indexed mesh with a set of vertex positions per morph target or keyframe,
+/// one aabb per triangle of `indices`.
+pub fn morph_target_aabbs(targets: &[&[Vec3A]], indices: &[[u32; 3]]) -> Vec<Aabb> {
+    crate::scope!("morph_target_aabbs");
+    init_ploc_scheduler();
+    let sch = ploc_scheduler();
+    let mut aabbs = vec![Aabb::empty(); indices.len()];
+    sch.par_map(
+        &mut aabbs,
+        &|i, aabb| {
+            for positions in targets {
+                let [a, b, c] = indices[i].map(|v| positions[v as usize]);
+                *aabb = aabb.union(&Aabb::new(a.min(b).min(c), a.max(b).max(c)));
+            }
+        },
+        sch.current_num_threads() as u32 * 4,
+    );
+    aabbs
+}
+
+/// `keyframe_aabbs` for any primitive type, with `aabb` giving the bounds of one primitive.
+pub fn keyframe_aabbs_with<T, F>(keyframes: &[&[T]], aabb: F) -> Vec<Aabb>
+where
+    T: Sync,
+    F: Fn(&T) -> Aabb + Sync,
+{
+    crate::scope!("keyframe_aabbs");
+    let Some(first) = keyframes.first() else {
+        return Vec::new();
+    };
+    assert!(
+        keyframes.iter().all(|k| k.len() == first.len()),
+        "every keyframe needs the same number of primitives"
+    );
+    init_ploc_scheduler();
+    let sch = ploc_scheduler();
+    let mut aabbs = vec![Aabb::empty(); first.len()];
+    sch.par_map(
+        &mut aabbs,
+        &|i, out| {
+            for keyframe in keyframes {
+                *out = out.union(&aabb(&keyframe[i]));
+            }
+        },
+        sch.current_num_threads() as u32 * 4,
+    );
+    aabbs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keyframe_aabbs() {
+        let tri = |offset: f32| Triangle {
+            v0: Vec3A::new(offset, 0.0, 0.0),
+            v1: Vec3A::new(offset + 1.0, 0.0, 0.0),
+            v2: Vec3A::new(offset, 1.0, 0.0),
+        };
+        let a = [tri(0.0), tri(5.0)];
+        let b = [tri(2.0), tri(-1.0)];
+        let aabbs = keyframe_triangle_aabbs(&[&a, &b]);
+        assert_eq!(aabbs[0].min, Vec3A::ZERO);
+        assert_eq!(aabbs[0].max, Vec3A::new(3.0, 1.0, 0.0));
+        assert_eq!(aabbs[1].min, Vec3A::new(-1.0, 0.0, 0.0));
+        assert_eq!(aabbs[1].max, Vec3A::new(6.0, 1.0, 0.0));
+
+        let rest = [Vec3A::ZERO, Vec3A::X, Vec3A::Y];
+        let moved = [Vec3A::ZERO, Vec3A::X, Vec3A::Z];
+        let morphed = morph_target_aabbs(&[&rest, &moved], &[[0, 1, 2]]);
+        assert_eq!(morphed[0].max, Vec3A::ONE);
+        assert!(keyframe_aabbs(&[]).is_empty());
+    }
+}
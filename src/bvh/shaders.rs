@@ -0,0 +1,168 @@
+//! WGSL and GLSL `Bvh2` traversal, reading the buffers from `Bvh2::to_gpu_buffers` together with a
+//! buffer of `GpuTriangle`s. The Rust structs here match the shader structs of the same name.
+//! Requires the `shaders` feature.
+//!
+//! `BVH2_TRAVERSAL_WGSL` expects the including shader to declare the `bvh_nodes`,
+//! `bvh_primitive_indices` and `bvh_triangles` storage buffers. `BVH2_TRAVERSAL_GLSL` declares them
+//! itself, at bindings 0, 1 and 2 unless `BVH_NODES_BINDING` etc. are defined before it.
+
+use std::fmt;
+
+use bytemuck::{Pod, Zeroable};
+use obvhs::{ray::Ray, triangle::Triangle};
+
+use super::Bvh2;
+
+pub const BVH2_TRAVERSAL_WGSL: &str = include_str!("shaders/bvh2_traversal.wgsl");
+pub const BVH2_TRAVERSAL_GLSL: &str = include_str!("shaders/bvh2_traversal.glsl");
+
+/// The size of the traversal stack in the shaders. The shaders skip subtrees that don't fit, use
+/// `check_gpu_stack_depth` before uploading a bvh to make sure none do.
+pub const GPU_STACK_SIZE: u32 = 32;
+/// `GpuHit::primitive_id` for rays that didn't hit anything.
+pub const GPU_MISS: u32 = u32::MAX;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Pod, Zeroable)]
+#[repr(C, align(16))]
+pub struct GpuTriangle {
+    pub v0: [f32; 3],
+    pub _pad0: u32,
+    pub v1: [f32; 3],
+    pub _pad1: u32,
+    pub v2: [f32; 3],
+    pub _pad2: u32,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Pod, Zeroable)]
+#[repr(C, align(16))]
+pub struct GpuRay {
+    pub origin: [f32; 3],
+    pub tmin: f32,
+    pub direction: [f32; 3],
+    pub tmax: f32,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Pod, Zeroable)]
+#[repr(C)]
+pub struct GpuHit {
+    pub t: f32,
+    pub primitive_id: u32,
+}
+
+/// Uniform or push constant parameters for a traversal dispatch, padded to 16 bytes for uniforms.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Pod, Zeroable)]
+#[repr(C)]
+pub struct GpuTraversalParams {
+    pub ray_count: u32,
+    pub _pad: [u32; 3],
+}
+
+const _: () = assert!(size_of::<GpuTriangle>() == 48);
+const _: () = assert!(size_of::<GpuRay>() == 32);
+const _: () = assert!(size_of::<GpuHit>() == 8);
+const _: () = assert!(size_of::<GpuTraversalParams>() == 16);
+
+impl From<&Triangle> for GpuTriangle {
+    fn from(tri: &Triangle) -> Self {
+        GpuTriangle {
+            v0: tri.v0.to_array(),
+            v1: tri.v1.to_array(),
+            v2: tri.v2.to_array(),
+            ..Default::default()
+        }
+    }
+}
+
+impl From<&Ray> for GpuRay {
+    fn from(ray: &Ray) -> Self {
+        GpuRay {
+            origin: ray.origin.to_array(),
+            tmin: ray.tmin,
+            direction: ray.direction.to_array(),
+            tmax: ray.tmax,
+        }
+    }
+}
+
+impl GpuHit {
+    #[inline(always)]
+    pub fn is_hit(&self) -> bool {
+        self.primitive_id != GPU_MISS
+    }
+}
+
+/// The bvh is too deep for the shader traversal stack, see `check_gpu_stack_depth`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GpuStackOverflow {
+    pub depth: u32,
+}
+
+impl fmt::Display for GpuStackOverflow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "bvh depth {} needs a traversal stack of {}, the shaders have {GPU_STACK_SIZE}",
+            self.depth,
+            self.depth + 1
+        )
+    }
+}
+
+impl std::error::Error for GpuStackOverflow {}
+
+/// Check the shaders can traverse the whole bvh. Traversal pops a node and pushes both of its
+/// children, so a bvh with leaves at depth `d` needs a stack of `d + 1`.
+pub fn check_gpu_stack_depth(bvh: &Bvh2) -> Result<(), GpuStackOverflow> {
+    let depth = bvh.depth();
+    if depth + 1 > GPU_STACK_SIZE {
+        Err(GpuStackOverflow { depth })
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bvh::Bvh2Node;
+    use obvhs::aabb::Aabb;
+
+    /// Every inner node has a leaf on the left and the rest of the chain on the right.
+    fn chain_bvh(depth: u32) -> Bvh2 {
+        let mut nodes = vec![Bvh2Node {
+            aabb: Aabb::empty(),
+            index: 1,
+        }];
+        for level in 1..=depth as i32 {
+            let last = level == depth as i32;
+            nodes.push(Bvh2Node {
+                aabb: Aabb::empty(),
+                index: -level,
+            });
+            nodes.push(Bvh2Node {
+                aabb: Aabb::empty(),
+                index: if last {
+                    -(level + 1)
+                } else {
+                    nodes.len() as i32 + 1
+                },
+            });
+        }
+        Bvh2 { nodes }
+    }
+
+    #[test]
+    fn test_check_gpu_stack_depth() {
+        assert_eq!(check_gpu_stack_depth(&Bvh2::default()), Ok(()));
+        let fits = chain_bvh(GPU_STACK_SIZE - 1);
+        assert_eq!(fits.depth(), GPU_STACK_SIZE - 1);
+        assert_eq!(check_gpu_stack_depth(&fits), Ok(()));
+
+        let deep = chain_bvh(100);
+        assert_eq!(deep.depth(), 100);
+        assert_eq!(
+            check_gpu_stack_depth(&deep),
+            Err(GpuStackOverflow { depth: 100 })
+        );
+    }
+}
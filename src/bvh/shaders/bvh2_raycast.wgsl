@@ -0,0 +1,20 @@
+// Closest hit compute ray cast used by pool_racing::bvh::wgpu_traversal, appended to bvh2_traversal.wgsl.
+
+const WORKGROUP_SIZE: u32 = 64u;
+
+@group(0) @binding(0) var<storage, read> bvh_nodes: array<GpuBvhNode>;
+@group(0) @binding(1) var<storage, read> bvh_primitive_indices: array<u32>;
+@group(0) @binding(2) var<storage, read> bvh_triangles: array<GpuTriangle>;
+@group(0) @binding(3) var<storage, read> rays: array<GpuRay>;
+@group(0) @binding(4) var<storage, read_write> hits: array<GpuHit>;
+@group(0) @binding(5) var<uniform> params: GpuTraversalParams;
+
+// Dispatched as a 2d grid of workgroups, since one dimension is limited to 65535 workgroups
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn main(@builtin(global_invocation_id) id: vec3<u32>, @builtin(num_workgroups) groups: vec3<u32>) {
+    let index = id.x + id.y * groups.x * WORKGROUP_SIZE;
+    if index >= params.ray_count {
+        return;
+    }
+    hits[index] = bvh_traverse(rays[index]);
+}
@@ -0,0 +1,130 @@
+// Bvh2 closest hit traversal, matching the layouts in pool_racing::bvh::gpu and pool_racing::bvh::shaders.
+//
+// Declares its own storage buffers, define these before including to change their bindings.
+#ifndef BVH_NODES_BINDING
+#define BVH_NODES_BINDING 0
+#endif
+#ifndef BVH_PRIMITIVE_INDICES_BINDING
+#define BVH_PRIMITIVE_INDICES_BINDING 1
+#endif
+#ifndef BVH_TRIANGLES_BINDING
+#define BVH_TRIANGLES_BINDING 2
+#endif
+
+#define BVH_STACK_SIZE 32u
+#define BVH_MISS 0xffffffffu
+#define BVH_INF 3.40282347e+38
+
+// stride 32
+struct GpuBvhNode {
+    vec3 min;
+    // Left child for inner nodes (the right child is at +1), first primitive index for leaves
+    uint child_or_first;
+    vec3 max;
+    // 0 for inner nodes
+    uint primitive_count;
+};
+
+// stride 48
+struct GpuTriangle {
+    vec3 v0;
+    vec3 v1;
+    vec3 v2;
+};
+
+// stride 32
+struct GpuRay {
+    vec3 origin;
+    float tmin;
+    vec3 direction;
+    float tmax;
+};
+
+// stride 8, primitive_id is BVH_MISS and t is the ray tmax if nothing was hit
+struct GpuHit {
+    float t;
+    uint primitive_id;
+};
+
+// stride 16, usable as a uniform or push constant
+struct GpuTraversalParams {
+    uint ray_count;
+    uint _pad0;
+    uint _pad1;
+    uint _pad2;
+};
+
+layout(std430, binding = BVH_NODES_BINDING) readonly buffer BvhNodes {
+    GpuBvhNode bvh_nodes[];
+};
+layout(std430, binding = BVH_PRIMITIVE_INDICES_BINDING) readonly buffer BvhPrimitiveIndices {
+    uint bvh_primitive_indices[];
+};
+layout(std430, binding = BVH_TRIANGLES_BINDING) readonly buffer BvhTriangles {
+    GpuTriangle bvh_triangles[];
+};
+
+// Returns the distance to the box, or BVH_INF if the ray misses it within [tmin, tmax]
+float bvh_intersect_aabb(vec3 origin, vec3 inv_direction, float tmin, float tmax, GpuBvhNode node) {
+    vec3 t1 = (node.min - origin) * inv_direction;
+    vec3 t2 = (node.max - origin) * inv_direction;
+    float tnear = max(max(min(t1.x, t2.x), min(t1.y, t2.y)), max(min(t1.z, t2.z), tmin));
+    float tfar = min(min(max(t1.x, t2.x), max(t1.y, t2.y)), min(max(t1.z, t2.z), tmax));
+    return tnear <= tfar ? tnear : BVH_INF;
+}
+
+// Möller–Trumbore, returns BVH_INF on a miss
+float bvh_intersect_triangle(vec3 origin, vec3 direction, GpuTriangle tri) {
+    vec3 e1 = tri.v1 - tri.v0;
+    vec3 e2 = tri.v2 - tri.v0;
+    vec3 p = cross(direction, e2);
+    float det = dot(e1, p);
+    if (abs(det) < 1e-12) {
+        return BVH_INF;
+    }
+    float inv_det = 1.0 / det;
+    vec3 s = origin - tri.v0;
+    float u = dot(s, p) * inv_det;
+    if (u < 0.0 || u > 1.0) {
+        return BVH_INF;
+    }
+    vec3 q = cross(s, e1);
+    float v = dot(direction, q) * inv_det;
+    if (v < 0.0 || u + v > 1.0) {
+        return BVH_INF;
+    }
+    float t = dot(e2, q) * inv_det;
+    return t > 0.0 ? t : BVH_INF;
+}
+
+GpuHit bvh_traverse(GpuRay ray) {
+    GpuHit hit = GpuHit(ray.tmax, BVH_MISS);
+    if (bvh_nodes.length() == 0) {
+        return hit;
+    }
+    vec3 inv_direction = 1.0 / ray.direction;
+    uint stack[BVH_STACK_SIZE];
+    uint stack_len = 1u;
+    stack[0] = 0u;
+    while (stack_len > 0u) {
+        stack_len -= 1u;
+        GpuBvhNode node = bvh_nodes[stack[stack_len]];
+        if (bvh_intersect_aabb(ray.origin, inv_direction, ray.tmin, hit.t, node) >= hit.t) {
+            continue;
+        }
+        if (node.primitive_count > 0u) {
+            for (uint i = 0u; i < node.primitive_count; i++) {
+                uint primitive_id = bvh_primitive_indices[node.child_or_first + i];
+                float t = bvh_intersect_triangle(ray.origin, ray.direction, bvh_triangles[primitive_id]);
+                if (t > ray.tmin && t < hit.t) {
+                    hit = GpuHit(t, primitive_id);
+                }
+            }
+        } else if (stack_len + 2u <= BVH_STACK_SIZE) {
+            stack[stack_len] = node.child_or_first;
+            stack[stack_len + 1u] = node.child_or_first + 1u;
+            stack_len += 2u;
+        }
+    }
+    return hit;
+}
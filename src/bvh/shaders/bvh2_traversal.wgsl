@@ -0,0 +1,114 @@
+// Bvh2 closest hit traversal, matching the layouts in pool_racing::bvh::gpu and pool_racing::bvh::shaders.
+//
+// The including shader must declare these storage buffers (with any group/binding):
+//   var<storage, read> bvh_nodes: array<GpuBvhNode>;
+//   var<storage, read> bvh_primitive_indices: array<u32>;
+//   var<storage, read> bvh_triangles: array<GpuTriangle>;
+
+const BVH_STACK_SIZE: u32 = 32u;
+const BVH_MISS: u32 = 0xffffffffu;
+const BVH_INF: f32 = 3.40282347e+38;
+
+// stride 32
+struct GpuBvhNode {
+    min: vec3<f32>,
+    // Left child for inner nodes (the right child is at +1), first primitive index for leaves
+    child_or_first: u32,
+    max: vec3<f32>,
+    // 0 for inner nodes
+    primitive_count: u32,
+}
+
+// stride 48
+struct GpuTriangle {
+    v0: vec3<f32>,
+    v1: vec3<f32>,
+    v2: vec3<f32>,
+}
+
+// stride 32
+struct GpuRay {
+    origin: vec3<f32>,
+    tmin: f32,
+    direction: vec3<f32>,
+    tmax: f32,
+}
+
+// stride 8, primitive_id is BVH_MISS and t is the ray tmax if nothing was hit
+struct GpuHit {
+    t: f32,
+    primitive_id: u32,
+}
+
+// stride 16, usable as a uniform
+struct GpuTraversalParams {
+    ray_count: u32,
+    _pad0: u32,
+    _pad1: u32,
+    _pad2: u32,
+}
+
+// Returns the distance to the box, or BVH_INF if the ray misses it within [tmin, tmax]
+fn bvh_intersect_aabb(origin: vec3<f32>, inv_direction: vec3<f32>, tmin: f32, tmax: f32, node: GpuBvhNode) -> f32 {
+    let t1 = (node.min - origin) * inv_direction;
+    let t2 = (node.max - origin) * inv_direction;
+    let tnear = max(max(min(t1.x, t2.x), min(t1.y, t2.y)), max(min(t1.z, t2.z), tmin));
+    let tfar = min(min(max(t1.x, t2.x), max(t1.y, t2.y)), min(max(t1.z, t2.z), tmax));
+    return select(BVH_INF, tnear, tnear <= tfar);
+}
+
+// Möller–Trumbore, returns BVH_INF on a miss
+fn bvh_intersect_triangle(origin: vec3<f32>, direction: vec3<f32>, tri: GpuTriangle) -> f32 {
+    let e1 = tri.v1 - tri.v0;
+    let e2 = tri.v2 - tri.v0;
+    let p = cross(direction, e2);
+    let det = dot(e1, p);
+    if abs(det) < 1e-12 {
+        return BVH_INF;
+    }
+    let inv_det = 1.0 / det;
+    let s = origin - tri.v0;
+    let u = dot(s, p) * inv_det;
+    if u < 0.0 || u > 1.0 {
+        return BVH_INF;
+    }
+    let q = cross(s, e1);
+    let v = dot(direction, q) * inv_det;
+    if v < 0.0 || u + v > 1.0 {
+        return BVH_INF;
+    }
+    let t = dot(e2, q) * inv_det;
+    return select(BVH_INF, t, t > 0.0);
+}
+
+fn bvh_traverse(ray: GpuRay) -> GpuHit {
+    var hit = GpuHit(ray.tmax, BVH_MISS);
+    if arrayLength(&bvh_nodes) == 0u {
+        return hit;
+    }
+    let inv_direction = 1.0 / ray.direction;
+    var stack: array<u32, BVH_STACK_SIZE>;
+    var stack_len = 1u;
+    stack[0] = 0u;
+    while stack_len > 0u {
+        stack_len -= 1u;
+        let node = bvh_nodes[stack[stack_len]];
+        if bvh_intersect_aabb(ray.origin, inv_direction, ray.tmin, hit.t, node) >= hit.t {
+            continue;
+        }
+        if node.primitive_count > 0u {
+            for (var i = 0u; i < node.primitive_count; i++) {
+                let primitive_id = bvh_primitive_indices[node.child_or_first + i];
+                let t = bvh_intersect_triangle(ray.origin, ray.direction, bvh_triangles[primitive_id]);
+                if t > ray.tmin && t < hit.t {
+                    hit = GpuHit(t, primitive_id);
+                }
+            }
+        } else if stack_len + 2u <= BVH_STACK_SIZE {
+            stack[stack_len] = node.child_or_first;
+            stack[stack_len + 1u] = node.child_or_first + 1u;
+            stack_len += 2u;
+        }
+    }
+    return hit;
+}
@@ -0,0 +1,173 @@
+//! Occlusion for batches of rays that all start at the same point, like shadow rays traced from a point
+//! light towards the surfaces it lights. Each node's bounds are made relative to the shared origin once,
+//! and the whole batch walks the tree together, carrying only the rays that still overlap each node.
+
+use glam::Vec3A;
+use obvhs::ray::Ray;
+
+use super::Bvh2;
+
+/// A batch of rays from one origin, each with its own direction and distance cutoff. Keeps its buffers
+/// between traces, so it can be cleared and reused each frame.
+#[derive(Clone, Debug, Default)]
+pub struct SharedOriginRays {
+    pub origin: Vec3A,
+    pub tmin: f32,
+    directions: Vec<Vec3A>,
+    inv_directions: Vec<Vec3A>,
+    tmax: Vec<f32>,
+    occluded: Vec<bool>,
+    active: Vec<u32>,
+    stack: Vec<(u32, u32, u32)>,
+}
+
+impl SharedOriginRays {
+    pub fn new(origin: Vec3A, tmin: f32) -> Self {
+        Self {
+            origin,
+            tmin,
+            ..Default::default()
+        }
+    }
+
+    /// Add a ray along the unit vector `direction`, hitting things up to `tmax` away.
+    pub fn push(&mut self, direction: Vec3A, tmax: f32) {
+        self.directions.push(direction);
+        self.inv_directions.push(1.0 / direction);
+        self.tmax.push(tmax);
+    }
+
+    /// Add a ray towards `target`, stopping `bias` before reaching it so the surface at `target` doesn't
+    /// occlude itself.
+    pub fn push_target(&mut self, target: Vec3A, bias: f32) {
+        let to_target = target - self.origin;
+        let distance = to_target.length();
+        self.push(to_target / distance, distance - bias);
+    }
+
+    pub fn len(&self) -> usize {
+        self.directions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.directions.is_empty()
+    }
+
+    /// Remove all rays, keeping the allocations.
+    pub fn clear(&mut self) {
+        self.directions.clear();
+        self.inv_directions.clear();
+        self.tmax.clear();
+        self.occluded.clear();
+    }
+
+    /// Ray `i` of the batch.
+    pub fn ray(&self, i: usize) -> Ray {
+        Ray::new(self.origin, self.directions[i], self.tmin, self.tmax[i])
+    }
+
+    /// Whether each ray, in the order they were added, hits anything before its `tmax`.
+    /// `intersection_fn` is called like for `Bvh2::occluded`. The result is also kept in `occluded()`
+    /// until the next trace or clear.
+    pub fn trace<F: FnMut(&Ray, usize) -> f32>(
+        &mut self,
+        bvh: &Bvh2,
+        mut intersection_fn: F,
+    ) -> &[bool] {
+        crate::scope!("shared origin occluded");
+        let Self {
+            origin,
+            tmin,
+            directions,
+            inv_directions,
+            tmax,
+            occluded,
+            active,
+            stack,
+        } = self;
+        occluded.clear();
+        occluded.resize(directions.len(), false);
+        if bvh.nodes.is_empty() || directions.is_empty() {
+            return occluded;
+        }
+        // Each stack entry is a node and the range of `active` holding the rays that reached it. Entries
+        // pushed later have ranges further along `active`, so popping one can truncate everything after
+        // its range.
+        active.clear();
+        active.extend(0..directions.len() as u32);
+        stack.clear();
+        stack.push((0, 0, directions.len() as u32));
+        while let Some((node_index, start, end)) = stack.pop() {
+            active.truncate(end as usize);
+            let node = &bvh.nodes[node_index as usize];
+            let rel_min = node.aabb.min - *origin;
+            let rel_max = node.aabb.max - *origin;
+
+            let child_start = active.len();
+            for i in start as usize..end as usize {
+                let r = active[i] as usize;
+                if occluded[r] {
+                    continue;
+                }
+                let t1 = rel_min * inv_directions[r];
+                let t2 = rel_max * inv_directions[r];
+                let tnear = t1.min(t2).max_element().max(*tmin);
+                let tfar = t1.max(t2).min_element().min(tmax[r]);
+                if tnear <= tfar {
+                    active.push(r as u32);
+                }
+            }
+            let child_end = active.len();
+            if child_start == child_end {
+                continue;
+            }
+
+            if node.index < 0 {
+                let primitive_id = -(node.index + 1) as usize;
+                for &r in &active[child_start..child_end] {
+                    let r = r as usize;
+                    let ray = Ray::new(*origin, directions[r], *tmin, tmax[r]);
+                    if intersection_fn(&ray, primitive_id) < tmax[r] {
+                        occluded[r] = true;
+                    }
+                }
+            } else {
+                let (child_start, child_end) = (child_start as u32, child_end as u32);
+                stack.push((node.index as u32, child_start, child_end));
+                stack.push((node.index as u32 + 1, child_start, child_end));
+            }
+        }
+        occluded
+    }
+
+    /// The result of the last `trace`.
+    pub fn occluded(&self) -> &[bool] {
+        &self.occluded
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{random_bvh, reference::Rng};
+
+    #[test]
+    fn test_shared_origin_matches_occluded() {
+        let (triangles, bvh) = random_bvh(17, 400);
+        let mut rng = Rng::new(18);
+
+        let light = Vec3A::new(0.5, 1.5, 0.5);
+        let mut batch = SharedOriginRays::new(light, 0.0);
+        for _ in 0..512 {
+            batch.push_target(rng.next_vec3a(), 1e-3);
+        }
+        let occluded = batch
+            .trace(&bvh, |ray, i| triangles[i].intersect(ray))
+            .to_vec();
+        assert!(occluded.iter().any(|o| *o) && occluded.iter().any(|o| !*o));
+        for (i, o) in occluded.iter().enumerate() {
+            let expected = bvh.occluded(&batch.ray(i), |ray, i| triangles[i].intersect(ray));
+            assert_eq!(*o, expected, "ray {i}");
+        }
+    }
+}
@@ -0,0 +1,123 @@
+//! Structure statistics of a `Bvh2`, to print alongside build times.
+
+use std::fmt;
+
+use super::Bvh2;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LevelStats {
+    pub nodes: usize,
+    pub leaves: usize,
+    /// Sum of the surface areas of the nodes at this depth
+    pub surface_area: f32,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BvhStats {
+    pub node_count: usize,
+    pub leaf_count: usize,
+    /// Leaf depths, the root is at depth 0
+    pub min_depth: u32,
+    pub avg_depth: f32,
+    pub max_depth: u32,
+    /// Number of leaves by primitive count, index 1 is leaves with one primitive
+    pub leaf_size_histogram: Vec<usize>,
+    /// Indexed by depth
+    pub levels: Vec<LevelStats>,
+}
+
+impl Bvh2 {
+    pub fn stats(&self) -> BvhStats {
+        crate::scope!("bvh stats");
+        let mut stats = BvhStats {
+            node_count: self.nodes.len(),
+            ..Default::default()
+        };
+        if self.nodes.is_empty() {
+            return stats;
+        }
+
+        let depths = self.node_depths();
+        let max_node_depth = depths.iter().copied().max().unwrap_or(0);
+        stats.levels = vec![LevelStats::default(); max_node_depth as usize + 1];
+        stats.min_depth = u32::MAX;
+        let mut depth_sum = 0u64;
+        for (node, &depth) in self.nodes.iter().zip(&depths) {
+            let level = &mut stats.levels[depth as usize];
+            level.nodes += 1;
+            level.surface_area += node.aabb.surface_area();
+            if node.index < 0 {
+                level.leaves += 1;
+                stats.leaf_count += 1;
+                stats.min_depth = stats.min_depth.min(depth);
+                stats.max_depth = stats.max_depth.max(depth);
+                depth_sum += depth as u64;
+            }
+        }
+        stats.avg_depth = depth_sum as f32 / stats.leaf_count as f32;
+        // Bvh2 leaves always hold a single primitive
+        stats.leaf_size_histogram = vec![0, stats.leaf_count];
+        stats
+    }
+}
+
+impl fmt::Display for BvhStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} nodes, {} leaves, leaf depth min {} avg {:.2} max {}",
+            self.node_count, self.leaf_count, self.min_depth, self.avg_depth, self.max_depth
+        )?;
+        let sizes: Vec<String> = self
+            .leaf_size_histogram
+            .iter()
+            .enumerate()
+            .filter(|(_, count)| **count > 0)
+            .map(|(size, count)| format!("{size}: {count}"))
+            .collect();
+        writeln!(f, "leaf sizes {}", sizes.join(", "))?;
+        for (depth, level) in self.levels.iter().enumerate() {
+            writeln!(
+                f,
+                "  depth {depth:>3} {:>10} nodes {:>10} leaves {:>14.2} area",
+                level.nodes, level.leaves, level.surface_area
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bvh::Bvh2Node;
+    use glam::Vec3A;
+    use obvhs::aabb::Aabb;
+
+    #[test]
+    fn test_stats() {
+        let node = |max: f32, index: i32| Bvh2Node {
+            aabb: Aabb::new(Vec3A::ZERO, Vec3A::splat(max)),
+            index,
+        };
+        let bvh = Bvh2 {
+            nodes: vec![
+                node(2.0, 1),
+                node(1.0, -1),
+                node(1.0, 3),
+                node(1.0, -2),
+                node(1.0, -3),
+            ],
+        };
+        let stats = bvh.stats();
+        assert_eq!(stats.node_count, 5);
+        assert_eq!(stats.leaf_count, 3);
+        assert_eq!((stats.min_depth, stats.max_depth), (1, 2));
+        assert!((stats.avg_depth - 5.0 / 3.0).abs() < 1e-6);
+        assert_eq!(stats.leaf_size_histogram, vec![0, 3]);
+        assert_eq!(stats.levels.len(), 3);
+        assert_eq!(stats.levels[1].nodes, 2);
+        assert_eq!(stats.levels[1].leaves, 1);
+        assert_eq!(stats.levels[0].surface_area, 24.0);
+    }
+}
@@ -0,0 +1,156 @@
+//! Pulling subtrees out into bvhs of their own, and splitting a bvh into a number of similarly sized
+//! parts, for distributing or streaming parts of a scene separately.
+
+use crate::bvh::{Bvh2, Bvh2Node};
+
+/// A bvh cut out of a larger one. Its primitive ids are renumbered from zero, `primitives` maps them back
+/// to ids in the original bvh.
+#[derive(Clone, Default)]
+pub struct Subtree {
+    pub bvh: Bvh2,
+    /// Original primitive id of each of the subtree's primitives
+    pub primitives: Vec<u32>,
+}
+
+impl Bvh2 {
+    /// Copy the subtree rooted at `node` into its own bvh. Nodes keep their relative order, so the layout
+    /// invariants of the original carry over.
+    pub fn extract_subtree(&self, node: usize) -> Subtree {
+        crate::scope!("extract subtree");
+        let mut indices = Vec::new();
+        let mut stack = vec![node];
+        while let Some(i) = stack.pop() {
+            indices.push(i);
+            let index = self.nodes[i].index;
+            if index >= 0 {
+                stack.push(index as usize);
+                stack.push(index as usize + 1);
+            }
+        }
+        indices.sort_unstable();
+
+        let mut new_index = vec![u32::MAX; self.nodes.len()];
+        for (new, &old) in indices.iter().enumerate() {
+            new_index[old] = new as u32;
+        }
+        let mut primitives = Vec::new();
+        let nodes = indices
+            .iter()
+            .map(|&old| {
+                let node = self.nodes[old];
+                let index = if node.index < 0 {
+                    primitives.push(-(node.index + 1) as u32);
+                    -(primitives.len() as i32)
+                } else {
+                    new_index[node.index as usize] as i32
+                };
+                Bvh2Node {
+                    aabb: node.aabb,
+                    index,
+                }
+            })
+            .collect();
+        Subtree {
+            bvh: Bvh2 { nodes },
+            primitives,
+        }
+    }
+
+    /// Split into at most `parts` subtrees that together hold every primitive, by repeatedly splitting the
+    /// subtree with the most primitives into its two children. Fewer parts are returned when there are
+    /// fewer primitives than `parts`.
+    pub fn split(&self, parts: usize) -> Vec<Subtree> {
+        crate::scope!("split");
+        if self.nodes.is_empty() || parts == 0 {
+            return Vec::new();
+        }
+        let mut roots = self.split_roots(parts);
+        roots.sort_unstable();
+        roots
+            .into_iter()
+            .map(|root| self.extract_subtree(root))
+            .collect()
+    }
+
+    /// Roots of at most `parts` subtrees covering every primitive, found by repeatedly splitting the
+    /// subtree with the most primitives.
+    pub(crate) fn split_roots(&self, parts: usize) -> Vec<usize> {
+        // Children come after their parent, so counts can be summed from the back
+        let mut primitive_counts = vec![0u32; self.nodes.len()];
+        for (i, node) in self.nodes.iter().enumerate().rev() {
+            primitive_counts[i] = if node.index < 0 {
+                1
+            } else {
+                let child = node.index as usize;
+                primitive_counts[child] + primitive_counts[child + 1]
+            };
+        }
+
+        let mut roots = vec![0];
+        while roots.len() < parts {
+            let (largest, &root) = roots
+                .iter()
+                .enumerate()
+                .max_by_key(|(_, &root)| primitive_counts[root])
+                .unwrap();
+            let index = self.nodes[root].index;
+            if index < 0 {
+                // The largest part is a single primitive
+                break;
+            }
+            roots[largest] = index as usize;
+            roots.push(index as usize + 1);
+        }
+        roots
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_util::{
+        random_bvh,
+        reference::{random_rays, Rng},
+    };
+
+    #[test]
+    fn test_split_covers_all_primitives() {
+        let (triangles, bvh) = random_bvh(31, 500);
+        let mut rng = Rng::new(32);
+
+        let parts = bvh.split(4);
+        assert_eq!(parts.len(), 4);
+        let mut seen = vec![false; triangles.len()];
+        for part in &parts {
+            part.bvh.validate(part.primitives.len()).unwrap();
+            for &p in &part.primitives {
+                assert!(!seen[p as usize]);
+                seen[p as usize] = true;
+            }
+        }
+        assert!(seen.iter().all(|s| *s));
+
+        // The closest hit over all parts is the closest hit of the whole bvh
+        for ray in random_rays(&mut rng, 128) {
+            let mut expected_ray = ray;
+            let mut expected = u32::MAX;
+            bvh.traverse(&mut expected_ray, &mut expected, |ray, i| {
+                triangles[i].intersect(ray)
+            });
+            let mut ray = ray;
+            let mut closest = u32::MAX;
+            for part in &parts {
+                let mut id = u32::MAX;
+                part.bvh.traverse(&mut ray, &mut id, |ray, i| {
+                    triangles[part.primitives[i] as usize].intersect(ray)
+                });
+                if id != u32::MAX {
+                    closest = part.primitives[id as usize];
+                }
+            }
+            assert_eq!(closest, expected);
+        }
+
+        assert_eq!(bvh.split(1).len(), 1);
+        assert_eq!(bvh.split(1)[0].primitives.len(), triangles.len());
+    }
+}
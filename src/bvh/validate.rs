@@ -0,0 +1,220 @@
+//! Structural checks for `Bvh2`, for developing new builders and refit paths.
+
+use std::fmt;
+
+use super::Bvh2;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ValidationError {
+    /// An inner node's children at `child` and `child + 1` aren't both in the node array.
+    ChildOutOfRange { node: usize, child: usize },
+    /// A child's aabb extends outside its parent's aabb.
+    ChildNotContained { node: usize, child: usize },
+    /// A leaf references a primitive index >= the primitive count.
+    PrimitiveOutOfRange { node: usize, primitive: usize },
+    /// A primitive is referenced by more than one leaf.
+    DuplicatePrimitive { primitive: usize, nodes: [usize; 2] },
+    /// A primitive isn't referenced by any leaf.
+    MissingPrimitive { primitive: usize },
+    /// A node can't be reached from the root, because nothing references it or it's only referenced from
+    /// other unreachable nodes, like a detached cycle.
+    OrphanedNode { node: usize },
+    /// A node is referenced by more than one parent, or the root is referenced at all.
+    MultipleParents { node: usize },
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValidationError::ChildOutOfRange { node, child } => {
+                write!(
+                    f,
+                    "node {node} has out of range children {child}, {}",
+                    child + 1
+                )
+            }
+            ValidationError::ChildNotContained { node, child } => {
+                write!(
+                    f,
+                    "node {node} does not contain the aabb of its child {child}"
+                )
+            }
+            ValidationError::PrimitiveOutOfRange { node, primitive } => {
+                write!(f, "leaf {node} has out of range primitive {primitive}")
+            }
+            ValidationError::DuplicatePrimitive { primitive, nodes } => write!(
+                f,
+                "primitive {primitive} is in both leaf {} and {}",
+                nodes[0], nodes[1]
+            ),
+            ValidationError::MissingPrimitive { primitive } => {
+                write!(f, "primitive {primitive} is not in any leaf")
+            }
+            ValidationError::OrphanedNode { node } => {
+                write!(f, "node {node} is not reachable from the root")
+            }
+            ValidationError::MultipleParents { node } => {
+                write!(f, "node {node} is referenced by more than one parent")
+            }
+        }
+    }
+}
+
+impl Bvh2 {
+    /// Check child aabbs are contained in their parents, child and primitive indices are in range,
+    /// each of the `primitive_count` primitives is in exactly one leaf, every node is reachable from the
+    /// root and every node except the root has exactly one parent. Returns every problem found.
+    pub fn validate(&self, primitive_count: usize) -> Result<(), Vec<ValidationError>> {
+        crate::scope!("validate");
+        let mut errors = Vec::new();
+        let node_count = self.nodes.len();
+        let mut parent_counts = vec![0u32; node_count];
+        let mut primitive_leaves = vec![None; primitive_count];
+
+        for (i, node) in self.nodes.iter().enumerate() {
+            if node.index < 0 {
+                let primitive = -(node.index + 1) as usize;
+                match primitive_leaves.get_mut(primitive) {
+                    None => {
+                        errors.push(ValidationError::PrimitiveOutOfRange { node: i, primitive })
+                    }
+                    Some(Some(first)) => errors.push(ValidationError::DuplicatePrimitive {
+                        primitive,
+                        nodes: [*first, i],
+                    }),
+                    Some(leaf) => *leaf = Some(i),
+                }
+                continue;
+            }
+
+            let child = node.index as usize;
+            if child + 1 >= node_count {
+                errors.push(ValidationError::ChildOutOfRange { node: i, child });
+                continue;
+            }
+            for child in [child, child + 1] {
+                parent_counts[child] += 1;
+                let child_aabb = &self.nodes[child].aabb;
+                if (0..3).any(|axis| {
+                    child_aabb.min[axis] < node.aabb.min[axis]
+                        || child_aabb.max[axis] > node.aabb.max[axis]
+                }) {
+                    errors.push(ValidationError::ChildNotContained { node: i, child });
+                }
+            }
+        }
+
+        for (primitive, leaf) in primitive_leaves.iter().enumerate() {
+            if leaf.is_none() {
+                errors.push(ValidationError::MissingPrimitive { primitive });
+            }
+        }
+
+        // Parent counts alone miss nodes that only reference each other, so walk down from the root
+        let mut reached = vec![false; node_count];
+        let mut stack = Vec::new();
+        if node_count > 0 {
+            reached[0] = true;
+            stack.push(0);
+        }
+        while let Some(i) = stack.pop() {
+            let child = self.nodes[i].index;
+            if child < 0 || child as usize + 1 >= node_count {
+                continue;
+            }
+            for child in [child as usize, child as usize + 1] {
+                if !reached[child] {
+                    reached[child] = true;
+                    stack.push(child);
+                }
+            }
+        }
+
+        for (node, &count) in parent_counts.iter().enumerate() {
+            if !reached[node] {
+                errors.push(ValidationError::OrphanedNode { node });
+            }
+            if count > 1 || (node == 0 && count > 0) {
+                errors.push(ValidationError::MultipleParents { node });
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bvh::Bvh2Node;
+    use glam::Vec3A;
+    use obvhs::aabb::Aabb;
+
+    fn node(min: f32, max: f32, index: i32) -> Bvh2Node {
+        Bvh2Node {
+            aabb: Aabb {
+                min: Vec3A::splat(min),
+                max: Vec3A::splat(max),
+            },
+            index,
+        }
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut bvh = Bvh2 {
+            nodes: vec![
+                node(0.0, 3.0, 1),
+                node(0.0, 1.0, -3),
+                node(1.0, 3.0, 3),
+                node(1.0, 2.0, -1),
+                node(2.0, 3.0, -2),
+            ],
+        };
+        assert_eq!(bvh.validate(3), Ok(()));
+        assert_eq!(
+            bvh.validate(4),
+            Err(vec![ValidationError::MissingPrimitive { primitive: 3 }])
+        );
+
+        bvh.nodes[4] = node(2.0, 4.0, -1);
+        let errors = bvh.validate(3).unwrap_err();
+        assert!(errors.contains(&ValidationError::ChildNotContained { node: 2, child: 4 }));
+        assert!(errors.contains(&ValidationError::DuplicatePrimitive {
+            primitive: 0,
+            nodes: [3, 4]
+        }));
+        assert!(errors.contains(&ValidationError::MissingPrimitive { primitive: 1 }));
+
+        bvh.nodes[2].index = 1;
+        let errors = bvh.validate(3).unwrap_err();
+        assert!(errors.contains(&ValidationError::MultipleParents { node: 1 }));
+        assert!(errors.contains(&ValidationError::OrphanedNode { node: 3 }));
+    }
+
+    #[test]
+    fn test_validate_detached_cycle() {
+        // Nodes 3 and 5 are each other's parents, so every node has one parent but 3..=6 are cut off
+        let bvh = Bvh2 {
+            nodes: vec![
+                node(0.0, 3.0, 1),
+                node(0.0, 1.0, -1),
+                node(1.0, 3.0, -2),
+                node(0.0, 1.0, 5),
+                node(0.0, 1.0, -3),
+                node(0.0, 1.0, 3),
+                node(0.0, 1.0, -4),
+            ],
+        };
+        assert_eq!(
+            bvh.validate(4),
+            Err((3..=6)
+                .map(|node| ValidationError::OrphanedNode { node })
+                .collect())
+        );
+    }
+}
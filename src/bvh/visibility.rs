@@ -0,0 +1,160 @@
+//! Conservative region to region visibility, for baking cell to cell potentially visible sets. The beam
+//! between two aabbs, the convex hull of both, is traversed through the bvh, and the regions only count
+//! as hidden from each other when a single primitive in the beam blocks every segment between them.
+//! Occlusion by several primitives together isn't detected, so the answer can be "visible" for regions
+//! that aren't, but never "hidden" for regions that are visible.
+
+use glam::Vec2;
+use obvhs::aabb::Aabb;
+
+use super::Bvh2;
+
+impl Bvh2 {
+    /// Whether anything in `b` might be visible from anything in `a`. `blocks` is called with primitive
+    /// ids whose aabb overlaps the beam between them, and must only return true when that primitive alone
+    /// blocks every segment from `a` to `b`, like [`box_blocks`] does for solid boxes.
+    pub fn potentially_visible<F: FnMut(usize, &Aabb, &Aabb) -> bool>(
+        &self,
+        a: &Aabb,
+        b: &Aabb,
+        mut blocks: F,
+    ) -> bool {
+        crate::scope!("potentially_visible");
+        if self.nodes.is_empty() || overlaps(a, b) {
+            return true;
+        }
+        let beam = Beam::new(a, b);
+        let mut stack = vec![0u32];
+        while let Some(node_index) = stack.pop() {
+            let node = &self.nodes[node_index as usize];
+            if !beam.overlaps(&node.aabb) {
+                continue;
+            }
+            if node.index < 0 {
+                if blocks(-(node.index + 1) as usize, a, b) {
+                    return false;
+                }
+            } else {
+                stack.push(node.index as u32);
+                stack.push(node.index as u32 + 1);
+            }
+        }
+        true
+    }
+}
+
+/// Whether the solid box `occluder` blocks every segment from `a` to `b`. True when the occluder lies
+/// between them along some axis, and covers both of them along the other two axes. Every segment then
+/// crosses the occluder's slab inside the occluder.
+pub fn box_blocks(occluder: &Aabb, a: &Aabb, b: &Aabb) -> bool {
+    (0..3).any(|k| {
+        let between = (a.max[k] <= occluder.min[k] && occluder.max[k] <= b.min[k])
+            || (b.max[k] <= occluder.min[k] && occluder.max[k] <= a.min[k]);
+        between
+            && (0..3).filter(|&j| j != k).all(|j| {
+                occluder.min[j] <= a.min[j].min(b.min[j])
+                    && occluder.max[j] >= a.max[j].max(b.max[j])
+            })
+    })
+}
+
+fn overlaps(a: &Aabb, b: &Aabb) -> bool {
+    a.min.cmple(b.max).all() && b.min.cmple(a.max).all()
+}
+
+/// The convex hull of two aabbs, tested against nodes conservatively: a node is only rejected when a
+/// separating axis is found.
+struct Beam {
+    bounds: Aabb,
+    /// For each axis pair the beam is projected onto, the corners of both projected rectangles and the
+    /// candidate separating axes, the normals of every line between a corner of `a` and a corner of `b`.
+    /// These include the hull's edges, and any other separating axis found is still valid.
+    projections: [([usize; 2], [Vec2; 8], Vec<Vec2>); 3],
+}
+
+impl Beam {
+    fn new(a: &Aabb, b: &Aabb) -> Self {
+        let projection = |axes: [usize; 2]| {
+            let rect = |aabb: &Aabb| {
+                let min = Vec2::new(aabb.min[axes[0]], aabb.min[axes[1]]);
+                let max = Vec2::new(aabb.max[axes[0]], aabb.max[axes[1]]);
+                [min, Vec2::new(min.x, max.y), Vec2::new(max.x, min.y), max]
+            };
+            let (a_rect, b_rect) = (rect(a), rect(b));
+            let mut points = [Vec2::ZERO; 8];
+            points[..4].copy_from_slice(&a_rect);
+            points[4..].copy_from_slice(&b_rect);
+            let mut normals = Vec::new();
+            for p in a_rect {
+                for q in b_rect {
+                    let edge = q - p;
+                    if edge.length_squared() > 0.0 {
+                        normals.push(edge.perp());
+                    }
+                }
+            }
+            (axes, points, normals)
+        };
+        Beam {
+            bounds: a.union(b),
+            projections: [projection([0, 1]), projection([0, 2]), projection([1, 2])],
+        }
+    }
+
+    fn overlaps(&self, node: &Aabb) -> bool {
+        if !overlaps(&self.bounds, node) {
+            return false;
+        }
+        self.projections.iter().all(|(axes, points, normals)| {
+            let min = Vec2::new(node.min[axes[0]], node.min[axes[1]]);
+            let max = Vec2::new(node.max[axes[0]], node.max[axes[1]]);
+            let node_points = [min, Vec2::new(min.x, max.y), Vec2::new(max.x, min.y), max];
+            normals.iter().all(|n| {
+                let range = |ps: &[Vec2]| {
+                    ps.iter().fold((f32::MAX, f32::MIN), |(lo, hi), p| {
+                        let d = n.dot(*p);
+                        (lo.min(d), hi.max(d))
+                    })
+                };
+                let (beam_lo, beam_hi) = range(points);
+                let (node_lo, node_hi) = range(&node_points);
+                node_lo <= beam_hi && beam_lo <= node_hi
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{par::Scheduler, ploc::PlocBuilder};
+    use glam::Vec3A;
+
+    #[test]
+    fn test_potentially_visible() {
+        let cell = |x: f32| Aabb::new(Vec3A::new(x, 0.0, 0.0), Vec3A::new(x + 1.0, 1.0, 1.0));
+        let a = cell(0.0);
+        let b = cell(4.0);
+        let wall = Aabb::new(Vec3A::new(2.0, -1.0, -1.0), Vec3A::new(2.5, 2.0, 2.0));
+        let pillar = Aabb::new(Vec3A::new(2.0, 0.4, 0.4), Vec3A::new(2.5, 0.6, 0.6));
+        let aside = Aabb::new(Vec3A::new(2.0, 5.0, 5.0), Vec3A::new(2.5, 6.0, 6.0));
+        assert!(box_blocks(&wall, &a, &b));
+        assert!(!box_blocks(&pillar, &a, &b));
+
+        let visible = |occluders: &[Aabb]| {
+            let bvh = PlocBuilder::with_scheduler(Scheduler::SequentialOptimized, occluders.len())
+                .build_ploc(occluders);
+            let mut tested = Vec::new();
+            let visible = bvh.potentially_visible(&a, &b, |i, a, b| {
+                tested.push(i);
+                box_blocks(&occluders[i], a, b)
+            });
+            (visible, tested)
+        };
+        assert!(!visible(&[pillar, wall, aside]).0);
+        let (open, tested) = visible(&[pillar, aside]);
+        assert!(open);
+        // The box off to the side is outside the beam
+        assert_eq!(tested, [0]);
+    }
+}
@@ -0,0 +1,212 @@
+//! Reference compute shader ray casting on the GPU through wgpu, as a baseline to compare the CPU
+//! schedulers against. It only uses the public `bvh::gpu` export and `bvh::shaders` traversal, so it
+//! also checks those stay consistent. Requires the `wgpu` feature.
+
+use obvhs::{ray::Ray, triangle::Triangle};
+use wgpu::util::DeviceExt;
+
+use super::{
+    shaders::{
+        check_gpu_stack_depth, GpuHit, GpuRay, GpuStackOverflow, GpuTraversalParams, GpuTriangle,
+        BVH2_TRAVERSAL_WGSL, GPU_MISS,
+    },
+    Bvh2,
+};
+
+const WORKGROUP_SIZE: u32 = 64;
+const MAX_WORKGROUPS_PER_DIMENSION: u32 = 65535;
+
+pub struct GpuTraversal {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    pipeline: wgpu::ComputePipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+}
+
+fn storage_entry(binding: u32, read_only: bool) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Storage { read_only },
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    }
+}
+
+impl GpuTraversal {
+    /// Create a traversal on the default adapter. Returns None if there is no usable adapter.
+    pub fn new() -> Option<Self> {
+        crate::scope!("GpuTraversal::new");
+        let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor::default());
+        let adapter =
+            pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions::default()))
+                .ok()?;
+        let (device, queue) =
+            pollster::block_on(adapter.request_device(&wgpu::DeviceDescriptor::default())).ok()?;
+        Some(Self::from_device(device, queue))
+    }
+
+    pub fn from_device(device: wgpu::Device, queue: wgpu::Queue) -> Self {
+        let source = format!(
+            "{BVH2_TRAVERSAL_WGSL}\n{}",
+            include_str!("shaders/bvh2_raycast.wgsl")
+        );
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("bvh2_raycast"),
+            source: wgpu::ShaderSource::Wgsl(source.into()),
+        });
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("bvh2_raycast"),
+            entries: &[
+                storage_entry(0, true),
+                storage_entry(1, true),
+                storage_entry(2, true),
+                storage_entry(3, true),
+                storage_entry(4, false),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 5,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("bvh2_raycast"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("bvh2_raycast"),
+            layout: Some(&layout),
+            module: &module,
+            entry_point: Some("main"),
+            compilation_options: Default::default(),
+            cache: None,
+        });
+        Self {
+            device,
+            queue,
+            pipeline,
+            bind_group_layout,
+        }
+    }
+
+    /// Upload the bvh, triangles and rays, cast every ray and read back the closest hits. The bvh leaves
+    /// must index into `triangles`, like the bvh built from their aabbs does. Fails without uploading
+    /// anything if the bvh is too deep for the shader traversal stack.
+    pub fn cast_rays(
+        &self,
+        bvh: &Bvh2,
+        triangles: &[Triangle],
+        rays: &[Ray],
+    ) -> Result<Vec<GpuHit>, GpuStackOverflow> {
+        crate::scope!("GpuTraversal::cast_rays");
+        // Zero sized bindings aren't allowed, and there is nothing to hit anyway. Without triangles the
+        // leaves can't index into them, so every ray misses.
+        if bvh.nodes.is_empty() || triangles.is_empty() || rays.is_empty() {
+            return Ok(rays
+                .iter()
+                .map(|ray| GpuHit {
+                    t: ray.tmax,
+                    primitive_id: GPU_MISS,
+                })
+                .collect());
+        }
+        check_gpu_stack_depth(bvh)?;
+
+        let buffers = bvh.to_gpu_buffers();
+        let gpu_triangles: Vec<GpuTriangle> = triangles.iter().map(GpuTriangle::from).collect();
+        let gpu_rays: Vec<GpuRay> = rays.iter().map(GpuRay::from).collect();
+        let params = GpuTraversalParams {
+            ray_count: rays.len() as u32,
+            ..Default::default()
+        };
+
+        let storage = |label, contents: &[u8]| {
+            self.device
+                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some(label),
+                    contents,
+                    usage: wgpu::BufferUsages::STORAGE,
+                })
+        };
+        let nodes = storage("bvh_nodes", &buffers.nodes);
+        let primitive_indices = storage("bvh_primitive_indices", &buffers.primitive_indices);
+        let triangles = storage("bvh_triangles", bytemuck::cast_slice(&gpu_triangles));
+        let rays_buffer = storage("rays", bytemuck::cast_slice(&gpu_rays));
+        let params = self
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("params"),
+                contents: bytemuck::bytes_of(&params),
+                usage: wgpu::BufferUsages::UNIFORM,
+            });
+        let hits_size = (rays.len() * size_of::<GpuHit>()) as u64;
+        let hits = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("hits"),
+            size: hits_size,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let readback = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("hits_readback"),
+            size: hits_size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("bvh2_raycast"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                (0, &nodes),
+                (1, &primitive_indices),
+                (2, &triangles),
+                (3, &rays_buffer),
+                (4, &hits),
+                (5, &params),
+            ]
+            .map(|(binding, buffer)| wgpu::BindGroupEntry {
+                binding,
+                resource: buffer.as_entire_binding(),
+            }),
+        });
+
+        let workgroups = (rays.len() as u32).div_ceil(WORKGROUP_SIZE);
+        let groups_x = workgroups.min(MAX_WORKGROUPS_PER_DIMENSION);
+        let groups_y = workgroups.div_ceil(groups_x);
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+        {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor::default());
+            pass.set_pipeline(&self.pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.dispatch_workgroups(groups_x, groups_y, 1);
+        }
+        encoder.copy_buffer_to_buffer(&hits, 0, &readback, 0, hits_size);
+        self.queue.submit([encoder.finish()]);
+
+        let slice = readback.slice(..);
+        slice.map_async(wgpu::MapMode::Read, |result| {
+            result.expect("failed to map gpu hits for reading")
+        });
+        self.device
+            .poll(wgpu::PollType::Wait)
+            .expect("failed to wait on gpu ray cast");
+        let view = slice.get_mapped_range();
+        let hits = bytemuck::cast_slice(&view[..]).to_vec();
+        drop(view);
+        readback.unmap();
+        Ok(hits)
+    }
+}
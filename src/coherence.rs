@@ -0,0 +1,84 @@
+//! Reordering batches of rays so neighbouring rays take similar paths through the bvh. Rays are keyed by
+//! their direction octant, then a morton code of their origin within the batch's bounds, then a morton
+//! code of their direction, and sorted with the crate's radix sorter.
+
+use glam::Vec3A;
+use obvhs::ray::Ray;
+
+use crate::{
+    morton::morton_encode_u32_unorm,
+    radix::{init_radix_scheduler, radix_scheduler, sorter::sort_indices},
+};
+
+/// Sort key of each ray: 3 bits of direction octant, then 30 bits of origin morton code within the
+/// bounds of all origins, then 30 bits of direction morton code.
+pub fn ray_sort_keys(rays: &[Ray]) -> Vec<u64> {
+    crate::scope!("ray_sort_keys");
+    let (min, max) = rays.iter().fold(
+        (Vec3A::splat(f32::MAX), Vec3A::splat(f32::MIN)),
+        |(min, max), ray| (min.min(ray.origin), max.max(ray.origin)),
+    );
+    let scale = (max - min).max(Vec3A::splat(f32::MIN_POSITIVE)).recip();
+    init_radix_scheduler();
+    let sch = radix_scheduler();
+    let mut keys = vec![0u64; rays.len()];
+    sch.par_map(
+        &mut keys,
+        &|i, key| {
+            let ray = &rays[i];
+            let d = ray.direction;
+            let octant = (d.x < 0.0) as u64 | ((d.y < 0.0) as u64) << 1 | ((d.z < 0.0) as u64) << 2;
+            let origin = ((ray.origin - min) * scale).clamp(Vec3A::ZERO, Vec3A::ONE);
+            let direction = (d * 0.5 + 0.5).clamp(Vec3A::ZERO, Vec3A::ONE);
+            let origin = morton_encode_u32_unorm(origin.as_dvec3()) as u64;
+            let direction = morton_encode_u32_unorm(direction.as_dvec3()) as u64;
+            *key = octant << 60 | origin << 30 | direction;
+        },
+        sch.current_num_threads() as u32 * 4,
+    );
+    keys
+}
+
+/// The order to trace `rays` in for coherent traversal: `rays[order[0]]` first. `rays` is not moved.
+pub fn coherent_order(rays: &[Ray]) -> Vec<u32> {
+    crate::scope!("coherent_order");
+    sort_indices(&ray_sort_keys(rays))
+}
+
+/// Sort `rays` into coherent order, returning the permutation so results can be scattered back:
+/// the ray now at `i` was at `order[i]`.
+pub fn sort_rays(rays: &mut [Ray]) -> Vec<u32> {
+    let order = coherent_order(rays);
+    let sorted: Vec<Ray> = order.iter().map(|&i| rays[i as usize]).collect();
+    rays.copy_from_slice(&sorted);
+    order
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::reference::{random_rays, Rng};
+
+    #[test]
+    fn test_sort_rays() {
+        let original = random_rays(&mut Rng::new(61), 1000);
+        let mut rays = original.clone();
+        let order = sort_rays(&mut rays);
+
+        let mut seen = vec![false; rays.len()];
+        for (ray, &i) in rays.iter().zip(&order) {
+            assert_eq!(ray.origin, original[i as usize].origin);
+            assert!(!seen[i as usize]);
+            seen[i as usize] = true;
+        }
+        // Keys are sorted, so each direction octant is one contiguous run
+        let keys = ray_sort_keys(&rays);
+        assert!(keys.windows(2).all(|w| w[0] <= w[1]));
+        let octant = |r: &Ray| r.direction.cmplt(Vec3A::ZERO).bitmask();
+        let changes = rays
+            .windows(2)
+            .filter(|w| octant(&w[0]) != octant(&w[1]))
+            .count();
+        assert!(changes <= 7);
+    }
+}
@@ -1,15 +1,22 @@
+//! Interactive debug rendering: a shared color buffer that render threads write into while a minifb
+//! window shows it, with progressive accumulation, tonemapping and PNG output.
+
 use glam::{vec4, Vec3, Vec4, Vec4Swizzles};
 use minifb::{Key, Window, WindowOptions};
-use std::sync::{
-    atomic::{AtomicU32, Ordering},
-    Arc,
+use std::{
+    path::Path,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    },
 };
 
-/// Spawn a debug window in a separate thread.
-#[allow(dead_code)]
+/// Open a debug window and call `draw` to fill its buffer each frame, until it is closed or Escape is
+/// pressed. Blocks the calling thread, run rendering on another thread (windows can't be run off the
+/// main thread on MacOS).
 pub fn debug_window<F>(width: usize, height: usize, options: WindowOptions, draw: F)
 where
-    F: Fn(&mut Window, &mut [u32]) + Send + 'static,
+    F: Fn(&mut Window, &mut [u32]),
 {
     let mut window = Window::new("", width, height, options).unwrap();
     window.set_target_fps(30);
@@ -20,8 +27,7 @@ where
     }
 }
 
-/// Spawn a simple debug window in a separate thread. Shared buffer is drawn directly to window.
-#[allow(dead_code)]
+/// Open a simple debug window. Shared buffer is drawn directly to window.
 pub fn simple_debug_window(width: usize, height: usize, shared_buffer: AtomicColorBuffer) {
     debug_window(width, height, Default::default(), move |_window, buffer| {
         for (i, pixel) in buffer.iter_mut().enumerate() {
@@ -30,16 +36,56 @@ pub fn simple_debug_window(width: usize, height: usize, shared_buffer: AtomicCol
     });
 }
 
+/// Open a debug window showing the tonemapped average of the samples added with
+/// `AtomicColorBuffer::accumulate`, so the image converges as samples come in.
+pub fn accumulating_debug_window(
+    width: usize,
+    height: usize,
+    shared_buffer: AtomicColorBuffer,
+    tonemap: Tonemap,
+) {
+    debug_window(width, height, Default::default(), move |_window, buffer| {
+        for (i, pixel) in buffer.iter_mut().enumerate() {
+            *pixel = color_to_minifb_pixel(tonemap.apply(shared_buffer.average(i)).extend(1.0));
+        }
+    });
+}
+
+/// Maps hdr colors into 0..1 for display.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Tonemap {
+    /// Clamp to 0..1
+    #[default]
+    None,
+    /// x / (1 + x)
+    Reinhard,
+    /// Krzysztof Narkowicz's ACES filmic curve fit
+    Aces,
+}
+
+impl Tonemap {
+    pub fn apply(self, color: Vec3) -> Vec3 {
+        let color = color.max(Vec3::ZERO);
+        match self {
+            Tonemap::None => color,
+            Tonemap::Reinhard => color / (1.0 + color),
+            Tonemap::Aces => {
+                (color * (2.51 * color + 0.03)) / (color * (2.43 * color + 0.59) + 0.14)
+            }
+        }
+        .min(Vec3::ONE)
+    }
+}
+
 /// A very basic buffer for async debug rendering
 #[derive(Clone)]
-#[allow(dead_code)]
 pub struct AtomicColorBuffer {
     pub data: Arc<Vec<[AtomicU32; 4]>>,
     pub width: usize,
     pub height: usize,
 }
+
 impl AtomicColorBuffer {
-    #[allow(dead_code)]
     pub fn new(width: usize, height: usize) -> Self {
         Self {
             data: Arc::new(
@@ -52,7 +98,6 @@ impl AtomicColorBuffer {
         }
     }
 
-    #[allow(dead_code)]
     pub fn get(&self, i: usize) -> Vec4 {
         vec4(
             f32::from_bits(self.data[i][0].load(Ordering::Relaxed)),
@@ -62,7 +107,6 @@ impl AtomicColorBuffer {
         )
     }
 
-    #[allow(dead_code)]
     pub fn set(&self, i: usize, color: Vec4) {
         self.data[i][0].store(color.x.to_bits(), Ordering::Relaxed);
         self.data[i][1].store(color.y.to_bits(), Ordering::Relaxed);
@@ -70,24 +114,66 @@ impl AtomicColorBuffer {
         self.data[i][3].store(color.w.to_bits(), Ordering::Relaxed);
     }
 
-    #[allow(dead_code)]
     pub fn get_px(&self, x: usize, y: usize) -> Vec4 {
         self.get(y * self.width + x)
     }
 
-    #[allow(dead_code)]
     pub fn set_px(&self, x: usize, y: usize, color: Vec4) {
         self.set(y * self.width + x, color)
     }
+
+    /// Add a sample to pixel `i`, the sum is kept in xyz and the sample count in w. Pixels are not
+    /// locked, only one thread should accumulate into a given pixel at a time.
+    pub fn accumulate(&self, i: usize, color: Vec3) {
+        self.set(i, self.get(i) + color.extend(1.0));
+    }
+
+    /// Average of the samples added to pixel `i` with `accumulate`, or black if there are none.
+    pub fn average(&self, i: usize) -> Vec3 {
+        let sum = self.get(i);
+        if sum.w > 0.0 {
+            sum.xyz() / sum.w
+        } else {
+            Vec3::ZERO
+        }
+    }
+
+    /// Reset every pixel to zero, e.g. to restart accumulation after the camera moved.
+    pub fn clear(&self) {
+        for i in 0..self.data.len() {
+            self.set(i, Vec4::ZERO);
+        }
+    }
+
+    /// Tonemapped average of every pixel as 8 bit rgba, row by row from the top.
+    pub fn to_rgba8(&self, tonemap: Tonemap) -> Vec<u8> {
+        (0..self.data.len())
+            .flat_map(|i| {
+                let c = (tonemap.apply(self.average(i)) * 255.0).as_uvec3();
+                [c.x as u8, c.y as u8, c.z as u8, 255]
+            })
+            .collect()
+    }
+
+    /// Save the tonemapped average of every pixel as a PNG.
+    pub fn save_png<P: AsRef<Path>>(&self, path: P, tonemap: Tonemap) -> image::ImageResult<()> {
+        image::save_buffer_with_format(
+            path,
+            &self.to_rgba8(tonemap),
+            self.width as u32,
+            self.height as u32,
+            image::ColorType::Rgba8,
+            image::ImageFormat::Png,
+        )
+    }
 }
 
-#[allow(dead_code)]
 pub fn color_to_minifb_pixel(color: Vec4) -> u32 {
     let c = (color.xyz().clamp(Vec3::ZERO, Vec3::ONE) * 255.0).as_uvec3();
     ((c.x & 0xff) << 16) | ((c.y & 0xff) << 8) | (c.z & 0xff)
 }
 
-#[allow(dead_code)]
+/// Bitmap font for drawing debug text into window buffers.
 pub mod text {
     use std::time::Duration;
 
@@ -0,0 +1,292 @@
+//! Embree reference backend, building and traversing the same triangles with Embree 4 so correctness
+//! and performance of the builders here can be compared against it in the same harness. Requires the
+//! `embree` feature and an installed `embree4` library.
+//!
+//! There are no builder or traversal traits in the crate, so the API mirrors `PlocBuilder` and
+//! `Bvh2::traverse` instead.
+
+use std::{
+    ffi::{c_char, c_uint, c_void},
+    fmt, ptr,
+};
+
+use obvhs::{ray::Ray, triangle::Triangle};
+
+mod ffi {
+    use super::*;
+
+    pub type RTCDevice = *mut c_void;
+    pub type RTCScene = *mut c_void;
+    pub type RTCGeometry = *mut c_void;
+
+    pub const RTC_GEOMETRY_TYPE_TRIANGLE: c_uint = 0;
+    pub const RTC_BUFFER_TYPE_INDEX: c_uint = 0;
+    pub const RTC_BUFFER_TYPE_VERTEX: c_uint = 1;
+    pub const RTC_FORMAT_UINT3: c_uint = 0x5003;
+    pub const RTC_FORMAT_FLOAT3: c_uint = 0x9003;
+    pub const RTC_BUILD_QUALITY_LOW: c_uint = 0;
+    pub const RTC_BUILD_QUALITY_MEDIUM: c_uint = 1;
+    pub const RTC_BUILD_QUALITY_HIGH: c_uint = 2;
+    pub const RTC_ERROR_NONE: c_uint = 0;
+    pub const RTC_ERROR_UNKNOWN: c_uint = 1;
+    pub const RTC_INVALID_GEOMETRY_ID: c_uint = c_uint::MAX;
+
+    #[repr(C, align(16))]
+    pub struct RTCRay {
+        pub org: [f32; 3],
+        pub tnear: f32,
+        pub dir: [f32; 3],
+        pub time: f32,
+        pub tfar: f32,
+        pub mask: c_uint,
+        pub id: c_uint,
+        pub flags: c_uint,
+    }
+
+    #[repr(C, align(16))]
+    pub struct RTCHit {
+        pub ng: [f32; 3],
+        pub u: f32,
+        pub v: f32,
+        pub prim_id: c_uint,
+        pub geom_id: c_uint,
+        pub inst_id: [c_uint; 1],
+        // Only present when embree is built with instance arrays, unused otherwise
+        pub inst_prim_id: [c_uint; 1],
+    }
+
+    #[repr(C, align(16))]
+    pub struct RTCRayHit {
+        pub ray: RTCRay,
+        pub hit: RTCHit,
+    }
+
+    #[link(name = "embree4")]
+    extern "C" {
+        pub fn rtcNewDevice(config: *const c_char) -> RTCDevice;
+        pub fn rtcReleaseDevice(device: RTCDevice);
+        pub fn rtcGetDeviceError(device: RTCDevice) -> c_uint;
+        pub fn rtcNewScene(device: RTCDevice) -> RTCScene;
+        pub fn rtcReleaseScene(scene: RTCScene);
+        pub fn rtcSetSceneBuildQuality(scene: RTCScene, quality: c_uint);
+        pub fn rtcCommitScene(scene: RTCScene);
+        pub fn rtcNewGeometry(device: RTCDevice, ty: c_uint) -> RTCGeometry;
+        pub fn rtcReleaseGeometry(geometry: RTCGeometry);
+        pub fn rtcSetNewGeometryBuffer(
+            geometry: RTCGeometry,
+            ty: c_uint,
+            slot: c_uint,
+            format: c_uint,
+            byte_stride: usize,
+            item_count: usize,
+        ) -> *mut c_void;
+        pub fn rtcCommitGeometry(geometry: RTCGeometry);
+        pub fn rtcAttachGeometry(scene: RTCScene, geometry: RTCGeometry) -> c_uint;
+        pub fn rtcIntersect1(scene: RTCScene, rayhit: *mut RTCRayHit, args: *mut c_void);
+    }
+}
+
+/// Embree's bvh build quality, `Medium` is Embree's default.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum EmbreeQuality {
+    Low,
+    #[default]
+    Medium,
+    High,
+}
+
+/// Why an Embree build failed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EmbreeError {
+    /// An Embree call failed with this `RTCError` code
+    Device(u32),
+    /// Embree's `u32` vertex indices can't address three vertices for each of this many triangles
+    TooManyTriangles(usize),
+}
+
+impl fmt::Display for EmbreeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EmbreeError::Device(code) => {
+                let name = match *code {
+                    2 => "invalid argument",
+                    3 => "invalid operation",
+                    4 => "out of memory",
+                    5 => "unsupported cpu",
+                    6 => "cancelled",
+                    _ => "unknown error",
+                };
+                write!(f, "embree error {code}: {name}")
+            }
+            EmbreeError::TooManyTriangles(count) => {
+                write!(f, "{count} triangles is too many for embree's u32 indices")
+            }
+        }
+    }
+}
+
+impl std::error::Error for EmbreeError {}
+
+/// An Embree device, reuse it between builds.
+pub struct EmbreeDevice {
+    device: ffi::RTCDevice,
+}
+
+/// A committed Embree scene containing a single triangle mesh.
+pub struct EmbreeScene {
+    scene: ffi::RTCScene,
+}
+
+// SAFETY: Embree devices and committed scenes can be used from any thread, and rtcIntersect1 may be
+// called concurrently on the same scene.
+unsafe impl Send for EmbreeDevice {}
+unsafe impl Sync for EmbreeDevice {}
+unsafe impl Send for EmbreeScene {}
+unsafe impl Sync for EmbreeScene {}
+
+impl EmbreeDevice {
+    /// Create a device with the default config. Returns None if Embree fails to create one.
+    pub fn new() -> Option<Self> {
+        // SAFETY: a null config selects the defaults.
+        let device = unsafe { ffi::rtcNewDevice(ptr::null()) };
+        (!device.is_null()).then_some(Self { device })
+    }
+
+    /// Build an Embree scene from `triangles`. Hits report the index into `triangles`, like the leaves
+    /// of a bvh built from their aabbs.
+    pub fn build(
+        &self,
+        triangles: &[Triangle],
+        quality: EmbreeQuality,
+    ) -> Result<EmbreeScene, EmbreeError> {
+        crate::scope!("embree build");
+        if triangles.len() > u32::MAX as usize / 3 {
+            return Err(EmbreeError::TooManyTriangles(triangles.len()));
+        }
+        // SAFETY: the buffers returned by rtcSetNewGeometryBuffer are owned by the geometry, sized for
+        // the item count and stride requested, checked for null, and fully written before the geometry
+        // is committed. Embree retains the device, geometry and scene it needs internally.
+        unsafe {
+            // Drop an error left over from an earlier call, so it isn't reported for this build
+            self.take_error();
+            let scene = ffi::rtcNewScene(self.device);
+            if scene.is_null() {
+                return Err(self.error());
+            }
+            // Released by its Drop on the error returns below
+            let scene = EmbreeScene { scene };
+            ffi::rtcSetSceneBuildQuality(
+                scene.scene,
+                match quality {
+                    EmbreeQuality::Low => ffi::RTC_BUILD_QUALITY_LOW,
+                    EmbreeQuality::Medium => ffi::RTC_BUILD_QUALITY_MEDIUM,
+                    EmbreeQuality::High => ffi::RTC_BUILD_QUALITY_HIGH,
+                },
+            );
+            if !triangles.is_empty() {
+                let geometry = ffi::rtcNewGeometry(self.device, ffi::RTC_GEOMETRY_TYPE_TRIANGLE);
+                if geometry.is_null() {
+                    return Err(self.error());
+                }
+                let vertices = ffi::rtcSetNewGeometryBuffer(
+                    geometry,
+                    ffi::RTC_BUFFER_TYPE_VERTEX,
+                    0,
+                    ffi::RTC_FORMAT_FLOAT3,
+                    size_of::<[f32; 3]>(),
+                    triangles.len() * 3,
+                ) as *mut [f32; 3];
+                let indices = ffi::rtcSetNewGeometryBuffer(
+                    geometry,
+                    ffi::RTC_BUFFER_TYPE_INDEX,
+                    0,
+                    ffi::RTC_FORMAT_UINT3,
+                    size_of::<[u32; 3]>(),
+                    triangles.len(),
+                ) as *mut [u32; 3];
+                if vertices.is_null() || indices.is_null() {
+                    ffi::rtcReleaseGeometry(geometry);
+                    return Err(self.error());
+                }
+                for (i, tri) in triangles.iter().enumerate() {
+                    vertices.add(i * 3).write(tri.v0.to_array());
+                    vertices.add(i * 3 + 1).write(tri.v1.to_array());
+                    vertices.add(i * 3 + 2).write(tri.v2.to_array());
+                    let first = (i * 3) as u32;
+                    indices.add(i).write([first, first + 1, first + 2]);
+                }
+                ffi::rtcCommitGeometry(geometry);
+                ffi::rtcAttachGeometry(scene.scene, geometry);
+                ffi::rtcReleaseGeometry(geometry);
+            }
+            ffi::rtcCommitScene(scene.scene);
+            match self.take_error() {
+                Some(err) => Err(err),
+                None => Ok(scene),
+            }
+        }
+    }
+
+    /// The first error since the last call, clearing it.
+    fn take_error(&self) -> Option<EmbreeError> {
+        // SAFETY: the device is valid for the lifetime of self.
+        let code = unsafe { ffi::rtcGetDeviceError(self.device) };
+        (code != ffi::RTC_ERROR_NONE).then_some(EmbreeError::Device(code))
+    }
+
+    /// The error behind a call that returned null.
+    fn error(&self) -> EmbreeError {
+        self.take_error()
+            .unwrap_or(EmbreeError::Device(ffi::RTC_ERROR_UNKNOWN))
+    }
+}
+
+impl Drop for EmbreeDevice {
+    fn drop(&mut self) {
+        // SAFETY: scenes hold their own reference to the device.
+        unsafe { ffi::rtcReleaseDevice(self.device) }
+    }
+}
+
+impl EmbreeScene {
+    /// Find the closest hit along `ray`, matching `Bvh2::traverse`: on a hit `ray.tmax` is set to the
+    /// hit distance and `closest_id` to the triangle index.
+    #[inline(always)]
+    pub fn traverse(&self, ray: &mut Ray, closest_id: &mut u32) {
+        let mut rayhit = ffi::RTCRayHit {
+            ray: ffi::RTCRay {
+                org: ray.origin.to_array(),
+                tnear: ray.tmin,
+                dir: ray.direction.to_array(),
+                time: 0.0,
+                tfar: ray.tmax,
+                mask: u32::MAX,
+                id: 0,
+                flags: 0,
+            },
+            hit: ffi::RTCHit {
+                ng: [0.0; 3],
+                u: 0.0,
+                v: 0.0,
+                prim_id: ffi::RTC_INVALID_GEOMETRY_ID,
+                geom_id: ffi::RTC_INVALID_GEOMETRY_ID,
+                inst_id: [ffi::RTC_INVALID_GEOMETRY_ID],
+                inst_prim_id: [ffi::RTC_INVALID_GEOMETRY_ID],
+            },
+        };
+        // SAFETY: the scene is committed and rayhit is a valid, aligned RTCRayHit. Null arguments
+        // select the default intersection arguments.
+        unsafe { ffi::rtcIntersect1(self.scene, &mut rayhit, ptr::null_mut()) };
+        if rayhit.hit.geom_id != ffi::RTC_INVALID_GEOMETRY_ID {
+            ray.tmax = rayhit.ray.tfar;
+            *closest_id = rayhit.hit.prim_id;
+        }
+    }
+}
+
+impl Drop for EmbreeScene {
+    fn drop(&mut self) {
+        // SAFETY: the scene was created by rtcNewScene and is released once.
+        unsafe { ffi::rtcReleaseScene(self.scene) }
+    }
+}
@@ -0,0 +1,227 @@
+//! C API for building bvhs and casting rays from other languages, see `include/pool_racing.h`. The
+//! header is generated with `cbindgen --config cbindgen.toml --features ffi -o include/pool_racing.h`.
+//! Build a static or dynamic library with `cargo rustc --release --features ffi --crate-type staticlib`
+//! (or `cdylib`). Requires the `ffi` feature.
+//!
+//! Schedulers are configured through `pool_racing_set_scheduler`. If it isn't called before the first
+//! build, the `POOL_RACING_*SCHEDULER` environment variables or the default are used, command line
+//! args of the host process are never parsed.
+//!
+//! Panics never unwind into the caller, a function that panics returns false or null instead.
+
+use std::{
+    panic::{self, AssertUnwindSafe},
+    slice,
+};
+
+use glam::Vec3A;
+use obvhs::ray::Ray;
+
+use crate::{
+    bindings::{set_scheduler, BoundBvh, Primitives},
+    par::Scheduler,
+};
+
+/// Opaque bvh handle, free with `pool_racing_bvh_free`.
+pub struct PoolRacingBvh(BoundBvh);
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct PoolRacingRay {
+    pub origin: [f32; 3],
+    pub tmin: f32,
+    pub direction: [f32; 3],
+    pub tmax: f32,
+}
+
+/// `primitive_id` is `UINT32_MAX` and `t` is the ray tmax if nothing was hit.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct PoolRacingHit {
+    pub t: f32,
+    pub primitive_id: u32,
+}
+
+/// Run `f`, returning `on_panic` if it panics rather than unwinding across the C boundary.
+fn catch_panic<T>(on_panic: T, f: impl FnOnce() -> T) -> T {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or(on_panic)
+}
+
+fn build(primitives: Primitives) -> *mut PoolRacingBvh {
+    Box::into_raw(Box::new(PoolRacingBvh(BoundBvh::build(primitives))))
+}
+
+/// Select the scheduler used for building, by its index in `Scheduler`: 0 seq_opt, 1 seq, 2 forte,
+/// 3 chili, 4 rayon, 5 rayon_join, 6 raw, 7 bevy. Returns false for an unknown index.
+#[no_mangle]
+pub extern "C" fn pool_racing_set_scheduler(scheduler: u32) -> bool {
+    catch_panic(false, || {
+        if scheduler as usize >= Scheduler::ALL.len() {
+            return false;
+        }
+        set_scheduler(Scheduler::from(scheduler));
+        true
+    })
+}
+
+/// Build a bvh over `count` aabbs, each 6 floats: min x, y, z then max x, y, z. The aabbs are copied, so
+/// rays cast against the bvh are intersected with them. Returns null if `aabbs` is null and `count`
+/// isn't 0, or if building fails.
+///
+/// # Safety
+/// `aabbs` must point to `count * 6` readable floats.
+#[no_mangle]
+pub unsafe extern "C" fn pool_racing_bvh_build_aabbs(
+    aabbs: *const f32,
+    count: usize,
+) -> *mut PoolRacingBvh {
+    if aabbs.is_null() && count != 0 {
+        return std::ptr::null_mut();
+    }
+    catch_panic(std::ptr::null_mut(), || {
+        let floats = if count == 0 {
+            &[]
+        } else {
+            slice::from_raw_parts(aabbs, count * 6)
+        };
+        build(Primitives::from_aabb_floats(floats))
+    })
+}
+
+/// Build a bvh over `count` triangles, each 9 floats: x, y, z of each vertex. The triangles are copied,
+/// so rays cast against the bvh are intersected with them. Returns null if `vertices` is null and
+/// `count` isn't 0, or if building fails.
+///
+/// # Safety
+/// `vertices` must point to `count * 9` readable floats.
+#[no_mangle]
+pub unsafe extern "C" fn pool_racing_bvh_build_triangles(
+    vertices: *const f32,
+    count: usize,
+) -> *mut PoolRacingBvh {
+    if vertices.is_null() && count != 0 {
+        return std::ptr::null_mut();
+    }
+    catch_panic(std::ptr::null_mut(), || {
+        let floats = if count == 0 {
+            &[]
+        } else {
+            slice::from_raw_parts(vertices, count * 9)
+        };
+        build(Primitives::from_triangle_floats(floats))
+    })
+}
+
+/// # Safety
+/// `bvh` must be null or a handle returned by one of the build functions that hasn't been freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn pool_racing_bvh_free(bvh: *mut PoolRacingBvh) {
+    if !bvh.is_null() {
+        catch_panic((), || drop(Box::from_raw(bvh)));
+    }
+}
+
+/// # Safety
+/// `bvh` must be a valid handle.
+#[no_mangle]
+pub unsafe extern "C" fn pool_racing_bvh_node_count(bvh: *const PoolRacingBvh) -> usize {
+    catch_panic(0, || (*bvh).0.bvh.nodes.len())
+}
+
+/// Cast `count` rays, writing the closest hit of each to `hits`. Can be called from multiple threads
+/// at once on the same bvh. Returns false if casting fails, `hits` may then be partially written.
+///
+/// # Safety
+/// `bvh` must be a valid handle, `rays` must point to `count` readable rays and `hits` to `count`
+/// writable hits.
+#[no_mangle]
+pub unsafe extern "C" fn pool_racing_bvh_cast_rays(
+    bvh: *const PoolRacingBvh,
+    rays: *const PoolRacingRay,
+    hits: *mut PoolRacingHit,
+    count: usize,
+) -> bool {
+    if count == 0 {
+        return true;
+    }
+    catch_panic(false, || {
+        let bound = &(*bvh).0;
+        let rays = slice::from_raw_parts(rays, count);
+        let hits = slice::from_raw_parts_mut(hits, count);
+        for (ray, hit) in rays.iter().zip(hits) {
+            let mut ray = Ray::new(
+                Vec3A::from_array(ray.origin),
+                Vec3A::from_array(ray.direction),
+                ray.tmin,
+                ray.tmax,
+            );
+            let primitive_id = bound.closest_hit(&mut ray);
+            *hit = PoolRacingHit {
+                t: ray.tmax,
+                primitive_id,
+            };
+        }
+        true
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ffi_build_and_cast_aabbs() {
+        assert!(pool_racing_set_scheduler(
+            Scheduler::SequentialOptimized as u32
+        ));
+        assert!(!pool_racing_set_scheduler(Scheduler::ALL.len() as u32));
+
+        #[rustfmt::skip]
+        let aabbs = [
+            0.0, 0.0, 0.0, 1.0, 1.0, 1.0,
+            2.0, 0.0, 0.0, 3.0, 1.0, 1.0,
+            4.0, 0.0, 0.0, 5.0, 1.0, 1.0,
+        ];
+        let rays = [
+            PoolRacingRay {
+                origin: [2.5, 0.5, 5.0],
+                tmin: 0.0,
+                direction: [0.0, 0.0, -1.0],
+                tmax: f32::INFINITY,
+            },
+            PoolRacingRay {
+                origin: [1.5, 0.5, 5.0],
+                tmin: 0.0,
+                direction: [0.0, 0.0, -1.0],
+                tmax: f32::INFINITY,
+            },
+        ];
+        let mut hits = [PoolRacingHit {
+            t: 0.0,
+            primitive_id: 0,
+        }; 2];
+        unsafe {
+            let bvh = pool_racing_bvh_build_aabbs(aabbs.as_ptr(), 3);
+            assert_eq!(pool_racing_bvh_node_count(bvh), 5);
+            assert!(pool_racing_bvh_cast_rays(
+                bvh,
+                rays.as_ptr(),
+                hits.as_mut_ptr(),
+                2
+            ));
+            pool_racing_bvh_free(bvh);
+            assert!(pool_racing_bvh_build_aabbs(std::ptr::null(), 1).is_null());
+        }
+        assert_eq!(hits[0].primitive_id, 1);
+        assert_eq!(hits[0].t, 4.0);
+        assert_eq!(hits[1].primitive_id, u32::MAX);
+    }
+
+    #[test]
+    fn test_ffi_catch_panic() {
+        assert!(!catch_panic(true, || panic!(
+            "panics don't cross the boundary"
+        )));
+        assert_eq!(catch_panic(0, || 3), 3);
+    }
+}
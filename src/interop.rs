@@ -0,0 +1,175 @@
+//! Input conversions at the API boundary, so callers can pass plain arrays (or mint types with the
+//! `mint` feature) instead of types from the exact glam version this crate uses. The glam version in
+//! use is re-exported as `pool_racing::glam`.
+
+use glam::Vec3A;
+use obvhs::{aabb::Aabb, ray::Ray, triangle::Triangle};
+
+use crate::{bvh::Bvh2, ploc::PlocBuilder};
+
+/// Anything that can be turned into an aabb.
+pub trait ToAabb {
+    fn to_aabb(&self) -> Aabb;
+}
+
+/// Anything that can be turned into a ray. Pairs of points are an origin and direction with an
+/// infinite tmax.
+pub trait ToRay {
+    fn to_ray(&self) -> Ray;
+}
+
+/// A collection of aabb inputs, like `&[[f32; 6]]` or `Vec<([f32; 3], [f32; 3])>`.
+pub trait IntoAabbs {
+    fn into_aabbs(self) -> Vec<Aabb>;
+}
+
+/// A collection of ray inputs, like `&[([f32; 3], [f32; 3])]`.
+pub trait IntoRays {
+    fn into_rays(self) -> Vec<Ray>;
+}
+
+impl<I> IntoAabbs for I
+where
+    I: IntoIterator,
+    I::Item: ToAabb,
+{
+    fn into_aabbs(self) -> Vec<Aabb> {
+        self.into_iter().map(|a| a.to_aabb()).collect()
+    }
+}
+
+impl<I> IntoRays for I
+where
+    I: IntoIterator,
+    I::Item: ToRay,
+{
+    fn into_rays(self) -> Vec<Ray> {
+        self.into_iter().map(|r| r.to_ray()).collect()
+    }
+}
+
+impl<T: ToAabb> ToAabb for &T {
+    fn to_aabb(&self) -> Aabb {
+        (**self).to_aabb()
+    }
+}
+
+impl<T: ToRay> ToRay for &T {
+    fn to_ray(&self) -> Ray {
+        (**self).to_ray()
+    }
+}
+
+impl ToAabb for Aabb {
+    fn to_aabb(&self) -> Aabb {
+        *self
+    }
+}
+
+impl ToAabb for Triangle {
+    fn to_aabb(&self) -> Aabb {
+        self.aabb()
+    }
+}
+
+/// min x, y, z then max x, y, z
+impl ToAabb for [f32; 6] {
+    fn to_aabb(&self) -> Aabb {
+        let a = self;
+        Aabb::new(Vec3A::new(a[0], a[1], a[2]), Vec3A::new(a[3], a[4], a[5]))
+    }
+}
+
+/// (min, max)
+impl ToAabb for ([f32; 3], [f32; 3]) {
+    fn to_aabb(&self) -> Aabb {
+        Aabb::new(Vec3A::from_array(self.0), Vec3A::from_array(self.1))
+    }
+}
+
+/// [min, max]
+impl ToAabb for [[f32; 3]; 2] {
+    fn to_aabb(&self) -> Aabb {
+        Aabb::new(Vec3A::from_array(self[0]), Vec3A::from_array(self[1]))
+    }
+}
+
+impl ToRay for Ray {
+    fn to_ray(&self) -> Ray {
+        *self
+    }
+}
+
+/// (origin, direction)
+impl ToRay for ([f32; 3], [f32; 3]) {
+    fn to_ray(&self) -> Ray {
+        Ray::new_inf(Vec3A::from_array(self.0), Vec3A::from_array(self.1))
+    }
+}
+
+#[cfg(feature = "mint")]
+mod mint_impls {
+    use super::*;
+
+    fn vec3a(v: mint::Vector3<f32>) -> Vec3A {
+        Vec3A::new(v.x, v.y, v.z)
+    }
+
+    fn point3a(p: mint::Point3<f32>) -> Vec3A {
+        Vec3A::new(p.x, p.y, p.z)
+    }
+
+    /// (min, max)
+    impl ToAabb for (mint::Point3<f32>, mint::Point3<f32>) {
+        fn to_aabb(&self) -> Aabb {
+            Aabb::new(point3a(self.0), point3a(self.1))
+        }
+    }
+
+    /// (min, max)
+    impl ToAabb for (mint::Vector3<f32>, mint::Vector3<f32>) {
+        fn to_aabb(&self) -> Aabb {
+            Aabb::new(vec3a(self.0), vec3a(self.1))
+        }
+    }
+
+    /// (origin, direction)
+    impl ToRay for (mint::Point3<f32>, mint::Vector3<f32>) {
+        fn to_ray(&self) -> Ray {
+            Ray::new_inf(point3a(self.0), vec3a(self.1))
+        }
+    }
+
+    /// (origin, direction)
+    impl ToRay for (mint::Vector3<f32>, mint::Vector3<f32>) {
+        fn to_ray(&self) -> Ray {
+            Ray::new_inf(vec3a(self.0), vec3a(self.1))
+        }
+    }
+}
+
+impl PlocBuilder {
+    /// `build_ploc` for any aabb input, see `IntoAabbs`.
+    pub fn build_ploc_from<A: IntoAabbs>(&mut self, aabbs: A) -> Bvh2 {
+        self.build_ploc(&aabbs.into_aabbs())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_into_aabbs_and_rays() {
+        let flat = [[0.0, 1.0, 2.0, 3.0, 4.0, 5.0]];
+        let pairs = vec![([0.0, 1.0, 2.0], [3.0, 4.0, 5.0])];
+        let from_flat = flat.into_aabbs();
+        let from_pairs = (&pairs).into_aabbs();
+        assert_eq!(from_flat[0].min, from_pairs[0].min);
+        assert_eq!(from_flat[0].max, Vec3A::new(3.0, 4.0, 5.0));
+
+        let rays = [([0.0, 0.0, 1.0], [0.0, 0.0, -1.0])].into_rays();
+        assert_eq!(rays[0].direction, Vec3A::new(0.0, 0.0, -1.0));
+        assert_eq!(rays[0].tmax, f32::INFINITY);
+    }
+}
@@ -0,0 +1,185 @@
+//! Ray triangle intersection for one ray against 4 triangles at once, with a hand written NEON path on
+//! aarch64 selected at compile time, and `Triangle::intersect` per lane elsewhere. Single triangle tests
+//! already use NEON on aarch64 through glam's `Vec3A`, so `intersect_triangle` just forwards to
+//! `Triangle::intersect`.
+
+use obvhs::{ray::Ray, triangle::Triangle};
+
+/// Triangles per packet.
+pub const TRIANGLE_LANES: usize = 4;
+
+/// Distance along `ray` to `triangle`, or infinity on a miss.
+#[inline(always)]
+pub fn intersect_triangle(ray: &Ray, triangle: &Triangle) -> f32 {
+    triangle.intersect(ray)
+}
+
+/// Distance along `ray` to each of `triangles`, or infinity for misses. Hits outside `ray.tmin..ray.tmax`
+/// are misses.
+#[inline]
+pub fn intersect_triangles_x4(
+    ray: &Ray,
+    triangles: &[Triangle; TRIANGLE_LANES],
+) -> [f32; TRIANGLE_LANES] {
+    #[cfg(target_arch = "aarch64")]
+    return neon::intersect_triangles_x4(ray, triangles);
+    #[cfg(not(target_arch = "aarch64"))]
+    triangles.map(|t| t.intersect(ray))
+}
+
+/// The closest hit of `ray` among `triangles`, 4 at a time, as (index into `triangles`, t). Shortens
+/// `ray.tmax` to the hit.
+pub fn closest_triangle_hit(ray: &mut Ray, triangles: &[Triangle]) -> Option<(usize, f32)> {
+    let mut hit = None;
+    let mut chunks = triangles.chunks_exact(TRIANGLE_LANES);
+    for (c, chunk) in (&mut chunks).enumerate() {
+        let ts = intersect_triangles_x4(ray, chunk.try_into().unwrap());
+        for (lane, t) in ts.into_iter().enumerate() {
+            if t < ray.tmax {
+                ray.tmax = t;
+                hit = Some((c * TRIANGLE_LANES + lane, t));
+            }
+        }
+    }
+    let offset = triangles.len() - chunks.remainder().len();
+    for (i, triangle) in chunks.remainder().iter().enumerate() {
+        let t = triangle.intersect(ray);
+        if t < ray.tmax {
+            ray.tmax = t;
+            hit = Some((offset + i, t));
+        }
+    }
+    hit
+}
+
+#[cfg(target_arch = "aarch64")]
+mod neon {
+    use std::arch::aarch64::*;
+
+    use super::*;
+
+    #[inline(always)]
+    #[allow(unused_unsafe)]
+    fn load(triangles: &[Triangle; TRIANGLE_LANES], f: impl Fn(&Triangle) -> f32) -> float32x4_t {
+        let lanes = triangles.each_ref().map(f);
+        // SAFETY: reads 4 floats from a 4 float array, NEON is always available on aarch64
+        unsafe { vld1q_f32(lanes.as_ptr()) }
+    }
+
+    /// Moller-Trumbore on structure of arrays lanes, one triangle per lane.
+    #[inline]
+    #[allow(unused_unsafe)]
+    pub fn intersect_triangles_x4(
+        ray: &Ray,
+        triangles: &[Triangle; TRIANGLE_LANES],
+    ) -> [f32; TRIANGLE_LANES] {
+        // SAFETY: NEON is always available on aarch64, and the only memory access is the final store to
+        // a 4 float array
+        unsafe {
+            let v0 = [
+                load(triangles, |t| t.v0.x),
+                load(triangles, |t| t.v0.y),
+                load(triangles, |t| t.v0.z),
+            ];
+            let sub = |a: [float32x4_t; 3], b: [float32x4_t; 3]| {
+                [
+                    vsubq_f32(a[0], b[0]),
+                    vsubq_f32(a[1], b[1]),
+                    vsubq_f32(a[2], b[2]),
+                ]
+            };
+            let cross = |a: [float32x4_t; 3], b: [float32x4_t; 3]| {
+                [
+                    vfmsq_f32(vmulq_f32(a[1], b[2]), a[2], b[1]),
+                    vfmsq_f32(vmulq_f32(a[2], b[0]), a[0], b[2]),
+                    vfmsq_f32(vmulq_f32(a[0], b[1]), a[1], b[0]),
+                ]
+            };
+            let dot = |a: [float32x4_t; 3], b: [float32x4_t; 3]| {
+                vfmaq_f32(vfmaq_f32(vmulq_f32(a[0], b[0]), a[1], b[1]), a[2], b[2])
+            };
+            let e1 = sub(
+                [
+                    load(triangles, |t| t.v1.x),
+                    load(triangles, |t| t.v1.y),
+                    load(triangles, |t| t.v1.z),
+                ],
+                v0,
+            );
+            let e2 = sub(
+                [
+                    load(triangles, |t| t.v2.x),
+                    load(triangles, |t| t.v2.y),
+                    load(triangles, |t| t.v2.z),
+                ],
+                v0,
+            );
+            let direction = [
+                vdupq_n_f32(ray.direction.x),
+                vdupq_n_f32(ray.direction.y),
+                vdupq_n_f32(ray.direction.z),
+            ];
+            let origin = [
+                vdupq_n_f32(ray.origin.x),
+                vdupq_n_f32(ray.origin.y),
+                vdupq_n_f32(ray.origin.z),
+            ];
+
+            let p = cross(direction, e2);
+            let det = dot(e1, p);
+            let inv_det = vdivq_f32(vdupq_n_f32(1.0), det);
+            let s = sub(origin, v0);
+            let u = vmulq_f32(dot(s, p), inv_det);
+            let q = cross(s, e1);
+            let v = vmulq_f32(dot(direction, q), inv_det);
+            let t = vmulq_f32(dot(e2, q), inv_det);
+
+            let zero = vdupq_n_f32(0.0);
+            let one = vdupq_n_f32(1.0);
+            // A zero determinant gives infinite or NaN barycentrics, which fail these comparisons
+            let mut hit = vcgeq_f32(u, zero);
+            hit = vandq_u32(hit, vcgeq_f32(v, zero));
+            hit = vandq_u32(hit, vcleq_f32(vaddq_f32(u, v), one));
+            hit = vandq_u32(hit, vcgeq_f32(t, vdupq_n_f32(ray.tmin)));
+            hit = vandq_u32(hit, vcleq_f32(t, vdupq_n_f32(ray.tmax)));
+            let t = vbslq_f32(hit, t, vdupq_n_f32(f32::INFINITY));
+
+            let mut out = [0.0; TRIANGLE_LANES];
+            vst1q_f32(out.as_mut_ptr(), t);
+            out
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::reference::{brute_force_closest_hit, random_triangles, Rng};
+
+    #[test]
+    fn test_triangle_packets() {
+        let mut rng = Rng::new(81);
+        let triangles = random_triangles(&mut rng, 37, 0.3);
+        for (i, triangle) in triangles.iter().enumerate() {
+            // Aimed at the centroid, well inside the triangle
+            let centroid = (triangle.v0 + triangle.v1 + triangle.v2) / 3.0;
+            let origin = centroid + rng.next_vec3a() - 0.5;
+            let ray = Ray::new_inf(origin, (centroid - origin).normalize());
+            let lanes = [*triangle, triangles[(i + 1) % 37], *triangle, *triangle];
+            let ts = intersect_triangles_x4(&ray, &lanes);
+            let expected = triangle.intersect(&ray);
+            assert!(expected.is_finite());
+            assert!((ts[0] - expected).abs() <= expected * 1e-4);
+            assert_eq!(ts[0], ts[2]);
+
+            // Pointing away misses
+            let away = Ray::new_inf(origin, (origin - centroid).normalize());
+            assert!(intersect_triangles_x4(&away, &lanes)[0].is_infinite());
+
+            let mut packet_ray = ray;
+            let hit = closest_triangle_hit(&mut packet_ray, &triangles);
+            let expected = brute_force_closest_hit(&triangles, &ray);
+            assert_eq!(hit.map(|(i, _)| i as u32), expected.map(|(i, _)| i));
+        }
+    }
+}
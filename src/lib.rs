@@ -1,34 +1,140 @@
+#![cfg_attr(feature = "simd", feature(portable_simd))]
+
+// `#[derive(RadixKey)]` names `::pool_racing`, this lets the derive tests use it inside the crate
+#[cfg(all(test, feature = "derive"))]
+extern crate self as pool_racing;
+
 use std::time::Instant;
 
+#[cfg(feature = "cli")]
 use argh::FromArgs;
 
+#[cfg(feature = "cli")]
+use crate::morton::SpaceFillingCurve;
 use crate::par::Scheduler;
+#[cfg(feature = "cli")]
+use crate::par::SplitStrategy;
+#[cfg(feature = "cli")]
+use crate::radix::RadixAlgorithm;
 
+#[cfg(feature = "bindings")]
+pub mod bindings;
 pub mod bvh;
+pub mod coherence;
+#[cfg(feature = "debug_vis")]
+pub mod debug_vis;
+#[cfg(feature = "embree")]
+pub mod embree;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod interop;
+pub mod intersect;
+pub mod morton;
 pub mod par;
 pub mod ploc;
+pub mod quantized;
+pub mod race;
 pub mod radix;
+pub mod regression;
+pub mod scene;
+#[cfg(feature = "serde")]
+pub mod serde_remote;
+#[cfg(feature = "simd")]
+pub mod simd;
+#[cfg(any(test, feature = "test_util"))]
+pub mod test_util;
+pub mod timings;
 
+/// The glam version used by the public API, to avoid version mismatches. See `interop` for passing in
+/// plain arrays or mint types instead.
+pub use glam;
+/// The obvhs types used throughout the public API, so users don't need to depend on obvhs themselves.
+/// There is a single `Aabb` type, `PlocBuilder`, `Bvh2Node` and traversal all use this one.
+pub use obvhs::{aabb::Aabb, ray::Ray, triangle::Triangle};
+
+#[cfg(feature = "cli")]
 #[derive(FromArgs)]
-/// `demoscene` example
+/// pool_racing examples
 pub struct Args {
     /// threading scheduler backend for ploc. Modes: 'seq_opt', 'seq', 'forte', 'chili', 'rayon'
-    #[argh(option, default = "Scheduler::Forte")]
-    pub ploc_sch: Scheduler,
+    /// Falls back to POOL_RACING_PLOC_SCHEDULER, then POOL_RACING_SCHEDULER, then 'forte'
+    #[argh(option)]
+    pub ploc_sch: Option<Scheduler>,
 
     /// threading scheduler backend for radix. Modes: 'seq_opt', 'seq', 'forte', 'chili', 'rayon'
-    #[argh(option, default = "Scheduler::Forte")]
-    pub radix_sch: Scheduler,
+    /// Falls back to POOL_RACING_RADIX_SCHEDULER, then POOL_RACING_SCHEDULER, then 'forte'
+    #[argh(option)]
+    pub radix_sch: Option<Scheduler>,
+
+    /// splitting strategy for the forte backend's par_map. Modes: 'fixed', 'adaptive'
+    #[argh(option)]
+    pub forte_split: Option<SplitStrategy>,
+
+    /// multi-threaded radix sort algorithm. Modes: 'auto', 'regions', 'recombinating', 'scanning', 'lsb'
+    #[argh(option)]
+    pub radix_algo: Option<RadixAlgorithm>,
+
+    /// curve ploc orders primitives along before clustering. Modes: 'morton', 'hilbert', 'morton128'
+    #[argh(option)]
+    pub ploc_curve: Option<SpaceFillingCurve>,
+
+    /// use a fixed chunk to thread mapping with no stealing for the raw backend
+    #[argh(switch)]
+    pub deterministic: bool,
+
+    /// pin raw backend workers to NUMA nodes by chunk, Linux only
+    #[argh(switch)]
+    pub numa: bool,
+}
+
+#[cfg(feature = "cli")]
+impl Args {
+    /// Parse the command line. Only for binaries that own their command line, like the examples, the
+    /// library itself never parses it.
+    pub fn from_env() -> Self {
+        argh::from_env()
+    }
+
+    /// Apply the options that were given through the explicit configuration functions. Schedulers that
+    /// weren't given still fall back to the environment variables on first use.
+    pub fn apply(&self) {
+        if let Some(split) = self.forte_split {
+            par::set_split_strategy(split);
+        }
+        if let Some(algorithm) = self.radix_algo {
+            radix::set_radix_algorithm(algorithm);
+        }
+        if let Some(curve) = self.ploc_curve {
+            ploc::set_ploc_curve(curve);
+        }
+        if self.deterministic {
+            par::set_deterministic(true);
+        }
+        if self.numa {
+            par::numa::set_numa_aware(true);
+        }
+        if let Some(scheduler) = self.ploc_sch {
+            ploc::set_ploc_scheduler(scheduler);
+        }
+        if let Some(scheduler) = self.radix_sch {
+            radix::set_radix_scheduler(scheduler);
+        }
+    }
 }
 
+/// Reports its label and elapsed time to the `timings` sink when dropped (stdout by default), and stores
+/// them in `timings` while recording.
 pub struct Timer {
     start: Instant,
     label: String,
+    depth: u32,
 }
 
 impl Timer {
     pub fn new(label: &str) -> Self {
+        timings::epoch();
         Self {
+            depth: timings::enter(label),
             start: Instant::now(),
             label: label.to_string(),
         }
@@ -38,11 +144,7 @@ impl Timer {
 impl Drop for Timer {
     fn drop(&mut self) {
         let elapsed = self.start.elapsed();
-        println!(
-            "{:>8} {}",
-            format!("{}", obvhs::PrettyDuration(elapsed)),
-            self.label
-        )
+        timings::exit(&self.label, self.start, elapsed, self.depth);
     }
 }
 
@@ -58,7 +160,7 @@ macro_rules! scope {
 }
 
 /// Add profile scope and timer.
-/// Use scope_print feature to print times to console.
+/// Use scope_print feature to print times to console, or to the sink set with `timings::set_sink`.
 /// Use profile feature to enable profiling.
 #[doc(hidden)]
 #[macro_export]
@@ -72,7 +174,7 @@ macro_rules! scope_print {
 }
 
 /// Add profile scope and timer.
-/// Use scope_print_major feature to print times to console.
+/// Use scope_print_major feature to print times to console, or to the sink set with `timings::set_sink`.
 /// Use profile feature to enable profiling.
 #[doc(hidden)]
 #[macro_export]
@@ -84,3 +186,24 @@ macro_rules! scope_print_major {
         let _t = $crate::Timer::new($label);
     };
 }
+
+/// Add profile scope for a chunk executed by a scheduler backend, tagged with the chunk id and length.
+/// Also registers the executing thread with the profiler the first time it runs a chunk.
+/// Use profile feature to enable profiling.
+/// Use scope_print feature to store chunks while `timings` is recording, e.g. for `write_chrome_trace`.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! chunk_scope {
+    [$backend:expr, $chunk_id:expr, $len:expr] => {
+        #[cfg(feature = "profile")]
+        $crate::par::register_worker_thread($backend);
+        #[cfg(feature = "profile")]
+        let _chunk_label = format!("chunk {} len {}", $chunk_id, $len);
+        #[cfg(feature = "profile")]
+        profiling::scope!($backend, _chunk_label.as_str());
+        #[cfg(feature = "scope_print")]
+        let _r = $crate::timings::RecordScope::new(|| {
+            format!("{} chunk {} len {}", $backend, $chunk_id, $len)
+        });
+    };
+}
@@ -0,0 +1,630 @@
+//! Space filling curve codes for sorting primitives spatially before building a BVH. Morton codes
+//! (Z-order curve) are the cheapest to compute, Hilbert codes cost more but never jump between distant
+//! cells, which Morton codes do at every power of two boundary.
+//!
+//! The `_unorm` variants take positions already normalized to the 0..1 range of the scene bounds.
+//! [`sort_nodes_m64`] and [`sort_nodes_m128`] are the pre-sort builders run on their leaf nodes.
+
+use std::str::FromStr;
+
+use bytemuck::Zeroable;
+use glam::DVec3;
+
+use crate::{
+    bvh::Bvh2Node,
+    par::Scheduler,
+    radix::{
+        radix_key::{sort_small_by_ord, KeyValue, RadixKey},
+        sorter::Sorter,
+    },
+    scope, scope_print, scope_print_major,
+};
+
+/// Which curve the PLOC pre-sort orders primitives along.
+#[derive(PartialEq, Eq, Default, Clone, Copy, Debug)]
+#[repr(u32)]
+pub enum SpaceFillingCurve {
+    #[default]
+    Morton = 0,
+    Hilbert = 1,
+    /// 128 bit morton codes, for huge scenes where 21 bits per axis map many distant primitives to the
+    /// same code
+    Morton128 = 2,
+}
+
+impl FromStr for SpaceFillingCurve {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "morton" => Ok(Self::Morton),
+            "hilbert" => Ok(Self::Hilbert),
+            "morton128" => Ok(Self::Morton128),
+            _ => Err(format!(
+                "Unknown curve: '{s}', valid curves: 'morton', 'hilbert', 'morton128'"
+            )),
+        }
+    }
+}
+
+impl SpaceFillingCurve {
+    pub const ALL: [SpaceFillingCurve; 3] = [
+        SpaceFillingCurve::Morton,
+        SpaceFillingCurve::Hilbert,
+        SpaceFillingCurve::Morton128,
+    ];
+
+    /// The 64 bit code of a position in 0..1 along this curve. Morton128 is cut down to its top 64 bits.
+    #[inline(always)]
+    pub fn encode_u64_unorm(self, p: DVec3) -> u64 {
+        match self {
+            SpaceFillingCurve::Morton => morton_encode_u64_unorm(p),
+            SpaceFillingCurve::Hilbert => hilbert_encode_u64_unorm(p),
+            SpaceFillingCurve::Morton128 => (morton_encode_u128_unorm(p) >> 62) as u64,
+        }
+    }
+}
+
+/// Bits per axis of a 64 bit morton code
+pub const MORTON_U64_BITS: u32 = 21;
+/// Bits per axis of a 128 bit morton code
+pub const MORTON_U128_BITS: u32 = 42;
+/// Bits per axis of a 32 bit morton code
+pub const MORTON_U32_BITS: u32 = 10;
+
+/// Spread the lowest 21 bits of `a` out so there are two zero bits between each.
+#[inline(always)]
+pub fn split_by_3_u64(a: u32) -> u64 {
+    let mut x = a as u64 & 0x1f_ffff;
+    x = (x | x << 32) & 0x1f_0000_0000_ffff;
+    x = (x | x << 16) & 0x1f_0000_ff00_00ff;
+    x = (x | x << 8) & 0x100f_00f0_0f00_f00f;
+    x = (x | x << 4) & 0x10c3_0c30_c30c_30c3;
+    x = (x | x << 2) & 0x1249_2492_4924_9249;
+    x
+}
+
+/// Inverse of [`split_by_3_u64`], gathers every third bit of `x` into the lowest 21 bits.
+#[inline(always)]
+pub fn compact_by_3_u64(x: u64) -> u32 {
+    let mut x = x & 0x1249_2492_4924_9249;
+    x = (x | x >> 2) & 0x10c3_0c30_c30c_30c3;
+    x = (x | x >> 4) & 0x100f_00f0_0f00_f00f;
+    x = (x | x >> 8) & 0x1f_0000_ff00_00ff;
+    x = (x | x >> 16) & 0x1f_0000_0000_ffff;
+    x = (x | x >> 32) & 0x1f_ffff;
+    x as u32
+}
+
+/// Interleave the lowest 21 bits of each axis, x in the lowest bit.
+#[inline(always)]
+pub fn morton_encode_u64(x: u32, y: u32, z: u32) -> u64 {
+    split_by_3_u64(x) | split_by_3_u64(y) << 1 | split_by_3_u64(z) << 2
+}
+
+/// Inverse of [`morton_encode_u64`].
+#[inline(always)]
+pub fn morton_decode_u64(code: u64) -> (u32, u32, u32) {
+    (
+        compact_by_3_u64(code),
+        compact_by_3_u64(code >> 1),
+        compact_by_3_u64(code >> 2),
+    )
+}
+
+/// Encode a position in 0..1, values outside of it are clamped.
+#[inline(always)]
+pub fn morton_encode_u64_unorm(p: DVec3) -> u64 {
+    let max = ((1u32 << MORTON_U64_BITS) - 1) as f64;
+    let p = (p * (1u32 << MORTON_U64_BITS) as f64).clamp(DVec3::ZERO, DVec3::splat(max));
+    morton_encode_u64(p.x as u32, p.y as u32, p.z as u32)
+}
+
+/// A 64 bit morton code, for sorting along the curve.
+#[derive(Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Zeroable)]
+#[repr(transparent)]
+pub struct Morton64(pub u64);
+
+impl Morton64 {
+    #[inline(always)]
+    pub fn from_unorm(p: DVec3) -> Self {
+        Self(morton_encode_u64_unorm(p))
+    }
+}
+
+impl RadixKey for Morton64 {
+    const LEVELS: usize = 8;
+
+    #[inline(always)]
+    fn get_level(&self, level: usize) -> u8 {
+        self.0.get_level(level)
+    }
+
+    sort_small_by_ord!();
+}
+
+/// Positions encoded at once by [`morton_encode_u64_unorm_x8`].
+pub const MORTON_LANES: usize = 8;
+
+/// [`morton_encode_u64_unorm`] for 8 positions at once. Each step runs over all lanes before the next, so
+/// it compiles to vector instructions on targets with 64 bit integer SIMD.
+#[inline(always)]
+pub fn morton_encode_u64_unorm_x8(p: &[DVec3; MORTON_LANES]) -> [u64; MORTON_LANES] {
+    let scale = (1u32 << MORTON_U64_BITS) as f64;
+    let max = scale - 1.0;
+    let mut x = [0u32; MORTON_LANES];
+    let mut y = [0u32; MORTON_LANES];
+    let mut z = [0u32; MORTON_LANES];
+    for i in 0..MORTON_LANES {
+        x[i] = (p[i].x * scale).clamp(0.0, max) as u32;
+        y[i] = (p[i].y * scale).clamp(0.0, max) as u32;
+        z[i] = (p[i].z * scale).clamp(0.0, max) as u32;
+    }
+
+    let mut codes = [0u64; MORTON_LANES];
+    for i in 0..MORTON_LANES {
+        codes[i] = split_by_3_u64(x[i]) | split_by_3_u64(y[i]) << 1 | split_by_3_u64(z[i]) << 2;
+    }
+    codes
+}
+
+/// Encode `points` into `codes`, 8 at a time. Uses `simd::morton_encode_u64_unorm_batch` with the
+/// `simd` feature.
+#[inline]
+pub fn morton_encode_u64_unorm_batch(points: &[DVec3], codes: &mut [u64]) {
+    #[cfg(feature = "simd")]
+    return crate::simd::morton_encode_u64_unorm_batch(points, codes);
+    #[cfg(not(feature = "simd"))]
+    morton_encode_u64_unorm_batch_scalar(points, codes);
+}
+
+#[cfg(not(feature = "simd"))]
+#[inline(always)]
+fn morton_encode_u64_unorm_batch_scalar(points: &[DVec3], codes: &mut [u64]) {
+    assert_eq!(points.len(), codes.len());
+    let mut point_chunks = points.chunks_exact(MORTON_LANES);
+    let mut code_chunks = codes.chunks_exact_mut(MORTON_LANES);
+    for (p, c) in (&mut point_chunks).zip(&mut code_chunks) {
+        c.copy_from_slice(&morton_encode_u64_unorm_x8(p.try_into().unwrap()));
+    }
+    for (p, c) in point_chunks
+        .remainder()
+        .iter()
+        .zip(code_chunks.into_remainder())
+    {
+        *c = morton_encode_u64_unorm(*p);
+    }
+}
+
+/// Inverse of [`morton_encode_u64_unorm`]. Returns the center of the cell the code covers, so it is
+/// within half a cell of the encoded position.
+#[inline(always)]
+pub fn morton_decode_u64_unorm(code: u64) -> DVec3 {
+    let (x, y, z) = morton_decode_u64(code);
+    (DVec3::new(x as f64, y as f64, z as f64) + 0.5) / (1u32 << MORTON_U64_BITS) as f64
+}
+
+/// Interleave the lowest 42 bits of each axis, x in the lowest bit.
+#[inline(always)]
+pub fn morton_encode_u128(x: u64, y: u64, z: u64) -> u128 {
+    // Each 21 bit half of an axis fills 63 bits of the code
+    let half = |a: u64| {
+        split_by_3_u64(a as u32) as u128
+            | (split_by_3_u64((a >> MORTON_U64_BITS) as u32) as u128) << (3 * MORTON_U64_BITS)
+    };
+    half(x) | half(y) << 1 | half(z) << 2
+}
+
+/// Inverse of [`morton_encode_u128`].
+#[inline(always)]
+pub fn morton_decode_u128(code: u128) -> (u64, u64, u64) {
+    let half = |code: u128| {
+        compact_by_3_u64(code as u64) as u64
+            | (compact_by_3_u64((code >> (3 * MORTON_U64_BITS)) as u64) as u64) << MORTON_U64_BITS
+    };
+    (half(code), half(code >> 1), half(code >> 2))
+}
+
+/// Encode a position in 0..1, values outside of it are clamped.
+#[inline(always)]
+pub fn morton_encode_u128_unorm(p: DVec3) -> u128 {
+    let max = ((1u64 << MORTON_U128_BITS) - 1) as f64;
+    let p = (p * (1u64 << MORTON_U128_BITS) as f64).clamp(DVec3::ZERO, DVec3::splat(max));
+    morton_encode_u128(p.x as u64, p.y as u64, p.z as u64)
+}
+
+/// Inverse of [`morton_encode_u128_unorm`], returning the center of the cell.
+#[inline(always)]
+pub fn morton_decode_u128_unorm(code: u128) -> DVec3 {
+    let (x, y, z) = morton_decode_u128(code);
+    (DVec3::new(x as f64, y as f64, z as f64) + 0.5) / (1u64 << MORTON_U128_BITS) as f64
+}
+
+/// A 128 bit morton code, for sorting along the curve.
+#[derive(Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Zeroable)]
+#[repr(transparent)]
+pub struct Morton128(pub u128);
+
+impl Morton128 {
+    #[inline(always)]
+    pub fn from_unorm(p: DVec3) -> Self {
+        Self(morton_encode_u128_unorm(p))
+    }
+}
+
+impl RadixKey for Morton128 {
+    const LEVELS: usize = 16;
+
+    #[inline(always)]
+    fn get_level(&self, level: usize) -> u8 {
+        self.0.get_level(level)
+    }
+
+    sort_small_by_ord!();
+}
+
+/// Spread the lowest 10 bits of `a` out so there are two zero bits between each.
+#[inline(always)]
+pub fn split_by_3_u32(a: u32) -> u32 {
+    let mut x = a & 0x3ff;
+    x = (x | x << 16) & 0x0300_00ff;
+    x = (x | x << 8) & 0x0300_f00f;
+    x = (x | x << 4) & 0x030c_30c3;
+    x = (x | x << 2) & 0x0924_9249;
+    x
+}
+
+/// Inverse of [`split_by_3_u32`], gathers every third bit of `x` into the lowest 10 bits.
+#[inline(always)]
+pub fn compact_by_3_u32(x: u32) -> u32 {
+    let mut x = x & 0x0924_9249;
+    x = (x | x >> 2) & 0x030c_30c3;
+    x = (x | x >> 4) & 0x0300_f00f;
+    x = (x | x >> 8) & 0x0300_00ff;
+    x = (x | x >> 16) & 0x3ff;
+    x
+}
+
+/// Interleave the lowest 10 bits of each axis, x in the lowest bit. Coarser than [`morton_encode_u64`],
+/// but half the key size makes the sort noticeably faster for quick, lower quality builds.
+#[inline(always)]
+pub fn morton_encode_u32(x: u32, y: u32, z: u32) -> u32 {
+    split_by_3_u32(x) | split_by_3_u32(y) << 1 | split_by_3_u32(z) << 2
+}
+
+/// Inverse of [`morton_encode_u32`].
+#[inline(always)]
+pub fn morton_decode_u32(code: u32) -> (u32, u32, u32) {
+    (
+        compact_by_3_u32(code),
+        compact_by_3_u32(code >> 1),
+        compact_by_3_u32(code >> 2),
+    )
+}
+
+/// Encode a position in 0..1, values outside of it are clamped.
+#[inline(always)]
+pub fn morton_encode_u32_unorm(p: DVec3) -> u32 {
+    let max = ((1u32 << MORTON_U32_BITS) - 1) as f64;
+    let p = (p * (1u32 << MORTON_U32_BITS) as f64).clamp(DVec3::ZERO, DVec3::splat(max));
+    morton_encode_u32(p.x as u32, p.y as u32, p.z as u32)
+}
+
+/// Inverse of [`morton_encode_u32_unorm`], returning the center of the cell.
+#[inline(always)]
+pub fn morton_decode_u32_unorm(code: u32) -> DVec3 {
+    let (x, y, z) = morton_decode_u32(code);
+    (DVec3::new(x as f64, y as f64, z as f64) + 0.5) / (1u32 << MORTON_U32_BITS) as f64
+}
+
+/// Index along a 3D Hilbert curve of the lowest `bits` bits of each axis, using Skilling's transform.
+/// <https://doi.org/10.1063/1.1751381>
+#[inline(always)]
+fn hilbert_encode(x: u32, y: u32, z: u32, bits: u32) -> u64 {
+    let mut p = [x, y, z];
+    let m = 1u32 << (bits - 1);
+
+    // Inverse undo excess work
+    let mut q = m;
+    while q > 1 {
+        let mask = q - 1;
+        for i in 0..3 {
+            if p[i] & q != 0 {
+                p[0] ^= mask;
+            } else {
+                let t = (p[0] ^ p[i]) & mask;
+                p[0] ^= t;
+                p[i] ^= t;
+            }
+        }
+        q >>= 1;
+    }
+
+    // Gray encode
+    p[1] ^= p[0];
+    p[2] ^= p[1];
+    let mut t = 0;
+    let mut q = m;
+    while q > 1 {
+        if p[2] & q != 0 {
+            t ^= q - 1;
+        }
+        q >>= 1;
+    }
+    for v in &mut p {
+        *v ^= t;
+    }
+
+    // The index is the transposed axes interleaved, with the first axis in the highest bit
+    morton_encode_u64(p[2], p[1], p[0])
+}
+
+/// Index along a 3D Hilbert curve of the lowest 21 bits of each axis.
+#[inline(always)]
+pub fn hilbert_encode_u64(x: u32, y: u32, z: u32) -> u64 {
+    let mask = (1u32 << MORTON_U64_BITS) - 1;
+    hilbert_encode(x & mask, y & mask, z & mask, MORTON_U64_BITS)
+}
+
+/// Encode a position in 0..1, values outside of it are clamped.
+#[inline(always)]
+pub fn hilbert_encode_u64_unorm(p: DVec3) -> u64 {
+    let max = ((1u32 << MORTON_U64_BITS) - 1) as f64;
+    let p = (p * (1u32 << MORTON_U64_BITS) as f64).clamp(DVec3::ZERO, DVec3::splat(max));
+    hilbert_encode_u64(p.x as u32, p.y as u32, p.z as u32)
+}
+
+/// A 64 bit Hilbert index, for sorting along the curve.
+#[derive(Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Zeroable)]
+#[repr(transparent)]
+pub struct Hilbert64(pub u64);
+
+impl Hilbert64 {
+    #[inline(always)]
+    pub fn from_unorm(p: DVec3) -> Self {
+        Self(hilbert_encode_u64_unorm(p))
+    }
+}
+
+impl RadixKey for Hilbert64 {
+    const LEVELS: usize = 8;
+
+    #[inline(always)]
+    fn get_level(&self, level: usize) -> u8 {
+        self.0.get_level(level)
+    }
+
+    sort_small_by_ord!();
+}
+
+/// Sort `nodes` by the `curve` code of their centers, encoding and radix sorting on `sch`. `scale` and
+/// `offset` map the centers into the 0..1 range of the scene bounds. The nodes are carried through the
+/// radix sort as the payload of their morton code, so they are already in order afterwards and only need
+/// to be copied back.
+#[inline(always)]
+pub fn sort_nodes_m64(
+    sch: Scheduler,
+    curve: SpaceFillingCurve,
+    nodes: &mut [Bvh2Node],
+    mortons: &mut [KeyValue<u64, Bvh2Node>],
+    sorter: &mut Sorter<KeyValue<u64, Bvh2Node>>,
+    scale: DVec3,
+    offset: DVec3,
+) {
+    scope_print_major!("sort_nodes_m64");
+    let chunk_size = sch.current_num_threads() as u32;
+    {
+        scope!("par generate mortons");
+        let task_size = mortons.len().div_ceil(sch.current_num_threads()).max(1);
+        sch.par_chunks_mut(
+            mortons,
+            &|chunk_id: usize, chunk: &mut [KeyValue<u64, Bvh2Node>]| {
+                let nodes = &nodes[chunk_id * task_size..][..chunk.len()];
+                let center = |node: &Bvh2Node| node.aabb.center().as_dvec3() * scale + offset;
+
+                // Morton codes go through the batch encoder 8 at a time, hilbert codes are encoded one
+                // by one
+                if curve != SpaceFillingCurve::Morton {
+                    for (m, node) in chunk.iter_mut().zip(nodes) {
+                        *m = KeyValue {
+                            key: curve.encode_u64_unorm(center(node)),
+                            value: *node,
+                        };
+                    }
+                    return;
+                }
+                let mut centers = [DVec3::ZERO; MORTON_LANES];
+                let mut codes = [0; MORTON_LANES];
+                for (m, n) in chunk
+                    .chunks_mut(MORTON_LANES)
+                    .zip(nodes.chunks(MORTON_LANES))
+                {
+                    for (c, node) in centers.iter_mut().zip(n) {
+                        *c = center(node);
+                    }
+                    morton_encode_u64_unorm_batch(&centers[..n.len()], &mut codes[..n.len()]);
+                    for ((m, node), key) in m.iter_mut().zip(n).zip(codes) {
+                        *m = KeyValue { key, value: *node };
+                    }
+                }
+            },
+            task_size,
+        );
+    }
+
+    {
+        scope_print!("radix sort");
+        sorter.sort_with(sch, mortons)
+    }
+
+    {
+        scope!("par copy back sorted");
+        sch.par_map(
+            nodes,
+            &|i: usize, n: &mut Bvh2Node| *n = mortons[i].value,
+            chunk_size,
+        );
+    }
+}
+
+/// [`sort_nodes_m64`] with 128 bit morton codes.
+#[inline(always)]
+pub fn sort_nodes_m128(
+    sch: Scheduler,
+    nodes: &mut [Bvh2Node],
+    mortons: &mut [KeyValue<u128, Bvh2Node>],
+    sorter: &mut Sorter<KeyValue<u128, Bvh2Node>>,
+    scale: DVec3,
+    offset: DVec3,
+) {
+    scope_print_major!("sort_nodes_m128");
+    let chunk_size = sch.current_num_threads() as u32;
+    {
+        scope!("par generate mortons");
+        sch.par_map(
+            mortons,
+            &|index: usize, m: &mut KeyValue<u128, Bvh2Node>| {
+                let node = nodes[index];
+                let center = node.aabb.center().as_dvec3() * scale + offset;
+                *m = KeyValue {
+                    key: morton_encode_u128_unorm(center),
+                    value: node,
+                };
+            },
+            chunk_size,
+        );
+    }
+
+    {
+        scope_print!("radix sort");
+        sorter.sort_with(sch, mortons)
+    }
+
+    {
+        scope!("par copy back sorted");
+        sch.par_map(
+            nodes,
+            &|i: usize, n: &mut Bvh2Node| *n = mortons[i].value,
+            chunk_size,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_morton_u64_round_trip() {
+        for (x, y, z) in [
+            (0, 0, 0),
+            (1, 2, 3),
+            (0x1f_ffff, 0, 0x15_5555),
+            (12345, 999_999, 7),
+        ] {
+            let code = morton_encode_u64(x, y, z);
+            assert_eq!(morton_decode_u64(code), (x, y, z));
+        }
+        assert_eq!(morton_encode_u64(1, 0, 0), 0b001);
+        assert_eq!(morton_encode_u64(0, 1, 0), 0b010);
+        assert_eq!(morton_encode_u64(0, 0, 1), 0b100);
+
+        let cell = 1.0 / (1u32 << MORTON_U64_BITS) as f64;
+        for p in [DVec3::ZERO, DVec3::new(0.25, 0.5, 0.999), DVec3::splat(1.0)] {
+            let decoded = morton_decode_u64_unorm(morton_encode_u64_unorm(p));
+            assert!((decoded - p).abs().max_element() <= cell);
+        }
+    }
+
+    #[test]
+    fn test_morton_batch_matches_scalar() {
+        let points: Vec<DVec3> = (0..27)
+            .map(|i| {
+                DVec3::new(
+                    i as f64 / 26.0,
+                    (i * 7 % 27) as f64 / 20.0 - 0.1,
+                    0.123 * i as f64,
+                )
+            })
+            .collect();
+        let mut codes = vec![0; points.len()];
+        morton_encode_u64_unorm_batch(&points, &mut codes);
+        for (p, code) in points.iter().zip(&codes) {
+            assert_eq!(*code, morton_encode_u64_unorm(*p));
+        }
+    }
+
+    #[test]
+    fn test_morton_u128_round_trip() {
+        let max = (1u64 << MORTON_U128_BITS) - 1;
+        for (x, y, z) in [
+            (0, 0, 0),
+            (1, 2, 3),
+            (max, 0, max / 3),
+            (1 << 30, 1 << 41, 12345),
+        ] {
+            let code = morton_encode_u128(x, y, z);
+            assert_eq!(morton_decode_u128(code), (x, y, z));
+        }
+        // The low bits interleave the same as the 64 bit codes
+        assert_eq!(
+            morton_encode_u128(0x1f_ffff, 5, 77) as u64,
+            morton_encode_u64(0x1f_ffff, 5, 77)
+        );
+
+        // Positions too close together for 64 bit codes still get different codes
+        let a = DVec3::splat(0.5);
+        let b = a + 1e-9;
+        assert_eq!(morton_encode_u64_unorm(a), morton_encode_u64_unorm(b));
+        assert!(Morton128::from_unorm(a) < Morton128::from_unorm(b));
+
+        let cell = 1.0 / (1u64 << MORTON_U128_BITS) as f64;
+        let p = DVec3::new(0.25, 0.5, 0.999);
+        let decoded = morton_decode_u128_unorm(morton_encode_u128_unorm(p));
+        assert!((decoded - p).abs().max_element() <= cell);
+    }
+
+    #[test]
+    fn test_morton_u32_round_trip() {
+        for (x, y, z) in [(0, 0, 0), (1, 2, 3), (0x3ff, 0, 0x155), (512, 1023, 7)] {
+            let code = morton_encode_u32(x, y, z);
+            assert_eq!(morton_decode_u32(code), (x, y, z));
+            // Same interleaving as the 64 bit codes, just fewer bits
+            assert_eq!(code as u64, morton_encode_u64(x, y, z));
+        }
+
+        let cell = 1.0 / (1u32 << MORTON_U32_BITS) as f64;
+        for p in [DVec3::ZERO, DVec3::new(0.25, 0.5, 0.999), DVec3::splat(1.0)] {
+            let decoded = morton_decode_u32_unorm(morton_encode_u32_unorm(p));
+            assert!((decoded - p).abs().max_element() <= cell);
+        }
+    }
+
+    #[test]
+    fn test_hilbert_visits_neighbours_in_order() {
+        // Every cell of an 8x8x8 grid appears once, and each step moves to an adjacent cell
+        let bits = 3;
+        let side = 1u32 << bits;
+        let mut cells = vec![None; (side * side * side) as usize];
+        for x in 0..side {
+            for y in 0..side {
+                for z in 0..side {
+                    let index = hilbert_encode(x, y, z, bits) as usize;
+                    assert!(cells[index].is_none());
+                    cells[index] = Some([x as i32, y as i32, z as i32]);
+                }
+            }
+        }
+        for w in cells.windows(2) {
+            let (a, b) = (w[0].unwrap(), w[1].unwrap());
+            let dist: i32 = (0..3).map(|i| (a[i] - b[i]).abs()).sum();
+            assert_eq!(dist, 1);
+        }
+
+        assert_eq!(hilbert_encode_u64(0, 0, 0), 0);
+        assert_eq!(
+            SpaceFillingCurve::Hilbert.encode_u64_unorm(DVec3::splat(0.3)),
+            Hilbert64::from_unorm(DVec3::splat(0.3)).0
+        );
+    }
+}
@@ -0,0 +1,92 @@
+use std::cell::RefCell;
+
+use obvhs::aabb::Aabb;
+use thread_local::ThreadLocal;
+
+/// Combine two partial results from different workers.
+pub trait Merge {
+    fn merge(&mut self, other: &Self);
+}
+
+impl Merge for Aabb {
+    #[inline(always)]
+    fn merge(&mut self, other: &Self) {
+        *self = self.union(other);
+    }
+}
+
+macro_rules! impl_merge_sum {
+    ($($t:ty),*) => {
+        $(
+            impl Merge for $t {
+                #[inline(always)]
+                fn merge(&mut self, other: &Self) {
+                    *self += *other;
+                }
+            }
+        )*
+    };
+}
+
+impl_merge_sum!(u8, u16, u32, u64, usize, i8, i16, i32, i64, isize, f32, f64);
+
+// Element wise, for histograms etc...
+impl<T: Merge, const N: usize> Merge for [T; N] {
+    #[inline(always)]
+    fn merge(&mut self, other: &Self) {
+        for (a, b) in self.iter_mut().zip(other.iter()) {
+            a.merge(b);
+        }
+    }
+}
+
+/// Per-worker partial results that are merged at the end of a dispatch. Workers fill their own `T` with
+/// [`ThreadLocalAccumulator::with`], then [`ThreadLocalAccumulator::merge_into`] combines them.
+pub struct ThreadLocalAccumulator<T: Send> {
+    locals: ThreadLocal<RefCell<T>>,
+}
+
+impl<T: Send> Default for ThreadLocalAccumulator<T> {
+    fn default() -> Self {
+        Self {
+            locals: ThreadLocal::default(),
+        }
+    }
+}
+
+impl<T: Send + Default + Merge> ThreadLocalAccumulator<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Access this thread's partial result. Panics if called again on the same thread from inside `f`.
+    #[inline(always)]
+    pub fn with<R>(&self, f: impl FnOnce(&mut T) -> R) -> R {
+        f(&mut self.locals.get_or_default().borrow_mut())
+    }
+
+    /// Reset all partial results to `T::default()`, keeping the per-thread slots around for the next dispatch.
+    #[inline(always)]
+    pub fn reset(&mut self) {
+        for local in self.locals.iter_mut() {
+            *local.get_mut() = T::default();
+        }
+    }
+
+    /// Merge all partial results into `out`.
+    #[inline(always)]
+    pub fn merge_into(&mut self, out: &mut T) {
+        for local in self.locals.iter_mut() {
+            out.merge(local.get_mut());
+        }
+    }
+
+    /// Merge all partial results into a new `T` and reset them.
+    #[inline(always)]
+    pub fn finish(&mut self) -> T {
+        let mut out = T::default();
+        self.merge_into(&mut out);
+        self.reset();
+        out
+    }
+}
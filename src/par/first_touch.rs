@@ -0,0 +1,53 @@
+// Helpers for first-touch page placement. Most OSes back a page with physical memory on the node of the
+// thread that first writes to it. Zeroing large scratch buffers in parallel with the same chunking that the
+// bandwidth-bound passes use later means each worker mostly reads and writes memory local to its node.
+// With the raw backend, `numa::set_numa_aware` also keeps each chunk on the same node between passes.
+
+use std::mem::MaybeUninit;
+
+use bytemuck::Zeroable;
+
+use crate::par::Scheduler;
+
+/// Allocate a zeroed Vec of `len` elements, touching each `chunk_size` chunk from the scheduler's workers.
+#[inline(always)]
+pub fn first_touch_zeroed_vec<T>(scheduler: Scheduler, len: usize, chunk_size: usize) -> Vec<T>
+where
+    T: Zeroable + Send + Sync,
+{
+    let mut v = Vec::new();
+    first_touch_resize(scheduler, &mut v, len, chunk_size);
+    v
+}
+
+/// Resize `v` to `new_len`. Any newly allocated elements are zeroed in parallel using `chunk_size` chunks.
+#[inline(always)]
+pub fn first_touch_resize<T>(
+    scheduler: Scheduler,
+    v: &mut Vec<T>,
+    new_len: usize,
+    chunk_size: usize,
+) where
+    T: Zeroable + Send + Sync,
+{
+    let len = v.len();
+    if new_len <= len {
+        v.truncate(new_len);
+        return;
+    }
+
+    v.reserve_exact(new_len - len);
+    let spare = &mut v.spare_capacity_mut()[..new_len - len];
+    scheduler.par_chunks_mut(
+        spare,
+        &|_chunk_id: usize, chunk: &mut [MaybeUninit<T>]| {
+            for item in chunk.iter_mut() {
+                item.write(T::zeroed());
+            }
+        },
+        chunk_size,
+    );
+
+    // SAFETY: All elements in len..new_len were initialized above.
+    unsafe { v.set_len(new_len) };
+}
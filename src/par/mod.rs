@@ -1,5 +1,20 @@
-use std::{str::FromStr, sync::Once};
+use std::{
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        Mutex, MutexGuard, Once, PoisonError,
+    },
+};
 
+use crate::{
+    morton::SpaceFillingCurve,
+    ploc::{ploc_curve, ploc_scheduler, set_ploc_curve, set_ploc_scheduler},
+    radix::{radix_scheduler, set_radix_scheduler},
+};
+
+pub mod accumulator;
+pub mod first_touch;
+pub mod numa;
 pub mod par_bevy;
 pub mod par_chili;
 pub mod par_forte;
@@ -7,6 +22,9 @@ pub mod par_raw;
 pub mod par_rayon;
 pub mod par_rayon_join;
 pub mod par_sequential;
+pub mod race_iter;
+pub mod scratch;
+pub mod task_graph;
 
 static INIT: Once = Once::new();
 static mut AVAILABLE_PARALLELISM: usize = 1;
@@ -29,6 +47,151 @@ pub fn cached_available_parallelism() -> usize {
     unsafe { AVAILABLE_PARALLELISM }
 }
 
+/// Register the current thread with the profiler, once per thread. Threads the backend already named
+/// (raw, rayon, bevy) keep their name, anonymous workers (forte, chili) are registered as "{backend}-{n}".
+#[cfg(feature = "profile")]
+pub fn register_worker_thread(backend: &'static str) {
+    use std::{cell::Cell, sync::atomic::AtomicUsize};
+
+    thread_local! {
+        static REGISTERED: Cell<bool> = const { Cell::new(false) };
+    }
+    static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+    REGISTERED.with(|registered| {
+        if !registered.replace(true) {
+            let name = match std::thread::current().name() {
+                Some(name) => name.to_string(),
+                None => format!("{backend}-{}", NEXT_ID.fetch_add(1, Ordering::Relaxed)),
+            };
+            profiling::register_thread!(&name);
+        }
+    });
+}
+
+static DETERMINISTIC: AtomicBool = AtomicBool::new(false);
+
+/// When enabled, the Raw backend assigns each worker a fixed contiguous run of chunks with no stealing, so
+/// the chunk to thread mapping is reproducible between runs. Intended for profiling experiments. Forte
+/// can't pin a job to a worker, so it always steals.
+pub fn set_deterministic(enabled: bool) {
+    DETERMINISTIC.store(enabled, Ordering::Relaxed);
+}
+
+#[inline(always)]
+pub fn deterministic() -> bool {
+    DETERMINISTIC.load(Ordering::Relaxed)
+}
+
+/// How the Forte backend's `par_map` decides how far to split its input.
+#[derive(PartialEq, Eq, Default, Clone, Copy, Debug)]
+#[repr(u32)]
+pub enum SplitStrategy {
+    /// Split to a fixed depth of log2(chunks)
+    #[default]
+    Fixed = 0,
+    /// Rayon style splitting. Halve the split budget on each split, and reset the budget when a half is
+    /// stolen by another (idle) worker. Halves stop splitting at `threads` times finer than the fixed
+    /// chunks, so repeated steals don't go down to single items.
+    Adaptive = 1,
+}
+
+impl FromStr for SplitStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fixed" => Ok(Self::Fixed),
+            "adaptive" => Ok(Self::Adaptive),
+            _ => Err(format!(
+                "Unknown split strategy: '{s}', valid strategies: 'fixed', 'adaptive'"
+            )),
+        }
+    }
+}
+
+static SPLIT_STRATEGY: AtomicU32 = AtomicU32::new(SplitStrategy::Fixed as u32);
+
+pub fn set_split_strategy(strategy: SplitStrategy) {
+    SPLIT_STRATEGY.store(strategy as u32, Ordering::Relaxed);
+}
+
+#[inline(always)]
+pub fn split_strategy() -> SplitStrategy {
+    match SPLIT_STRATEGY.load(Ordering::Relaxed) {
+        1 => SplitStrategy::Adaptive,
+        _ => SplitStrategy::Fixed,
+    }
+}
+
+/// Read a scheduler from the environment variable `var`, falling back to `POOL_RACING_SCHEDULER`.
+/// Errors with the variable's name if the first one that's set isn't a known scheduler name.
+pub fn scheduler_from_env(var: &str) -> Result<Option<Scheduler>, String> {
+    for var in [var, "POOL_RACING_SCHEDULER"] {
+        if let Ok(value) = std::env::var(var) {
+            return value
+                .parse()
+                .map(Some)
+                .map_err(|err| format!("{var}: {err}"));
+        }
+    }
+    Ok(None)
+}
+
+/// [`scheduler_from_env`] or the default scheduler. An unknown scheduler name is reported through `log`,
+/// or stderr without the `log` feature, and the default is used instead.
+pub(crate) fn scheduler_from_env_or_default(var: &str) -> Scheduler {
+    scheduler_from_env(var)
+        .unwrap_or_else(|err| {
+            #[cfg(feature = "log")]
+            log::warn!("{err}, using the default scheduler");
+            #[cfg(not(feature = "log"))]
+            eprintln!("{err}, using the default scheduler");
+            None
+        })
+        .unwrap_or_default()
+}
+
+static SCHEDULER_GUARD_LOCK: Mutex<()> = Mutex::new(());
+
+/// Snapshot of the global ploc scheduler, radix scheduler and ploc curve, restored when dropped, also on
+/// panic. Only one guard is alive at a time, `new` waits for the others to drop, so code that changes
+/// the globals under a guard doesn't interleave with other guarded code.
+pub struct SchedulerGuard {
+    ploc: Scheduler,
+    radix: Scheduler,
+    curve: SpaceFillingCurve,
+    _lock: MutexGuard<'static, ()>,
+}
+
+impl SchedulerGuard {
+    pub fn new() -> Self {
+        let lock = SCHEDULER_GUARD_LOCK
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        Self {
+            ploc: ploc_scheduler(),
+            radix: radix_scheduler(),
+            curve: ploc_curve(),
+            _lock: lock,
+        }
+    }
+}
+
+impl Default for SchedulerGuard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for SchedulerGuard {
+    fn drop(&mut self) {
+        set_ploc_scheduler(self.ploc);
+        set_radix_scheduler(self.radix);
+        set_ploc_curve(self.curve);
+    }
+}
+
 // Used for now instead of features just for rust-analyzer
 #[derive(PartialEq, Eq, Default, Clone, Copy, Debug)]
 #[repr(u32)]
@@ -66,6 +229,31 @@ impl FromStr for Scheduler {
 }
 
 impl Scheduler {
+    pub const ALL: [Scheduler; 8] = [
+        Scheduler::SequentialOptimized,
+        Scheduler::Sequential,
+        Scheduler::Forte,
+        Scheduler::Chili,
+        Scheduler::Rayon,
+        Scheduler::RayonJoin,
+        Scheduler::Raw,
+        Scheduler::Bevy,
+    ];
+
+    /// Name as accepted by `from_str`
+    pub fn name(self) -> &'static str {
+        match self {
+            Scheduler::SequentialOptimized => "seq_opt",
+            Scheduler::Sequential => "seq",
+            Scheduler::Forte => "forte",
+            Scheduler::Chili => "chili",
+            Scheduler::Rayon => "rayon",
+            Scheduler::RayonJoin => "rayon_join",
+            Scheduler::Raw => "raw",
+            Scheduler::Bevy => "bevy",
+        }
+    }
+
     pub fn from(value: u32) -> Self {
         match value {
             0 => Scheduler::SequentialOptimized,
@@ -91,7 +279,7 @@ impl Scheduler {
             Scheduler::Sequential => par_sequential::par_map(data, func),
             Scheduler::Forte => par_forte::par_map(data, func, chunks),
             Scheduler::Chili => par_chili::par_map(data, func, chunks),
-            Scheduler::Rayon => par_rayon::par_map(data, func),
+            Scheduler::Rayon => par_rayon::par_map(data, func, chunks),
             Scheduler::RayonJoin => par_rayon_join::par_map(data, func, chunks),
             Scheduler::Raw => par_raw::par_map(data, func, chunks),
             Scheduler::Bevy => par_bevy::par_map(data, func, chunks),
@@ -146,6 +334,9 @@ impl Scheduler {
             Scheduler::Chili => {
                 par_chili::init_chili();
             }
+            Scheduler::Rayon | Scheduler::RayonJoin => {
+                par_rayon::init_rayon();
+            }
             Scheduler::Bevy => {
                 par_bevy::init_bevy();
             }
@@ -161,10 +352,32 @@ impl Scheduler {
             Scheduler::Sequential => 1,
             Scheduler::Forte => cached_available_parallelism(),
             Scheduler::Chili => cached_available_parallelism(),
-            Scheduler::Rayon => cached_available_parallelism(),
-            Scheduler::RayonJoin => cached_available_parallelism(),
+            Scheduler::Rayon => par_rayon::current_num_threads(),
+            Scheduler::RayonJoin => par_rayon::current_num_threads(),
             Scheduler::Raw => cached_available_parallelism(),
             Scheduler::Bevy => cached_available_parallelism(),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scheduler_from_env() {
+        // Variable names only used here so other tests reading the environment aren't affected
+        std::env::set_var("POOL_RACING_TEST_SCHEDULER", "seq");
+        assert_eq!(
+            scheduler_from_env("POOL_RACING_TEST_SCHEDULER"),
+            Ok(Some(Scheduler::Sequential))
+        );
+        std::env::set_var("POOL_RACING_TEST_BAD_SCHEDULER", "nope");
+        let err = scheduler_from_env("POOL_RACING_TEST_BAD_SCHEDULER").unwrap_err();
+        assert!(err.starts_with("POOL_RACING_TEST_BAD_SCHEDULER: "), "{err}");
+        assert_eq!(
+            scheduler_from_env_or_default("POOL_RACING_TEST_BAD_SCHEDULER"),
+            Scheduler::default()
+        );
+    }
+}
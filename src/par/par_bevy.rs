@@ -13,7 +13,10 @@ pub fn init_bevy() {
             let n = std::thread::available_parallelism()
                 .map(|n| n.get())
                 .unwrap_or(1);
-            let pool = TaskPoolBuilder::new().num_threads(n).build();
+            let pool = TaskPoolBuilder::new()
+                .num_threads(n)
+                .thread_name("bevy".to_string())
+                .build();
             COMPUTE = Some(pool);
         });
     }
@@ -41,6 +44,7 @@ where
         let chunk_count = (chunks as usize).max(1).min(max_chunks);
         let chunk_size = data.len().div_ceil(chunk_count);
         if chunk_count == 1 {
+            crate::chunk_scope!("bevy", 0, data.len());
             for (i, output) in data.iter_mut().enumerate() {
                 func(i, output);
             }
@@ -54,12 +58,14 @@ where
                         slice = right;
                         if chunk_id == chunk_count - 1 {
                             let start = chunk_id * chunk_size;
+                            crate::chunk_scope!("bevy", chunk_id, left.len());
                             for (i, output) in left.iter_mut().enumerate() {
                                 func(start + i, output);
                             }
                         } else {
                             s.spawn(async move {
                                 let start = chunk_id * chunk_size;
+                                crate::chunk_scope!("bevy", chunk_id, left.len());
                                 for (i, output) in left.iter_mut().enumerate() {
                                     func(start + i, output);
                                 }
@@ -82,6 +88,7 @@ where
         let chunk_size = chunk_size.max(1);
         let chunk_count = data.len().div_ceil(chunk_size);
         if chunk_count == 1 {
+            crate::chunk_scope!("bevy", 0, data.len());
             func(0, data)
         } else {
             with_bevy(|worker| {
@@ -92,9 +99,13 @@ where
                         let (left, right) = slice.split_at_mut(chunk_size.min(slice_len));
                         slice = right;
                         if chunk_id == chunk_count - 1 {
+                            crate::chunk_scope!("bevy", chunk_id, left.len());
                             func(chunk_id, left) // Run the last one on this thread
                         } else {
-                            s.spawn(async move { func(chunk_id, left) });
+                            s.spawn(async move {
+                                crate::chunk_scope!("bevy", chunk_id, left.len());
+                                func(chunk_id, left)
+                            });
                         }
                     }
                 });
@@ -113,6 +124,7 @@ where
         let chunk_size = chunk_size.max(1);
         let chunk_count = data.len().div_ceil(chunk_size);
         if chunk_count == 1 {
+            crate::chunk_scope!("bevy", 0, data.len());
             func(0, data)
         } else {
             with_bevy(|worker| {
@@ -123,9 +135,13 @@ where
                         let (left, right) = slice.split_at(chunk_size.min(slice_len));
                         slice = right;
                         if chunk_id == chunk_count - 1 {
+                            crate::chunk_scope!("bevy", chunk_id, left.len());
                             func(chunk_id, left) // Run the last one on this thread
                         } else {
-                            s.spawn(async move { func(chunk_id, left) });
+                            s.spawn(async move {
+                                crate::chunk_scope!("bevy", chunk_id, left.len());
+                                func(chunk_id, left)
+                            });
                         }
                     }
                 });
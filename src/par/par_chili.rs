@@ -1,23 +1,23 @@
 use std::sync::Once;
 
-static mut COMPUTE: Option<chili::Scope> = None;
 static INIT: Once = Once::new();
 
 pub fn init_chili() {
-    unsafe {
-        INIT.call_once(|| {
-            COMPUTE = Some(chili::Scope::global());
-        });
-    }
+    INIT.call_once(|| {
+        // Spin up the global pool ahead of the first dispatch
+        let _scope = chili::Scope::global();
+    });
 }
 
+/// Each dispatch gets its own scope on the global pool rather than sharing one `&mut chili::Scope`,
+/// so concurrent builds from different threads on the Chili backend don't alias.
 #[inline(always)]
 pub fn with_chili<F, R>(f: F) -> R
 where
     F: FnOnce(&mut chili::Scope) -> R,
 {
-    #[allow(static_mut_refs)]
-    f(unsafe { COMPUTE.as_mut().unwrap() }) // chat, is this ub?
+    let mut scope = chili::Scope::global();
+    f(&mut scope)
 }
 
 #[inline(always)]
@@ -38,6 +38,7 @@ where
         F: Fn(usize, &mut T) + Send + Sync,
     {
         if splits_left == 0 {
+            crate::chunk_scope!("chili", base_id, data.len());
             for (index, output) in data.iter_mut().enumerate() {
                 func(base_id + index, output);
             }
@@ -74,6 +75,7 @@ where
     {
         let len = slice.len();
         if len <= chunk_size {
+            crate::chunk_scope!("chili", start_chunk, len);
             func(start_chunk, slice);
         } else {
             let n_chunks = len.div_ceil(chunk_size);
@@ -115,6 +117,7 @@ where
     {
         let len = slice.len();
         if len <= chunk_size {
+            crate::chunk_scope!("chili", start_chunk, len);
             func(start_chunk, slice);
         } else {
             let n_chunks = len.div_ceil(chunk_size);
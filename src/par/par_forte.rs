@@ -1,3 +1,7 @@
+use std::thread;
+
+use crate::par::{cached_available_parallelism, split_strategy, SplitStrategy};
+
 pub static COMPUTE: forte::ThreadPool = forte::ThreadPool::new();
 
 #[inline(always)]
@@ -18,6 +22,7 @@ where
         F: Fn(usize, &mut T) + Send + Sync,
     {
         if splits_left == 0 {
+            crate::chunk_scope!("forte", base_id, data.len());
             for (index, output) in data.iter_mut().enumerate() {
                 func(base_id + index, output);
             }
@@ -30,12 +35,69 @@ where
             );
         }
     }
+    if split_strategy() == SplitStrategy::Adaptive {
+        let threads = cached_available_parallelism();
+        // Stolen halves may split up to `threads` times finer than the requested chunks, no further
+        let min_len = data
+            .len()
+            .div_ceil((chunks as usize).max(1) * threads)
+            .max(1);
+        COMPUTE.with_worker(|worker| {
+            adaptive_split(worker, data, &func, 0, chunks as usize, threads, min_len);
+        });
+        return;
+    }
     let splits = 31 - chunks.leading_zeros().max(1);
     COMPUTE.with_worker(|worker| {
         recursive_split(worker, data, &func, 0, splits);
     });
 }
 
+#[inline(always)]
+fn adaptive_split<T, F>(
+    worker: &forte::Worker,
+    data: &mut [T],
+    func: &F,
+    base_id: usize,
+    splits: usize,
+    threads: usize,
+    min_len: usize,
+) where
+    T: Send + Sync,
+    F: Fn(usize, &mut T) + Send + Sync,
+{
+    if splits == 0 || data.len() < 2 * min_len {
+        crate::chunk_scope!("forte", base_id, data.len());
+        for (index, output) in data.iter_mut().enumerate() {
+            func(base_id + index, output);
+        }
+    } else {
+        let split_id = data.len() / 2;
+        let (left, right) = data.split_at_mut(split_id);
+        let origin = thread::current().id();
+        worker.join(
+            |worker| adaptive_split(worker, left, func, base_id, splits / 2, threads, min_len),
+            |worker| {
+                // If this half was stolen another worker was idle, so allow it to keep splitting
+                let splits = if thread::current().id() != origin {
+                    (splits / 2).max(threads)
+                } else {
+                    splits / 2
+                };
+                adaptive_split(
+                    worker,
+                    right,
+                    func,
+                    base_id + split_id,
+                    splits,
+                    threads,
+                    min_len,
+                )
+            },
+        );
+    }
+}
+
 #[inline(always)]
 pub fn par_chunks_mut<T, F>(data: &mut [T], func: &F, chunk_size: usize)
 where
@@ -54,6 +116,7 @@ where
     {
         let len = slice.len();
         if len <= chunk_size {
+            crate::chunk_scope!("forte", start_chunk, len);
             func(start_chunk, slice);
         } else {
             let n_chunks = len.div_ceil(chunk_size);
@@ -95,6 +158,7 @@ where
     {
         let len = slice.len();
         if len <= chunk_size {
+            crate::chunk_scope!("forte", start_chunk, len);
             func(start_chunk, slice);
         } else {
             let n_chunks = len.div_ceil(chunk_size);
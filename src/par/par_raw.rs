@@ -1,15 +1,45 @@
 use std::thread;
 
-use crate::par::cached_available_parallelism;
+use crate::par::{cached_available_parallelism, deterministic, numa::pin_to_chunk_node};
 
 pub static COMPUTE: forte::ThreadPool = forte::ThreadPool::new();
 
+/// Spawn a scoped thread named "raw-{index}" so it is identifiable in profiler captures. It is pinned to
+/// the NUMA node of `index` out of `count` when NUMA aware placement is enabled, see `numa`.
+#[inline(always)]
+fn spawn_named<'scope, F>(s: &'scope thread::Scope<'scope, '_>, index: usize, count: usize, f: F)
+where
+    F: FnOnce() + Send + 'scope,
+{
+    thread::Builder::new()
+        .name(format!("raw-{index}"))
+        .spawn_scoped(s, move || {
+            pin_to_chunk_node(index, count);
+            f()
+        })
+        .expect("failed to spawn raw worker thread");
+}
+
 #[inline(always)]
 pub fn par_map<T, F>(data: &mut [T], func: &F, chunks: u32)
 where
     T: Send + Sync,
     F: Fn(usize, &mut T) + Send + Sync,
 {
+    if deterministic() {
+        let chunk_count = (chunks as usize).clamp(1, cached_available_parallelism());
+        let chunk_size = data.len().div_ceil(chunk_count);
+        return static_chunks_mut(
+            data,
+            &|chunk_id, chunk| {
+                let start = chunk_id * chunk_size;
+                for (i, output) in chunk.iter_mut().enumerate() {
+                    func(start + i, output);
+                }
+            },
+            chunk_size,
+        );
+    }
     if !data.is_empty() {
         // Limit the max number of chunks in this case since they are actual threads
         let max_chunks = cached_available_parallelism() * 6;
@@ -17,6 +47,7 @@ where
         let chunk_count = (chunks as usize).max(1).min(max_chunks);
         let chunk_size = data.len().div_ceil(chunk_count);
         if chunk_count == 1 {
+            crate::chunk_scope!("raw", 0, data.len());
             for (i, output) in data.iter_mut().enumerate() {
                 func(i, output);
             }
@@ -29,12 +60,14 @@ where
                     slice = right;
                     if chunk_id == chunk_count - 1 {
                         let start = chunk_id * chunk_size;
+                        crate::chunk_scope!("raw", chunk_id, left.len());
                         for (i, output) in left.iter_mut().enumerate() {
                             func(start + i, output);
                         }
                     } else {
-                        s.spawn(move || {
+                        spawn_named(s, chunk_id, chunk_count, move || {
                             let start = chunk_id * chunk_size;
+                            crate::chunk_scope!("raw", chunk_id, left.len());
                             for (i, output) in left.iter_mut().enumerate() {
                                 func(start + i, output);
                             }
@@ -52,10 +85,14 @@ where
     T: Send + Sync,
     F: Fn(usize, &mut [T]) + Send + Sync,
 {
+    if deterministic() {
+        return static_chunks_mut(data, func, chunk_size);
+    }
     if !data.is_empty() {
         let chunk_size = chunk_size.max(1);
         let chunk_count = data.len().div_ceil(chunk_size);
         if chunk_count == 1 {
+            crate::chunk_scope!("raw", 0, data.len());
             func(0, data)
         } else {
             thread::scope(|s| {
@@ -65,9 +102,13 @@ where
                     let (left, right) = slice.split_at_mut(chunk_size.min(slice_len));
                     slice = right;
                     if chunk_id == chunk_count - 1 {
+                        crate::chunk_scope!("raw", chunk_id, left.len());
                         func(chunk_id, left) // Run the last one on this thread
                     } else {
-                        s.spawn(move || func(chunk_id, left));
+                        spawn_named(s, chunk_id, chunk_count, move || {
+                            crate::chunk_scope!("raw", chunk_id, left.len());
+                            func(chunk_id, left)
+                        });
                     }
                 }
             });
@@ -81,10 +122,14 @@ where
     T: Send + Sync,
     F: Fn(usize, &[T]) + Send + Sync,
 {
+    if deterministic() {
+        return static_chunks(data, func, chunk_size);
+    }
     if !data.is_empty() {
         let chunk_size = chunk_size.max(1);
         let chunk_count = data.len().div_ceil(chunk_size);
         if chunk_count == 1 {
+            crate::chunk_scope!("raw", 0, data.len());
             func(0, data)
         } else {
             thread::scope(|s| {
@@ -94,9 +139,13 @@ where
                     let (left, right) = slice.split_at(chunk_size.min(slice_len));
                     slice = right;
                     if chunk_id == chunk_count - 1 {
+                        crate::chunk_scope!("raw", chunk_id, left.len());
                         func(chunk_id, left) // Run the last one on this thread
                     } else {
-                        s.spawn(move || func(chunk_id, left));
+                        spawn_named(s, chunk_id, chunk_count, move || {
+                            crate::chunk_scope!("raw", chunk_id, left.len());
+                            func(chunk_id, left)
+                        });
                     }
                 }
             });
@@ -104,6 +153,78 @@ where
     }
 }
 
+/// Split `data` into one contiguous run of whole chunks per available thread. Each worker always gets the
+/// same chunks for the same input size and runs them in order, with no stealing.
+#[inline(always)]
+pub fn static_chunks_mut<T, F>(data: &mut [T], func: &F, chunk_size: usize)
+where
+    T: Send + Sync,
+    F: Fn(usize, &mut [T]) + Send + Sync,
+{
+    if !data.is_empty() {
+        let chunk_size = chunk_size.max(1);
+        let chunk_count = data.len().div_ceil(chunk_size);
+        let workers = cached_available_parallelism().min(chunk_count);
+        let chunks_per_worker = chunk_count.div_ceil(workers);
+        thread::scope(|s| {
+            let mut slice = data;
+            for worker in 0..workers {
+                let slice_len = slice.len();
+                let (left, right) =
+                    slice.split_at_mut((chunks_per_worker * chunk_size).min(slice_len));
+                slice = right;
+                let first_chunk = worker * chunks_per_worker;
+                let mut run = move || {
+                    for (i, chunk) in left.chunks_mut(chunk_size).enumerate() {
+                        crate::chunk_scope!("raw", first_chunk + i, chunk.len());
+                        func(first_chunk + i, chunk);
+                    }
+                };
+                if worker == workers - 1 {
+                    run() // Run the last one on this thread
+                } else {
+                    spawn_named(s, worker, workers, run);
+                }
+            }
+        });
+    }
+}
+
+/// Immutable version of [`static_chunks_mut`].
+#[inline(always)]
+pub fn static_chunks<T, F>(data: &[T], func: &F, chunk_size: usize)
+where
+    T: Send + Sync,
+    F: Fn(usize, &[T]) + Send + Sync,
+{
+    if !data.is_empty() {
+        let chunk_size = chunk_size.max(1);
+        let chunk_count = data.len().div_ceil(chunk_size);
+        let workers = cached_available_parallelism().min(chunk_count);
+        let chunks_per_worker = chunk_count.div_ceil(workers);
+        thread::scope(|s| {
+            let mut slice = data;
+            for worker in 0..workers {
+                let slice_len = slice.len();
+                let (left, right) = slice.split_at((chunks_per_worker * chunk_size).min(slice_len));
+                slice = right;
+                let first_chunk = worker * chunks_per_worker;
+                let run = move || {
+                    for (i, chunk) in left.chunks(chunk_size).enumerate() {
+                        crate::chunk_scope!("raw", first_chunk + i, chunk.len());
+                        func(first_chunk + i, chunk);
+                    }
+                };
+                if worker == workers - 1 {
+                    run() // Run the last one on this thread
+                } else {
+                    spawn_named(s, worker, workers, run);
+                }
+            }
+        });
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -137,4 +258,20 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn test_static_chunks_mut_covers_all_chunks() {
+        for chunk_size in 1..24 {
+            for data_len in 1..24 {
+                let mut data = vec![0u32; data_len];
+                let func = |chunk_id: usize, chunk: &mut [u32]| {
+                    for (i, item) in chunk.iter_mut().enumerate() {
+                        *item = (chunk_id * chunk_size + i) as u32;
+                    }
+                };
+                static_chunks_mut(&mut data, &func, chunk_size);
+                assert!(data.iter().enumerate().all(|(i, d)| *d == i as u32));
+            }
+        }
+    }
 }
@@ -1,19 +1,79 @@
-use rayon::iter::IntoParallelRefMutIterator;
+use std::sync::{Arc, Once, RwLock};
+
 use rayon::slice::ParallelSlice;
 use rayon::{
     iter::{IndexedParallelIterator, ParallelIterator},
     slice::ParallelSliceMut,
+    ThreadPool,
 };
 
+use crate::par::cached_available_parallelism;
+
+static POOL: RwLock<Option<Arc<ThreadPool>>> = RwLock::new(None);
+static INIT: Once = Once::new();
+
+/// Build the rayon global pool with named threads ("rayon-{n}"). Does nothing if the global pool was
+/// already initialized elsewhere.
+pub fn init_rayon() {
+    INIT.call_once(|| {
+        let _ = rayon::ThreadPoolBuilder::new()
+            .thread_name(|i| format!("rayon-{i}"))
+            .build_global();
+    });
+}
+
+/// Route Rayon and RayonJoin work into a caller provided pool instead of the rayon global pool.
+/// Pass None to go back to using the global pool.
+pub fn set_rayon_pool(pool: Option<Arc<ThreadPool>>) {
+    *POOL.write().unwrap() = pool;
+}
+
+pub fn rayon_pool() -> Option<Arc<ThreadPool>> {
+    POOL.read().unwrap().clone()
+}
+
+/// Run `f` inside the user provided pool if there is one, otherwise on the global pool.
+#[inline(always)]
+pub fn with_rayon<F, R>(f: F) -> R
+where
+    F: FnOnce() -> R + Send,
+    R: Send,
+{
+    match rayon_pool() {
+        Some(pool) => pool.install(f),
+        None => f(),
+    }
+}
+
+pub fn current_num_threads() -> usize {
+    match rayon_pool() {
+        Some(pool) => pool.current_num_threads(),
+        None => cached_available_parallelism(),
+    }
+}
+
+/// Split into `chunks` chunks like the other backends, so each chunk gets a profiling scope. Rayon still
+/// balances the chunks across its workers.
 #[inline(always)]
-pub fn par_map<T, F>(data: &mut [T], func: &F)
+pub fn par_map<T, F>(data: &mut [T], func: &F, chunks: u32)
 where
     T: Send + Sync,
     F: Fn(usize, &mut T) + Send + Sync,
 {
-    data.par_iter_mut()
-        .enumerate()
-        .for_each(|(index, item)| func(index, item));
+    if !data.is_empty() {
+        let chunk_size = data.len().div_ceil((chunks as usize).max(1));
+        with_rayon(|| {
+            data.par_chunks_mut(chunk_size)
+                .enumerate()
+                .for_each(|(chunk_index, chunk)| {
+                    crate::chunk_scope!("rayon", chunk_index, chunk.len());
+                    let start = chunk_index * chunk_size;
+                    for (i, item) in chunk.iter_mut().enumerate() {
+                        func(start + i, item);
+                    }
+                })
+        });
+    }
 }
 
 #[inline(always)]
@@ -23,9 +83,14 @@ where
     F: Fn(usize, &mut [T]) + Send + Sync,
 {
     if !data.is_empty() {
-        data.par_chunks_mut(chunk_size.max(1))
-            .enumerate()
-            .for_each(|(chunk_index, chunk)| func(chunk_index, chunk));
+        with_rayon(|| {
+            data.par_chunks_mut(chunk_size.max(1))
+                .enumerate()
+                .for_each(|(chunk_index, chunk)| {
+                    crate::chunk_scope!("rayon", chunk_index, chunk.len());
+                    func(chunk_index, chunk)
+                })
+        });
     }
 }
 
@@ -36,8 +101,13 @@ where
     F: Fn(usize, &[T]) + Send + Sync,
 {
     if !data.is_empty() {
-        data.par_chunks(chunk_size.max(1))
-            .enumerate()
-            .for_each(|(chunk_index, chunk)| func(chunk_index, chunk));
+        with_rayon(|| {
+            data.par_chunks(chunk_size.max(1))
+                .enumerate()
+                .for_each(|(chunk_index, chunk)| {
+                    crate::chunk_scope!("rayon", chunk_index, chunk.len());
+                    func(chunk_index, chunk)
+                })
+        });
     }
 }
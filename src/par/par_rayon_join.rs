@@ -1,3 +1,5 @@
+use crate::par::par_rayon::with_rayon;
+
 pub static COMPUTE: forte::ThreadPool = forte::ThreadPool::new();
 
 #[inline(always)]
@@ -13,6 +15,7 @@ where
         F: Fn(usize, &mut T) + Send + Sync,
     {
         if splits_left == 0 {
+            crate::chunk_scope!("rayon_join", base_id, data.len());
             for (index, output) in data.iter_mut().enumerate() {
                 func(base_id + index, output);
             }
@@ -26,7 +29,7 @@ where
         }
     }
     let splits = 31 - chunks.leading_zeros().max(1);
-    recursive_split(data, &func, 0, splits);
+    with_rayon(|| recursive_split(data, &func, 0, splits));
 }
 
 #[inline(always)]
@@ -42,6 +45,7 @@ where
     {
         let len = slice.len();
         if len <= chunk_size {
+            crate::chunk_scope!("rayon_join", start_chunk, len);
             func(start_chunk, slice);
         } else {
             let n_chunks = len.div_ceil(chunk_size);
@@ -57,7 +61,7 @@ where
         }
     }
     if !data.is_empty() {
-        recursive_split(0, data, func, chunk_size.max(1));
+        with_rayon(|| recursive_split(0, data, func, chunk_size.max(1)));
     }
 }
 
@@ -74,6 +78,7 @@ where
     {
         let len = slice.len();
         if len <= chunk_size {
+            crate::chunk_scope!("rayon_join", start_chunk, len);
             func(start_chunk, slice);
         } else {
             let n_chunks = len.div_ceil(chunk_size);
@@ -89,6 +94,6 @@ where
         }
     }
     if !data.is_empty() {
-        recursive_split(0, data, func, chunk_size.max(1));
+        with_rayon(|| recursive_split(0, data, func, chunk_size.max(1)));
     }
 }
@@ -4,6 +4,7 @@ where
     T: Send + Sync,
     F: Fn(usize, &mut T) + Send + Sync,
 {
+    crate::chunk_scope!("seq", 0, data.len());
     for (index, output) in data.iter_mut().enumerate() {
         func(index, output);
     }
@@ -22,6 +23,7 @@ where
     {
         let len = slice.len();
         if len <= chunk_size {
+            crate::chunk_scope!("seq", start_chunk, len);
             func(start_chunk, slice);
         } else {
             let n_chunks = len.div_ceil(chunk_size);
@@ -52,6 +54,7 @@ where
     {
         let len = slice.len();
         if len <= chunk_size {
+            crate::chunk_scope!("seq", start_chunk, len);
             func(start_chunk, slice);
         } else {
             let n_chunks = len.div_ceil(chunk_size);
@@ -0,0 +1,121 @@
+// Iterator style wrappers over the Scheduler entry points, so call sites can write
+// `data.race_iter_mut(sch).for_each(|x| ...)` instead of passing closures and chunk counts directly.
+
+use crate::par::Scheduler;
+
+pub trait RaceIterExt<T: Send + Sync> {
+    fn race_iter(&self, scheduler: Scheduler) -> RaceIter<'_, T>;
+    fn race_iter_mut(&mut self, scheduler: Scheduler) -> RaceIterMut<'_, T>;
+}
+
+impl<T: Send + Sync> RaceIterExt<T> for [T] {
+    #[inline(always)]
+    fn race_iter(&self, scheduler: Scheduler) -> RaceIter<'_, T> {
+        RaceIter {
+            data: self,
+            scheduler,
+            chunks: scheduler.current_num_threads() as u32,
+        }
+    }
+
+    #[inline(always)]
+    fn race_iter_mut(&mut self, scheduler: Scheduler) -> RaceIterMut<'_, T> {
+        RaceIterMut {
+            data: self,
+            scheduler,
+            chunks: scheduler.current_num_threads() as u32,
+        }
+    }
+}
+
+pub struct RaceIter<'a, T> {
+    data: &'a [T],
+    scheduler: Scheduler,
+    chunks: u32,
+}
+
+impl<'a, T: Send + Sync> RaceIter<'a, T> {
+    /// How many chunks to split the work into. Defaults to the scheduler's thread count.
+    #[inline(always)]
+    pub fn chunks(mut self, chunks: u32) -> Self {
+        self.chunks = chunks.max(1);
+        self
+    }
+
+    #[inline(always)]
+    pub fn map<U, F: Fn(&T) -> U + Send + Sync>(self, func: F) -> RaceMap<'a, T, F> {
+        RaceMap { iter: self, func }
+    }
+
+    #[inline(always)]
+    pub fn for_each<F: Fn(usize, &T) + Send + Sync>(self, func: F) {
+        let chunk_size = self.data.len().div_ceil(self.chunks as usize);
+        self.scheduler.par_chunks(
+            self.data,
+            &|chunk_id, chunk: &[T]| {
+                let start = chunk_id * chunk_size;
+                for (i, item) in chunk.iter().enumerate() {
+                    func(start + i, item);
+                }
+            },
+            chunk_size,
+        );
+    }
+}
+
+pub struct RaceMap<'a, T, F> {
+    iter: RaceIter<'a, T>,
+    func: F,
+}
+
+impl<T, U, F> RaceMap<'_, T, F>
+where
+    T: Send + Sync,
+    U: Send + Sync,
+    F: Fn(&T) -> U + Send + Sync,
+{
+    #[inline(always)]
+    pub fn for_each<G: Fn(usize, U) + Send + Sync>(self, consume: G) {
+        let func = self.func;
+        self.iter.for_each(|i, item| consume(i, func(item)));
+    }
+
+    /// Write each mapped item into the matching index of `output`, which must be the same length as the input.
+    #[inline(always)]
+    pub fn collect_into(self, output: &mut [U]) {
+        assert_eq!(self.iter.data.len(), output.len());
+        let data = self.iter.data;
+        let func = self.func;
+        self.iter.scheduler.par_map(
+            output,
+            &|i, out: &mut U| *out = func(&data[i]),
+            self.iter.chunks,
+        );
+    }
+}
+
+pub struct RaceIterMut<'a, T> {
+    data: &'a mut [T],
+    scheduler: Scheduler,
+    chunks: u32,
+}
+
+impl<T: Send + Sync> RaceIterMut<'_, T> {
+    /// How many chunks to split the work into. Defaults to the scheduler's thread count.
+    #[inline(always)]
+    pub fn chunks(mut self, chunks: u32) -> Self {
+        self.chunks = chunks.max(1);
+        self
+    }
+
+    #[inline(always)]
+    pub fn for_each<F: Fn(usize, &mut T) + Send + Sync>(self, func: F) {
+        self.scheduler.par_map(self.data, &func, self.chunks);
+    }
+
+    /// Run `func` once per chunk of `chunk_size` items, with the chunk id.
+    #[inline(always)]
+    pub fn for_each_chunk<F: Fn(usize, &mut [T]) + Send + Sync>(self, chunk_size: usize, func: F) {
+        self.scheduler.par_chunks_mut(self.data, &func, chunk_size);
+    }
+}
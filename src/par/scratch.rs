@@ -0,0 +1,48 @@
+use std::cell::RefCell;
+
+use thread_local::ThreadLocal;
+
+/// Per-thread reusable scratch values. Each worker checks out its own `T` for the duration of a chunk, so
+/// allocations inside `T` (Vecs etc...) are reused between chunks and between dispatches. Works with any
+/// backend since it only relies on the identity of the thread running the chunk.
+pub struct ScratchPool<T: Send> {
+    buffers: ThreadLocal<RefCell<T>>,
+}
+
+impl<T: Send> Default for ScratchPool<T> {
+    fn default() -> Self {
+        Self {
+            buffers: ThreadLocal::default(),
+        }
+    }
+}
+
+impl<T: Send + Default> ScratchPool<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Check out this thread's scratch value for the duration of `f`.
+    #[inline(always)]
+    pub fn with<R>(&self, f: impl FnOnce(&mut T) -> R) -> R {
+        let cell = self.buffers.get_or_default();
+        match cell.try_borrow_mut() {
+            Ok(mut scratch) => f(&mut scratch),
+            // A backend may run a nested chunk on the same thread while this one is still checked out
+            // (join running both sides inline, etc...). Hand out a temporary instead of panicking.
+            Err(_) => f(&mut T::default()),
+        }
+    }
+}
+
+impl<T: Send> ScratchPool<T> {
+    /// All scratch values created so far, one per thread that has used the pool.
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut T> {
+        self.buffers.iter_mut().map(|b| b.get_mut())
+    }
+
+    /// Drop all scratch values, releasing their memory.
+    pub fn clear(&mut self) {
+        self.buffers.clear();
+    }
+}
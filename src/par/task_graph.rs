@@ -0,0 +1,115 @@
+// A small dependency graph executor on top of Scheduler. Tasks are run in waves: every task whose
+// dependencies have all finished is dispatched together with par_map, then the next wave is collected.
+// This doesn't start a task the moment it becomes ready, but it only uses the entry points every backend
+// already has.
+
+use crate::par::Scheduler;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct TaskId(usize);
+
+struct Job<'a>(Option<Box<dyn FnOnce() + Send + 'a>>);
+
+// SAFETY: A Job is only ever accessed through the &mut handed out by par_map, never shared between threads.
+unsafe impl Sync for Job<'_> {}
+
+#[derive(Default)]
+pub struct TaskGraph<'a> {
+    jobs: Vec<Job<'a>>,
+    dependents: Vec<Vec<usize>>,
+    dependency_counts: Vec<usize>,
+}
+
+impl<'a> TaskGraph<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_task<F: FnOnce() + Send + 'a>(&mut self, func: F) -> TaskId {
+        self.jobs.push(Job(Some(Box::new(func))));
+        self.dependents.push(Vec::new());
+        self.dependency_counts.push(0);
+        TaskId(self.jobs.len() - 1)
+    }
+
+    /// Add a task that will only run after all of `dependencies` have finished.
+    pub fn add_task_after<F: FnOnce() + Send + 'a>(
+        &mut self,
+        dependencies: &[TaskId],
+        func: F,
+    ) -> TaskId {
+        let task = self.add_task(func);
+        for &dependency in dependencies {
+            self.add_dependency(task, dependency);
+        }
+        task
+    }
+
+    /// `task` will only run after `dependency` has finished.
+    pub fn add_dependency(&mut self, task: TaskId, dependency: TaskId) {
+        self.dependents[dependency.0].push(task.0);
+        self.dependency_counts[task.0] += 1;
+    }
+
+    pub fn len(&self) -> usize {
+        self.jobs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.jobs.is_empty()
+    }
+
+    /// Run all tasks on `scheduler`, respecting dependencies. Panics if the graph contains a cycle.
+    pub fn run(mut self, scheduler: Scheduler) {
+        let mut ready: Vec<usize> = (0..self.jobs.len())
+            .filter(|&i| self.dependency_counts[i] == 0)
+            .collect();
+        let mut finished = 0;
+
+        while !ready.is_empty() {
+            let mut wave: Vec<Job> = ready.iter().map(|&i| Job(self.jobs[i].0.take())).collect();
+            let chunks = wave.len() as u32;
+            scheduler.par_map(
+                &mut wave,
+                &|_, job: &mut Job| {
+                    if let Some(func) = job.0.take() {
+                        func();
+                    }
+                },
+                chunks,
+            );
+            finished += wave.len();
+
+            let mut next = Vec::new();
+            for &i in &ready {
+                for &dependent in &self.dependents[i] {
+                    self.dependency_counts[dependent] -= 1;
+                    if self.dependency_counts[dependent] == 0 {
+                        next.push(dependent);
+                    }
+                }
+            }
+            ready = next;
+        }
+
+        assert_eq!(finished, self.jobs.len(), "task graph contains a cycle");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[test]
+    fn test_task_graph_respects_dependencies() {
+        let order = Mutex::new(Vec::new());
+        let mut graph = TaskGraph::new();
+        let a = graph.add_task(|| order.lock().unwrap().push(0));
+        let b = graph.add_task_after(&[a], || order.lock().unwrap().push(1));
+        let c = graph.add_task_after(&[a], || order.lock().unwrap().push(1));
+        graph.add_task_after(&[b, c], || order.lock().unwrap().push(2));
+        graph.run(Scheduler::Sequential);
+        assert_eq!(*order.lock().unwrap(), vec![0, 1, 1, 2]);
+    }
+}
@@ -4,59 +4,200 @@
 // https://github.com/madmann91/bvh/blob/v1/include/bvh/locally_ordered_clustering_builder.hpp
 
 use std::{
-    cell::RefCell,
-    mem,
-    sync::atomic::{AtomicU32, Ordering},
+    collections::TryReserveError,
+    fmt, mem,
+    sync::atomic::{AtomicBool, AtomicU32, Ordering},
+    time::{Duration, Instant},
 };
 
 use crate::{
     bvh::{Bvh2, Bvh2Node},
-    radix::radix_key::RadixKey,
-    scope, scope_print, scope_print_major, Args, Scheduler,
+    morton::{sort_nodes_m128, sort_nodes_m64, SpaceFillingCurve},
+    par::{
+        accumulator::ThreadLocalAccumulator,
+        first_touch::{first_touch_resize, first_touch_zeroed_vec},
+        scheduler_from_env_or_default,
+    },
+    radix::{radix_key::KeyValue, sorter::Sorter},
+    scope, scope_print, scope_print_major, Scheduler,
 };
 
-use bytemuck::{zeroed_vec, Zeroable};
-use obvhs::{aabb::Aabb, ploc::morton::morton_encode_u64_unorm};
-
-use glam::*;
-use thread_local::ThreadLocal;
+use glam::Vec3A;
+use obvhs::aabb::Aabb;
 
 static PLOC_SCHEDULER: AtomicU32 = AtomicU32::new(0);
+static PLOC_CURVE: AtomicU32 = AtomicU32::new(SpaceFillingCurve::Morton as u32);
+static PLOC_SCHEDULER_INITIALIZED: AtomicBool = AtomicBool::new(false);
 
 pub fn ploc_scheduler() -> Scheduler {
     Scheduler::from(PLOC_SCHEDULER.load(Ordering::Relaxed))
 }
 
+/// Order primitives along `curve` before clustering, in builders created from now on. Existing builders
+/// keep their `PlocBuilder::curve`.
+pub fn set_ploc_curve(curve: SpaceFillingCurve) {
+    PLOC_CURVE.store(curve as u32, Ordering::Relaxed);
+}
+
+pub fn ploc_curve() -> SpaceFillingCurve {
+    match PLOC_CURVE.load(Ordering::Relaxed) {
+        1 => SpaceFillingCurve::Hilbert,
+        2 => SpaceFillingCurve::Morton128,
+        _ => SpaceFillingCurve::Morton,
+    }
+}
+
+/// Use `scheduler` for ploc, ignoring environment variables from now on.
+pub fn set_ploc_scheduler(scheduler: Scheduler) {
+    scheduler.init();
+    PLOC_SCHEDULER.store(scheduler as u32, Ordering::Relaxed);
+    PLOC_SCHEDULER_INITIALIZED.store(true, Ordering::Relaxed);
+}
+
+/// Select the ploc scheduler the first time it's called, unless [`set_ploc_scheduler`] was already used.
+/// Falls back to `POOL_RACING_PLOC_SCHEDULER`, `POOL_RACING_SCHEDULER`, then the default. Command line
+/// args are never parsed here, see `Args::apply` with the `cli` feature for that.
 pub fn init_ploc_scheduler() {
     scope!("init_ploc_scheduler");
-    let config: Args = argh::from_env();
-    config.ploc_sch.init();
-    PLOC_SCHEDULER.store(config.ploc_sch as u32, Ordering::Relaxed);
+    if PLOC_SCHEDULER_INITIALIZED.load(Ordering::Relaxed) {
+        return;
+    }
+    let scheduler = scheduler_from_env_or_default("POOL_RACING_PLOC_SCHEDULER");
+    set_ploc_scheduler(scheduler);
 }
 
 // Holds allocations so they can be reused and are profiled separately.
 pub struct PlocBuilder {
     pub current_nodes: Vec<Bvh2Node>,
     pub next_nodes: Vec<Bvh2Node>,
-    pub sorted_nodes: Vec<Bvh2Node>,
     pub merge: Vec<i8>,
-    pub mortons: Vec<Morton64>,
-    pub local_aabbs: ThreadLocal<RefCell<Aabb>>,
+    pub mortons: Vec<KeyValue<u64, Bvh2Node>>,
+    pub sorter: Sorter<KeyValue<u64, Bvh2Node>>,
+    /// Only used with [`SpaceFillingCurve::Morton128`]
+    pub mortons128: Vec<KeyValue<u128, Bvh2Node>>,
+    pub sorter128: Sorter<KeyValue<u128, Bvh2Node>>,
+    pub local_aabbs: ThreadLocalAccumulator<Aabb>,
+    /// The curve builds order primitives along, taken from [`ploc_curve`] when the builder is created.
+    /// Decides which of `mortons` and `mortons128` is used.
+    pub curve: SpaceFillingCurve,
+    /// Scheduler used for builds, including the radix sort. When None the configured ploc scheduler is
+    /// used.
+    pub scheduler: Option<Scheduler>,
 }
 
 impl PlocBuilder {
     pub fn preallocate_builder(leaf_count: usize) -> PlocBuilder {
+        init_ploc_scheduler();
+        Self::preallocate_inner(ploc_scheduler(), leaf_count, None)
+    }
+
+    /// `preallocate_builder` for a builder that always builds on `sch`, regardless of the configured ploc
+    /// scheduler.
+    pub fn with_scheduler(sch: Scheduler, leaf_count: usize) -> PlocBuilder {
+        Self::preallocate_inner(sch, leaf_count, Some(sch))
+    }
+
+    fn preallocate_inner(
+        sch: Scheduler,
+        leaf_count: usize,
+        scheduler: Option<Scheduler>,
+    ) -> PlocBuilder {
         scope_print_major!("preallocate_builder");
+        // Touch the scratch memory with the same chunking the build passes use
+        let chunk_size = leaf_count / sch.current_num_threads();
+        let curve = ploc_curve();
         PlocBuilder {
-            current_nodes: zeroed_vec(leaf_count),
-            next_nodes: zeroed_vec(leaf_count),
-            sorted_nodes: zeroed_vec(leaf_count),
-            merge: zeroed_vec(leaf_count),
-            mortons: zeroed_vec(leaf_count),
-            local_aabbs: ThreadLocal::default(),
+            current_nodes: first_touch_zeroed_vec(sch, leaf_count, chunk_size),
+            next_nodes: first_touch_zeroed_vec(sch, leaf_count, chunk_size),
+            merge: first_touch_zeroed_vec(sch, leaf_count, chunk_size),
+            mortons: first_touch_zeroed_vec(sch, leaf_count, chunk_size),
+            sorter: Sorter::new(),
+            mortons128: if curve == SpaceFillingCurve::Morton128 {
+                first_touch_zeroed_vec(sch, leaf_count, chunk_size)
+            } else {
+                Vec::new()
+            },
+            sorter128: Sorter::new(),
+            local_aabbs: ThreadLocalAccumulator::default(),
+            curve,
+            scheduler,
         }
     }
 
+    /// Same as `preallocate_builder`.
+    pub fn with_capacity(leaf_count: usize) -> PlocBuilder {
+        Self::preallocate_builder(leaf_count)
+    }
+
+    /// The builder's scheduler, or the configured ploc scheduler if it has none.
+    pub fn scheduler(&self) -> Scheduler {
+        self.scheduler.unwrap_or_else(|| {
+            init_ploc_scheduler();
+            ploc_scheduler()
+        })
+    }
+
+    /// Number of leaves that can be built without growing the scratch buffers. The radix sorters' own
+    /// buffers are not included.
+    pub fn capacity(&self) -> usize {
+        let codes = if self.curve == SpaceFillingCurve::Morton128 {
+            self.mortons128.capacity()
+        } else {
+            self.mortons.capacity()
+        };
+        self.current_nodes
+            .capacity()
+            .min(self.next_nodes.capacity())
+            .min(self.merge.capacity())
+            .min(codes)
+    }
+
+    /// Grow the scratch buffers ahead of a build of `leaf_count` leaves, touching the new memory from
+    /// the ploc scheduler's workers like `preallocate_builder` does. Buffers that are already large
+    /// enough are left alone.
+    pub fn reserve(&mut self, leaf_count: usize) {
+        scope_print_major!("reserve builder");
+        let sch = self.scheduler();
+        let chunk_size = leaf_count / sch.current_num_threads();
+        let len = |current: usize| current.max(leaf_count);
+        first_touch_resize(
+            sch,
+            &mut self.current_nodes,
+            len(self.current_nodes.len()),
+            chunk_size,
+        );
+        first_touch_resize(
+            sch,
+            &mut self.next_nodes,
+            len(self.next_nodes.len()),
+            chunk_size,
+        );
+        first_touch_resize(sch, &mut self.merge, len(self.merge.len()), chunk_size);
+        if self.curve == SpaceFillingCurve::Morton128 {
+            first_touch_resize(
+                sch,
+                &mut self.mortons128,
+                len(self.mortons128.len()),
+                chunk_size,
+            );
+        } else {
+            first_touch_resize(sch, &mut self.mortons, len(self.mortons.len()), chunk_size);
+        }
+    }
+
+    /// Release all scratch memory, including the radix sorters' buffers, e.g. after a level unload. The
+    /// next build allocates again as needed.
+    pub fn clear_and_free(&mut self) {
+        scope!("clear_and_free builder");
+        self.current_nodes = Vec::new();
+        self.next_nodes = Vec::new();
+        self.merge = Vec::new();
+        self.mortons = Vec::new();
+        self.mortons128 = Vec::new();
+        self.sorter.clear();
+        self.sorter128.clear();
+    }
+
     #[inline(always)]
     pub fn build_ploc(&mut self, aabbs: &[Aabb]) -> Bvh2 {
         let mut bvh = Bvh2::default();
@@ -66,24 +207,115 @@ impl PlocBuilder {
 
     #[inline(always)]
     pub fn rebuild_ploc(&mut self, aabbs: &[Aabb], bvh: &mut Bvh2) {
+        self.rebuild_ploc_inner(aabbs, vec_nodes(bvh), None);
+    }
+
+    /// Build into caller owned node memory, like a frame allocator or arena, instead of a `Bvh2`'s Vec.
+    /// Returns the first `node_count(aabbs.len())` nodes of `nodes`, which hold the bvh. Traverse them
+    /// with `bvh::traverse_nodes`. Panics if `nodes` is too short.
+    pub fn rebuild_ploc_into<'n>(
+        &mut self,
+        aabbs: &[Aabb],
+        nodes: &'n mut [Bvh2Node],
+    ) -> &'n mut [Bvh2Node] {
+        let count = node_count(aabbs.len());
+        assert!(
+            nodes.len() >= count,
+            "{} nodes is not enough for {} primitives, {count} are needed",
+            nodes.len(),
+            aabbs.len()
+        );
+        self.rebuild_ploc_inner(aabbs, move |_| &mut nodes[..count], None)
+    }
+
+    /// `build_ploc` that checks `aabbs` and reserves memory up front, returning an error instead of
+    /// panicking or building a corrupt bvh from bad input.
+    pub fn try_build_ploc(&mut self, aabbs: &[Aabb]) -> Result<Bvh2, BuildError> {
+        let mut bvh = Bvh2::default();
+        self.try_rebuild_ploc(aabbs, &mut bvh)?;
+        Ok(bvh)
+    }
+
+    /// `rebuild_ploc` that checks `aabbs` and reserves memory up front, returning an error instead of
+    /// panicking or building a corrupt bvh from bad input. `bvh` is left unchanged on error.
+    pub fn try_rebuild_ploc(&mut self, aabbs: &[Aabb], bvh: &mut Bvh2) -> Result<(), BuildError> {
+        scope!("try_rebuild_ploc");
+        check_input(aabbs)?;
+        let prim_count = aabbs.len();
+        try_reserve_len(&mut self.current_nodes, prim_count)?;
+        try_reserve_len(&mut self.next_nodes, prim_count)?;
+        try_reserve_len(&mut self.merge, prim_count)?;
+        if self.curve == SpaceFillingCurve::Morton128 {
+            try_reserve_len(&mut self.mortons128, prim_count)?;
+        } else {
+            try_reserve_len(&mut self.mortons, prim_count)?;
+        }
+        try_reserve_len(&mut bvh.nodes, node_count(prim_count))?;
+        self.rebuild_ploc(aabbs, bvh);
+        Ok(())
+    }
+
+    /// Refit `bvh` to a deformed mesh, like a skinned character each frame. `positions` are the updated
+    /// vertex positions and `indices` the original index buffer the bvh was built over, one triangle per
+    /// primitive. Leaf aabbs are computed in parallel on the ploc scheduler into the builder's scratch,
+    /// then the tree is refit bottom up. The tree structure is kept, so quality degrades as the mesh
+    /// moves further from its build pose.
+    pub fn refit_indexed(&mut self, bvh: &mut Bvh2, positions: &[Vec3A], indices: &[[u32; 3]]) {
+        scope!("refit_indexed");
+        let sch = self.scheduler();
+        if self.current_nodes.len() < indices.len() {
+            let chunk_size = indices.len() / sch.current_num_threads();
+            first_touch_resize(sch, &mut self.current_nodes, indices.len(), chunk_size);
+        }
+        let leaves = &mut self.current_nodes[..indices.len()];
+        sch.par_map(
+            leaves,
+            &|i, leaf| {
+                let [a, b, c] = indices[i].map(|v| positions[v as usize]);
+                leaf.aabb = Aabb::new(a.min(b).min(c), a.max(b).max(c));
+            },
+            sch.current_num_threads() as u32 * 4,
+        );
+        bvh.refit_with(|primitive| leaves[primitive].aabb);
+    }
+
+    /// `build_ploc` that also returns counters and timings of each build phase.
+    pub fn build_ploc_with_stats(&mut self, aabbs: &[Aabb]) -> (Bvh2, PlocStats) {
+        let mut bvh = Bvh2::default();
+        let stats = self.rebuild_ploc_with_stats(aabbs, &mut bvh);
+        (bvh, stats)
+    }
+
+    /// `rebuild_ploc` that also returns counters and timings of each build phase.
+    pub fn rebuild_ploc_with_stats(&mut self, aabbs: &[Aabb], bvh: &mut Bvh2) -> PlocStats {
+        let mut stats = PlocStats::default();
+        self.rebuild_ploc_inner(aabbs, vec_nodes(bvh), Some(&mut stats));
+        stats
+    }
+
+    /// `out_nodes` is called once with the node count and returns where to write the nodes, which are
+    /// returned again at the end.
+    #[inline(always)]
+    fn rebuild_ploc_inner<'n>(
+        &mut self,
+        aabbs: &[Aabb],
+        out_nodes: impl FnOnce(usize) -> &'n mut [Bvh2Node],
+        mut stats: Option<&mut PlocStats>,
+    ) -> &'n mut [Bvh2Node] {
         scope_print_major!("build_ploc");
-        init_ploc_scheduler();
+        let phase_start = stats.is_some().then(Instant::now);
+        // Read once so a scheduler change from another thread can't switch it mid build
+        let sch = self.scheduler();
 
         // How many workers per available_parallelism thread.
         // If tasks take an non-uniform amount of time more workers per thread can improve cpu utilization.
-        let default_chunk_count = ploc_scheduler().current_num_threads();
+        let default_chunk_count = sch.current_num_threads();
 
         let prim_count = aabbs.len();
 
-        if prim_count == 0 {
-            bvh.clear();
-        }
-
         let mut total_aabb = Aabb::empty();
 
-        for local_aabb in self.local_aabbs.iter_mut() {
-            *local_aabb = Default::default();
-        }
+        self.local_aabbs.reset();
 
         {
             scope_print_major!("init nodes");
@@ -107,7 +339,7 @@ impl PlocBuilder {
 
             let chunk_size = self.current_nodes.len() / default_chunk_count;
 
-            match ploc_scheduler() {
+            match sch {
                 Scheduler::SequentialOptimized => {
                     for (prim_index, aabb) in aabbs.iter().enumerate() {
                         total_aabb.extend(aabb.min).extend(aabb.max);
@@ -115,64 +347,81 @@ impl PlocBuilder {
                             init_node(prim_index, aabbs[prim_index], &mut total_aabb);
                     }
                 }
-                _ => ploc_scheduler().par_chunks_mut(
+                _ => sch.par_chunks_mut(
                     &mut self.current_nodes,
                     &|chunk_id: usize, nodes: &mut [Bvh2Node]| {
                         scope!("init_nodes closure");
                         let start = chunk_id * chunk_size;
-                        for (i, node) in nodes.iter_mut().enumerate() {
-                            let prim_index = start + i;
-                            *node = init_node(
-                                prim_index,
-                                aabbs[prim_index],
-                                &mut self.local_aabbs.get_or_default().borrow_mut(),
-                            );
-                        }
+                        self.local_aabbs.with(|local_aabb| {
+                            for (i, node) in nodes.iter_mut().enumerate() {
+                                let prim_index = start + i;
+                                *node = init_node(prim_index, aabbs[prim_index], local_aabb);
+                            }
+                        });
                     },
                     chunk_size,
                 ),
             }
 
-            if ploc_scheduler() != Scheduler::SequentialOptimized {
-                for local_aabb in self.local_aabbs.iter_mut() {
-                    total_aabb.extend(local_aabb.get_mut().min);
-                    total_aabb.extend(local_aabb.get_mut().max);
-                }
+            if sch != Scheduler::SequentialOptimized {
+                self.local_aabbs.merge_into(&mut total_aabb);
             }
         }
 
+        if let (Some(stats), Some(start)) = (stats.as_deref_mut(), phase_start) {
+            stats.prim_count = prim_count;
+            stats.init_time = start.elapsed();
+        }
+        let phase_start = stats.is_some().then(Instant::now);
+
         // Merge nodes until there is only one left
-        let nodes_count = (2 * prim_count as i64 - 1).max(0) as usize;
+        let nodes_count = node_count(prim_count);
 
         let scale = 1.0 / total_aabb.diagonal().as_dvec3();
         let offset = -total_aabb.min.as_dvec3() * scale;
 
-        {
-            scope!("resize sorted_nodes");
-            self.sorted_nodes
-                .resize(self.current_nodes.len(), Default::default());
-        };
+        // Sort primitives according to their morton (or hilbert) code
+        if self.curve == SpaceFillingCurve::Morton128 {
+            {
+                scope!("resize mortons");
+                self.mortons128
+                    .resize(self.current_nodes.len(), Default::default());
+            }
 
-        {
-            scope!("resize mortons");
-            self.mortons
-                .resize(self.current_nodes.len(), Default::default());
-        }
+            sort_nodes_m128(
+                sch,
+                &mut self.current_nodes,
+                &mut self.mortons128,
+                &mut self.sorter128,
+                scale,
+                offset,
+            );
+        } else {
+            {
+                scope!("resize mortons");
+                self.mortons
+                    .resize(self.current_nodes.len(), Default::default());
+            }
 
-        // Sort primitives according to their morton code
-        sort_nodes_m64(
-            &mut self.current_nodes,
-            &mut self.sorted_nodes,
-            &mut self.mortons,
-            scale,
-            offset,
-        );
+            sort_nodes_m64(
+                sch,
+                self.curve,
+                &mut self.current_nodes,
+                &mut self.mortons,
+                &mut self.sorter,
+                scale,
+                offset,
+            );
+        }
 
-        mem::swap(&mut self.current_nodes, &mut self.sorted_nodes);
+        if let (Some(stats), Some(start)) = (stats.as_deref_mut(), phase_start) {
+            stats.sort_time = start.elapsed();
+        }
+        let phase_start = stats.is_some().then(Instant::now);
 
-        {
+        let out_nodes = {
             scope!("resize nodes");
-            bvh.nodes.resize(nodes_count, Bvh2Node::default());
+            out_nodes(nodes_count)
         };
 
         let mut insert_index = nodes_count;
@@ -187,6 +436,7 @@ impl PlocBuilder {
         let mut depth: usize = 0;
         while self.current_nodes.len() > 1 {
             scope!("merge pass");
+            let clusters = self.current_nodes.len();
             let mut last_cost = f32::MAX;
             let count = self.current_nodes.len() - 1;
             assert!(count < self.merge.len()); // Try to elide bounds check
@@ -217,7 +467,7 @@ impl PlocBuilder {
                     }
                 };
 
-                match ploc_scheduler() {
+                match sch {
                     Scheduler::SequentialOptimized => (0..count).for_each(|i| {
                         let cost = self.current_nodes[i]
                             .aabb
@@ -226,11 +476,7 @@ impl PlocBuilder {
                         self.merge[i] = if last_cost < cost { -1 } else { 1 };
                         last_cost = cost;
                     }),
-                    _ => ploc_scheduler().par_chunks_mut(
-                        &mut self.merge[..count],
-                        &calculate_costs,
-                        chunk_size,
-                    ),
+                    _ => sch.par_chunks_mut(&mut self.merge[..count], &calculate_costs, chunk_size),
                 }
 
                 // Have the last box to always prefer the box before it since there is none after it
@@ -273,8 +519,8 @@ impl PlocBuilder {
                 });
 
                 // Out of bounds here error here could indicate NaN present in input aabb. Try running in debug mode.
-                bvh.nodes[insert_index] = left;
-                bvh.nodes[insert_index + 1] = right;
+                out_nodes[insert_index] = left;
+                out_nodes[insert_index + 1] = right;
 
                 if index_offset == 1 {
                     // Since search distance is only 1, and the next index was merged with this one,
@@ -288,70 +534,294 @@ impl PlocBuilder {
                 }
             }
 
+            if let Some(stats) = stats.as_deref_mut() {
+                let merged = clusters - self.next_nodes.len();
+                stats.merge_passes += 1;
+                stats.clusters_per_pass.push(clusters);
+                stats.merged_per_pass.push(merged);
+                stats.nodes_emitted_per_pass.push(merged * 2);
+                stats.scratch_bytes += self.next_nodes.len() * size_of::<Bvh2Node>();
+            }
+
             mem::swap(&mut self.current_nodes, &mut self.next_nodes);
             self.next_nodes.clear();
             depth += 1;
         }
 
         insert_index = insert_index.saturating_sub(1);
-        bvh.nodes[insert_index] = self.current_nodes[0];
+        out_nodes[insert_index] = self.current_nodes[0];
+
+        if let (Some(stats), Some(start)) = (stats, phase_start) {
+            stats.merge_time = start.elapsed();
+            let code_bytes = if self.curve == SpaceFillingCurve::Morton128 {
+                prim_count * size_of::<KeyValue<u128, Bvh2Node>>()
+            } else {
+                prim_count * size_of::<KeyValue<u64, Bvh2Node>>()
+            };
+            stats.scratch_bytes += prim_count * size_of::<Bvh2Node>() + code_bytes + prim_count;
+        }
+        out_nodes
     }
 }
 
-#[derive(Clone, Copy, Default, Zeroable)]
-pub struct Morton64 {
-    pub index: usize,
-    pub code: u64,
+/// Number of nodes in a bvh over `leaf_count` primitives.
+pub fn node_count(leaf_count: usize) -> usize {
+    (2 * leaf_count).saturating_sub(1)
 }
 
-impl RadixKey for Morton64 {
-    const LEVELS: usize = 8;
-    #[inline(always)]
-    fn get_level(&self, level: usize) -> u8 {
-        self.code.get_level(level)
+/// Resizes the bvh's nodes to the requested count.
+fn vec_nodes<'n>(bvh: &'n mut Bvh2) -> impl FnOnce(usize) -> &'n mut [Bvh2Node] + 'n {
+    move |count| {
+        bvh.nodes.resize(count, Bvh2Node::default());
+        &mut bvh.nodes[..]
+    }
+}
+
+/// Why `PlocBuilder::try_build_ploc` couldn't build a bvh.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BuildError {
+    /// There were no aabbs to build from.
+    EmptyInput,
+    /// The aabb of `primitive` has a NaN component.
+    NanBounds { primitive: usize },
+    /// More primitives than node indices can address.
+    TooManyPrimitives { count: usize, max: usize },
+    /// Reserving memory for the builder or the bvh failed.
+    AllocationFailed(TryReserveError),
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BuildError::EmptyInput => write!(f, "no primitives to build from"),
+            BuildError::NanBounds { primitive } => {
+                write!(f, "primitive {primitive} has NaN bounds")
+            }
+            BuildError::TooManyPrimitives { count, max } => {
+                write!(f, "{count} primitives is more than the maximum of {max}")
+            }
+            BuildError::AllocationFailed(err) => write!(f, "allocation failed: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for BuildError {}
+
+impl From<TryReserveError> for BuildError {
+    fn from(err: TryReserveError) -> Self {
+        BuildError::AllocationFailed(err)
     }
 }
 
-#[inline(always)]
-pub fn sort_nodes_m64(
-    current_nodes: &mut [Bvh2Node],
-    sorted_nodes: &mut [Bvh2Node],
-    mortons: &mut [Morton64],
-    scale: DVec3,
-    offset: DVec3,
-) {
-    scope_print_major!("sort_nodes_m64");
-    let chunk_size = ploc_scheduler().current_num_threads() as u32;
+/// Node indices are i32 and a bvh over n primitives has 2n - 1 nodes.
+pub const MAX_PRIMITIVES: usize = (i32::MAX as usize + 1) / 2;
+
+fn check_input(aabbs: &[Aabb]) -> Result<(), BuildError> {
+    if aabbs.is_empty() {
+        return Err(BuildError::EmptyInput);
+    }
+    if aabbs.len() > MAX_PRIMITIVES {
+        return Err(BuildError::TooManyPrimitives {
+            count: aabbs.len(),
+            max: MAX_PRIMITIVES,
+        });
+    }
+    if let Some(primitive) = aabbs
+        .iter()
+        .position(|aabb| aabb.min.is_nan() || aabb.max.is_nan())
     {
-        scope!("par generate Morton64s");
-        ploc_scheduler().par_map(
-            mortons,
-            &|index: usize, m: &mut Morton64| {
-                //scope!("generate Morton64s");
-                let center = current_nodes[index].aabb.center().as_dvec3() * scale + offset;
-                *m = Morton64 {
-                    index,
-                    code: morton_encode_u64_unorm(center),
-                };
-            },
-            chunk_size,
+        return Err(BuildError::NanBounds { primitive });
+    }
+    Ok(())
+}
+
+/// Make sure `v` can be resized to `len` without allocating.
+fn try_reserve_len<T>(v: &mut Vec<T>, len: usize) -> Result<(), TryReserveError> {
+    v.try_reserve(len.saturating_sub(v.len()))
+}
+
+/// Counters and timings of one ploc build, from `PlocBuilder::build_ploc_with_stats`.
+#[derive(Clone, Debug, Default)]
+pub struct PlocStats {
+    pub prim_count: usize,
+    pub merge_passes: usize,
+    /// Clusters at the start of each merge pass
+    pub clusters_per_pass: Vec<usize>,
+    /// Merges in each merge pass, each joins 2 clusters into a parent
+    pub merged_per_pass: Vec<usize>,
+    /// Nodes written to the bvh in each merge pass
+    pub nodes_emitted_per_pass: Vec<usize>,
+    /// Bytes of builder scratch memory written: the leaf nodes, space filling curve codes, merge
+    /// directions and each pass's next clusters. The radix sorter's own buffers are not included.
+    pub scratch_bytes: usize,
+    pub init_time: Duration,
+    pub sort_time: Duration,
+    pub merge_time: Duration,
+}
+
+impl fmt::Display for PlocStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "built {} primitives in {} merge passes, {} bytes of scratch",
+            self.prim_count, self.merge_passes, self.scratch_bytes
+        )?;
+        for (phase, time) in [
+            ("init", self.init_time),
+            ("sort", self.sort_time),
+            ("merge", self.merge_time),
+        ] {
+            writeln!(
+                f,
+                "  {phase:<6} {:>8}",
+                format!("{}", obvhs::PrettyDuration(time))
+            )?;
+        }
+        for (pass, (clusters, merged)) in self
+            .clusters_per_pass
+            .iter()
+            .zip(&self.merged_per_pass)
+            .enumerate()
+        {
+            writeln!(
+                f,
+                "  pass {pass:>3} {clusters:>10} clusters {merged:>10} merged"
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_ploc_with_stats() {
+        let aabbs: Vec<Aabb> = (0..100)
+            .map(|i| {
+                let p = Vec3A::new((i % 7) as f32, (i % 11) as f32, (i % 13) as f32);
+                Aabb::new(p, p + 0.5)
+            })
+            .collect();
+        let (bvh, stats) = PlocBuilder::with_scheduler(Scheduler::SequentialOptimized, aabbs.len())
+            .build_ploc_with_stats(&aabbs);
+        assert_eq!(bvh.validate(aabbs.len()), Ok(()));
+        assert_eq!(stats.prim_count, 100);
+        assert_eq!(stats.merge_passes, stats.merged_per_pass.len());
+        assert_eq!(stats.clusters_per_pass[0], 100);
+        assert_eq!(stats.merged_per_pass.iter().sum::<usize>(), 99);
+        assert_eq!(
+            stats.nodes_emitted_per_pass.iter().sum::<usize>(),
+            bvh.nodes.len() - 1
         );
     }
 
-    {
-        scope_print!("radix sort");
-        crate::radix::sorter::sort(mortons)
+    #[test]
+    fn test_builder_capacity() {
+        let mut builder = PlocBuilder::with_capacity(10);
+        assert!(builder.capacity() >= 10);
+        builder.reserve(50);
+        assert!(builder.capacity() >= 50);
+        builder.reserve(20);
+        assert!(builder.capacity() >= 50);
+
+        builder.clear_and_free();
+        assert_eq!(builder.capacity(), 0);
+        let aabbs: Vec<Aabb> = (0..30)
+            .map(|i| Aabb::new(Vec3A::splat(i as f32), Vec3A::splat(i as f32 + 1.0)))
+            .collect();
+        let bvh = builder.build_ploc(&aabbs);
+        assert_eq!(bvh.validate(30), Ok(()));
+
+        let mut builder = PlocBuilder::with_capacity(10);
+        builder.curve = SpaceFillingCurve::Morton128;
+        builder.reserve(40);
+        assert!(builder.mortons128.len() >= 40);
+        assert!(builder.capacity() >= 40);
+        let bvh = builder.build_ploc(&aabbs);
+        assert_eq!(bvh.validate(30), Ok(()));
     }
 
-    {
-        scope!("par copy back sorted");
-        ploc_scheduler().par_map(
-            sorted_nodes,
-            &|i: usize, n: &mut Bvh2Node| {
-                //scope!("copy back sorted");
-                *n = current_nodes[mortons[i].index]
-            },
-            chunk_size,
+    #[test]
+    fn test_builder_with_scheduler() {
+        let aabbs: Vec<Aabb> = (0..100)
+            .map(|i| Aabb::new(Vec3A::splat(i as f32), Vec3A::splat(i as f32 + 1.0)))
+            .collect();
+        for sch in Scheduler::ALL {
+            let mut builder = PlocBuilder::with_scheduler(sch, 0);
+            assert_eq!(builder.scheduler(), sch);
+            let bvh = builder.build_ploc(&aabbs);
+            assert_eq!(bvh.validate(100), Ok(()));
+        }
+    }
+
+    #[test]
+    fn test_rebuild_ploc_into() {
+        let aabbs: Vec<Aabb> = (0..40)
+            .map(|i| {
+                let p = Vec3A::new((i % 5) as f32, (i % 3) as f32, i as f32);
+                Aabb::new(p, p + 0.5)
+            })
+            .collect();
+        let mut builder = PlocBuilder::with_scheduler(Scheduler::SequentialOptimized, aabbs.len());
+        let expected = builder.build_ploc(&aabbs);
+        let mut arena = vec![Bvh2Node::default(); 100];
+        let nodes = builder.rebuild_ploc_into(&aabbs, &mut arena);
+        assert_eq!(nodes.len(), node_count(40));
+        let bvh = Bvh2 {
+            nodes: nodes.to_vec(),
+        };
+        assert_eq!(bvh.content_hash(), expected.content_hash());
+    }
+
+    #[test]
+    fn test_try_build_ploc() {
+        let mut builder = PlocBuilder::with_scheduler(Scheduler::SequentialOptimized, 3);
+        assert_eq!(
+            builder.try_build_ploc(&[]).err(),
+            Some(BuildError::EmptyInput)
         );
+
+        let mut aabbs = vec![Aabb::new(Vec3A::ZERO, Vec3A::ONE); 3];
+        aabbs[1].max.y = f32::NAN;
+        assert_eq!(
+            builder.try_build_ploc(&aabbs).err(),
+            Some(BuildError::NanBounds { primitive: 1 })
+        );
+
+        aabbs[1].max.y = 1.0;
+        let bvh = builder.try_build_ploc(&aabbs).unwrap();
+        assert_eq!(bvh.validate(3), Ok(()));
+    }
+
+    #[test]
+    fn test_refit_indexed() {
+        let mut positions: Vec<Vec3A> = (0..60)
+            .map(|i| Vec3A::new((i % 5) as f32, (i / 5 % 4) as f32, (i / 20) as f32))
+            .collect();
+        let indices: Vec<[u32; 3]> = (0..40).map(|i| [i, i + 1, i + 20]).collect();
+        let aabbs = |positions: &[Vec3A]| -> Vec<Aabb> {
+            indices
+                .iter()
+                .map(|t| {
+                    let [a, b, c] = t.map(|v| positions[v as usize]);
+                    Aabb::new(a.min(b).min(c), a.max(b).max(c))
+                })
+                .collect()
+        };
+        let mut builder =
+            PlocBuilder::with_scheduler(Scheduler::SequentialOptimized, indices.len());
+        let mut bvh = builder.build_ploc(&aabbs(&positions));
+
+        // Bend the mesh, the refit bvh matches refitting with the new aabbs
+        for p in &mut positions {
+            p.y += p.x * p.x * 0.25;
+        }
+        let mut expected = bvh.clone();
+        expected.refit(&aabbs(&positions));
+        builder.refit_indexed(&mut bvh, &positions, &indices);
+        assert_eq!(bvh.content_hash(), expected.content_hash());
+        assert_eq!(bvh.validate(indices.len()), Ok(()));
     }
 }
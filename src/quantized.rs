@@ -0,0 +1,142 @@
+//! Compressed triangle storage for huge scenes. Vertices are stored as 16 bit fixed point offsets within
+//! the bounds of their chunk of 64 consecutive triangles, 18 bytes per triangle plus 32 per chunk instead
+//! of 48 for `Triangle`. Decoding costs a multiply add per coordinate before the usual intersection, so
+//! whether it wins depends on how bandwidth bound traversal is, see the `traverse_quantized` groups of
+//! `quantized_traversal` in benches/traverse.rs.
+//!
+//! Precision is best when consecutive triangles are close together, like in mesh order. Vertices shared
+//! between chunks can decode to slightly different positions, leaving hairline cracks. Build or refit the
+//! bvh from `QuantizedTriangles::aabbs` so it bounds the decoded triangles.
+
+use glam::Vec3A;
+use obvhs::{aabb::Aabb, ray::Ray, triangle::Triangle};
+
+/// Triangles sharing one set of quantization bounds.
+pub const QUANTIZED_CHUNK: usize = 64;
+
+const STEPS: f32 = u16::MAX as f32;
+
+#[derive(Clone, Default)]
+pub struct QuantizedTriangles {
+    /// Min corner and step size of each chunk
+    chunks: Vec<(Vec3A, Vec3A)>,
+    /// x, y, z of v0, v1 and v2
+    vertices: Vec<[u16; 9]>,
+}
+
+impl QuantizedTriangles {
+    pub fn new(triangles: &[Triangle]) -> Self {
+        crate::scope!("quantize triangles");
+        let mut chunks = Vec::with_capacity(triangles.len().div_ceil(QUANTIZED_CHUNK));
+        let mut vertices = Vec::with_capacity(triangles.len());
+        for chunk in triangles.chunks(QUANTIZED_CHUNK) {
+            let (min, max) = chunk.iter().fold(
+                (Vec3A::splat(f32::MAX), Vec3A::splat(f32::MIN)),
+                |(min, max), t| {
+                    (
+                        min.min(t.v0).min(t.v1).min(t.v2),
+                        max.max(t.v0).max(t.v1).max(t.v2),
+                    )
+                },
+            );
+            let step = (max - min) / STEPS;
+            let inv_step = Vec3A::select(step.cmpgt(Vec3A::ZERO), step.recip(), Vec3A::ZERO);
+            let quantize = |v: Vec3A| {
+                let q = ((v - min) * inv_step)
+                    .round()
+                    .clamp(Vec3A::ZERO, Vec3A::splat(STEPS));
+                [q.x as u16, q.y as u16, q.z as u16]
+            };
+            chunks.push((min, step));
+            vertices.extend(chunk.iter().map(|t| {
+                let [a, b, c] = [quantize(t.v0), quantize(t.v1), quantize(t.v2)];
+                [a[0], a[1], a[2], b[0], b[1], b[2], c[0], c[1], c[2]]
+            }));
+        }
+        Self { chunks, vertices }
+    }
+
+    pub fn len(&self) -> usize {
+        self.vertices.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.vertices.is_empty()
+    }
+
+    /// Decode triangle `i`.
+    #[inline(always)]
+    pub fn get(&self, i: usize) -> Triangle {
+        let (min, step) = self.chunks[i / QUANTIZED_CHUNK];
+        let q = &self.vertices[i];
+        let vertex =
+            |o: usize| min + Vec3A::new(q[o] as f32, q[o + 1] as f32, q[o + 2] as f32) * step;
+        Triangle {
+            v0: vertex(0),
+            v1: vertex(3),
+            v2: vertex(6),
+        }
+    }
+
+    /// `Triangle::intersect` on decoded triangle `i`, for traversal intersection functions.
+    #[inline(always)]
+    pub fn intersect(&self, ray: &Ray, i: usize) -> f32 {
+        self.get(i).intersect(ray)
+    }
+
+    /// Aabbs of the decoded triangles, to build the bvh from.
+    pub fn aabbs(&self) -> Vec<Aabb> {
+        (0..self.len()).map(|i| self.get(i).aabb()).collect()
+    }
+
+    /// The largest distance along any axis between an original vertex and its decoded position in chunk
+    /// `chunk`, half a quantization step.
+    pub fn max_error(&self, chunk: usize) -> f32 {
+        self.chunks[chunk].1.max_element() * 0.5
+    }
+
+    /// Bytes used by the quantized triangles, not counting unused capacity.
+    pub fn memory_size(&self) -> usize {
+        self.chunks.len() * std::mem::size_of::<(Vec3A, Vec3A)>()
+            + self.vertices.len() * std::mem::size_of::<[u16; 9]>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        par::Scheduler,
+        ploc::PlocBuilder,
+        test_util::reference::{brute_force_closest_hit, random_rays, random_triangles, Rng},
+    };
+
+    #[test]
+    fn test_quantized_triangles() {
+        let mut rng = Rng::new(91);
+        let triangles = random_triangles(&mut rng, 300, 0.2);
+        let quantized = QuantizedTriangles::new(&triangles);
+        assert_eq!(quantized.len(), triangles.len());
+        assert!(quantized.memory_size() < triangles.len() * std::mem::size_of::<Triangle>() / 2);
+
+        for (i, t) in triangles.iter().enumerate() {
+            let d = quantized.get(i);
+            let error = quantized.max_error(i / QUANTIZED_CHUNK) * 1.01 + 1e-6;
+            for (a, b) in [(t.v0, d.v0), (t.v1, d.v1), (t.v2, d.v2)] {
+                assert!((a - b).abs().max_element() <= error);
+            }
+        }
+
+        // Traversal over a bvh of the decoded aabbs finds the closest decoded triangle
+        let decoded: Vec<_> = (0..quantized.len()).map(|i| quantized.get(i)).collect();
+        let bvh = PlocBuilder::with_scheduler(Scheduler::SequentialOptimized, decoded.len())
+            .build_ploc(&quantized.aabbs());
+        for ray in random_rays(&mut rng, 128) {
+            let mut traced = ray;
+            let mut id = u32::MAX;
+            bvh.traverse(&mut traced, &mut id, |ray, i| quantized.intersect(ray, i));
+            let expected = brute_force_closest_hit(&decoded, &ray);
+            assert_eq!((id != u32::MAX).then_some((id, traced.tmax)), expected);
+        }
+    }
+}
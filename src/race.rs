@@ -0,0 +1,450 @@
+// Run the same workload on each scheduler backend and collect timings.
+
+use std::{
+    fmt,
+    io::{self, Write},
+    time::{Duration, Instant},
+};
+
+use obvhs::{aabb::Aabb, ray::Ray, triangle::Triangle};
+
+use crate::{
+    bvh::Bvh2,
+    par::{Scheduler, SchedulerGuard},
+    ploc::{set_ploc_scheduler, PlocBuilder},
+    radix::set_radix_scheduler,
+    timings::{nearest_rank, write_csv_field, write_json_string},
+};
+
+pub struct RaceResult {
+    pub scheduler: Scheduler,
+    /// Time of each repetition, in the order they ran. Warm-up runs are not included.
+    pub times: Vec<Duration>,
+}
+
+impl RaceResult {
+    pub fn min(&self) -> Duration {
+        self.times.iter().copied().min().unwrap_or_default()
+    }
+
+    pub fn max(&self) -> Duration {
+        self.times.iter().copied().max().unwrap_or_default()
+    }
+
+    pub fn mean(&self) -> Duration {
+        if self.times.is_empty() {
+            return Duration::ZERO;
+        }
+        self.times.iter().sum::<Duration>() / self.times.len() as u32
+    }
+
+    pub fn median(&self) -> Duration {
+        let mut sorted = self.times.clone();
+        sorted.sort_unstable();
+        nearest_rank(&sorted, 0.5)
+    }
+}
+
+impl fmt::Display for RaceResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:>10} min {:>8} median {:>8} mean {:>8} max {:>8}",
+            self.scheduler.name(),
+            format!("{}", obvhs::PrettyDuration(self.min())),
+            format!("{}", obvhs::PrettyDuration(self.median())),
+            format!("{}", obvhs::PrettyDuration(self.mean())),
+            format!("{}", obvhs::PrettyDuration(self.max())),
+        )
+    }
+}
+
+pub struct Race {
+    pub schedulers: Vec<Scheduler>,
+    /// Untimed runs per scheduler before timing starts
+    pub warmup: usize,
+    /// Timed runs per scheduler
+    pub reps: usize,
+}
+
+impl Default for Race {
+    fn default() -> Self {
+        Self {
+            schedulers: Scheduler::ALL.to_vec(),
+            warmup: 1,
+            reps: 10,
+        }
+    }
+}
+
+impl Race {
+    /// Run `workload` `warmup + reps` times for each scheduler. The workload is given the scheduler it
+    /// should dispatch its parallel work on.
+    pub fn run<F: FnMut(Scheduler)>(&self, mut workload: F) -> Vec<RaceResult> {
+        self.schedulers
+            .iter()
+            .map(|&scheduler| {
+                scheduler.init();
+                for _ in 0..self.warmup {
+                    workload(scheduler);
+                }
+                let times = (0..self.reps)
+                    .map(|_| {
+                        let start = Instant::now();
+                        workload(scheduler);
+                        start.elapsed()
+                    })
+                    .collect();
+                RaceResult { scheduler, times }
+            })
+            .collect()
+    }
+}
+
+/// Time of each phase of one full pipeline run.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PipelinePhases {
+    /// Triangle aabbs
+    pub aabbs: Duration,
+    /// Ploc leaf nodes and scene bounds
+    pub init: Duration,
+    /// Space filling curve codes and the radix sort
+    pub sort: Duration,
+    /// Ploc merge passes
+    pub merge: Duration,
+    pub ray_cast: Duration,
+}
+
+impl PipelinePhases {
+    pub fn total(&self) -> Duration {
+        self.aabbs + self.init + self.sort + self.merge + self.ray_cast
+    }
+}
+
+pub struct PipelineResult {
+    pub scheduler: Scheduler,
+    /// Phase times of each repetition, in the order they ran. Warm-up runs are not included.
+    pub runs: Vec<PipelinePhases>,
+    /// Rays that hit something, to check every scheduler built a working bvh
+    pub hits: usize,
+}
+
+impl PipelineResult {
+    /// The median of each phase separately.
+    pub fn median(&self) -> PipelinePhases {
+        let median = |phase: fn(&PipelinePhases) -> Duration| {
+            let mut sorted: Vec<Duration> = self.runs.iter().map(phase).collect();
+            sorted.sort_unstable();
+            nearest_rank(&sorted, 0.5)
+        };
+        PipelinePhases {
+            aabbs: median(|p| p.aabbs),
+            init: median(|p| p.init),
+            sort: median(|p| p.sort),
+            merge: median(|p| p.merge),
+            ray_cast: median(|p| p.ray_cast),
+        }
+    }
+}
+
+/// Table of the median phase times of each scheduler.
+pub struct PipelineTable<'a>(pub &'a [PipelineResult]);
+
+impl fmt::Display for PipelineTable<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{:>10} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8}",
+            "scheduler", "aabbs", "init", "sort", "merge", "rays", "total", "hits"
+        )?;
+        for result in self.0 {
+            let m = result.median();
+            let d = |d: Duration| format!("{}", obvhs::PrettyDuration(d));
+            writeln!(
+                f,
+                "{:>10} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8}",
+                result.scheduler.name(),
+                d(m.aabbs),
+                d(m.init),
+                d(m.sort),
+                d(m.merge),
+                d(m.ray_cast),
+                d(m.total()),
+                result.hits
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl Race {
+    /// Run the full pipeline for each scheduler: triangle aabbs, ploc build (init, space filling curve
+    /// sort and merge), then closest hit ray casts, all on that scheduler. Print the results with
+    /// `PipelineTable`.
+    ///
+    /// The builder is given each scheduler directly. The global ploc and radix schedulers are also set to
+    /// it while that scheduler runs, under a `SchedulerGuard` that restores them afterwards.
+    pub fn run_pipeline(&self, triangles: &[Triangle], rays: &[Ray]) -> Vec<PipelineResult> {
+        let _guard = SchedulerGuard::new();
+        let mut builder = PlocBuilder::preallocate_builder(triangles.len());
+        let mut aabbs = vec![Aabb::empty(); triangles.len()];
+        let mut hit_ids = vec![u32::MAX; rays.len()];
+        let mut bvh = Bvh2::default();
+        self.schedulers
+            .iter()
+            .map(|&scheduler| {
+                set_ploc_scheduler(scheduler);
+                set_radix_scheduler(scheduler);
+                builder.scheduler = Some(scheduler);
+                let chunks = scheduler.current_num_threads() as u32 * 4;
+                let mut run = || {
+                    let start = Instant::now();
+                    scheduler.par_map(&mut aabbs, &|i, aabb| *aabb = triangles[i].aabb(), chunks);
+                    let aabbs_time = start.elapsed();
+
+                    let stats = builder.rebuild_ploc_with_stats(&aabbs, &mut bvh);
+
+                    let start = Instant::now();
+                    scheduler.par_map(
+                        &mut hit_ids,
+                        &|i, hit_id| {
+                            let mut ray = rays[i];
+                            *hit_id = u32::MAX;
+                            bvh.traverse(&mut ray, hit_id, |ray, id| triangles[id].intersect(ray));
+                        },
+                        chunks,
+                    );
+                    PipelinePhases {
+                        aabbs: aabbs_time,
+                        init: stats.init_time,
+                        sort: stats.sort_time,
+                        merge: stats.merge_time,
+                        ray_cast: start.elapsed(),
+                    }
+                };
+                for _ in 0..self.warmup {
+                    run();
+                }
+                let runs = (0..self.reps).map(|_| run()).collect();
+                PipelineResult {
+                    scheduler,
+                    runs,
+                    hits: hit_ids.iter().filter(|id| **id != u32::MAX).count(),
+                }
+            })
+            .collect()
+    }
+}
+
+/// One backend and phase of a race, in a form that can be written out as CSV or JSON and compared
+/// across machines.
+/// With the `serde` feature the durations are (de)serialized as microseconds, named like the CSV and
+/// JSON columns.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RaceRecord {
+    pub backend: String,
+    pub phase: String,
+    pub reps: usize,
+    #[cfg_attr(feature = "serde", serde(rename = "median_us", with = "micros"))]
+    pub median: Duration,
+    /// 10th percentile
+    #[cfg_attr(feature = "serde", serde(rename = "p10_us", with = "micros"))]
+    pub p10: Duration,
+    /// 90th percentile
+    #[cfg_attr(feature = "serde", serde(rename = "p90_us", with = "micros"))]
+    pub p90: Duration,
+    pub threads: usize,
+    pub cpu_model: String,
+}
+
+/// A `Duration` as fractional microseconds.
+#[cfg(feature = "serde")]
+mod micros {
+    use std::time::Duration;
+
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_f64(duration.as_secs_f64() * 1e6)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        let us = f64::deserialize(deserializer)?;
+        // Round to whole nanoseconds so durations written with write_race_json read back exactly
+        Ok(Duration::from_nanos((us.max(0.0) * 1e3).round() as u64))
+    }
+}
+
+impl RaceRecord {
+    fn new(scheduler: Scheduler, phase: &str, times: &[Duration], cpu_model: &str) -> Self {
+        let mut sorted = times.to_vec();
+        sorted.sort_unstable();
+        RaceRecord {
+            backend: scheduler.name().to_string(),
+            phase: phase.to_string(),
+            reps: sorted.len(),
+            median: nearest_rank(&sorted, 0.5),
+            p10: nearest_rank(&sorted, 0.1),
+            p90: nearest_rank(&sorted, 0.9),
+            threads: scheduler.current_num_threads(),
+            cpu_model: cpu_model.to_string(),
+        }
+    }
+}
+
+impl RaceResult {
+    /// A single "run" phase record.
+    pub fn records(&self) -> Vec<RaceRecord> {
+        vec![RaceRecord::new(
+            self.scheduler,
+            "run",
+            &self.times,
+            &cpu_model(),
+        )]
+    }
+}
+
+impl PipelineResult {
+    /// A record for each phase and the total.
+    pub fn records(&self) -> Vec<RaceRecord> {
+        let cpu_model = cpu_model();
+        let phases: [(&str, fn(&PipelinePhases) -> Duration); 6] = [
+            ("aabbs", |p| p.aabbs),
+            ("init", |p| p.init),
+            ("sort", |p| p.sort),
+            ("merge", |p| p.merge),
+            ("ray_cast", |p| p.ray_cast),
+            ("total", PipelinePhases::total),
+        ];
+        phases
+            .iter()
+            .map(|(name, phase)| {
+                let times: Vec<Duration> = self.runs.iter().map(phase).collect();
+                RaceRecord::new(self.scheduler, name, &times, &cpu_model)
+            })
+            .collect()
+    }
+}
+
+/// The CPU model name from `/proc/cpuinfo`, or "unknown" on other platforms.
+pub fn cpu_model() -> String {
+    std::fs::read_to_string("/proc/cpuinfo")
+        .ok()
+        .and_then(|info| {
+            info.lines()
+                .find(|line| line.starts_with("model name"))
+                .and_then(|line| line.split_once(':'))
+                .map(|(_, name)| name.trim().to_string())
+        })
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+/// Write `records` as CSV with a `backend,phase,reps,median_us,p10_us,p90_us,threads,cpu_model`
+/// header.
+pub fn write_race_csv<W: Write>(mut writer: W, records: &[RaceRecord]) -> io::Result<()> {
+    writeln!(
+        writer,
+        "backend,phase,reps,median_us,p10_us,p90_us,threads,cpu_model"
+    )?;
+    for r in records {
+        write_csv_field(&mut writer, &r.backend)?;
+        write!(writer, ",")?;
+        write_csv_field(&mut writer, &r.phase)?;
+        write!(
+            writer,
+            ",{},{:.3},{:.3},{:.3},{},",
+            r.reps,
+            r.median.as_secs_f64() * 1e6,
+            r.p10.as_secs_f64() * 1e6,
+            r.p90.as_secs_f64() * 1e6,
+            r.threads
+        )?;
+        write_csv_field(&mut writer, &r.cpu_model)?;
+        writeln!(writer)?;
+    }
+    Ok(())
+}
+
+/// Write `records` as a JSON array of objects with the same fields as `write_race_csv`.
+pub fn write_race_json<W: Write>(mut writer: W, records: &[RaceRecord]) -> io::Result<()> {
+    writeln!(writer, "[")?;
+    for (i, r) in records.iter().enumerate() {
+        write!(writer, "  {{\"backend\": ")?;
+        write_json_string(&mut writer, &r.backend)?;
+        write!(writer, ", \"phase\": ")?;
+        write_json_string(&mut writer, &r.phase)?;
+        write!(
+            writer,
+            ", \"reps\": {}, \"median_us\": {:.3}, \"p10_us\": {:.3}, \"p90_us\": {:.3}, \"threads\": {}, \"cpu_model\": ",
+            r.reps,
+            r.median.as_secs_f64() * 1e6,
+            r.p10.as_secs_f64() * 1e6,
+            r.p90.as_secs_f64() * 1e6,
+            r.threads
+        )?;
+        write_json_string(&mut writer, &r.cpu_model)?;
+        let separator = if i + 1 < records.len() { "," } else { "" };
+        writeln!(writer, "}}{separator}")?;
+    }
+    writeln!(writer, "]")
+}
+
+/// Race `workload` on every scheduler. See [`Race`] to pick a subset of schedulers.
+pub fn race<F: FnMut(Scheduler)>(warmup: usize, reps: usize, workload: F) -> Vec<RaceResult> {
+    Race {
+        warmup,
+        reps,
+        ..Default::default()
+    }
+    .run(workload)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::reference::{random_rays, random_triangles, Rng};
+
+    #[test]
+    fn test_run_pipeline() {
+        let mut rng = Rng::new(5);
+        let triangles = random_triangles(&mut rng, 500, 0.2);
+        let rays = random_rays(&mut rng, 200);
+        let race = Race {
+            schedulers: vec![Scheduler::SequentialOptimized, Scheduler::Sequential],
+            warmup: 0,
+            reps: 3,
+        };
+        let results = race.run_pipeline(&triangles, &rays);
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].runs.len(), 3);
+        assert!(results[0].hits > 0);
+        assert_eq!(results[0].hits, results[1].hits);
+        let table = PipelineTable(&results).to_string();
+        assert_eq!(table.lines().count(), 3);
+
+        let records: Vec<RaceRecord> = results.iter().flat_map(|r| r.records()).collect();
+        assert_eq!(records.len(), 12);
+        assert_eq!(records[5].phase, "total");
+        assert_eq!(records[5].reps, 3);
+        let mut csv = Vec::new();
+        write_race_csv(&mut csv, &records).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        assert_eq!(csv.lines().count(), 13);
+        assert!(csv.lines().nth(1).unwrap().starts_with("seq_opt,aabbs,3,"));
+        let mut json = Vec::new();
+        write_race_json(&mut json, &records).unwrap();
+        let json = String::from_utf8(json).unwrap();
+        assert_eq!(json.matches("\"backend\": \"seq\"").count(), 6);
+    }
+
+    #[test]
+    fn test_race_record_percentiles() {
+        let times: Vec<Duration> = (1..=10).map(Duration::from_millis).collect();
+        let record = RaceRecord::new(Scheduler::Sequential, "run", &times, "cpu");
+        assert_eq!(record.p10, Duration::from_millis(1));
+        assert_eq!(record.median, Duration::from_millis(5));
+        assert_eq!(record.p90, Duration::from_millis(9));
+        assert_eq!(record.threads, 1);
+    }
+}
@@ -0,0 +1,133 @@
+//! `american_flag_sort` is a single-threaded, in-place MSB radix sort for small to mid-sized buckets.
+//!
+//! Each level is partitioned by cycling items directly into their output bucket, then every bucket is
+//! sorted recursively on the current thread. Buckets that fall under the comparative cutoff are finished
+//! with comparative_sort, which sorts them with an insertion sort on the remaining radix levels.
+//!
+//! ## Characteristics
+//!
+//!  * in-place
+//!  * unstable
+//!  * single-threaded
+//!
+//! ## Performance
+//!
+//! Going through the director for a bucket of a few hundred items costs more in scheduling than the
+//! sort itself. Keeping the whole recursion on one thread avoids that, and mid-sized buckets are common
+//! enough after the first level or two that this is a large part of the total time.
+
+use std::mem;
+
+use crate::radix::{
+    comparative_sort::comparative_sort,
+    radix_key::RadixKey,
+    sort_utils::{get_counts, get_end_offsets, get_prefix_sums},
+    stats::{SortPass, StatsRecorder},
+    tuner::tuning,
+};
+
+/// Partition `bucket` by the digit at `level` in place.
+#[inline]
+fn american_flag_pass<T>(bucket: &mut [T], counts: &[usize; 256], level: usize)
+where
+    T: RadixKey + Copy,
+{
+    let mut heads = get_prefix_sums(counts);
+    let ends = get_end_offsets(counts, &heads);
+
+    for b in 0..256 {
+        while heads[b] < ends[b] {
+            let mut item = bucket[heads[b]];
+            let mut d = item.get_level(level) as usize;
+            while d != b {
+                mem::swap(&mut item, &mut bucket[heads[d]]);
+                heads[d] += 1;
+                d = item.get_level(level) as usize;
+            }
+            bucket[heads[b]] = item;
+            heads[b] += 1;
+        }
+    }
+}
+
+fn american_flag_sort_level<T>(bucket: &mut [T], level: usize, cutoff: usize, stats: StatsRecorder)
+where
+    T: RadixKey + Sized + Send + Copy + Sync,
+{
+    if bucket.len() <= cutoff {
+        comparative_sort(bucket, level, stats);
+        return;
+    }
+
+    let (counts, already_sorted) = get_counts(bucket, level);
+    if !already_sorted {
+        let start = stats.start();
+        american_flag_pass(bucket, &counts, level);
+        stats.record(start, SortPass::AmericanFlag, level, bucket.len());
+    }
+
+    if level == 0 {
+        return;
+    }
+
+    let mut offset = 0;
+    for count in counts {
+        if count > 1 {
+            american_flag_sort_level(
+                &mut bucket[offset..offset + count],
+                level - 1,
+                cutoff,
+                stats,
+            );
+        }
+        offset += count;
+    }
+}
+
+/// Sort `bucket` by the digits `level..=0`.
+pub fn american_flag_sort<T>(bucket: &mut [T], level: usize)
+where
+    T: RadixKey + Sized + Send + Copy + Sync,
+{
+    american_flag_sort_with(
+        bucket,
+        level,
+        tuning().comparative_cutoff,
+        StatsRecorder::default(),
+    );
+}
+
+/// [`american_flag_sort`] with the comparative cutoff from the sort's [`crate::radix::tuner::Tuning`],
+/// recording into the sort's stats.
+pub(crate) fn american_flag_sort_with<T>(
+    bucket: &mut [T],
+    level: usize,
+    comparative_cutoff: usize,
+    stats: StatsRecorder,
+) where
+    T: RadixKey + Sized + Send + Copy + Sync,
+{
+    crate::scope!("american_flag_sort");
+    if bucket.len() < 2 {
+        return;
+    }
+
+    american_flag_sort_level(bucket, level, comparative_cutoff, stats);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_american_flag_sort_sorts() {
+        let mut data: Vec<u64> = (0..2_000u64)
+            .map(|i| i.wrapping_mul(0x9e37_79b9_7f4a_7c15) % 50_000)
+            .collect();
+        let mut expected = data.clone();
+        expected.sort_unstable();
+
+        american_flag_sort(&mut data, u64::LEVELS - 1);
+        assert_eq!(data, expected);
+    }
+}
@@ -20,12 +20,22 @@
 //! This is even slower than a typical comparison sort and so is only used as a fallback for very
 //! small inputs. However for those very small inputs it provides a significant speed-up due to
 //! having essentially no overhead (from count arrays, buffers etc.) compared to a radix sort.
+//!
+//! Key types whose `Ord` agrees with their radix order can override [`RadixKey::sort_small`] to use
+//! `sort_unstable` (pattern-defeating quicksort) on whole keys instead, the primitive integers do.
+//! How small "very small" is comes from [`crate::radix::tuner::Tuner::comparative_cutoff`].
 
 use std::cmp::Ordering;
 
-use crate::radix::radix_key::RadixKey;
+use crate::radix::{
+    radix_key::RadixKey,
+    stats::{SortPass, StatsRecorder},
+};
 
-pub(crate) fn comparative_sort<T>(bucket: &mut [T], start_level: usize)
+/// Sort a bucket below the comparative cutoff, whose items are all equal on the levels above
+/// `start_level`. Dispatches to [`RadixKey::sort_small`], so key types can plug in their own fallback.
+#[inline]
+pub(crate) fn comparative_sort<T>(bucket: &mut [T], start_level: usize, stats: StatsRecorder)
 where
     T: RadixKey + Sized + Send + Copy + Sync,
 {
@@ -34,6 +44,17 @@ where
         return;
     }
 
+    let start = stats.start();
+    T::sort_small(bucket, start_level);
+    stats.record(start, SortPass::Comparative, start_level, bucket.len());
+}
+
+/// Sort `bucket` by comparing the levels `start_level..=0` one at a time. The default
+/// [`RadixKey::sort_small`].
+pub fn sort_by_levels<T>(bucket: &mut [T], start_level: usize)
+where
+    T: RadixKey,
+{
     bucket.sort_unstable_by(|a, b| -> Ordering {
         let mut level = start_level;
         loop {
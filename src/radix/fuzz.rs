@@ -0,0 +1,198 @@
+//! Verifies every radix algorithm on every scheduler against `sort_unstable`, over key distributions
+//! that hit the edge cases of the algorithm selection (already sorted, reversed, homogenous buckets...)
+//! and lengths straddling each [`Tuner`] threshold.
+//!
+//! The thresholds are scaled down with [`FuzzTuner`] so every path is reached without huge inputs.
+
+use std::sync::Mutex;
+
+use crate::{
+    par::Scheduler,
+    radix::{
+        radix_key::RadixKey,
+        set_radix_algorithm,
+        sorter::sort_with,
+        tuner::{set_tuner, DefaultTuner, Tuner},
+        RadixAlgorithm,
+    },
+};
+
+/// The algorithm and tuner are global, so only one fuzz test may change them at a time
+static GLOBALS: Mutex<()> = Mutex::new(());
+
+struct FuzzTuner;
+
+impl Tuner for FuzzTuner {
+    fn comparative_cutoff(&self) -> usize {
+        32
+    }
+
+    fn small_bucket_cutoff(&self) -> usize {
+        96
+    }
+
+    fn tile_threshold(&self) -> usize {
+        1024
+    }
+
+    fn min_tile_size(&self) -> usize {
+        256
+    }
+
+    fn homogenous_threshold(&self) -> usize {
+        256
+    }
+
+    fn par_count_threshold(&self) -> usize {
+        300
+    }
+
+    fn recombinating_max_len(&self) -> usize {
+        4096
+    }
+}
+
+const ALGORITHMS: [RadixAlgorithm; 5] = [
+    RadixAlgorithm::Auto,
+    RadixAlgorithm::Regions,
+    RadixAlgorithm::Recombinating,
+    RadixAlgorithm::Scanning,
+    RadixAlgorithm::Lsb,
+];
+
+#[derive(Clone, Copy, Debug)]
+enum Distribution {
+    AllEqual,
+    FewUnique,
+    Sorted,
+    ReverseSorted,
+    Sawtooth,
+    Random,
+}
+
+const DISTRIBUTIONS: [Distribution; 6] = [
+    Distribution::AllEqual,
+    Distribution::FewUnique,
+    Distribution::Sorted,
+    Distribution::ReverseSorted,
+    Distribution::Sawtooth,
+    Distribution::Random,
+];
+
+/// Lengths just below, at and just above every threshold of [`FuzzTuner`]
+fn lengths() -> Vec<usize> {
+    let t = FuzzTuner;
+    let mut lengths = vec![0, 1, 2, 3];
+    for threshold in [
+        t.comparative_cutoff(),
+        t.small_bucket_cutoff(),
+        t.tile_threshold(),
+        t.min_tile_size(),
+        t.homogenous_threshold(),
+        t.par_count_threshold(),
+        t.recombinating_max_len(),
+    ] {
+        lengths.extend([threshold - 1, threshold, threshold + 1]);
+    }
+    lengths.sort_unstable();
+    lengths.dedup();
+    lengths
+}
+
+/// xorshift64*, so the inputs are reproducible without pulling in rand
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+}
+
+fn generate(distribution: Distribution, len: usize) -> Vec<u64> {
+    let mut rng = Rng(0x9e37_79b9_7f4a_7c15 ^ len as u64);
+    let mut data: Vec<u64> = match distribution {
+        Distribution::AllEqual => vec![0x0123_4567_89ab_cdef; len],
+        Distribution::FewUnique => (0..len).map(|_| rng.next() % 4 * 0x0101_0101).collect(),
+        Distribution::Sorted | Distribution::ReverseSorted => {
+            (0..len).map(|_| rng.next()).collect()
+        }
+        Distribution::Sawtooth => (0..len as u64).map(|i| (i % 97) << 40 | i).collect(),
+        Distribution::Random => (0..len).map(|_| rng.next()).collect(),
+    };
+    match distribution {
+        Distribution::Sorted => data.sort_unstable(),
+        Distribution::ReverseSorted => data.sort_unstable_by(|a, b| b.cmp(a)),
+        _ => (),
+    }
+    data
+}
+
+fn verify<T>(data: &[T], sch: Scheduler, algorithm: RadixAlgorithm, distribution: Distribution)
+where
+    T: RadixKey + Ord + Copy + Send + Sync + std::fmt::Debug,
+{
+    let mut expected = data.to_vec();
+    expected.sort_unstable();
+    let mut sorted = data.to_vec();
+    sort_with(sch, &mut sorted);
+    assert!(
+        sorted == expected,
+        "{algorithm:?} on {sch:?} failed for {distribution:?} with {} items",
+        data.len()
+    );
+}
+
+fn fuzz(test: impl Fn(Scheduler, RadixAlgorithm, Distribution, &[u64])) {
+    let _guard = GLOBALS.lock().unwrap_or_else(|e| e.into_inner());
+    set_tuner(&FuzzTuner);
+
+    let lengths = lengths();
+    for sch in Scheduler::ALL {
+        sch.init();
+        for algorithm in ALGORITHMS {
+            set_radix_algorithm(algorithm);
+            for distribution in DISTRIBUTIONS {
+                for &len in &lengths {
+                    test(sch, algorithm, distribution, &generate(distribution, len));
+                }
+            }
+        }
+    }
+
+    set_radix_algorithm(RadixAlgorithm::Auto);
+    set_tuner(&DefaultTuner);
+}
+
+#[test]
+fn fuzz_u64() {
+    fuzz(|sch, algorithm, distribution, data| verify(data, sch, algorithm, distribution));
+}
+
+#[test]
+fn fuzz_u32() {
+    fuzz(|sch, algorithm, distribution, data| {
+        let data: Vec<u32> = data.iter().map(|&v| (v >> 32) as u32).collect();
+        verify(&data, sch, algorithm, distribution)
+    });
+}
+
+#[test]
+fn fuzz_u16() {
+    // Two level keys take the counting sort path
+    fuzz(|sch, algorithm, distribution, data| {
+        let data: Vec<u16> = data.iter().map(|&v| (v >> 48) as u16).collect();
+        verify(&data, sch, algorithm, distribution)
+    });
+}
+
+#[test]
+fn fuzz_composite() {
+    // More than 8 levels, with a shared prefix in the first element
+    fuzz(|sch, algorithm, distribution, data| {
+        let data: Vec<(u64, u64)> = data.iter().map(|&v| (v >> 60, v)).collect();
+        verify(&data, sch, algorithm, distribution)
+    });
+}
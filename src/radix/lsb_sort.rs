@@ -0,0 +1,96 @@
+//! `lsb_sort` is a classic out-of-place least-significant-bit first radix sort.
+//!
+//! Each level scatters every item from one buffer into the other, ping-ponging between the input and
+//! a scratch buffer of the same length. Levels where the input is already ordered by that digit are
+//! skipped, since a stable scatter of them would not move anything.
+//!
+//! ## Characteristics
+//!
+//!  * out-of-place
+//!  * stable
+//!  * single-threaded
+//!
+//! ## Performance
+//!
+//! This is typically faster than `ska_sort` for small to medium sized keys and inputs, at the cost of
+//! a scratch buffer as large as the input. Keep the scratch buffer around between sorts to avoid paying
+//! for the allocation each time.
+
+use crate::radix::{
+    radix_key::RadixKey,
+    sort_utils::{get_counts, get_prefix_sums},
+};
+
+/// Scatter `src_bucket` into `dst_bucket` ordered by the digit at `level`. `counts` must be the counts
+/// of that digit in `src_bucket`.
+#[inline]
+pub fn lsb_sort<T>(src_bucket: &[T], dst_bucket: &mut [T], counts: &[usize; 256], level: usize)
+where
+    T: RadixKey + Sized + Send + Copy + Sync,
+{
+    crate::scope!("lsb_sort");
+    let mut prefix_sums = get_prefix_sums(counts);
+
+    for item in src_bucket {
+        let b = item.get_level(level) as usize;
+        dst_bucket[prefix_sums[b]] = *item;
+        prefix_sums[b] += 1;
+    }
+}
+
+/// Sort `bucket` by the digits `start_level..=end_level`, using `tmp_bucket` as scratch. `tmp_bucket`
+/// must be the same length as `bucket`. The result always ends up in `bucket`.
+pub fn lsb_sort_adapter<T>(
+    bucket: &mut [T],
+    tmp_bucket: &mut [T],
+    start_level: usize,
+    end_level: usize,
+) where
+    T: RadixKey + Sized + Send + Copy + Sync,
+{
+    crate::scope!("lsb_sort_adapter");
+    assert_eq!(bucket.len(), tmp_bucket.len());
+    if bucket.len() < 2 {
+        return;
+    }
+
+    let mut invert = false;
+    for level in start_level..=end_level {
+        let (src, dst) = if invert {
+            (&*tmp_bucket, &mut *bucket)
+        } else {
+            (&*bucket, &mut *tmp_bucket)
+        };
+
+        let (counts, already_sorted) = get_counts(src, level);
+        if already_sorted {
+            continue;
+        }
+
+        lsb_sort(src, dst, &counts, level);
+        invert = !invert;
+    }
+
+    if invert {
+        crate::scope!("copy back lsb");
+        bucket.copy_from_slice(tmp_bucket);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lsb_sort_adapter_sorts_all_levels() {
+        let mut data: Vec<u32> = (0..1000u32)
+            .map(|i| i.wrapping_mul(2_654_435_761))
+            .collect();
+        let mut expected = data.clone();
+        expected.sort_unstable();
+
+        let mut tmp = vec![0u32; data.len()];
+        lsb_sort_adapter(&mut data, &mut tmp, 0, u32::LEVELS - 1);
+        assert_eq!(data, expected);
+    }
+}
@@ -1,25 +1,101 @@
 // https://github.com/nessex/rdst/
 
-use std::sync::atomic::{AtomicU32, Ordering};
+use std::{
+    str::FromStr,
+    sync::atomic::{AtomicBool, AtomicU32, Ordering},
+};
 
-use crate::{par::Scheduler, scope, Args};
+use crate::{
+    par::{scheduler_from_env_or_default, Scheduler},
+    scope,
+};
 
+pub mod american_flag_sort;
 pub mod comparative_sort;
+#[cfg(test)]
+mod fuzz;
+pub mod lsb_sort;
+pub mod mt_lsb_sort;
 pub mod radix_key;
+pub mod recombinating_sort;
 pub mod regions_sort;
+pub mod scanning_sort;
 pub mod ska_sort;
 pub mod sort_utils;
 pub mod sorter;
+pub mod stats;
+pub mod tuner;
+
+/// Which multi-threaded algorithm the sorter uses for chunks large enough to be split into tiles.
+#[derive(PartialEq, Eq, Default, Clone, Copy, Debug)]
+#[repr(u32)]
+pub enum RadixAlgorithm {
+    /// recombinating_sort, falling back to regions_sort for very large chunks
+    #[default]
+    Auto = 0,
+    Regions = 1,
+    Recombinating = 2,
+    Scanning = 3,
+    /// mt_lsb_sort over all remaining levels at once
+    Lsb = 4,
+}
+
+impl FromStr for RadixAlgorithm {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(Self::Auto),
+            "regions" => Ok(Self::Regions),
+            "recombinating" => Ok(Self::Recombinating),
+            "scanning" => Ok(Self::Scanning),
+            "lsb" => Ok(Self::Lsb),
+            _ => Err(format!(
+                "Unknown radix algorithm: '{s}', valid algorithms: 'auto', 'regions', 'recombinating', 'scanning', 'lsb'"
+            )),
+        }
+    }
+}
+
+static RADIX_ALGORITHM: AtomicU32 = AtomicU32::new(RadixAlgorithm::Auto as u32);
+
+pub fn set_radix_algorithm(algorithm: RadixAlgorithm) {
+    RADIX_ALGORITHM.store(algorithm as u32, Ordering::Relaxed);
+}
+
+#[inline(always)]
+pub fn radix_algorithm() -> RadixAlgorithm {
+    match RADIX_ALGORITHM.load(Ordering::Relaxed) {
+        1 => RadixAlgorithm::Regions,
+        2 => RadixAlgorithm::Recombinating,
+        3 => RadixAlgorithm::Scanning,
+        4 => RadixAlgorithm::Lsb,
+        _ => RadixAlgorithm::Auto,
+    }
+}
 
 static RADIX_SCHEDULER: AtomicU32 = AtomicU32::new(0);
+static RADIX_SCHEDULER_INITIALIZED: AtomicBool = AtomicBool::new(false);
 
 pub fn radix_scheduler() -> Scheduler {
     Scheduler::from(RADIX_SCHEDULER.load(Ordering::Relaxed))
 }
 
+/// Use `scheduler` for radix, ignoring environment variables from now on.
+pub fn set_radix_scheduler(scheduler: Scheduler) {
+    scheduler.init();
+    RADIX_SCHEDULER.store(scheduler as u32, Ordering::Relaxed);
+    RADIX_SCHEDULER_INITIALIZED.store(true, Ordering::Relaxed);
+}
+
+/// Select the radix scheduler the first time it's called, unless [`set_radix_scheduler`] was already
+/// used. Falls back to `POOL_RACING_RADIX_SCHEDULER`, `POOL_RACING_SCHEDULER`, then the default. Command
+/// line args are never parsed here, see `Args::apply` with the `cli` feature for that.
 pub fn init_radix_scheduler() {
     scope!("init_radix_scheduler");
-    let config: Args = argh::from_env();
-    config.radix_sch.init();
-    RADIX_SCHEDULER.store(config.radix_sch as u32, Ordering::Relaxed);
+    if RADIX_SCHEDULER_INITIALIZED.load(Ordering::Relaxed) {
+        return;
+    }
+    let scheduler = scheduler_from_env_or_default("POOL_RACING_RADIX_SCHEDULER");
+    set_radix_scheduler(scheduler);
 }
@@ -0,0 +1,180 @@
+//! `mt_lsb_sort` is a multi-threaded, out-of-place least-significant-bit first radix sort.
+//!
+//! Each level is a parallel version of `lsb_sort`:
+//! 1. Count the current digit for each tile of the input
+//! 2. Split the destination into one slice per (digit, tile) pair, in digit then tile order
+//! 3. Scatter every tile into its own slices in parallel
+//!
+//! Because each tile writes to a slice reserved for it, the scatter is stable and needs no
+//! synchronization.
+//!
+//! ## Characteristics
+//!
+//!  * out-of-place
+//!  * multi-threaded
+//!  * stable
+//!
+//! ## Performance
+//!
+//! Every level touches the whole input, but each pass is a simple streaming scatter. For large inputs
+//! with small keys, such as 64-bit Morton codes, this is often the fastest option.
+
+use std::mem;
+
+use crate::par::Scheduler;
+use crate::radix::{
+    radix_key::RadixKey,
+    sort_utils::{get_tile_counts_into, grow_tmp_bucket, SortBuffers},
+    stats::{SortPass, StatsRecorder},
+};
+
+/// Scatter `src_bucket` into `dst_bucket` ordered by the digit at `level`. `tile_counts` must be the
+/// counts of that digit for each `tile_size` tile of `src_bucket`.
+pub fn mt_lsb_sort<T>(
+    sch: Scheduler,
+    src_bucket: &[T],
+    dst_bucket: &mut [T],
+    tile_counts: &[[usize; 256]],
+    tile_size: usize,
+    level: usize,
+) where
+    T: RadixKey + Sized + Send + Copy + Sync,
+{
+    crate::scope!("mt_lsb_sort");
+    let tiles = tile_counts.len();
+    let mut tile_buckets: Vec<Vec<&mut [T]>> =
+        (0..tiles).map(|_| Vec::with_capacity(256)).collect();
+
+    {
+        crate::scope!("split dst_bucket");
+        let mut rem_bucket = dst_bucket;
+        for b in 0..256 {
+            for (buckets, counts) in tile_buckets.iter_mut().zip(tile_counts) {
+                let (chunk, rem) = mem::take(&mut rem_bucket).split_at_mut(counts[b]);
+                buckets.push(chunk);
+                rem_bucket = rem;
+            }
+        }
+    }
+
+    sch.par_map(
+        &mut tile_buckets,
+        &|tile_id, buckets| {
+            let start = tile_id * tile_size;
+            let end = (start + tile_size).min(src_bucket.len());
+            let mut offsets = [0usize; 256];
+            for item in &src_bucket[start..end] {
+                let b = item.get_level(level) as usize;
+                buckets[b][offsets[b]] = *item;
+                offsets[b] += 1;
+            }
+        },
+        tiles as u32,
+    );
+}
+
+/// Sort `bucket` by the digits `start_level..=end_level`, splitting each pass into `tile_size` tiles.
+/// The scratch buffer and tile counts are taken from `buffers`.
+pub fn mt_lsb_sort_adapter<T>(
+    sch: Scheduler,
+    bucket: &mut [T],
+    start_level: usize,
+    end_level: usize,
+    tile_size: usize,
+    buffers: &mut SortBuffers<T>,
+) where
+    T: RadixKey + Sized + Send + Copy + Sync,
+{
+    mt_lsb_sort_adapter_with(
+        sch,
+        bucket,
+        start_level,
+        end_level,
+        tile_size,
+        buffers,
+        StatsRecorder::default(),
+    );
+}
+
+/// [`mt_lsb_sort_adapter`] recording each pass into the sort's stats.
+pub(crate) fn mt_lsb_sort_adapter_with<T>(
+    sch: Scheduler,
+    bucket: &mut [T],
+    start_level: usize,
+    end_level: usize,
+    tile_size: usize,
+    buffers: &mut SortBuffers<T>,
+    stats: StatsRecorder,
+) where
+    T: RadixKey + Sized + Send + Copy + Sync,
+{
+    crate::scope!("mt_lsb_sort_adapter");
+    if bucket.len() < 2 {
+        return;
+    }
+
+    let tile_size = tile_size.max(1);
+    let tmp_bucket = grow_tmp_bucket(&mut buffers.tmp_bucket, bucket);
+    let mut invert = false;
+
+    for level in start_level..=end_level {
+        let (src, dst) = if invert {
+            (&*tmp_bucket, &mut *bucket)
+        } else {
+            (&*bucket, &mut *tmp_bucket)
+        };
+
+        let (already_sorted, _) = get_tile_counts_into(
+            sch,
+            src,
+            tile_size,
+            level,
+            &mut buffers.tiles,
+            &mut buffers.tile_counts,
+        );
+        if already_sorted {
+            continue;
+        }
+
+        let start = stats.start();
+        mt_lsb_sort(sch, src, dst, &buffers.tile_counts, tile_size, level);
+        stats.record(start, SortPass::Lsb, level, src.len());
+        invert = !invert;
+    }
+
+    if invert {
+        crate::scope!("par copy back mt_lsb");
+        sch.par_chunks_mut(
+            bucket,
+            &|chunk_id, chunk| {
+                let start = chunk_id * tile_size;
+                chunk.copy_from_slice(&tmp_bucket[start..start + chunk.len()]);
+            },
+            tile_size,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mt_lsb_sort_adapter_sorts_multiple_tiles() {
+        let mut data: Vec<u64> = (0..10_000u64)
+            .map(|i| i.wrapping_mul(11_400_714_819_323_198_485))
+            .collect();
+        let mut expected = data.clone();
+        expected.sort_unstable();
+
+        mt_lsb_sort_adapter(
+            Scheduler::SequentialOptimized,
+            &mut data,
+            0,
+            u64::LEVELS - 1,
+            999,
+            &mut SortBuffers::default(),
+        );
+        assert_eq!(data, expected);
+    }
+}
@@ -1,36 +1,88 @@
+use std::cmp::{Ordering, Reverse};
+
+use bytemuck::Zeroable;
+
+use crate::radix::{comparative_sort::sort_by_levels, sort_utils::count_digits};
+
+#[cfg(feature = "derive")]
+pub use pool_racing_derive::RadixKey;
+
 pub trait RadixKey {
     const LEVELS: usize;
 
     fn get_level(&self, level: usize) -> u8;
-}
-
-impl RadixKey for u8 {
-    const LEVELS: usize = 1;
 
+    /// Sort a bucket below the comparative cutoff, whose items are all equal on the levels above
+    /// `level`. The default compares the remaining levels one at a time. Override it with
+    /// `bucket.sort_unstable()` when `Ord` agrees with the radix order, which is much faster.
     #[inline]
-    fn get_level(&self, _: usize) -> u8 {
-        *self
+    fn sort_small(bucket: &mut [Self], level: usize)
+    where
+        Self: Sized,
+    {
+        sort_by_levels(bucket, level);
     }
-}
-
-impl RadixKey for u16 {
-    const LEVELS: usize = 2;
 
+    /// Count the digits at `level` in `bucket`. The default reads one item at a time into interleaved
+    /// tables, `u64` overrides it with `simd::count_digits_u64` when the `simd` feature is enabled.
     #[inline]
-    fn get_level(&self, level: usize) -> u8 {
-        (self >> (level * 8)) as u8
+    fn count_digits(bucket: &[Self], level: usize) -> [usize; 256]
+    where
+        Self: Sized,
+    {
+        count_digits(bucket, level)
     }
 }
 
-impl RadixKey for u32 {
-    const LEVELS: usize = 4;
+/// `sort_small` with `sort_unstable`, for keys whose `Ord` agrees with the radix order.
+macro_rules! sort_small_by_ord {
+    () => {
+        #[inline]
+        fn sort_small(bucket: &mut [Self], _: usize) {
+            bucket.sort_unstable();
+        }
+    };
+}
+pub(crate) use sort_small_by_ord;
+
+macro_rules! impl_unsigned_key {
+    ($($int:ty),*) => {
+        $(
+            impl RadixKey for $int {
+                const LEVELS: usize = std::mem::size_of::<$int>();
+
+                #[inline]
+                fn get_level(&self, level: usize) -> u8 {
+                    (self >> (level * 8)) as u8
+                }
+
+                sort_small_by_ord!();
+            }
+        )*
+    };
+}
 
-    #[inline]
-    fn get_level(&self, level: usize) -> u8 {
-        (self >> (level * 8)) as u8
-    }
+// The sign bit is flipped so negative values sort before positive ones
+macro_rules! impl_signed_key {
+    ($($int:ty),*) => {
+        $(
+            impl RadixKey for $int {
+                const LEVELS: usize = std::mem::size_of::<$int>();
+
+                #[inline]
+                fn get_level(&self, level: usize) -> u8 {
+                    ((self ^ <$int>::MIN) >> (level * 8)) as u8
+                }
+
+                sort_small_by_ord!();
+            }
+        )*
+    };
 }
 
+impl_unsigned_key!(u8, u16, u32, u128, usize);
+impl_signed_key!(i8, i16, i32, i64, i128, isize);
+
 impl RadixKey for u64 {
     const LEVELS: usize = 8;
 
@@ -38,152 +90,461 @@ impl RadixKey for u64 {
     fn get_level(&self, level: usize) -> u8 {
         (self >> (level * 8)) as u8
     }
-}
 
-impl RadixKey for u128 {
-    const LEVELS: usize = 16;
+    sort_small_by_ord!();
 
+    #[cfg(feature = "simd")]
     #[inline]
-    fn get_level(&self, level: usize) -> u8 {
-        (self >> (level * 8)) as u8
+    fn count_digits(bucket: &[Self], level: usize) -> [usize; 256] {
+        crate::simd::count_digits_u64(bucket, level)
     }
 }
 
-#[cfg(target_pointer_width = "16")]
-impl RadixKey for usize {
-    const LEVELS: usize = 2;
+/// The last element is the most significant, so `[u8; N]` sorts like a little endian integer. Reverse
+/// the elements to sort lexicographically.
+impl<T: RadixKey, const N: usize> RadixKey for [T; N] {
+    const LEVELS: usize = N * T::LEVELS;
 
     #[inline]
     fn get_level(&self, level: usize) -> u8 {
-        (self >> (level * 8)) as u8
+        self[level / T::LEVELS].get_level(level % T::LEVELS)
     }
 }
 
-#[cfg(target_pointer_width = "32")]
-impl RadixKey for usize {
+impl RadixKey for f32 {
     const LEVELS: usize = 4;
 
     #[inline]
     fn get_level(&self, level: usize) -> u8 {
-        (self >> (level * 8)) as u8
+        f32_to_ordered_u32(*self).get_level(level)
     }
 }
 
-#[cfg(target_pointer_width = "64")]
-impl RadixKey for usize {
+impl RadixKey for f64 {
     const LEVELS: usize = 8;
 
     #[inline]
     fn get_level(&self, level: usize) -> u8 {
-        (self >> (level * 8)) as u8
+        f64_to_ordered_u64(*self).get_level(level)
     }
 }
 
-impl<const N: usize> RadixKey for [u8; N] {
-    const LEVELS: usize = N;
-
-    #[inline]
-    fn get_level(&self, level: usize) -> u8 {
-        self[level]
+/// Map an f32 to a u32 with the same total order as `f32::total_cmp`. Positive values get their sign
+/// bit set, negative values have all their bits flipped so larger magnitudes sort first.
+#[inline]
+pub fn f32_to_ordered_u32(value: f32) -> u32 {
+    let bits = value.to_bits();
+    if bits & (1 << 31) != 0 {
+        !bits
+    } else {
+        bits | (1 << 31)
     }
 }
 
-impl RadixKey for i8 {
-    const LEVELS: usize = 1;
+/// Inverse of [`f32_to_ordered_u32`].
+#[inline]
+pub fn ordered_u32_to_f32(key: u32) -> f32 {
+    f32::from_bits(if key & (1 << 31) != 0 {
+        key & !(1 << 31)
+    } else {
+        !key
+    })
+}
 
-    #[inline]
-    fn get_level(&self, _: usize) -> u8 {
-        (*self ^ i8::MIN) as u8
+/// Map an f64 to a u64 with the same total order as `f64::total_cmp`. See [`f32_to_ordered_u32`].
+#[inline]
+pub fn f64_to_ordered_u64(value: f64) -> u64 {
+    let bits = value.to_bits();
+    if bits & (1 << 63) != 0 {
+        !bits
+    } else {
+        bits | (1 << 63)
     }
 }
 
-impl RadixKey for i16 {
-    const LEVELS: usize = 2;
+/// Inverse of [`f64_to_ordered_u64`].
+#[inline]
+pub fn ordered_u64_to_f64(key: u64) -> f64 {
+    f64::from_bits(if key & (1 << 63) != 0 {
+        key & !(1 << 63)
+    } else {
+        !key
+    })
+}
+
+/// Float wrapper that is totally ordered (consistent with `total_cmp`, so -0.0 < 0.0 and NaNs sort by
+/// sign at the ends), for use as a sort key or anywhere `Ord` is needed.
+#[derive(Clone, Copy, Default, Debug)]
+#[repr(transparent)]
+pub struct FloatKey<F>(pub F);
+
+macro_rules! impl_float_key {
+    ($float:ty, $to_ordered:ident, $levels:expr) => {
+        impl RadixKey for FloatKey<$float> {
+            const LEVELS: usize = $levels;
+
+            #[inline]
+            fn get_level(&self, level: usize) -> u8 {
+                $to_ordered(self.0).get_level(level)
+            }
+
+            sort_small_by_ord!();
+        }
+
+        impl PartialEq for FloatKey<$float> {
+            #[inline]
+            fn eq(&self, other: &Self) -> bool {
+                self.cmp(other) == Ordering::Equal
+            }
+        }
+
+        impl Eq for FloatKey<$float> {}
+
+        impl PartialOrd for FloatKey<$float> {
+            #[inline]
+            fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+                Some(self.cmp(other))
+            }
+        }
+
+        impl Ord for FloatKey<$float> {
+            #[inline]
+            fn cmp(&self, other: &Self) -> Ordering {
+                self.0.total_cmp(&other.0)
+            }
+        }
+    };
+}
+
+impl_float_key!(f32, f32_to_ordered_u32, 4);
+impl_float_key!(f64, f64_to_ordered_u64, 8);
+
+/// Sorts in descending order of the wrapped key.
+impl<K: RadixKey> RadixKey for Reverse<K> {
+    const LEVELS: usize = K::LEVELS;
 
     #[inline]
     fn get_level(&self, level: usize) -> u8 {
-        ((self ^ i16::MIN) >> (level * 8)) as u8
+        !self.0.get_level(level)
     }
 }
 
-impl RadixKey for i32 {
-    const LEVELS: usize = 4;
+// Tuples sort lexicographically, the first element is the most significant.
+
+impl<A: RadixKey, B: RadixKey> RadixKey for (A, B) {
+    const LEVELS: usize = A::LEVELS + B::LEVELS;
 
     #[inline]
     fn get_level(&self, level: usize) -> u8 {
-        ((self ^ i32::MIN) >> (level * 8)) as u8
+        if level < B::LEVELS {
+            self.1.get_level(level)
+        } else {
+            self.0.get_level(level - B::LEVELS)
+        }
     }
 }
 
-impl RadixKey for i64 {
-    const LEVELS: usize = 8;
+impl<A: RadixKey, B: RadixKey, C: RadixKey> RadixKey for (A, B, C) {
+    const LEVELS: usize = A::LEVELS + B::LEVELS + C::LEVELS;
 
     #[inline]
     fn get_level(&self, level: usize) -> u8 {
-        ((self ^ i64::MIN) >> (level * 8)) as u8
+        if level < C::LEVELS {
+            self.2.get_level(level)
+        } else if level < C::LEVELS + B::LEVELS {
+            self.1.get_level(level - C::LEVELS)
+        } else {
+            self.0.get_level(level - C::LEVELS - B::LEVELS)
+        }
     }
 }
 
-impl RadixKey for i128 {
-    const LEVELS: usize = 16;
+impl<A: RadixKey, B: RadixKey, C: RadixKey, D: RadixKey> RadixKey for (A, B, C, D) {
+    const LEVELS: usize = A::LEVELS + B::LEVELS + C::LEVELS + D::LEVELS;
 
     #[inline]
     fn get_level(&self, level: usize) -> u8 {
-        ((self ^ i128::MIN) >> (level * 8)) as u8
+        if level < D::LEVELS {
+            self.3.get_level(level)
+        } else if level < D::LEVELS + C::LEVELS {
+            self.2.get_level(level - D::LEVELS)
+        } else if level < D::LEVELS + C::LEVELS + B::LEVELS {
+            self.1.get_level(level - D::LEVELS - C::LEVELS)
+        } else {
+            self.0.get_level(level - D::LEVELS - C::LEVELS - B::LEVELS)
+        }
     }
 }
 
-#[cfg(target_pointer_width = "16")]
-impl RadixKey for isize {
-    const LEVELS: usize = 2;
+/// Orders by `primary`, then by `secondary` where the primary keys are equal. The same ordering as the
+/// `(P, S)` tuple, with named fields and a `repr(C)` layout so it can be part of `Pod` style records,
+/// like a material id followed by a Morton code.
+#[derive(Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+#[repr(C)]
+pub struct CompositeKey<P, S> {
+    pub primary: P,
+    pub secondary: S,
+}
 
+impl<P, S> CompositeKey<P, S> {
     #[inline]
-    fn get_level(&self, level: usize) -> u8 {
-        ((self ^ isize::MIN) >> (level * 8)) as u8
+    pub fn new(primary: P, secondary: S) -> Self {
+        Self { primary, secondary }
     }
 }
 
-#[cfg(target_pointer_width = "32")]
-impl RadixKey for isize {
-    const LEVELS: usize = 4;
+// SAFETY: A CompositeKey is all zeros exactly when both fields are.
+unsafe impl<P: Zeroable, S: Zeroable> Zeroable for CompositeKey<P, S> {}
+
+impl<P: RadixKey, S: RadixKey> RadixKey for CompositeKey<P, S> {
+    const LEVELS: usize = P::LEVELS + S::LEVELS;
 
     #[inline]
     fn get_level(&self, level: usize) -> u8 {
-        ((self ^ isize::MIN) >> (level * 8)) as u8
+        if level < S::LEVELS {
+            self.secondary.get_level(level)
+        } else {
+            self.primary.get_level(level - S::LEVELS)
+        }
     }
 }
 
-#[cfg(target_pointer_width = "64")]
-impl RadixKey for isize {
-    const LEVELS: usize = 8;
-
+impl<P, S> From<(P, S)> for CompositeKey<P, S> {
     #[inline]
-    fn get_level(&self, level: usize) -> u8 {
-        ((self ^ isize::MIN) >> (level * 8)) as u8
+    fn from((primary, secondary): (P, S)) -> Self {
+        Self { primary, secondary }
     }
 }
 
-impl RadixKey for f32 {
-    const LEVELS: usize = 4;
+/// A key with a payload that is moved along with it during the sort. Only the key is used for ordering.
+#[derive(Clone, Copy, Default, Debug)]
+#[repr(C)]
+pub struct KeyValue<K, V> {
+    pub key: K,
+    pub value: V,
+}
+
+// SAFETY: A KeyValue is all zeros exactly when both fields are.
+unsafe impl<K: Zeroable, V: Zeroable> Zeroable for KeyValue<K, V> {}
+
+impl<K: RadixKey, V> RadixKey for KeyValue<K, V> {
+    const LEVELS: usize = K::LEVELS;
 
     #[inline]
     fn get_level(&self, level: usize) -> u8 {
-        let mut s = self.to_bits() as i32;
+        self.key.get_level(level)
+    }
+}
 
-        s ^= (((s >> 31) as u32) >> 1) as i32;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::radix::{comparative_sort::comparative_sort, stats::StatsRecorder};
+
+    #[test]
+    fn test_ordered_float_keys_round_trip_and_preserve_order() {
+        let values = [
+            f32::NEG_INFINITY,
+            -3.5,
+            -0.0,
+            0.0,
+            1e-30,
+            2.0,
+            f32::INFINITY,
+        ];
+        for pair in values.windows(2) {
+            assert!(f32_to_ordered_u32(pair[0]) < f32_to_ordered_u32(pair[1]));
+            assert!(f64_to_ordered_u64(pair[0] as f64) < f64_to_ordered_u64(pair[1] as f64));
+        }
+        for v in values {
+            assert_eq!(
+                ordered_u32_to_f32(f32_to_ordered_u32(v)).to_bits(),
+                v.to_bits()
+            );
+            let v = v as f64;
+            assert_eq!(
+                ordered_u64_to_f64(f64_to_ordered_u64(v)).to_bits(),
+                v.to_bits()
+            );
+        }
+    }
 
-        ((s ^ i32::MIN) >> (level * 8)) as u8
+    #[test]
+    fn test_reverse_keys_sort_descending() {
+        let mut data: Vec<Reverse<i32>> = [5, -7, 300, 0, -1, 70_000]
+            .into_iter()
+            .map(Reverse)
+            .collect();
+        let mut expected = data.clone();
+        expected.sort();
+
+        comparative_sort(&mut data, 3, StatsRecorder::default());
+        assert_eq!(data, expected);
     }
-}
 
-impl RadixKey for f64 {
-    const LEVELS: usize = 8;
+    #[test]
+    fn test_tuple_keys_sort_lexicographically() {
+        let mut data: Vec<(i8, f32, u16)> = vec![
+            (1, -1.0, 3),
+            (-2, 5.0, 0),
+            (1, -1.0, 2),
+            (1, -3.5, 9),
+            (-2, -0.5, 1),
+        ];
+        let mut expected = data.clone();
+        expected.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+        comparative_sort(&mut data, 6, StatsRecorder::default());
+        assert_eq!(data, expected);
+    }
 
-    #[inline]
-    fn get_level(&self, level: usize) -> u8 {
-        let mut s = self.to_bits() as i64;
-        s ^= (((s >> 63) as u64) >> 1) as i64;
+    #[test]
+    fn test_array_keys_sort_last_element_first() {
+        let mut data: Vec<[i16; 3]> = (0..500i64)
+            .map(|i| {
+                let v = i.wrapping_mul(0x9e37_79b9_7f4a_7c15);
+                [(v >> 8) as i16, (v >> 24) as i16, (v % 5) as i16]
+            })
+            .collect();
+        let mut expected = data.clone();
+        expected.sort_by_key(|a| (a[2], a[1], a[0]));
+
+        comparative_sort(&mut data, <[i16; 3]>::LEVELS - 1, StatsRecorder::default());
+        assert_eq!(data, expected);
+
+        let mut bytes: Vec<[u8; 4]> = (0..300u32).map(|i| (i * 7919).to_le_bytes()).collect();
+        let mut expected = bytes.clone();
+        expected.sort_by_key(|b| u32::from_le_bytes(*b));
+        comparative_sort(&mut bytes, 3, StatsRecorder::default());
+        assert_eq!(bytes, expected);
+    }
+
+    #[test]
+    fn test_sort_small_matches_level_order() {
+        let data: Vec<i64> = (0..100i64)
+            .map(|i| i.wrapping_mul(-0x61c8_8646_80b5_83eb))
+            .collect();
+        let mut by_levels = data.clone();
+        sort_by_levels(&mut by_levels, i64::LEVELS - 1);
+        let mut small = data.clone();
+        i64::sort_small(&mut small, i64::LEVELS - 1);
+        assert_eq!(small, by_levels);
+
+        let data: Vec<FloatKey<f32>> = [3.5, -0.0, 0.0, -1e9, f32::INFINITY, 2.0]
+            .into_iter()
+            .map(FloatKey)
+            .collect();
+        let mut by_levels = data.clone();
+        sort_by_levels(&mut by_levels, 3);
+        let mut small = data.clone();
+        FloatKey::sort_small(&mut small, 3);
+        assert_eq!(small, by_levels);
+    }
+
+    #[test]
+    fn test_composite_keys_sort_by_primary_then_secondary() {
+        // Material id then Morton code
+        let mut data: Vec<CompositeKey<u16, u64>> = (0..500u64)
+            .map(|i| CompositeKey::new((i % 7) as u16, i.wrapping_mul(0x9e37_79b9_7f4a_7c15)))
+            .collect();
+        let mut expected = data.clone();
+        expected.sort_by_key(|k| (k.primary, k.secondary));
+
+        comparative_sort(
+            &mut data,
+            CompositeKey::<u16, u64>::LEVELS - 1,
+            StatsRecorder::default(),
+        );
+        assert_eq!(data, expected);
+    }
 
-        ((s ^ i64::MIN) >> (level * 8)) as u8
+    #[cfg(feature = "derive")]
+    mod derive {
+        use super::*;
+        use crate::{par::Scheduler, radix::sorter::sort_with};
+
+        #[derive(RadixKey, Clone, Copy, Debug, PartialEq)]
+        struct Named {
+            material: u16,
+            depth: f32,
+            #[radix_key(skip)]
+            payload: u32,
+        }
+
+        #[derive(RadixKey, Clone, Copy, Debug, PartialEq)]
+        struct Tuple(i8, u64);
+
+        #[derive(RadixKey, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+        #[radix_key(ord)]
+        struct Ordered {
+            high: u32,
+            low: u16,
+        }
+
+        fn values(n: u64) -> impl Iterator<Item = u64> {
+            (0..n).map(|i| i.wrapping_mul(0x9e37_79b9_7f4a_7c15))
+        }
+
+        #[test]
+        fn test_derive_named_struct_round_trip() {
+            assert_eq!(Named::LEVELS, 6);
+            let mut data: Vec<Named> = values(3000)
+                .enumerate()
+                .map(|(i, v)| Named {
+                    material: (v % 5) as u16,
+                    depth: (v >> 40) as f32 - 1000.0,
+                    payload: i as u32,
+                })
+                .collect();
+            let mut expected = data.clone();
+            expected.sort_by(|a, b| {
+                (a.material, a.depth)
+                    .partial_cmp(&(b.material, b.depth))
+                    .unwrap()
+            });
+
+            sort_with(Scheduler::SequentialOptimized, &mut data);
+            let key = |n: &Named| (n.material, n.depth.to_bits());
+            assert!(data.iter().map(key).eq(expected.iter().map(key)));
+            // Skipped fields are carried along with their item
+            let mut payloads: Vec<_> = data.iter().map(|n| n.payload).collect();
+            payloads.sort_unstable();
+            assert!(payloads.into_iter().eq(0..3000));
+        }
+
+        #[test]
+        fn test_derive_tuple_struct_round_trip() {
+            assert_eq!(Tuple::LEVELS, 9);
+            let mut data: Vec<Tuple> = values(3000).map(|v| Tuple(v as i8, v >> 3)).collect();
+            let mut expected = data.clone();
+            expected.sort_by_key(|t| (t.0, t.1));
+
+            sort_with(Scheduler::SequentialOptimized, &mut data);
+            assert_eq!(data, expected);
+        }
+
+        #[test]
+        fn test_derive_ord_round_trip() {
+            assert_eq!(Ordered::LEVELS, 6);
+            let mut data: Vec<Ordered> = values(3000)
+                .map(|v| Ordered {
+                    high: (v >> 48) as u32 % 64,
+                    low: v as u16,
+                })
+                .collect();
+            let mut expected = data.clone();
+            expected.sort();
+
+            sort_with(Scheduler::SequentialOptimized, &mut data);
+            assert_eq!(data, expected);
+
+            // `ord` sorts small buckets with `Ord`, which agrees with comparing level by level
+            let mut small = data[..64].to_vec();
+            small.reverse();
+            let mut by_levels = small.clone();
+            sort_by_levels(&mut by_levels, Ordered::LEVELS - 1);
+            Ordered::sort_small(&mut small, Ordered::LEVELS - 1);
+            assert_eq!(small, by_levels);
+        }
     }
 }
@@ -0,0 +1,159 @@
+//! `recombinating_sort` is a multi-threaded, out-of-place algorithm.
+//!
+//! 1. Split the input into tiles, one or more per thread
+//! 2. Scatter each tile by the current digit into the matching region of a scratch buffer
+//! 3. For each output bucket, in parallel, gather that bucket's part of every sorted tile back into the
+//!    input
+//!
+//! ## Characteristics
+//!
+//!  * out-of-place
+//!  * multi-threaded
+//!  * unstable
+//!
+//! ## Performance
+//!
+//! This is usually faster than `regions_sort` as both passes are simple, fully parallel copies. It
+//! needs a scratch buffer as large as the input, so very large inputs are better served by the
+//! in-place `regions_sort`.
+
+use std::mem;
+
+use crate::par::Scheduler;
+use crate::radix::{
+    lsb_sort::lsb_sort,
+    radix_key::RadixKey,
+    sort_utils::{get_prefix_sums, grow_tmp_bucket, SortBuffers},
+    sorter::{director, SortContext},
+    stats::SortPass,
+};
+
+pub fn recombinating_sort<T>(
+    sch: Scheduler,
+    bucket: &mut [T],
+    tmp_bucket: &mut [T],
+    counts: &[usize; 256],
+    tile_counts: &[[usize; 256]],
+    tile_size: usize,
+    level: usize,
+) where
+    T: RadixKey + Sized + Send + Copy + Sync,
+{
+    crate::scope!("recombinating_sort");
+    let threads = sch.current_num_threads();
+    {
+        let bucket: &[T] = bucket;
+        sch.par_chunks_mut(
+            tmp_bucket,
+            &|chunk_id, tmp_chunk| {
+                let start = chunk_id * tile_size;
+                let chunk = &bucket[start..start + tmp_chunk.len()];
+                lsb_sort(chunk, tmp_chunk, &tile_counts[chunk_id], level);
+            },
+            tile_size,
+        );
+    }
+
+    let tile_sums: Vec<[usize; 256]> = tile_counts.iter().map(get_prefix_sums).collect();
+
+    let mut global_chunks: Vec<&mut [T]> = Vec::with_capacity(256);
+    let mut rem_bucket = bucket;
+    for &count in counts {
+        let (chunk, rem) = mem::take(&mut rem_bucket).split_at_mut(count);
+        global_chunks.push(chunk);
+        rem_bucket = rem;
+    }
+
+    sch.par_map(
+        &mut global_chunks,
+        &|b, global_chunk| {
+            crate::scope!("recombine");
+            let mut write_offset = 0;
+            for (tile, (counts, sums)) in tile_counts.iter().zip(&tile_sums).enumerate() {
+                let read_start = tile * tile_size + sums[b];
+                let read_end = read_start + counts[b];
+                let write_end = write_offset + counts[b];
+                global_chunk[write_offset..write_end]
+                    .copy_from_slice(&tmp_bucket[read_start..read_end]);
+                write_offset = write_end;
+            }
+        },
+        threads as u32,
+    );
+}
+
+/// Sort `bucket` with the tile counts in `buffers.tile_counts`, using `buffers` for the scratch buffer.
+pub(crate) fn recombinating_sort_adapter<T>(
+    ctx: &SortContext<T>,
+    bucket: &mut [T],
+    counts: &[usize; 256],
+    tile_size: usize,
+    level: usize,
+    recursion_depth: u32,
+    buffers: &mut SortBuffers<T>,
+) where
+    T: RadixKey + Sized + Send + Copy + Sync,
+{
+    if bucket.len() < 2 {
+        return;
+    }
+
+    let tmp_bucket = grow_tmp_bucket(&mut buffers.tmp_bucket, bucket);
+    let start = ctx.stats.start();
+    recombinating_sort(
+        ctx.sch,
+        bucket,
+        tmp_bucket,
+        counts,
+        &buffers.tile_counts,
+        tile_size,
+        level,
+    );
+    ctx.stats
+        .record(start, SortPass::Recombinating, level, bucket.len());
+
+    if level == 0 {
+        return;
+    }
+
+    director(ctx, bucket, counts, level - 1, recursion_depth);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::radix::sort_utils::{aggregate_tile_counts, get_tile_counts_into};
+    use crate::radix::sorter::BufferPool;
+
+    #[test]
+    fn test_recombinating_sort_adapter_sorts_multiple_tiles() {
+        let mut data: Vec<u32> = (0..10_000u32)
+            .map(|i| i.wrapping_mul(2_654_435_761))
+            .collect();
+        let mut expected = data.clone();
+        expected.sort_unstable();
+
+        let tile_size = 999;
+        let level = u32::LEVELS - 1;
+        let mut buffers = SortBuffers::default();
+        get_tile_counts_into(
+            Scheduler::SequentialOptimized,
+            &data,
+            tile_size,
+            level,
+            &mut buffers.tiles,
+            &mut buffers.tile_counts,
+        );
+        let counts = aggregate_tile_counts(&buffers.tile_counts);
+        recombinating_sort_adapter(
+            &SortContext::new(Scheduler::SequentialOptimized, &BufferPool::default()),
+            &mut data,
+            &counts,
+            tile_size,
+            level,
+            0,
+            &mut buffers,
+        );
+        assert_eq!(data, expected);
+    }
+}
@@ -10,8 +10,8 @@
 //! 2. Compute counts for each bucket and sort each bucket in-place
 //! 3. Generate global counts
 //! 4. Generate Graph & Sort
-//!    4.1 List outbound regions for each country
-//!    4.2 For each country (C):
+//!    4.1 List outbound regions for each country, each tile in parallel
+//!    4.2 For each country (C), in parallel:
 //!    -- 4.2.1: List the inbounds for C (filter outbounds for each other country by destination: C)
 //!    -- 4.2.2: For each thread:
 //!    ------ 4.2.2.1: Pop an item off the inbound (country: I) & outbound (country: O) queues for C
@@ -38,16 +38,15 @@
 //! This may not be entirely the same as the algorithm described by the research paper. Some steps
 //! did not seem to provide any value, and have been omitted for performance reasons.
 
-use partition::partition_index;
-
 use std::cmp::{min, Ordering};
 
+use crate::par::Scheduler;
 use crate::radix::{
     radix_key::RadixKey,
-    radix_scheduler,
     ska_sort::ska_sort,
     sort_utils::{get_end_offsets, get_prefix_sums},
-    sorter::director,
+    sorter::{director, SortContext},
+    stats::SortPass,
 };
 
 /// Operation represents a pair of edges, which have content slices that need to be swapped.
@@ -64,29 +63,47 @@ struct Edge<'bucket, T> {
     slice: &'bucket mut [T],
 }
 
-/// generate_outbounds generates a Vec for each country containing all the outbound edges
-/// for that country.
-fn generate_outbounds<'bucket, T>(
-    bucket: &'bucket mut [T],
-    local_counts: &[[usize; 256]],
+/// The edges a country pairs up in one pass, and the operations it paired them into. Unmatched edges are
+/// left in `outbounds` and `inbounds`.
+struct CountryOperations<'bucket, T> {
+    outbounds: Vec<Edge<'bucket, T>>,
+    inbounds: Vec<Edge<'bucket, T>>,
+    operations: Vec<Operation<'bucket, T>>,
+}
+
+impl<T> Default for CountryOperations<'_, T> {
+    fn default() -> Self {
+        Self {
+            outbounds: Vec::new(),
+            inbounds: Vec::new(),
+            operations: Vec::new(),
+        }
+    }
+}
+
+/// tile_outbounds lists the outbound edges within a single tile, which starts at `tile_start` in the
+/// full bucket and has already been sorted locally by `local_counts`.
+fn tile_outbounds<'bucket, T>(
+    tile: &'bucket mut [T],
+    tile_start: usize,
+    local_counts: &[usize; 256],
     global_counts: &[usize; 256],
+    global_ends: &[usize; 256],
 ) -> Vec<Edge<'bucket, T>> {
-    crate::scope!("generate_outbounds");
-    let mut outbounds: Vec<Edge<T>> = Vec::new();
-    let mut rem_bucket = bucket;
-    let mut local_bucket = 0;
-    let mut local_country = 0;
+    let mut outbounds = Vec::new();
+    let mut rem_bucket = tile;
+    let mut pos = tile_start;
     let mut global_country = 0;
-    let mut target_global_dist = global_counts[0];
-    let mut target_local_dist = local_counts[0][0];
 
-    while !(global_country == 255 && local_country == 255 && local_bucket == local_counts.len() - 1)
-    {
-        let step = min(target_global_dist, target_local_dist);
+    for (local_country, &count) in local_counts.iter().enumerate() {
+        let mut local_rem = count;
+        while local_rem > 0 {
+            while global_counts[global_country] == 0 || global_ends[global_country] <= pos {
+                global_country += 1;
+            }
 
-        // 1. Add the current step to the outbounds
-        if step != 0 {
-            let (slice, rem) = rem_bucket.split_at_mut(step);
+            let step = min(local_rem, global_ends[global_country] - pos);
+            let (slice, rem) = std::mem::take(&mut rem_bucket).split_at_mut(step);
             rem_bucket = rem;
 
             if local_country != global_country {
@@ -96,79 +113,77 @@ fn generate_outbounds<'bucket, T>(
                     slice,
                 });
             }
-        }
 
-        // 2. Update target_global_dist
-        if step == target_global_dist && global_country < 255 {
-            global_country += 1;
-            target_global_dist = global_counts[global_country];
-        } else {
-            target_global_dist -= step;
-        }
-
-        // 3. Update target_local_dist
-        if step == target_local_dist
-            && !(local_bucket == local_counts.len() - 1 && local_country == 255)
-        {
-            if local_country < 255 {
-                local_country += 1;
-            } else {
-                local_bucket += 1;
-                local_country = 0;
-            }
-
-            target_local_dist = local_counts[local_bucket][local_country];
-        } else {
-            target_local_dist -= step;
+            pos += step;
+            local_rem -= step;
         }
     }
 
     outbounds
 }
 
-/// list_operations takes the lists of outbounds and turns it into a list of swaps to perform
-fn list_operations<T>(
-    country: usize,
-    mut outbounds: Vec<Edge<T>>,
-) -> (Vec<Edge<T>>, Vec<Operation<T>>) {
-    crate::scope!("list_operations");
-    // 1. Extract current country outbounds from full outbounds list
-    // NOTE(nathan): Partitioning a single array benched faster than
-    // keeping an array per country (256 arrays total).
-    let ob = partition_index(&mut outbounds, |e| e.init != country);
-    let mut current_outbounds = outbounds.split_off(ob);
-
-    // 2. Calculate inbounds for country
-    let p = partition_index(&mut outbounds, |e| e.dst != country);
-    let mut inbounds = outbounds.split_off(p);
-
-    // 3. Pair up inbounds & outbounds into an operation, returning unmatched data to the working arrays
-    let mut operations = Vec::new();
+/// generate_outbounds generates a Vec containing all the outbound edges of every country, in the order
+/// they appear in the bucket. Each tile is walked in parallel.
+fn generate_outbounds<'bucket, T>(
+    sch: Scheduler,
+    bucket: &'bucket mut [T],
+    local_counts: &[[usize; 256]],
+    global_counts: &[usize; 256],
+    tile_size: usize,
+) -> Vec<Edge<'bucket, T>>
+where
+    T: Send + Sync,
+{
+    crate::scope!("generate_outbounds");
+    let prefix_sums = get_prefix_sums(global_counts);
+    let global_ends = get_end_offsets(global_counts, &prefix_sums);
+
+    let mut tiles: Vec<(&mut [T], Vec<Edge<T>>)> = bucket
+        .chunks_mut(tile_size)
+        .map(|tile| (tile, Vec::new()))
+        .collect();
+
+    let tile_count = tiles.len();
+    sch.par_map(
+        &mut tiles,
+        &|tile_id, (tile, outbounds)| {
+            *outbounds = tile_outbounds(
+                std::mem::take(tile),
+                tile_id * tile_size,
+                &local_counts[tile_id],
+                global_counts,
+                &global_ends,
+            );
+        },
+        tile_count as u32,
+    );
 
-    loop {
-        let i = match inbounds.pop() {
-            Some(i) => i,
-            None => {
-                outbounds.append(&mut current_outbounds);
-                break;
-            }
-        };
+    let mut outbounds = Vec::with_capacity(tiles.iter().map(|t| t.1.len()).sum());
+    for (_, mut tile_outbounds) in tiles {
+        outbounds.append(&mut tile_outbounds);
+    }
 
-        let o = match current_outbounds.pop() {
-            Some(o) => o,
-            None => {
-                outbounds.push(i);
-                outbounds.append(&mut inbounds);
-                break;
-            }
-        };
+    outbounds
+}
 
+/// list_operations pairs up the inbounds & outbounds of a single country into a list of swaps to perform
+fn list_operations<T>(country: &mut CountryOperations<T>) {
+    crate::scope!("list_operations");
+    let CountryOperations {
+        outbounds,
+        inbounds,
+        operations,
+    } = country;
+
+    while !inbounds.is_empty() && !outbounds.is_empty() {
+        let i = inbounds.pop().unwrap();
+        let o = outbounds.pop().unwrap();
         let op = match i.slice.len().cmp(&o.slice.len()) {
             Ordering::Equal => Operation(i, o),
             Ordering::Less => {
                 let (sl, rem) = o.slice.split_at_mut(i.slice.len());
 
-                current_outbounds.push(Edge {
+                outbounds.push(Edge {
                     dst: o.dst,
                     init: o.init,
                     slice: rem,
@@ -203,12 +218,10 @@ fn list_operations<T>(
 
         operations.push(op);
     }
-
-    // 4. Return the paired operations
-    (outbounds, operations)
 }
 
 pub fn regions_sort<T>(
+    sch: Scheduler,
     bucket: &mut [T],
     counts: &[usize; 256],
     tile_counts: &[[usize; 256]],
@@ -218,7 +231,7 @@ pub fn regions_sort<T>(
     T: RadixKey + Sized + Send + Copy + Sync,
 {
     crate::scope!("regions_sort");
-    let threads = radix_scheduler().current_num_threads();
+    let threads = sch.current_num_threads();
 
     // Original rayon version:
     //bucket
@@ -230,7 +243,7 @@ pub fn regions_sort<T>(
     //        ska_sort(chunk, &mut prefix_sums, &end_offsets, level);
     //    });
 
-    radix_scheduler().par_chunks_mut(
+    sch.par_chunks_mut(
         bucket,
         &|chunk_id, chunk| {
             let counts = tile_counts[chunk_id];
@@ -241,8 +254,10 @@ pub fn regions_sort<T>(
         tile_size,
     );
 
-    let mut outbounds = generate_outbounds(bucket, tile_counts, counts);
+    let mut outbounds = generate_outbounds(sch, bucket, tile_counts, counts, tile_size);
     let mut operations = Vec::new();
+    let mut countries: Vec<CountryOperations<T>> =
+        (0..256).map(|_| CountryOperations::default()).collect();
 
     // This loop calculates and executes all operations that can be done in parallel, each pass.
     loop {
@@ -250,11 +265,31 @@ pub fn regions_sort<T>(
             break;
         }
 
-        // List out all the operations that need to be executed in this pass
-        for country in 0..256 {
-            let (new_outbounds, mut new_ops) = list_operations(country, outbounds);
-            outbounds = new_outbounds;
-            operations.append(&mut new_ops);
+        // List out all the operations that need to be executed in this pass. An edge is both an
+        // outbound of the country it lies in and an inbound of its destination, so hand each edge to
+        // the lower of the two to let every country pair its edges in parallel. The lowest country
+        // with any edges holds both its inbounds and outbounds, so every pass makes progress.
+        {
+            crate::scope!("split edges by country");
+            for edge in outbounds.drain(..) {
+                if edge.init < edge.dst {
+                    countries[edge.init].outbounds.push(edge);
+                } else {
+                    countries[edge.dst].inbounds.push(edge);
+                }
+            }
+        }
+
+        sch.par_map(
+            &mut countries,
+            &|_, country| list_operations(country),
+            threads as u32,
+        );
+
+        for country in &mut countries {
+            outbounds.append(&mut country.outbounds);
+            outbounds.append(&mut country.inbounds);
+            operations.append(&mut country.operations);
         }
 
         if operations.is_empty() {
@@ -272,7 +307,7 @@ pub fn regions_sort<T>(
         //     }
         // });
 
-        radix_scheduler().par_chunks_mut(
+        sch.par_chunks_mut(
             &mut operations,
             &|_chunk_id, chunk| {
                 crate::scope!("swap_with_slice");
@@ -295,6 +330,7 @@ pub fn regions_sort<T>(
 }
 
 pub(crate) fn regions_sort_adapter<T>(
+    ctx: &SortContext<T>,
     bucket: &mut [T],
     counts: &[usize; 256],
     tile_counts: &[[usize; 256]],
@@ -308,11 +344,51 @@ pub(crate) fn regions_sort_adapter<T>(
         return;
     }
 
-    regions_sort(bucket, counts, tile_counts, tile_size, level);
+    let start = ctx.stats.start();
+    regions_sort(ctx.sch, bucket, counts, tile_counts, tile_size, level);
+    ctx.stats
+        .record(start, SortPass::Regions, level, bucket.len());
 
     if level == 0 {
         return;
     }
 
-    director(bucket, counts, level - 1, recursion_depth);
+    director(ctx, bucket, counts, level - 1, recursion_depth);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::radix::sort_utils::{aggregate_tile_counts, get_tile_counts};
+    use crate::radix::sorter::BufferPool;
+
+    #[test]
+    fn test_regions_sort_adapter_sorts() {
+        let random: Vec<u32> = (0..20_000u32)
+            .map(|i| i.wrapping_mul(2_654_435_761))
+            .collect();
+        let few_unique: Vec<u32> = random.iter().map(|v| (v % 5) << 24).collect();
+
+        for data in [random, few_unique] {
+            let mut data = data;
+            let mut expected = data.clone();
+            expected.sort_unstable();
+
+            let tile_size = 3_000;
+            let level = u32::LEVELS - 1;
+            let (tile_counts, _) =
+                get_tile_counts(Scheduler::SequentialOptimized, &data, tile_size, level);
+            let counts = aggregate_tile_counts(&tile_counts);
+            regions_sort_adapter(
+                &SortContext::new(Scheduler::SequentialOptimized, &BufferPool::default()),
+                &mut data,
+                &counts,
+                &tile_counts,
+                tile_size,
+                level,
+                0,
+            );
+            assert_eq!(data, expected);
+        }
+    }
 }
@@ -0,0 +1,220 @@
+//! `scanning_sort` is a multi-threaded, in-place algorithm.
+//!
+//! 1. Compute the counts for the input and split it into one output bucket per digit
+//! 2. Spawn one scanner per thread. Each scanner repeatedly:
+//!    2.1 Locks any bucket that isn't already locked or finished
+//!    2.2 Reads a block of unread items from the bucket into a per-digit local stash
+//!    2.3 Writes stashed items that belong to that bucket back over the already read items
+//! 3. Once every bucket is full, the input is sorted by the current digit
+//!
+//! ## Characteristics
+//!
+//!  * in-place (apart from the small per-thread stashes)
+//!  * multi-threaded
+//!  * unstable
+//!
+//! ## Performance
+//!
+//! Scanners never wait on each other, they skip over locked buckets instead. This makes it robust to
+//! uneven thread progress, but the constant lock traffic makes it stress a pool very differently than
+//! the fork-join style algorithms.
+
+use std::{
+    cmp::{max, min},
+    mem,
+    sync::Mutex,
+};
+
+use partition::partition_index;
+
+use crate::par::Scheduler;
+use crate::radix::{
+    radix_key::RadixKey,
+    sorter::{director, SortContext},
+    stats::SortPass,
+};
+
+struct ScannerBucketInner<'bucket, T> {
+    write_head: usize,
+    read_head: usize,
+    chunk: &'bucket mut [T],
+    locally_partitioned: bool,
+}
+
+struct ScannerBucket<'bucket, T> {
+    index: usize,
+    len: usize,
+    inner: Mutex<ScannerBucketInner<'bucket, T>>,
+}
+
+fn get_scanner_buckets<'bucket, T>(
+    counts: &[usize; 256],
+    bucket: &'bucket mut [T],
+) -> Vec<ScannerBucket<'bucket, T>> {
+    let mut scanner_buckets = Vec::with_capacity(256);
+    let mut rem_bucket = bucket;
+
+    for (index, &count) in counts.iter().enumerate() {
+        if count == 0 {
+            continue;
+        }
+
+        let (chunk, rem) = mem::take(&mut rem_bucket).split_at_mut(count);
+        rem_bucket = rem;
+        scanner_buckets.push(ScannerBucket {
+            index,
+            len: count,
+            inner: Mutex::new(ScannerBucketInner {
+                write_head: 0,
+                read_head: 0,
+                chunk,
+                locally_partitioned: false,
+            }),
+        });
+    }
+
+    scanner_buckets
+}
+
+fn scanner_thread<T>(scanner_buckets: &[ScannerBucket<T>], level: usize, scanner_read_size: usize)
+where
+    T: RadixKey + Copy,
+{
+    crate::scope!("scanner_thread");
+    let mut stash: Vec<Vec<T>> = (0..256).map(|_| Vec::with_capacity(128)).collect();
+    let mut finished_count = 0;
+    let mut finished_map = [false; 256];
+
+    'outer: loop {
+        for m in scanner_buckets {
+            if finished_map[m.index] {
+                continue;
+            }
+
+            let mut guard = match m.inner.try_lock() {
+                Ok(guard) => guard,
+                Err(_) => continue,
+            };
+
+            if guard.write_head >= m.len {
+                finished_count += 1;
+                finished_map[m.index] = true;
+                if finished_count == scanner_buckets.len() {
+                    break 'outer;
+                }
+
+                continue;
+            }
+
+            // Items already in the right bucket never need to move, skip over them up front
+            if !guard.locally_partitioned {
+                guard.locally_partitioned = true;
+                let index = m.index as u8;
+                let start = guard.read_head;
+                let partition_point =
+                    partition_index(&mut guard.chunk[start..], |v| v.get_level(level) == index);
+                guard.read_head += partition_point;
+                guard.write_head += partition_point;
+            }
+
+            let to_read = min(m.len - guard.read_head, scanner_read_size);
+            if to_read > 0 {
+                let start = guard.read_head;
+                for item in &guard.chunk[start..start + to_read] {
+                    stash[item.get_level(level) as usize].push(*item);
+                }
+                guard.read_head += to_read;
+            }
+
+            let to_write = min(stash[m.index].len(), guard.read_head - guard.write_head);
+            if to_write == 0 {
+                continue;
+            }
+
+            let split = stash[m.index].len() - to_write;
+            let start = guard.write_head;
+            guard.chunk[start..start + to_write].copy_from_slice(&stash[m.index][split..]);
+            stash[m.index].truncate(split);
+            guard.write_head += to_write;
+
+            if guard.write_head >= m.len {
+                finished_count += 1;
+                finished_map[m.index] = true;
+                if finished_count == scanner_buckets.len() {
+                    break 'outer;
+                }
+            }
+        }
+    }
+}
+
+pub fn scanning_sort<T>(sch: Scheduler, bucket: &mut [T], counts: &[usize; 256], level: usize)
+where
+    T: RadixKey + Sized + Send + Copy + Sync,
+{
+    crate::scope!("scanning_sort");
+    let len = bucket.len();
+    let scanner_buckets = get_scanner_buckets(counts, bucket);
+    let threads = min(sch.current_num_threads(), scanner_buckets.len());
+    let scaling_factor = max(1, (len.div_ceil(threads) as f32).log2() as usize);
+    let scanner_read_size = max(1, 32_768 / scaling_factor);
+
+    let mut scanners = vec![(); threads];
+    sch.par_map(
+        &mut scanners,
+        &|_, _| scanner_thread(&scanner_buckets, level, scanner_read_size),
+        threads as u32,
+    );
+}
+
+pub(crate) fn scanning_sort_adapter<T>(
+    ctx: &SortContext<T>,
+    bucket: &mut [T],
+    counts: &[usize; 256],
+    level: usize,
+    recursion_depth: u32,
+) where
+    T: RadixKey + Sized + Send + Copy + Sync,
+{
+    if bucket.len() < 2 {
+        return;
+    }
+
+    let start = ctx.stats.start();
+    scanning_sort(ctx.sch, bucket, counts, level);
+    ctx.stats
+        .record(start, SortPass::Scanning, level, bucket.len());
+
+    if level == 0 {
+        return;
+    }
+
+    director(ctx, bucket, counts, level - 1, recursion_depth);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::radix::sort_utils::get_counts;
+    use crate::radix::sorter::BufferPool;
+
+    #[test]
+    fn test_scanning_sort_adapter_sorts() {
+        let mut data: Vec<u32> = (0..10_000u32)
+            .map(|i| i.wrapping_mul(2_654_435_761))
+            .collect();
+        let mut expected = data.clone();
+        expected.sort_unstable();
+
+        let level = u32::LEVELS - 1;
+        let (counts, _) = get_counts(&data, level);
+        scanning_sort_adapter(
+            &SortContext::new(Scheduler::SequentialOptimized, &BufferPool::default()),
+            &mut data,
+            &counts,
+            level,
+            0,
+        );
+        assert_eq!(data, expected);
+    }
+}
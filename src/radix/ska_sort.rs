@@ -22,11 +22,7 @@
 
 use partition::partition_index;
 
-use crate::radix::{
-    radix_key::RadixKey,
-    sort_utils::{get_end_offsets, get_prefix_sums},
-    sorter::director,
-};
+use crate::radix::radix_key::RadixKey;
 
 pub fn ska_sort<T>(
     bucket: &mut [T],
@@ -91,27 +87,3 @@ pub fn ska_sort<T>(
         }
     }
 }
-
-pub(crate) fn ska_sort_adapter<T>(
-    bucket: &mut [T],
-    counts: &[usize; 256],
-    level: usize,
-    recursion_depth: u32,
-) where
-    T: RadixKey + Sized + Send + Copy + Sync,
-{
-    if bucket.len() < 2 {
-        return;
-    }
-
-    let mut prefix_sums = get_prefix_sums(counts);
-    let end_offsets = get_end_offsets(counts, &prefix_sums);
-
-    ska_sort(bucket, &mut prefix_sums, &end_offsets, level);
-
-    if level == 0 {
-        return;
-    }
-
-    director(bucket, counts, level - 1, recursion_depth);
-}
@@ -1,8 +1,28 @@
-use std::sync::mpsc::channel;
+use std::mem::MaybeUninit;
 
-use bytemuck::zeroed_vec;
+use crate::par::Scheduler;
+use crate::radix::{radix_key::RadixKey, tuner::tuner};
 
-use crate::radix::{radix_key::RadixKey, radix_scheduler};
+/// Collect `f(i)` for every `i` in `0..len` into a Vec, filled in parallel without zeroing it first.
+#[inline]
+pub fn par_collect_vec<T, F>(sch: Scheduler, len: usize, chunks: u32, f: &F) -> Vec<T>
+where
+    T: Send + Sync,
+    F: Fn(usize) -> T + Send + Sync,
+{
+    crate::scope!("par_collect_vec");
+    let mut v = Vec::with_capacity(len);
+    sch.par_map(
+        &mut v.spare_capacity_mut()[..len],
+        &|i, item: &mut MaybeUninit<T>| {
+            item.write(f(i));
+        },
+        chunks,
+    );
+    // SAFETY: par_map calls the closure once for every index, so all elements in 0..len were written above.
+    unsafe { v.set_len(len) };
+    v
+}
 
 #[inline]
 pub fn get_prefix_sums(counts: &[usize; 256]) -> [usize; 256] {
@@ -29,20 +49,19 @@ pub fn get_end_offsets(counts: &[usize; 256], prefix_sums: &[usize; 256]) -> [us
 }
 
 #[inline]
-pub fn par_get_counts_with_ends<T>(bucket: &[T], level: usize) -> ([usize; 256], bool, u8, u8)
+pub fn par_get_counts_with_ends<T>(sch: Scheduler, bucket: &[T], level: usize) -> TileCounts
 where
     T: RadixKey + Sized + Send + Sync,
 {
     crate::scope!("par_get_counts_with_ends");
-    if bucket.len() < 400_000 {
+    if bucket.len() < tuner().par_count_threshold() {
         return get_counts_with_ends(bucket, level);
     }
 
-    let threads = radix_scheduler().current_num_threads();
+    let threads = sch.current_num_threads();
     let chunk_divisor = 8;
     let chunk_size = (bucket.len() / threads / chunk_divisor) + 1;
     let len = bucket.len().div_ceil(chunk_size);
-    let (tx, rx) = channel();
 
     // Original rayon version:
     //let chunks = bucket.par_chunks(chunk_size);
@@ -53,75 +72,84 @@ where
     //        .unwrap();
     //});
 
-    radix_scheduler().par_chunks(
-        bucket,
-        &|i, chunk| {
-            let counts = get_counts_with_ends(chunk, level);
-            tx.send((i, counts.0, counts.1, counts.2, counts.3))
-                .unwrap();
+    // Each chunk writes its counts into its own slot
+    let mut chunk_counts: Vec<TileCounts> = {
+        crate::scope!("alloc chunk counts");
+        vec![([0; 256], false, false, 0, 0); len]
+    };
+    sch.par_map(
+        &mut chunk_counts,
+        &|i, counts| {
+            let start = i * chunk_size;
+            let end = (start + chunk_size).min(bucket.len());
+            *counts = get_counts_with_ends(&bucket[start..end], level);
         },
-        chunk_size,
+        len as u32,
     );
 
+    // Sum the slots in parallel, each task reducing a range of digits over every chunk
     let mut msb_counts = [0usize; 256];
-    let mut already_sorted = true;
-    let mut boundaries: Vec<(u8, u8)> = {
-        crate::scope!("alloc boundaries");
-        zeroed_vec(len)
-    };
+    {
+        crate::scope!("reduce chunk counts");
+        let digits_per_task = 256usize.div_ceil(threads).max(32);
+        let chunk_counts = &chunk_counts;
+        sch.par_chunks_mut(
+            &mut msb_counts,
+            &|task, digits| {
+                let first = task * digits_per_task;
+                for (d, out) in digits.iter_mut().enumerate() {
+                    *out = chunk_counts.iter().map(|c| c.0[first + d]).sum();
+                }
+            },
+            digits_per_task,
+        );
+    }
 
-    for _ in 0..len {
-        let (i, counts, chunk_sorted, start, end) = rx.recv().unwrap();
+    let mut already_sorted = chunk_counts.iter().all(|c| c.1);
+    let mut reverse_sorted = chunk_counts.iter().all(|c| c.2);
 
-        if !chunk_sorted {
+    // Check the boundaries of each counted chunk, to see if the full bucket
+    // is already sorted, or sorted in reverse
+    for w in chunk_counts.windows(2) {
+        if w[1].3 < w[0].4 {
             already_sorted = false;
         }
-
-        boundaries[i].0 = start;
-        boundaries[i].1 = end;
-
-        for (i, c) in counts.iter().enumerate() {
-            msb_counts[i] += *c;
-        }
-    }
-
-    // Check the boundaries of each counted chunk, to see if the full bucket
-    // is already sorted
-    if already_sorted {
-        for w in boundaries.windows(2) {
-            if w[1].0 < w[0].1 {
-                already_sorted = false;
-                break;
-            }
+        if w[1].3 > w[0].4 {
+            reverse_sorted = false;
         }
     }
 
     (
         msb_counts,
         already_sorted,
-        boundaries[0].0,
-        boundaries[boundaries.len() - 1].1,
+        reverse_sorted,
+        chunk_counts[0].3,
+        chunk_counts[len - 1].4,
     )
 }
 
+/// Count the digits at `level`, also returning whether the bucket is ordered by that digit ascending
+/// or descending, and the digits of the first and last item.
 #[inline]
-pub fn get_counts_with_ends<T>(bucket: &[T], level: usize) -> ([usize; 256], bool, u8, u8)
+pub fn get_counts_with_ends<T>(bucket: &[T], level: usize) -> TileCounts
 where
     T: RadixKey,
 {
     crate::scope!("get_counts_with_ends");
     let mut already_sorted = true;
+    let mut reverse_sorted = true;
     let mut continue_from = bucket.len();
     let mut counts_1 = [0usize; 256];
-    let mut last = 0usize;
+    let mut last = bucket[0].get_level(level) as usize;
 
     for (i, item) in bucket.iter().enumerate() {
         let b = item.get_level(level) as usize;
         counts_1[b] += 1;
 
-        if b < last {
+        already_sorted &= b >= last;
+        reverse_sorted &= b <= last;
+        if !already_sorted && !reverse_sorted {
             continue_from = i + 1;
-            already_sorted = false;
             break;
         }
 
@@ -132,15 +160,35 @@ where
         return (
             counts_1,
             already_sorted,
+            reverse_sorted,
             bucket[0].get_level(level),
             last as u8,
         );
     }
 
+    let rest = T::count_digits(&bucket[continue_from..], level);
+    for (count, rest) in counts_1.iter_mut().zip(rest) {
+        *count += rest;
+    }
+
+    let b_first = bucket.first().unwrap().get_level(level);
+    let b_last = bucket.last().unwrap().get_level(level);
+
+    (counts_1, false, false, b_first, b_last)
+}
+
+/// Count the digits at `level`, reading 4 items at a time into separate tables so consecutive equal
+/// digits don't wait on each other's increments. The default `RadixKey::count_digits`.
+#[inline]
+pub fn count_digits<T>(bucket: &[T], level: usize) -> [usize; 256]
+where
+    T: RadixKey,
+{
+    let mut counts_1 = [0usize; 256];
     let mut counts_2 = [0usize; 256];
     let mut counts_3 = [0usize; 256];
     let mut counts_4 = [0usize; 256];
-    let chunks = bucket[continue_from..].chunks_exact(4);
+    let chunks = bucket.chunks_exact(4);
     let rem = chunks.remainder();
 
     chunks.into_iter().for_each(|chunk| {
@@ -166,28 +214,94 @@ where
         counts_1[i] += counts_4[i];
     }
 
-    let b_first = bucket.first().unwrap().get_level(level);
-    let b_last = bucket.last().unwrap().get_level(level);
-
-    (counts_1, already_sorted, b_first, b_last)
+    counts_1
 }
 
 #[inline]
 pub fn get_counts<T>(bucket: &[T], level: usize) -> ([usize; 256], bool)
+where
+    T: RadixKey,
+{
+    let (counts, sorted, _) = get_counts_with_reverse(bucket, level);
+
+    (counts, sorted)
+}
+
+/// [`get_counts`], also returning whether the bucket is ordered by the digit at `level` descending.
+#[inline]
+pub fn get_counts_with_reverse<T>(bucket: &[T], level: usize) -> ([usize; 256], bool, bool)
 where
     T: RadixKey,
 {
     if bucket.is_empty() {
-        return ([0usize; 256], true);
+        return ([0usize; 256], true, true);
     }
 
-    let (counts, sorted, _, _) = get_counts_with_ends(bucket, level);
+    let (counts, sorted, reversed, _, _) = get_counts_with_ends(bucket, level);
 
-    (counts, sorted)
+    (counts, sorted, reversed)
+}
+
+/// Counts, already sorted, reverse sorted, first digit and last digit of a tile.
+pub type TileCounts = ([usize; 256], bool, bool, u8, u8);
+
+/// Buffers a sort can reuse between calls instead of allocating. Empty until first used.
+pub struct SortBuffers<T> {
+    pub tiles: Vec<TileCounts>,
+    pub tile_counts: Vec<[usize; 256]>,
+    pub tmp_bucket: Vec<T>,
 }
 
+impl<T> Default for SortBuffers<T> {
+    fn default() -> Self {
+        Self {
+            tiles: Vec::new(),
+            tile_counts: Vec::new(),
+            tmp_bucket: Vec::new(),
+        }
+    }
+}
+
+/// Get `bucket.len()` items of scratch from `tmp_bucket` for an out-of-place sort of `bucket`. When it
+/// is too small it is refilled with a copy of `bucket`, so the scratch is always initialized.
 #[inline]
-pub fn get_tile_counts<T>(bucket: &[T], tile_size: usize, level: usize) -> (Vec<[usize; 256]>, bool)
+pub fn grow_tmp_bucket<'a, T: Copy>(tmp_bucket: &'a mut Vec<T>, bucket: &[T]) -> &'a mut [T] {
+    if tmp_bucket.len() < bucket.len() {
+        crate::scope!("grow_tmp_bucket");
+        tmp_bucket.clear();
+        tmp_bucket.extend_from_slice(bucket);
+    }
+    &mut tmp_bucket[..bucket.len()]
+}
+
+#[inline]
+pub fn get_tile_counts<T>(
+    sch: Scheduler,
+    bucket: &[T],
+    tile_size: usize,
+    level: usize,
+) -> (Vec<[usize; 256]>, bool)
+where
+    T: RadixKey + Copy + Sized + Send + Sync,
+{
+    let mut tiles = Vec::new();
+    let mut tile_counts = Vec::new();
+    let (all_sorted, _) =
+        get_tile_counts_into(sch, bucket, tile_size, level, &mut tiles, &mut tile_counts);
+    (tile_counts, all_sorted)
+}
+
+/// [`get_tile_counts`] writing into caller owned buffers. Returns whether the bucket is already sorted,
+/// and whether it is sorted in reverse.
+#[inline]
+pub fn get_tile_counts_into<T>(
+    sch: Scheduler,
+    bucket: &[T],
+    tile_size: usize,
+    level: usize,
+    tiles: &mut Vec<TileCounts>,
+    tile_counts: &mut Vec<[usize; 256]>,
+) -> (bool, bool)
 where
     T: RadixKey + Copy + Sized + Send + Sync,
 {
@@ -200,37 +314,77 @@ where
 
     let tile_count = bucket.len().div_ceil(tile_size);
 
-    let mut tiles: Vec<([usize; 256], bool, u8, u8)> = {
+    {
         crate::scope!("alloc tiles");
-        zeroed_vec(tile_count)
-    };
+        tiles.clear();
+        tiles.resize(tile_count, ([0; 256], false, false, 0, 0));
+    }
 
-    radix_scheduler().par_map(
-        &mut tiles,
+    sch.par_map(
+        tiles,
         &|i, tile| {
             let start = i * tile_size;
             let end = (start + tile_size).min(bucket.len());
-            *tile = par_get_counts_with_ends(&bucket[start..end], level)
+            *tile = par_get_counts_with_ends(sch, &bucket[start..end], level)
         },
         tile_count as u32,
     );
 
-    let mut all_sorted = true;
-
-    if tiles.len() == 1 {
-        // If there is only one tile, we already have a flag for if it is sorted
-        all_sorted = tiles[0].1;
-    } else {
-        // Check if any of the tiles, or any of the tile boundaries are unsorted
-        for tile in tiles.windows(2) {
-            if !tile[0].1 || !tile[1].1 || tile[1].2 < tile[0].3 {
-                all_sorted = false;
-                break;
-            }
+    let mut all_sorted = tiles.iter().all(|tile| tile.1);
+    let mut all_reversed = tiles.iter().all(|tile| tile.2);
+
+    // Check the tile boundaries too
+    for tile in tiles.windows(2) {
+        if tile[1].3 < tile[0].4 {
+            all_sorted = false;
+        }
+        if tile[1].3 > tile[0].4 {
+            all_reversed = false;
         }
     }
 
-    (tiles.into_iter().map(|v| v.0).collect(), all_sorted)
+    tile_counts.clear();
+    tile_counts.extend(tiles.iter().map(|v| v.0));
+
+    (all_sorted, all_reversed)
+}
+
+/// Reverse `bucket` in place, swapping the two halves chunk by chunk in parallel. Halves shorter than
+/// `min_tile_size` are reversed on the current thread.
+#[inline]
+pub fn par_reverse<T>(sch: Scheduler, bucket: &mut [T], min_tile_size: usize)
+where
+    T: Send + Sync,
+{
+    crate::scope!("par_reverse");
+    let half = bucket.len() / 2;
+    let threads = sch.current_num_threads();
+    if half < min_tile_size || threads <= 1 {
+        bucket.reverse();
+        return;
+    }
+
+    let (front, back) = bucket.split_at_mut(half);
+    let back_len = back.len();
+    let back = &mut back[back_len - half..];
+
+    // The n-th chunk from the front swaps with the n-th chunk from the back
+    let chunk_size = half.div_ceil(threads);
+    let mut pairs: Vec<(&mut [T], &mut [T])> = front
+        .chunks_mut(chunk_size)
+        .zip(back.rchunks_mut(chunk_size))
+        .collect();
+
+    sch.par_map(
+        &mut pairs,
+        &|_, (front, back)| {
+            let len = front.len();
+            for i in 0..len {
+                std::mem::swap(&mut front[i], &mut back[len - 1 - i]);
+            }
+        },
+        threads as u32,
+    );
 }
 
 #[inline]
@@ -265,18 +419,23 @@ pub fn is_homogenous_bucket(counts: &[usize; 256]) -> bool {
 
 #[cfg(test)]
 mod tests {
-    use crate::radix::sort_utils::get_tile_counts;
+    use crate::{
+        par::Scheduler,
+        radix::sort_utils::{get_counts_with_reverse, get_tile_counts, par_reverse},
+    };
 
     #[test]
     pub fn test_get_tile_counts_correctly_marks_already_sorted_single_tile() {
         let mut data: Vec<u8> = vec![0, 5, 2, 3, 1];
 
-        let (_counts, already_sorted) = get_tile_counts(&mut data, 5, 0);
+        let (_counts, already_sorted) =
+            get_tile_counts(Scheduler::SequentialOptimized, &mut data, 5, 0);
         assert_eq!(already_sorted, false);
 
         let mut data: Vec<u8> = vec![0, 0, 1, 1, 2];
 
-        let (_counts, already_sorted) = get_tile_counts(&mut data, 5, 0);
+        let (_counts, already_sorted) =
+            get_tile_counts(Scheduler::SequentialOptimized, &mut data, 5, 0);
         assert_eq!(already_sorted, true);
     }
 
@@ -284,12 +443,36 @@ mod tests {
     pub fn test_get_tile_counts_correctly_marks_already_sorted_multiple_tiles() {
         let mut data: Vec<u8> = vec![0, 5, 2, 3, 1];
 
-        let (_counts, already_sorted) = get_tile_counts(&mut data, 2, 0);
+        let (_counts, already_sorted) =
+            get_tile_counts(Scheduler::SequentialOptimized, &mut data, 2, 0);
         assert_eq!(already_sorted, false);
 
         let mut data: Vec<u8> = vec![0, 0, 1, 1, 2];
 
-        let (_counts, already_sorted) = get_tile_counts(&mut data, 2, 0);
+        let (_counts, already_sorted) =
+            get_tile_counts(Scheduler::SequentialOptimized, &mut data, 2, 0);
         assert_eq!(already_sorted, true);
     }
+
+    #[test]
+    pub fn test_get_counts_marks_reverse_sorted() {
+        let data: Vec<u8> = vec![5, 3, 3, 2, 0];
+        let (_, sorted, reversed) = get_counts_with_reverse(&data, 0);
+        assert!(!sorted);
+        assert!(reversed);
+
+        let data: Vec<u8> = vec![5, 3, 4, 2, 0];
+        let (_, sorted, reversed) = get_counts_with_reverse(&data, 0);
+        assert!(!sorted);
+        assert!(!reversed);
+    }
+
+    #[test]
+    pub fn test_par_reverse() {
+        for len in [0, 1, 2, 7, 100_001] {
+            let mut data: Vec<u32> = (0..len).collect();
+            par_reverse(Scheduler::SequentialOptimized, &mut data, 0);
+            assert!(data.iter().rev().copied().eq(0..len));
+        }
+    }
 }
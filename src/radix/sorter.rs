@@ -1,87 +1,246 @@
-use arbitrary_chunks::ArbitraryChunks;
-use std::cmp::max;
+use bytemuck::zeroed_vec;
+use std::{
+    cmp::{max, Reverse},
+    mem,
+};
 
 use crate::{
-    par::Scheduler,
+    par::{scratch::ScratchPool, Scheduler},
     radix::{
+        american_flag_sort::american_flag_sort_with,
         comparative_sort::comparative_sort,
-        radix_key::RadixKey,
+        lsb_sort::lsb_sort_adapter,
+        mt_lsb_sort::mt_lsb_sort_adapter_with,
+        radix_algorithm,
+        radix_key::{CompositeKey, KeyValue, RadixKey},
         radix_scheduler,
+        recombinating_sort::recombinating_sort_adapter,
         regions_sort::regions_sort_adapter,
-        ska_sort::ska_sort_adapter,
-        sort_utils::{aggregate_tile_counts, get_counts, get_tile_counts, is_homogenous_bucket},
+        scanning_sort::scanning_sort_adapter,
+        ska_sort::ska_sort,
+        sort_utils::{
+            aggregate_tile_counts, get_counts, get_counts_with_reverse, get_end_offsets,
+            get_prefix_sums, get_tile_counts_into, is_homogenous_bucket, par_collect_vec,
+            par_reverse, SortBuffers,
+        },
+        stats::{self, SortPass, SortStats, StatsRecorder},
+        tuner::{tuning, Tuning},
+        RadixAlgorithm,
     },
 };
 
+/// Buffers for the chunks below the top level of a sort. Each thread keeps a stack of them, so chunks
+/// nested on the same thread each check out their own and they are reused between chunks and sorts.
+pub(crate) type BufferPool<T> = ScratchPool<Vec<SortBuffers<T>>>;
+
+/// State for one sort that is passed down through every level of the recursion.
+pub(crate) struct SortContext<'a, T: Send> {
+    pub sch: Scheduler,
+    /// Taken once when the sort starts
+    pub tuning: Tuning,
+    pub buffers: &'a BufferPool<T>,
+    pub stats: StatsRecorder<'a>,
+}
+
+impl<'a, T: Send> SortContext<'a, T> {
+    pub fn new(sch: Scheduler, buffers: &'a BufferPool<T>) -> Self {
+        Self {
+            sch,
+            tuning: tuning(),
+            buffers,
+            stats: StatsRecorder::default(),
+        }
+    }
+
+    /// Check out buffers from the pool for the duration of `f`.
+    #[inline]
+    pub fn with_buffers<R>(&self, f: impl FnOnce(&mut SortBuffers<T>) -> R) -> R {
+        let mut buffers = self.buffers.with(|stack| stack.pop()).unwrap_or_default();
+        let result = f(&mut buffers);
+        self.buffers.with(|stack| stack.push(buffers));
+        result
+    }
+}
+
+/// [`handle_chunk`] for a chunk below the top level. Chunks that are finished without touching any
+/// buffers don't check them out of the pool.
 #[inline]
-fn handle_chunk<T>(chunk: &mut [T], level: usize, threads: usize, recursion_depth: u32)
-where
+fn handle_nested_chunk<T>(
+    ctx: &SortContext<T>,
+    chunk: &mut [T],
+    level: usize,
+    threads: usize,
+    recursion_depth: u32,
+) where
+    T: RadixKey + Sized + Send + Copy + Sync,
+{
+    if chunk.len() <= ctx.tuning.small_bucket_cutoff {
+        handle_chunk(
+            ctx,
+            chunk,
+            level,
+            threads,
+            recursion_depth,
+            &mut SortBuffers::default(),
+        );
+    } else {
+        ctx.with_buffers(|buffers| {
+            handle_chunk(ctx, chunk, level, threads, recursion_depth, buffers)
+        });
+    }
+}
+
+#[inline]
+fn handle_chunk<T>(
+    ctx: &SortContext<T>,
+    chunk: &mut [T],
+    level: usize,
+    threads: usize,
+    recursion_depth: u32,
+    buffers: &mut SortBuffers<T>,
+) where
     T: RadixKey + Sized + Send + Copy + Sync,
 {
     crate::scope!("handle_chunk");
+    let sch = ctx.sch;
+    let tuning = &ctx.tuning;
     if chunk.len() <= 1 {
         return;
-    } else if chunk.len() <= 128 {
-        comparative_sort(chunk, level);
+    } else if chunk.len() <= tuning.comparative_cutoff {
+        comparative_sort(chunk, level, ctx.stats);
+        return;
+    } else if chunk.len() <= tuning.small_bucket_cutoff {
+        american_flag_sort_with(chunk, level, tuning.comparative_cutoff, ctx.stats);
         return;
     }
 
-    let use_tiles = chunk.len() >= 260_000 && threads > 1;
+    let use_tiles = chunk.len() >= tuning.tile_threshold && threads > 1;
     let tile_size = if use_tiles {
-        max(30_000, chunk.len().div_ceil(threads))
+        max(tuning.min_tile_size, chunk.len().div_ceil(threads))
     } else {
         chunk.len()
     };
 
-    let mut tile_counts: Option<Vec<[usize; 256]>> = None;
-    let mut already_sorted = false;
-
-    if use_tiles {
-        let (tc, s) = get_tile_counts(chunk, tile_size, level);
-        tile_counts = Some(tc);
-        already_sorted = s;
+    // LSB sorts every remaining level in one go, so there is nothing to count at this level
+    if use_tiles && radix_algorithm() == RadixAlgorithm::Lsb {
+        mt_lsb_sort_adapter_with(sch, chunk, 0, level, tile_size, buffers, ctx.stats);
+        return;
     }
 
-    let counts = if let Some(tile_counts) = &tile_counts {
-        aggregate_tile_counts(tile_counts)
-    } else {
-        let (counts, s) = get_counts(chunk, level);
-        already_sorted = s;
+    let mut level = level;
+    let (counts, already_sorted, reverse_sorted) = loop {
+        let start = ctx.stats.start();
+        let (counts, already_sorted, reverse_sorted) = if use_tiles {
+            let (already_sorted, reverse_sorted) = get_tile_counts_into(
+                sch,
+                chunk,
+                tile_size,
+                level,
+                &mut buffers.tiles,
+                &mut buffers.tile_counts,
+            );
+            (
+                aggregate_tile_counts(&buffers.tile_counts),
+                already_sorted,
+                reverse_sorted,
+            )
+        } else {
+            get_counts_with_reverse(chunk, level)
+        };
+        ctx.stats.record(start, SortPass::Count, level, chunk.len());
 
-        counts
+        // A digit shared by every item (like the common prefix of a long key) doesn't order anything.
+        // Move on to the next level here instead of recursing through another director, so the stack
+        // only grows with the levels that actually split the chunk.
+        if level != 0 && counts.contains(&chunk.len()) {
+            level -= 1;
+            continue;
+        }
+
+        break (counts, already_sorted, reverse_sorted);
     };
 
-    if already_sorted || (chunk.len() >= 30_000 && is_homogenous_bucket(&counts)) {
+    // Descending input (like last frame's far to near list) only needs flipping to be sorted by this
+    // digit. The sort is unstable anyway, so the order within each digit doesn't matter.
+    if reverse_sorted && !already_sorted {
+        let start = ctx.stats.start();
+        par_reverse(sch, chunk, tuning.min_tile_size);
+        ctx.stats
+            .record(start, SortPass::Reverse, level, chunk.len());
+    }
+
+    if already_sorted
+        || reverse_sorted
+        || (chunk.len() >= tuning.homogenous_threshold && is_homogenous_bucket(&counts))
+    {
+        if !reverse_sorted || already_sorted {
+            ctx.stats
+                .record(ctx.stats.start(), SortPass::Skip, level, chunk.len());
+        }
         if level != 0 {
-            director(chunk, &counts, level - 1, recursion_depth);
+            director(ctx, chunk, &counts, level - 1, recursion_depth);
         }
 
         return;
     }
 
-    // Ensure tile_counts is always set when it is required
-    if tile_counts.is_none() {
-        crate::scope!("alloc tile_counts");
-        tile_counts = Some(vec![counts]);
+    if !use_tiles {
+        // The whole chunk as a single tile
+        buffers.tile_counts.clear();
+        buffers.tile_counts.push(counts);
+        return regions_sort_adapter(
+            ctx,
+            chunk,
+            &counts,
+            &buffers.tile_counts,
+            tile_size,
+            level,
+            recursion_depth,
+        );
     }
 
-    if let Some(tile_counts) = tile_counts {
-        regions_sort_adapter(
+    let algorithm = match radix_algorithm() {
+        // recombinating_sort needs a scratch buffer as large as the chunk, so very large chunks use
+        // the in-place regions_sort instead
+        RadixAlgorithm::Auto if chunk.len() > tuning.recombinating_max_len => {
+            RadixAlgorithm::Regions
+        }
+        RadixAlgorithm::Auto => RadixAlgorithm::Recombinating,
+        algorithm => algorithm,
+    };
+    match algorithm {
+        RadixAlgorithm::Recombinating => recombinating_sort_adapter(
+            ctx,
             chunk,
             &counts,
-            &tile_counts,
             tile_size,
             level,
             recursion_depth,
-        )
-    } else {
-        ska_sort_adapter(chunk, &counts, level, recursion_depth)
+            buffers,
+        ),
+        RadixAlgorithm::Scanning => {
+            scanning_sort_adapter(ctx, chunk, &counts, level, recursion_depth)
+        }
+        _ => regions_sort_adapter(
+            ctx,
+            chunk,
+            &counts,
+            &buffers.tile_counts,
+            tile_size,
+            level,
+            recursion_depth,
+        ),
     }
 }
 
 #[inline]
-pub fn director<T>(bucket: &mut [T], counts: &[usize; 256], level: usize, recursion_depth: u32)
-where
+pub(crate) fn director<T>(
+    ctx: &SortContext<T>,
+    bucket: &mut [T],
+    counts: &[usize; 256],
+    level: usize,
+    recursion_depth: u32,
+) where
     T: RadixKey + Send + Sync + Copy,
 {
     crate::scope!("director");
@@ -89,30 +248,39 @@ where
     // bucket.arbitrary_chunks_mut(counts).par_bridge()
     //       .for_each(|chunk| handle_chunk(chunk, level, current_num_threads()));
 
-    let threads = radix_scheduler().current_num_threads();
+    let sch = ctx.sch;
+    let threads = sch.current_num_threads();
     let chunk_count = match recursion_depth {
         0 => threads,
-        1 => match radix_scheduler() {
+        1 => match sch {
             Scheduler::Chili => 1,
             Scheduler::Raw => 2,
             _ => threads,
         },
-        _ => match radix_scheduler() {
+        _ => match sch {
             Scheduler::Chili => 1,
             Scheduler::Raw => 1,
             _ => threads,
         },
     };
 
-    // TODO don't allocate
-    let mut chunks = bucket.arbitrary_chunks_mut(counts).collect::<Vec<_>>();
-    radix_scheduler().par_map(
+    // Split on the stack so deep recursions don't allocate per level
+    let mut chunks: [&mut [T]; 256] = std::array::from_fn(|_| Default::default());
+    let mut rem_bucket = bucket;
+    for (chunk, &count) in chunks.iter_mut().zip(counts) {
+        let (left, rem) = mem::take(&mut rem_bucket).split_at_mut(count);
+        *chunk = left;
+        rem_bucket = rem;
+    }
+
+    sch.par_map(
         &mut chunks,
         &|_, chunk| {
-            handle_chunk(
+            handle_nested_chunk(
+                ctx,
                 chunk,
                 level,
-                radix_scheduler().current_num_threads(),
+                sch.current_num_threads(),
                 recursion_depth + 1,
             )
         },
@@ -120,20 +288,513 @@ where
     )
 }
 
+/// Reusable radix sorter. Keeps the tile counts and scratch buffers of the top level and the levels below
+/// it between sorts, so sorting every frame doesn't reallocate them. Hold on to it like the allocations
+/// in `PlocBuilder`.
+pub struct Sorter<T: Send> {
+    /// Scheduler used by [`Sorter::sort`]. When None the configured radix scheduler is used.
+    pub scheduler: Option<Scheduler>,
+    buffers: SortBuffers<T>,
+    nested_buffers: BufferPool<T>,
+}
+
+impl<T: Send> Default for Sorter<T> {
+    fn default() -> Self {
+        Self {
+            scheduler: None,
+            buffers: SortBuffers::default(),
+            nested_buffers: BufferPool::default(),
+        }
+    }
+}
+
+impl<T> Sorter<T>
+where
+    T: RadixKey + Copy + Send + Sync,
+{
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_scheduler(scheduler: Scheduler) -> Self {
+        Self {
+            scheduler: Some(scheduler),
+            ..Self::default()
+        }
+    }
+
+    /// Release the buffers kept between sorts.
+    pub fn clear(&mut self) {
+        self.buffers = SortBuffers::default();
+        self.nested_buffers.clear();
+    }
+
+    #[inline]
+    pub fn sort(&mut self, data: &mut [T]) {
+        let sch = self.scheduler.unwrap_or_else(configured_scheduler);
+        self.sort_with(sch, data);
+    }
+
+    /// Sort `data` like [`Sorter::sort`], recording which passes ran at each level and how long they took.
+    pub fn sort_with_stats(&mut self, data: &mut [T]) -> SortStats {
+        let sch = self.scheduler.unwrap_or_else(configured_scheduler);
+        stats::collect(data.len(), |stats| self.sort_recorded(sch, data, stats))
+    }
+
+    /// Sort `data` on `sch`, regardless of the configured radix scheduler.
+    #[inline]
+    pub fn sort_with(&mut self, sch: Scheduler, data: &mut [T]) {
+        self.sort_recorded(sch, data, StatsRecorder::default());
+    }
+
+    #[inline]
+    fn sort_recorded(&mut self, sch: Scheduler, data: &mut [T], stats: StatsRecorder) {
+        crate::scope!("sort");
+
+        // By definition, this is already sorted. Keys without any levels all compare equal.
+        if data.len() <= 1 || T::LEVELS == 0 {
+            return;
+        }
+
+        let ctx = SortContext {
+            stats,
+            ..SortContext::new(sch, &self.nested_buffers)
+        };
+        let threads = sch.current_num_threads();
+        if T::LEVELS <= 2 {
+            counting_sort(&ctx, data, threads, &mut self.buffers);
+            return;
+        }
+
+        let level = T::LEVELS - 1;
+        handle_chunk(&ctx, data, level, threads, 0, &mut self.buffers);
+    }
+}
+
+/// The radix scheduler selected through [`super::set_radix_scheduler`] or environment variables.
+#[inline]
+fn configured_scheduler() -> Scheduler {
+    super::init_radix_scheduler();
+    radix_scheduler()
+}
+
+/// Sort `data` with a temporary [`Sorter`] on the configured radix scheduler.
 #[inline]
 pub fn sort<T>(data: &mut [T])
 where
     T: RadixKey + Copy + Send + Sync,
 {
-    crate::scope!("sort");
-    super::init_radix_scheduler();
+    Sorter::new().sort(data)
+}
+
+/// Sort `data` on `scheduler`. Doesn't read environment variables.
+#[inline]
+pub fn sort_with<T>(scheduler: Scheduler, data: &mut [T])
+where
+    T: RadixKey + Copy + Send + Sync,
+{
+    Sorter::new().sort_with(scheduler, data)
+}
+
+/// Sort `data` in descending order. Equivalent to sorting `data` wrapped in [`Reverse`].
+pub fn sort_descending<T>(data: &mut [T])
+where
+    T: RadixKey + Copy + Send + Sync,
+{
+    // SAFETY: Reverse is repr(transparent), so [T] and [Reverse<T>] have the same layout
+    let data = unsafe { &mut *(data as *mut [T] as *mut [Reverse<T>]) };
+    sort(data);
+}
+
+/// Keys of one or two bytes are sorted with one parallel counting pass per byte, skipping the MSB
+/// recursion and its tile bookkeeping entirely.
+#[inline]
+fn counting_sort<T>(ctx: &SortContext, data: &mut [T], threads: usize, buffers: &mut SortBuffers<T>)
+where
+    T: RadixKey + Copy + Send + Sync,
+{
+    crate::scope!("counting_sort");
+    if data.len() <= ctx.tuning.comparative_cutoff {
+        comparative_sort(data, T::LEVELS - 1, ctx.stats);
+        return;
+    }
+
+    let tile_size = max(ctx.tuning.min_tile_size, data.len().div_ceil(threads));
+    mt_lsb_sort_adapter_with(
+        ctx.sch,
+        data,
+        0,
+        T::LEVELS - 1,
+        tile_size,
+        buffers,
+        ctx.stats,
+    );
+}
+
+/// Sort `data` with an out-of-place LSB radix sort, using `scratch` as the second buffer. `scratch` is
+/// only grown, so reusing it between sorts avoids reallocating.
+pub fn sort_with_scratch<T>(data: &mut [T], scratch: &mut Vec<T>)
+where
+    T: RadixKey + Copy + Send + Sync,
+{
+    crate::scope!("sort_with_scratch");
+    if data.len() <= 1 || T::LEVELS == 0 {
+        return;
+    }
+
+    if scratch.len() < data.len() {
+        crate::scope!("grow lsb scratch");
+        scratch.resize(data.len(), data[0]);
+    }
+
+    lsb_sort_adapter(data, &mut scratch[..data.len()], 0, T::LEVELS - 1);
+}
+
+/// Sort `keys`, applying the same permutation to `values`.
+///
+/// Keys and values are packed into [`KeyValue`] records for the sort and unpacked afterwards. Callers
+/// that can produce the records directly should sort a `&mut [KeyValue<K, V>]` with [`sort`] instead and
+/// skip the packing pass.
+pub fn sort_by_key<K, V>(keys: &mut [K], values: &mut [V])
+where
+    K: RadixKey + Copy + Send + Sync,
+    V: Copy + Send + Sync,
+{
+    crate::scope!("sort_by_key");
+    assert_eq!(
+        keys.len(),
+        values.len(),
+        "keys and values must have the same length"
+    );
+    let sch = configured_scheduler();
+    let chunk_count = sch.current_num_threads() as u32;
+    let mut records: Vec<KeyValue<K, V>> = {
+        crate::scope!("pack key values");
+        keys.iter()
+            .zip(values.iter())
+            .map(|(&key, &value)| KeyValue { key, value })
+            .collect()
+    };
+
+    sort_with(sch, &mut records);
+
+    {
+        crate::scope!("unpack key values");
+        sch.par_map(keys, &|i, key| *key = records[i].key, chunk_count);
+        sch.par_map(values, &|i, value| *value = records[i].value, chunk_count);
+    }
+}
+
+/// Return the permutation that sorts `keys`: `keys[indices[0]]` is the smallest key. `keys` is not moved.
+pub fn sort_indices<K>(keys: &[K]) -> Vec<u32>
+where
+    K: RadixKey + Copy + Send + Sync,
+{
+    crate::scope!("sort_indices");
+    assert!(
+        keys.len() <= u32::MAX as usize,
+        "sort_indices supports at most u32::MAX keys"
+    );
+    let sch = configured_scheduler();
+    let chunk_count = sch.current_num_threads() as u32;
+    let mut records = par_collect_vec(sch, keys.len(), chunk_count, &|i| KeyValue {
+        key: keys[i],
+        value: i as u32,
+    });
+
+    sort_with(sch, &mut records);
+
+    let mut indices = zeroed_vec(keys.len());
+    sch.par_map(
+        &mut indices,
+        &|i, index| *index = records[i].value,
+        chunk_count,
+    );
+    indices
+}
+
+/// Sort `data` by `f`, calling `f` exactly once per item. Keys are cached next to the item indices for the
+/// sort and the resulting permutation is then applied to `data`.
+pub fn sort_by_cached_key<T, K, F>(data: &mut [T], f: F)
+where
+    T: Copy + Send + Sync,
+    K: RadixKey + Copy + Send + Sync,
+    F: Fn(&T) -> K + Send + Sync,
+{
+    crate::scope!("sort_by_cached_key");
+    assert!(
+        data.len() <= u32::MAX as usize,
+        "sort_by_cached_key supports at most u32::MAX items"
+    );
+    let sch = configured_scheduler();
 
-    // By definition, this is already sorted
     if data.len() <= 1 {
         return;
     }
 
-    let threads = radix_scheduler().current_num_threads();
-    let level = T::LEVELS - 1;
-    handle_chunk(data, level, threads, 0);
+    let chunk_count = sch.current_num_threads() as u32;
+    let mut records = {
+        let data: &[T] = data;
+        par_collect_vec(sch, data.len(), chunk_count, &|i| KeyValue {
+            key: f(&data[i]),
+            value: i as u32,
+        })
+    };
+
+    sort_with(sch, &mut records);
+
+    let sorted = {
+        let data: &[T] = data;
+        crate::scope!("apply permutation");
+        par_collect_vec(sch, data.len(), chunk_count, &|i| {
+            data[records[i].value as usize]
+        })
+    };
+    sch.par_map(data, &|i, item| *item = sorted[i], chunk_count);
+}
+
+/// Sort `data` by `primary`, then by `secondary` where the primary keys are equal. Both are called once per
+/// item, see [`sort_by_cached_key`].
+pub fn sort_by_keys<T, P, S, FP, FS>(data: &mut [T], primary: FP, secondary: FS)
+where
+    T: Copy + Send + Sync,
+    P: RadixKey + Copy + Send + Sync,
+    S: RadixKey + Copy + Send + Sync,
+    FP: Fn(&T) -> P + Send + Sync,
+    FS: Fn(&T) -> S + Send + Sync,
+{
+    sort_by_cached_key(data, |item| {
+        CompositeKey::new(primary(item), secondary(item))
+    });
+}
+
+/// Partially sort `data` so that `data[..k]` holds the `k` smallest items in ascending order. The order
+/// of the rest is unspecified. Buckets that lie entirely past `k` are never sorted past their top digit.
+pub fn sort_smallest_k<T>(data: &mut [T], k: usize)
+where
+    T: RadixKey + Copy + Send + Sync,
+{
+    crate::scope!("sort_smallest_k");
+    let sch = configured_scheduler();
+
+    let k = k.min(data.len());
+    if k == 0 || data.len() <= 1 || T::LEVELS == 0 {
+        return;
+    }
+
+    let threads = sch.current_num_threads();
+    let buffers = BufferPool::default();
+    smallest_k(
+        &SortContext::new(sch, &buffers),
+        data,
+        k,
+        T::LEVELS - 1,
+        threads,
+    );
+}
+
+/// Partially sort `data` so that `data[..k]` holds the `k` largest items in descending order.
+pub fn sort_largest_k<T>(data: &mut [T], k: usize)
+where
+    T: RadixKey + Copy + Send + Sync,
+{
+    // SAFETY: Reverse is repr(transparent), so [T] and [Reverse<T>] have the same layout
+    let data = unsafe { &mut *(data as *mut [T] as *mut [Reverse<T>]) };
+    sort_smallest_k(data, k);
+}
+
+fn smallest_k<T>(ctx: &SortContext<T>, bucket: &mut [T], k: usize, level: usize, threads: usize)
+where
+    T: RadixKey + Copy + Send + Sync,
+{
+    crate::scope!("smallest_k");
+    if k >= bucket.len() || bucket.len() <= ctx.tuning.comparative_cutoff {
+        handle_nested_chunk(ctx, bucket, level, threads, 0);
+        return;
+    }
+
+    let (counts, already_sorted) = get_counts(bucket, level);
+    if !already_sorted {
+        let mut prefix_sums = get_prefix_sums(&counts);
+        let end_offsets = get_end_offsets(&counts, &prefix_sums);
+        let start = ctx.stats.start();
+        ska_sort(bucket, &mut prefix_sums, &end_offsets, level);
+        ctx.stats.record(start, SortPass::Ska, level, bucket.len());
+    }
+
+    if level == 0 {
+        // Items within a bucket are equal on the last level, so partitioning was enough
+        return;
+    }
+
+    let mut offset = 0;
+    for count in counts {
+        if offset >= k {
+            break;
+        }
+
+        let sub_bucket = &mut bucket[offset..offset + count];
+        if offset + count <= k {
+            handle_nested_chunk(ctx, sub_bucket, level - 1, threads, 1);
+        } else {
+            smallest_k(ctx, sub_bucket, k - offset, level - 1, threads);
+        }
+        offset += count;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_smallest_k_orders_prefix() {
+        let data: Vec<u32> = (0..5_000u32)
+            .map(|i| i.wrapping_mul(2_654_435_761) % 1_000)
+            .collect();
+        let mut expected = data.clone();
+        expected.sort_unstable();
+
+        for k in [1, 7, 129, 1_000, 5_000] {
+            let mut data = data.clone();
+            smallest_k(
+                &SortContext::new(Scheduler::SequentialOptimized, &BufferPool::default()),
+                &mut data,
+                k,
+                u32::LEVELS - 1,
+                1,
+            );
+            assert_eq!(data[..k], expected[..k]);
+        }
+    }
+
+    #[test]
+    fn test_sort_16_level_keys() {
+        let data: Vec<u128> = (0..10_000u128)
+            .map(|i| i.wrapping_mul(0x9e37_79b9_7f4a_7c15_f39c_c060_5ced_c835))
+            .collect();
+        let mut expected = data.clone();
+        expected.sort_unstable();
+        let mut sorted = data.clone();
+        sort_with(Scheduler::SequentialOptimized, &mut sorted);
+        assert_eq!(sorted, expected);
+
+        let data: Vec<i128> = data.iter().map(|&v| v as i128).collect();
+        let mut expected = data.clone();
+        expected.sort_unstable();
+        let mut sorted = data.clone();
+        sort_with(Scheduler::SequentialOptimized, &mut sorted);
+        assert_eq!(sorted, expected);
+    }
+
+    #[test]
+    fn test_sort_long_keys_with_shared_prefix() {
+        // Only the lowest bytes differ, so the upper levels are all skipped without splitting
+        let prefix = 0xabcd_ef01_2345_6789_abcd_ef01_0000_0000u128;
+        let mut data: Vec<(u128, u64)> = (0..10_000u64)
+            .map(|i| {
+                let v = i.wrapping_mul(2_654_435_761);
+                (prefix | (v as u128 & 0xffff), v)
+            })
+            .collect();
+        let mut expected = data.clone();
+        expected.sort_unstable();
+        assert_eq!(<(u128, u64)>::LEVELS, 24);
+
+        sort_with(Scheduler::SequentialOptimized, &mut data);
+        assert_eq!(data, expected);
+    }
+
+    #[test]
+    fn test_sort_reverse_sorted_input() {
+        let mut data: Vec<u64> = (0..50_000u64).rev().map(|i| i * 3).collect();
+        let mut expected = data.clone();
+        expected.sort_unstable();
+        sort_with(Scheduler::SequentialOptimized, &mut data);
+        assert_eq!(data, expected);
+
+        // Descending by the top digit only, the lower digits still need sorting
+        let mut data: Vec<u32> = (0..50_000u32)
+            .map(|i| ((50_000 - i) / 1_000) << 24 | (i.wrapping_mul(2_654_435_761) >> 8))
+            .collect();
+        let mut expected = data.clone();
+        expected.sort_unstable();
+        sort_with(Scheduler::SequentialOptimized, &mut data);
+        assert_eq!(data, expected);
+    }
+
+    #[test]
+    fn test_sort_with_stats_records_passes() {
+        let mut data: Vec<u32> = (0..10_000u32)
+            .map(|i| i.wrapping_mul(2_654_435_761))
+            .collect();
+        let mut sorter = Sorter::with_scheduler(Scheduler::SequentialOptimized);
+        let stats = sorter.sort_with_stats(&mut data);
+        assert!(data.windows(2).all(|w| w[0] <= w[1]));
+
+        assert_eq!(stats.len, 10_000);
+        assert!(stats.levels.len() >= u32::LEVELS);
+        let top = &stats.levels[u32::LEVELS - 1];
+        assert_eq!(top.pass(SortPass::Count).items, 10_000);
+        assert_eq!(top.pass(SortPass::Regions).largest_chunk, 10_000);
+        // The top level splits it into buckets small enough for comparative_sort
+        let next = stats.levels[u32::LEVELS - 2].pass(SortPass::Comparative);
+        assert!(next.chunks > 1 && next.items <= 10_000);
+    }
+
+    #[test]
+    fn test_sort_with_stats_concurrent_sorts_dont_mix() {
+        let lens = [20_000u32, 30_000];
+        let stats: Vec<SortStats> = std::thread::scope(|scope| {
+            let handles: Vec<_> = lens
+                .iter()
+                .map(|&len| {
+                    scope.spawn(move || {
+                        let mut data: Vec<u32> =
+                            (0..len).map(|i| i.wrapping_mul(2_654_435_761)).collect();
+                        Sorter::with_scheduler(Scheduler::SequentialOptimized)
+                            .sort_with_stats(&mut data)
+                    })
+                })
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+        for (stats, len) in stats.iter().zip(lens) {
+            let top = &stats.levels[u32::LEVELS - 1];
+            assert_eq!(top.pass(SortPass::Count).items, len as usize);
+        }
+    }
+
+    #[test]
+    fn test_sort_by_keys_matches_sort_by_key() {
+        // (material, depth, id), the depths are unique so the sorted order is unique too
+        let data: Vec<(u16, u32, u32)> = (0..20_000u32)
+            .map(|i| ((i % 13) as u16, i.wrapping_mul(2_654_435_761), i))
+            .collect();
+
+        let mut by_keys = data.clone();
+        sort_by_keys(&mut by_keys, |item| item.0, |item| item.1);
+
+        let mut keys: Vec<_> = data
+            .iter()
+            .map(|item| CompositeKey::new(item.0, item.1))
+            .collect();
+        let mut by_key = data.clone();
+        sort_by_key(&mut keys, &mut by_key);
+
+        assert_eq!(by_keys, by_key);
+        assert!(keys
+            .iter()
+            .zip(&by_key)
+            .all(|(key, item)| (key.primary, key.secondary) == (item.0, item.1)));
+        assert!(by_keys
+            .windows(2)
+            .all(|w| (w[0].0, w[0].1) < (w[1].0, w[1].1)));
+    }
+
+    #[test]
+    fn test_sort_zero_level_keys() {
+        let mut data = vec![[0u8; 0]; 500];
+        sort_with(Scheduler::SequentialOptimized, &mut data);
+    }
 }
@@ -0,0 +1,193 @@
+//! Opt-in statistics for a sort: which pass ran on how many items at each level, and how long it took.
+//! Collected with [`super::sorter::Sorter::sort_with_stats`].
+//!
+//! Each collecting sort owns its stats and passes a [`StatsRecorder`] for them down with the rest of its
+//! state. Workers record into their own thread's partial stats, which are merged when the sort is done,
+//! so sorts running at the same time never mix and recording takes no locks. When not collecting,
+//! recording is a check of an empty Option.
+
+use std::{
+    fmt,
+    time::{Duration, Instant},
+};
+
+use crate::par::accumulator::{Merge, ThreadLocalAccumulator};
+
+/// The kind of work done on a chunk at one level.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[repr(usize)]
+pub enum SortPass {
+    /// Counting the digits of the level
+    Count = 0,
+    /// Already sorted or homogenous at this level, nothing to move
+    Skip = 1,
+    /// Sorted in reverse at this level and flipped in place
+    Reverse = 2,
+    /// Small enough for comparative_sort, which sorts every remaining level at once
+    Comparative = 3,
+    Ska = 4,
+    Regions = 5,
+    Recombinating = 6,
+    Scanning = 7,
+    /// One mt_lsb_sort scatter pass
+    Lsb = 8,
+    /// One american_flag_sort partitioning pass
+    AmericanFlag = 9,
+}
+
+impl SortPass {
+    pub const ALL: [SortPass; 10] = [
+        SortPass::Count,
+        SortPass::Skip,
+        SortPass::Reverse,
+        SortPass::Comparative,
+        SortPass::Ska,
+        SortPass::Regions,
+        SortPass::Recombinating,
+        SortPass::Scanning,
+        SortPass::Lsb,
+        SortPass::AmericanFlag,
+    ];
+}
+
+#[derive(Clone, Copy, Default, Debug)]
+pub struct PassStats {
+    /// How many chunks the pass ran on
+    pub chunks: usize,
+    /// Total length of those chunks
+    pub items: usize,
+    pub largest_chunk: usize,
+    /// Summed over all chunks, so passes running in parallel can add up to more than the wall time
+    pub time: Duration,
+}
+
+impl Merge for PassStats {
+    fn merge(&mut self, other: &Self) {
+        self.chunks += other.chunks;
+        self.items += other.items;
+        self.largest_chunk = self.largest_chunk.max(other.largest_chunk);
+        self.time += other.time;
+    }
+}
+
+#[derive(Clone, Default, Debug)]
+pub struct LevelStats {
+    pub passes: [PassStats; SortPass::ALL.len()],
+}
+
+impl Merge for LevelStats {
+    fn merge(&mut self, other: &Self) {
+        self.passes.merge(&other.passes);
+    }
+}
+
+impl LevelStats {
+    pub fn pass(&self, pass: SortPass) -> &PassStats {
+        &self.passes[pass as usize]
+    }
+}
+
+#[derive(Clone, Default, Debug)]
+pub struct SortStats {
+    pub len: usize,
+    /// Indexed by digit level, the most significant digit is last
+    pub levels: Vec<LevelStats>,
+    pub total_time: Duration,
+}
+
+impl SortStats {
+    fn record(&mut self, pass: SortPass, level: usize, len: usize, time: Duration) {
+        if self.levels.len() <= level {
+            self.levels.resize(level + 1, LevelStats::default());
+        }
+        let stats = &mut self.levels[level].passes[pass as usize];
+        stats.chunks += 1;
+        stats.items += len;
+        stats.largest_chunk = stats.largest_chunk.max(len);
+        stats.time += time;
+    }
+}
+
+/// Only merges the levels, `len` and `total_time` are set by the sort itself.
+impl Merge for SortStats {
+    fn merge(&mut self, other: &Self) {
+        if self.levels.len() < other.levels.len() {
+            self.levels
+                .resize(other.levels.len(), LevelStats::default());
+        }
+        for (level, other) in self.levels.iter_mut().zip(&other.levels) {
+            level.merge(other);
+        }
+    }
+}
+
+impl fmt::Display for SortStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "sorted {} items in {}",
+            self.len,
+            obvhs::PrettyDuration(self.total_time)
+        )?;
+        for (level, stats) in self.levels.iter().enumerate().rev() {
+            if stats.passes.iter().all(|p| p.chunks == 0) {
+                continue;
+            }
+            writeln!(f, "level {level}")?;
+            for pass in SortPass::ALL {
+                let p = stats.pass(pass);
+                if p.chunks == 0 {
+                    continue;
+                }
+                writeln!(
+                    f,
+                    "  {:<14} {:>8} chunks {:>10} items {:>10} largest {:>8}",
+                    format!("{pass:?}"),
+                    p.chunks,
+                    p.items,
+                    p.largest_chunk,
+                    format!("{}", obvhs::PrettyDuration(p.time)),
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Records the passes of one sort into that sort's stats. The default records nothing.
+#[derive(Clone, Copy, Default)]
+pub struct StatsRecorder<'a> {
+    stats: Option<&'a ThreadLocalAccumulator<SortStats>>,
+}
+
+impl StatsRecorder<'_> {
+    /// Start timing a pass. None unless stats are being collected.
+    #[inline(always)]
+    pub(crate) fn start(&self) -> Option<Instant> {
+        self.stats.map(|_| Instant::now())
+    }
+
+    /// Record a pass over `len` items at `level`, started with [`StatsRecorder::start`].
+    #[inline(always)]
+    pub(crate) fn record(&self, start: Option<Instant>, pass: SortPass, level: usize, len: usize) {
+        if let (Some(stats), Some(start)) = (self.stats, start) {
+            let time = start.elapsed();
+            stats.with(|stats| stats.record(pass, level, len, time));
+        }
+    }
+}
+
+/// Run `sort` over `len` items, collecting stats for every pass recorded with the recorder it is given.
+pub(crate) fn collect(len: usize, sort: impl FnOnce(StatsRecorder)) -> SortStats {
+    let mut partial = ThreadLocalAccumulator::<SortStats>::new();
+    let start = Instant::now();
+    sort(StatsRecorder {
+        stats: Some(&partial),
+    });
+    let total_time = start.elapsed();
+
+    let mut stats = partial.finish();
+    stats.len = len;
+    stats.total_time = total_time;
+    stats
+}
@@ -0,0 +1,95 @@
+use std::sync::RwLock;
+
+/// Thresholds the sorter uses to pick between algorithms. The defaults were picked on a desktop CPU,
+/// override individual methods to tune for other machines and install the tuner with [`set_tuner`].
+pub trait Tuner: Send + Sync {
+    /// Chunks up to this length are sorted with comparative_sort
+    fn comparative_cutoff(&self) -> usize {
+        128
+    }
+
+    /// Chunks up to this length are sorted with american_flag_sort, without going through the director
+    fn small_bucket_cutoff(&self) -> usize {
+        1024
+    }
+
+    /// Chunks from this length are split into tiles and sorted with a multi-threaded algorithm
+    fn tile_threshold(&self) -> usize {
+        260_000
+    }
+
+    /// Smallest tile the multi-threaded algorithms split a chunk into
+    fn min_tile_size(&self) -> usize {
+        30_000
+    }
+
+    /// Chunks from this length are checked for having all items in a single bucket
+    fn homogenous_threshold(&self) -> usize {
+        30_000
+    }
+
+    /// Buckets from this length are counted in parallel
+    fn par_count_threshold(&self) -> usize {
+        400_000
+    }
+
+    /// Longest chunk the auto algorithm will use recombinating_sort for, since it needs a scratch buffer
+    /// as large as the chunk. Longer chunks use the in-place regions_sort.
+    fn recombinating_max_len(&self) -> usize {
+        10_000_000
+    }
+}
+
+pub struct DefaultTuner;
+
+impl Tuner for DefaultTuner {}
+
+static TUNER: RwLock<&'static dyn Tuner> = RwLock::new(&DefaultTuner);
+
+pub fn set_tuner(tuner: &'static dyn Tuner) {
+    *TUNER.write().unwrap() = tuner;
+}
+
+#[inline(always)]
+pub fn tuner() -> &'static dyn Tuner {
+    *TUNER.read().unwrap()
+}
+
+/// The values of a [`Tuner`] at one point in time. The sorter takes one of these at the start of each
+/// sort and passes it down, so the tuner isn't locked again for every chunk.
+#[derive(Clone, Copy, Debug)]
+pub struct Tuning {
+    pub comparative_cutoff: usize,
+    pub small_bucket_cutoff: usize,
+    pub tile_threshold: usize,
+    pub min_tile_size: usize,
+    pub homogenous_threshold: usize,
+    pub par_count_threshold: usize,
+    pub recombinating_max_len: usize,
+}
+
+impl Tuning {
+    pub fn from_tuner(tuner: &dyn Tuner) -> Self {
+        Self {
+            comparative_cutoff: tuner.comparative_cutoff(),
+            small_bucket_cutoff: tuner.small_bucket_cutoff(),
+            tile_threshold: tuner.tile_threshold(),
+            min_tile_size: tuner.min_tile_size(),
+            homogenous_threshold: tuner.homogenous_threshold(),
+            par_count_threshold: tuner.par_count_threshold(),
+            recombinating_max_len: tuner.recombinating_max_len(),
+        }
+    }
+}
+
+impl Default for Tuning {
+    fn default() -> Self {
+        Self::from_tuner(&DefaultTuner)
+    }
+}
+
+/// Snapshot the installed tuner.
+#[inline]
+pub fn tuning() -> Tuning {
+    Tuning::from_tuner(tuner())
+}
@@ -0,0 +1,303 @@
+//! Compare freshly measured race records against a stored baseline, for performance gates in tests.
+//! Loading baselines from JSON requires the `serde` feature.
+
+use std::{collections::HashMap, fmt, time::Duration};
+#[cfg(feature = "serde")]
+use std::{io, path::Path};
+
+use crate::race::RaceRecord;
+
+/// How much slower than the baseline a phase may get before it counts as a regression.
+#[derive(Clone, Debug)]
+pub struct Tolerance {
+    /// Allowed slowdown as a fraction of the baseline median, 0.1 allows 10% slower.
+    pub relative: f64,
+    /// Allowed slowdown regardless of `relative`, so very short phases don't fail on timer noise.
+    pub absolute: Duration,
+    /// `relative` overrides for individual phases, by phase name.
+    pub phases: HashMap<String, f64>,
+}
+
+impl Default for Tolerance {
+    fn default() -> Self {
+        Self {
+            relative: 0.1,
+            absolute: Duration::from_micros(50),
+            phases: HashMap::new(),
+        }
+    }
+}
+
+impl Tolerance {
+    pub fn relative(relative: f64) -> Self {
+        Self {
+            relative,
+            ..Default::default()
+        }
+    }
+
+    /// Use `relative` for `phase` instead of the default.
+    pub fn with_phase(mut self, phase: &str, relative: f64) -> Self {
+        self.phases.insert(phase.to_string(), relative);
+        self
+    }
+
+    /// The slowest median that still passes for `phase` with a `baseline` median.
+    pub fn limit(&self, phase: &str, baseline: Duration) -> Duration {
+        let relative = self.phases.get(phase).copied().unwrap_or(self.relative);
+        baseline
+            .mul_f64(1.0 + relative.max(0.0))
+            .max(baseline + self.absolute)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PhaseStatus {
+    /// Within tolerance of the baseline.
+    Pass,
+    /// Slower than the tolerance allows.
+    Regressed,
+    /// The baseline has no record for this backend and phase.
+    NoBaseline,
+}
+
+/// One backend and phase compared against the baseline, by median.
+#[derive(Clone, Debug)]
+pub struct PhaseComparison {
+    pub backend: String,
+    pub phase: String,
+    /// Zero when there is no baseline
+    pub baseline: Duration,
+    pub current: Duration,
+    /// Slowest passing median
+    pub limit: Duration,
+    pub status: PhaseStatus,
+}
+
+impl PhaseComparison {
+    /// `current / baseline`, above 1.0 when slower. `None` when there is no baseline.
+    pub fn ratio(&self) -> Option<f64> {
+        (self.status != PhaseStatus::NoBaseline)
+            .then(|| self.current.as_secs_f64() / self.baseline.as_secs_f64())
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct RegressionReport {
+    pub phases: Vec<PhaseComparison>,
+}
+
+impl RegressionReport {
+    /// No phase regressed. Phases without a baseline don't fail the check.
+    pub fn passed(&self) -> bool {
+        self.regressions().next().is_none()
+    }
+
+    pub fn regressions(&self) -> impl Iterator<Item = &PhaseComparison> {
+        self.phases
+            .iter()
+            .filter(|p| p.status == PhaseStatus::Regressed)
+    }
+
+    /// Panic with the report if any phase regressed, for use in tests.
+    #[track_caller]
+    pub fn assert_passed(&self) {
+        assert!(self.passed(), "performance regression:\n{self}");
+    }
+}
+
+impl fmt::Display for RegressionReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{:>10} {:>8} {:>10} {:>10} {:>10} {:>7}  status",
+            "backend", "phase", "baseline", "current", "limit", "ratio"
+        )?;
+        for p in &self.phases {
+            let d = |d: Duration| format!("{}", obvhs::PrettyDuration(d));
+            let status = match p.status {
+                PhaseStatus::Pass => "pass",
+                PhaseStatus::Regressed => "REGRESSED",
+                PhaseStatus::NoBaseline => "no baseline",
+            };
+            let ratio = match p.ratio() {
+                Some(ratio) => format!("{ratio:.2}"),
+                None => "-".to_string(),
+            };
+            writeln!(
+                f,
+                "{:>10} {:>8} {:>10} {:>10} {:>10} {:>7}  {status}",
+                p.backend,
+                p.phase,
+                d(p.baseline),
+                d(p.current),
+                d(p.limit),
+                ratio
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Compare the median of each of `current` against the `baseline` record with the same backend and
+/// phase.
+pub fn compare(
+    baseline: &[RaceRecord],
+    current: &[RaceRecord],
+    tolerance: &Tolerance,
+) -> RegressionReport {
+    let phases = current
+        .iter()
+        .map(|c| {
+            let base = baseline
+                .iter()
+                .find(|b| b.backend == c.backend && b.phase == c.phase);
+            match base {
+                Some(b) => {
+                    let limit = tolerance.limit(&c.phase, b.median);
+                    PhaseComparison {
+                        backend: c.backend.clone(),
+                        phase: c.phase.clone(),
+                        baseline: b.median,
+                        current: c.median,
+                        limit,
+                        status: if c.median > limit {
+                            PhaseStatus::Regressed
+                        } else {
+                            PhaseStatus::Pass
+                        },
+                    }
+                }
+                None => PhaseComparison {
+                    backend: c.backend.clone(),
+                    phase: c.phase.clone(),
+                    baseline: Duration::ZERO,
+                    current: c.median,
+                    limit: Duration::ZERO,
+                    status: PhaseStatus::NoBaseline,
+                },
+            }
+        })
+        .collect();
+    RegressionReport { phases }
+}
+
+/// Load a baseline written with `race::write_race_json` and compare `current` against it.
+#[cfg(feature = "serde")]
+pub fn compare_to_baseline_file<P: AsRef<Path>>(
+    path: P,
+    current: &[RaceRecord],
+    tolerance: &Tolerance,
+) -> Result<RegressionReport, BaselineError> {
+    let baseline = read_race_json(&std::fs::read_to_string(path)?)?;
+    Ok(compare(&baseline, current, tolerance))
+}
+
+/// Why a baseline couldn't be loaded.
+#[cfg(feature = "serde")]
+#[derive(Debug)]
+pub enum BaselineError {
+    Io(io::Error),
+    /// The JSON is malformed or a record is missing a field.
+    Parse(serde_json::Error),
+}
+
+#[cfg(feature = "serde")]
+impl fmt::Display for BaselineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BaselineError::Io(err) => write!(f, "couldn't read baseline: {err}"),
+            BaselineError::Parse(err) => write!(f, "invalid baseline: {err}"),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl std::error::Error for BaselineError {}
+
+#[cfg(feature = "serde")]
+impl From<io::Error> for BaselineError {
+    fn from(err: io::Error) -> Self {
+        BaselineError::Io(err)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<serde_json::Error> for BaselineError {
+    fn from(err: serde_json::Error) -> Self {
+        BaselineError::Parse(err)
+    }
+}
+
+/// Parse the output of `race::write_race_json`. Unknown fields are ignored.
+#[cfg(feature = "serde")]
+pub fn read_race_json(json: &str) -> Result<Vec<RaceRecord>, BaselineError> {
+    Ok(serde_json::from_str(json)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(backend: &str, phase: &str, median_us: u64) -> RaceRecord {
+        RaceRecord {
+            backend: backend.to_string(),
+            phase: phase.to_string(),
+            reps: 5,
+            median: Duration::from_micros(median_us),
+            p10: Duration::from_micros(median_us / 2),
+            p90: Duration::from_micros(median_us * 2),
+            threads: 8,
+            cpu_model: "Test \"CPU\"\\".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_compare_to_baseline() {
+        let baseline = vec![
+            record("forte", "sort", 1000),
+            record("forte", "merge", 2000),
+        ];
+        let current = vec![
+            record("forte", "sort", 1050),
+            record("forte", "merge", 3000),
+            record("rayon", "sort", 1000),
+        ];
+        let report = compare(&baseline, &current, &Tolerance::default());
+        let status: Vec<_> = report.phases.iter().map(|p| p.status).collect();
+        assert_eq!(
+            status,
+            [
+                PhaseStatus::Pass,
+                PhaseStatus::Regressed,
+                PhaseStatus::NoBaseline
+            ]
+        );
+        assert!(!report.passed());
+        assert_eq!(report.phases[2].ratio(), None);
+        let table = report.to_string();
+        assert!(table.contains("REGRESSED"));
+        assert!(!table.contains("NaN") && !table.contains("inf"));
+
+        let lenient = Tolerance::default().with_phase("merge", 0.6);
+        compare(&baseline, &current, &lenient).assert_passed();
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_read_race_json() {
+        use crate::race::write_race_json;
+
+        let baseline = vec![
+            record("forte", "sort", 1000),
+            record("forte", "merge", 2000),
+        ];
+        let mut json = Vec::new();
+        write_race_json(&mut json, &baseline).unwrap();
+        let parsed = read_race_json(&String::from_utf8(json).unwrap()).unwrap();
+        assert_eq!(parsed, baseline);
+
+        assert!(read_race_json("[{\"backend\": \"forte\"}]").is_err());
+        assert!(read_race_json("[]").unwrap().is_empty());
+    }
+}
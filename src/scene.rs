@@ -0,0 +1,177 @@
+//! `Scene` owns primitives together with their bvh, for the common case of casting rays against a set of
+//! triangles without writing traversal closures.
+
+use obvhs::{aabb::Aabb, ray::Ray, triangle::Triangle};
+
+use crate::{bvh::Bvh2, ploc::PlocBuilder};
+
+/// Anything a `Scene` can hold.
+pub trait Primitive {
+    fn aabb(&self) -> Aabb;
+    /// Distance along `ray` to the hit, or `f32::INFINITY` if there is none.
+    fn intersect(&self, ray: &Ray) -> f32;
+}
+
+impl Primitive for Triangle {
+    #[inline(always)]
+    fn aabb(&self) -> Aabb {
+        Triangle::aabb(self)
+    }
+
+    #[inline(always)]
+    fn intersect(&self, ray: &Ray) -> f32 {
+        Triangle::intersect(self, ray)
+    }
+}
+
+impl Primitive for Aabb {
+    #[inline(always)]
+    fn aabb(&self) -> Aabb {
+        *self
+    }
+
+    #[inline(always)]
+    fn intersect(&self, ray: &Ray) -> f32 {
+        let t = self.intersect_ray(ray);
+        // Rays starting inside the box hit it at tmin
+        if t < ray.tmax {
+            t.max(ray.tmin)
+        } else {
+            f32::INFINITY
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Hit {
+    pub primitive_id: u32,
+    /// Distance along the ray
+    pub t: f32,
+}
+
+/// Primitives and the bvh built over them. After changing `primitives_mut`, call `refit` for small
+/// movements or `rebuild` otherwise.
+pub struct Scene<P: Primitive = Triangle> {
+    primitives: Vec<P>,
+    aabbs: Vec<Aabb>,
+    bvh: Bvh2,
+    builder: PlocBuilder,
+}
+
+impl<P: Primitive> Scene<P> {
+    pub fn new(primitives: Vec<P>) -> Self {
+        crate::scope!("Scene::new");
+        let mut scene = Scene {
+            aabbs: Vec::new(),
+            bvh: Bvh2::default(),
+            builder: PlocBuilder::preallocate_builder(primitives.len()),
+            primitives,
+        };
+        scene.rebuild();
+        scene
+    }
+
+    pub fn primitives(&self) -> &[P] {
+        &self.primitives
+    }
+
+    /// Changes here are only seen by ray casts after `refit` or `rebuild`.
+    pub fn primitives_mut(&mut self) -> &mut Vec<P> {
+        &mut self.primitives
+    }
+
+    pub fn bvh(&self) -> &Bvh2 {
+        &self.bvh
+    }
+
+    /// The closest hit between `ray.tmin` and `ray.tmax`.
+    pub fn raycast(&self, ray: &Ray) -> Option<Hit> {
+        if self.bvh.nodes.is_empty() {
+            return None;
+        }
+        let mut ray = *ray;
+        let mut primitive_id = u32::MAX;
+        self.bvh.traverse(&mut ray, &mut primitive_id, |ray, id| {
+            self.primitives[id].intersect(ray)
+        });
+        (primitive_id != u32::MAX).then_some(Hit {
+            primitive_id,
+            t: ray.tmax,
+        })
+    }
+
+    /// Whether anything is hit between `ray.tmin` and `ray.tmax`, like for shadow rays.
+    pub fn occluded(&self, ray: &Ray) -> bool {
+        self.bvh
+            .occluded(ray, |ray, id| self.primitives[id].intersect(ray))
+    }
+
+    /// Update the bvh for moved primitives, keeping its structure. The primitive count must not have
+    /// changed since the last `rebuild`.
+    pub fn refit(&mut self) {
+        crate::scope!("Scene::refit");
+        assert_eq!(
+            self.primitives.len(),
+            self.aabbs.len(),
+            "primitive count changed, use rebuild"
+        );
+        self.update_aabbs();
+        self.bvh.refit(&self.aabbs);
+    }
+
+    /// Build the bvh again from the current primitives.
+    pub fn rebuild(&mut self) {
+        crate::scope!("Scene::rebuild");
+        self.update_aabbs();
+        if self.aabbs.is_empty() {
+            self.bvh.clear();
+        } else {
+            self.builder.rebuild_ploc(&self.aabbs, &mut self.bvh);
+        }
+    }
+
+    fn update_aabbs(&mut self) {
+        self.aabbs.clear();
+        self.aabbs.extend(self.primitives.iter().map(P::aabb));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::reference::{
+        brute_force_closest_hit, random_rays, random_triangles, Rng,
+    };
+    use glam::Vec3A;
+
+    #[test]
+    fn test_scene() {
+        let mut rng = Rng::new(11);
+        let mut scene = Scene::new(random_triangles(&mut rng, 300, 0.2));
+        let rays = random_rays(&mut rng, 128);
+        for ray in &rays {
+            let expected = brute_force_closest_hit(scene.primitives(), ray);
+            let hit = scene.raycast(ray);
+            assert_eq!(hit.map(|h| h.primitive_id), expected.map(|e| e.0));
+            assert_eq!(scene.occluded(ray), expected.is_some());
+        }
+
+        for tri in scene.primitives_mut() {
+            tri.v0 += Vec3A::X * 0.01;
+        }
+        scene.refit();
+        assert_eq!(scene.bvh().validate(300), Ok(()));
+        for ray in &rays {
+            let expected = brute_force_closest_hit(scene.primitives(), ray);
+            assert_eq!(
+                scene.raycast(ray).map(|h| h.primitive_id),
+                expected.map(|e| e.0)
+            );
+        }
+
+        scene.primitives_mut().clear();
+        scene.rebuild();
+        assert_eq!(scene.raycast(&rays[0]), None);
+        assert!(!scene.occluded(&rays[0]));
+    }
+}
@@ -0,0 +1,103 @@
+//! serde support for the obvhs types this crate uses, which don't implement serde themselves. Use the
+//! definitions on fields with `#[serde(with = "pool_racing::serde_remote::AabbDef")]`, or wrap values in
+//! the transparent wrappers to serialize them directly, like a `Vec<TriangleSerde>`.
+//! Requires the `serde` feature.
+
+use glam::Vec3A;
+use obvhs::{aabb::Aabb, ray::Ray, triangle::Triangle};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "Aabb")]
+pub struct AabbDef {
+    pub min: Vec3A,
+    pub max: Vec3A,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "Triangle")]
+pub struct TriangleDef {
+    pub v0: Vec3A,
+    pub v1: Vec3A,
+    pub v2: Vec3A,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "Ray")]
+pub struct RayDef {
+    pub origin: Vec3A,
+    pub direction: Vec3A,
+    pub inv_direction: Vec3A,
+    pub tmin: f32,
+    pub tmax: f32,
+}
+
+#[derive(Clone, Copy, Default, Debug, Serialize, Deserialize)]
+#[repr(transparent)]
+pub struct AabbSerde(#[serde(with = "AabbDef")] pub Aabb);
+
+#[derive(Clone, Copy, Default, Debug, Serialize, Deserialize)]
+#[repr(transparent)]
+pub struct TriangleSerde(#[serde(with = "TriangleDef")] pub Triangle);
+
+#[derive(Clone, Copy, Default, Debug, Serialize, Deserialize)]
+#[repr(transparent)]
+pub struct RaySerde(#[serde(with = "RayDef")] pub Ray);
+
+impl From<Aabb> for AabbSerde {
+    fn from(value: Aabb) -> Self {
+        Self(value)
+    }
+}
+
+impl From<Triangle> for TriangleSerde {
+    fn from(value: Triangle) -> Self {
+        Self(value)
+    }
+}
+
+impl From<Ray> for RaySerde {
+    fn from(value: Ray) -> Self {
+        Self(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{bvh::Bvh2, test_util::random_bvh};
+
+    fn round_trip<T: Serialize + for<'de> Deserialize<'de>>(value: &T) -> T {
+        serde_json::from_str(&serde_json::to_string(value).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_serde_round_trip() {
+        let (triangles, bvh) = random_bvh(71, 200);
+        let bvh_copy: Bvh2 = round_trip(&bvh);
+        assert_eq!(bvh_copy.content_hash(), bvh.content_hash());
+
+        let wrapped: Vec<TriangleSerde> = triangles.iter().copied().map(Into::into).collect();
+        for (t, copy) in triangles.iter().zip(round_trip(&wrapped)) {
+            assert_eq!([t.v0, t.v1, t.v2], [copy.0.v0, copy.0.v1, copy.0.v2]);
+        }
+
+        let aabb = bvh.nodes[0].aabb;
+        let copy = round_trip(&AabbSerde(aabb)).0;
+        assert_eq!((copy.min, copy.max), (aabb.min, aabb.max));
+
+        // JSON has no infinity, so the ray needs a finite tmax and no zero direction components
+        let ray = Ray::new(
+            Vec3A::new(0.5, -1.0, 2.0),
+            Vec3A::new(0.6, 0.8, -0.1),
+            0.0,
+            10.0,
+        );
+        let copy = round_trip(&RaySerde(ray)).0;
+        assert_eq!(
+            (copy.origin, copy.direction, copy.inv_direction),
+            (ray.origin, ray.direction, ray.inv_direction)
+        );
+        assert_eq!((copy.tmin, copy.tmax), (ray.tmin, ray.tmax));
+    }
+}
@@ -0,0 +1,202 @@
+//! `std::simd` versions of the hot kernels, for SIMD on every target without per target intrinsics.
+//! Needs a nightly compiler, enabled with the `simd` feature, which switches the ploc morton encoding
+//! (`morton::morton_encode_u64_unorm_batch`), the child aabb tests of `bvh::traverse_nodes` and the
+//! radix digit counts of `u64` keys over to this module. Morton codes and digit counts match the scalar
+//! versions exactly. 128 bit morton codes are still encoded one at a time, there are no 128 bit lanes.
+//!
+//! The feature uses `#![feature(portable_simd)]`, so `cargo build --all-features` only works on nightly.
+
+use std::{
+    array,
+    simd::{prelude::*, LaneCount, SupportedLaneCount},
+};
+
+use glam::DVec3;
+use obvhs::{aabb::Aabb, ray::Ray};
+
+use crate::{
+    morton::{morton_encode_u64_unorm, MORTON_U64_BITS},
+    radix::radix_key::RadixKey,
+};
+
+/// Lanes of the kernels below.
+pub const LANES: usize = 8;
+
+/// `Aabb::intersect_ray` against 8 aabbs at once: the entry distance of each, or infinity on a miss.
+#[inline]
+pub fn intersect_aabbs_x8(ray: &Ray, aabbs: &[Aabb; LANES]) -> [f32; LANES] {
+    intersect_aabbs_xn(ray, aabbs)
+}
+
+/// `intersect_aabbs_x8` for any supported lane count, e.g. the 2 children of a bvh node.
+#[inline(always)]
+pub fn intersect_aabbs_xn<const N: usize>(ray: &Ray, aabbs: &[Aabb; N]) -> [f32; N]
+where
+    LaneCount<N>: SupportedLaneCount,
+{
+    let load = |f: fn(&Aabb) -> f32| Simd::<f32, N>::from_array(array::from_fn(|i| f(&aabbs[i])));
+    let slab = |min: Simd<f32, N>, max: Simd<f32, N>, origin: f32, inv_direction: f32| {
+        let origin = Simd::splat(origin);
+        let inv_direction = Simd::splat(inv_direction);
+        let t1 = (min - origin) * inv_direction;
+        let t2 = (max - origin) * inv_direction;
+        (t1.simd_min(t2), t1.simd_max(t2))
+    };
+    let (near_x, far_x) = slab(
+        load(|a| a.min.x),
+        load(|a| a.max.x),
+        ray.origin.x,
+        ray.inv_direction.x,
+    );
+    let (near_y, far_y) = slab(
+        load(|a| a.min.y),
+        load(|a| a.max.y),
+        ray.origin.y,
+        ray.inv_direction.y,
+    );
+    let (near_z, far_z) = slab(
+        load(|a| a.min.z),
+        load(|a| a.max.z),
+        ray.origin.z,
+        ray.inv_direction.z,
+    );
+    let near = near_x
+        .simd_max(near_y)
+        .simd_max(near_z)
+        .simd_max(Simd::splat(ray.tmin));
+    let far = far_x
+        .simd_min(far_y)
+        .simd_min(far_z)
+        .simd_min(Simd::splat(ray.tmax));
+    near.simd_le(far)
+        .select(near, Simd::splat(f32::INFINITY))
+        .to_array()
+}
+
+/// `intersect_aabbs_x8` over any number of aabbs, writing the entry distances to `out`.
+pub fn intersect_aabbs(ray: &Ray, aabbs: &[Aabb], out: &mut [f32]) {
+    assert_eq!(aabbs.len(), out.len());
+    let mut aabb_chunks = aabbs.chunks_exact(LANES);
+    let mut out_chunks = out.chunks_exact_mut(LANES);
+    for (a, o) in (&mut aabb_chunks).zip(&mut out_chunks) {
+        o.copy_from_slice(&intersect_aabbs_x8(ray, a.try_into().unwrap()));
+    }
+    for (a, o) in aabb_chunks
+        .remainder()
+        .iter()
+        .zip(out_chunks.into_remainder())
+    {
+        *o = a.intersect_ray(ray);
+    }
+}
+
+#[inline(always)]
+fn split_by_3_x8(a: u64x8) -> u64x8 {
+    let mut x = a & u64x8::splat(0x1f_ffff);
+    x = (x | x << 32) & u64x8::splat(0x1f_0000_0000_ffff);
+    x = (x | x << 16) & u64x8::splat(0x1f_0000_ff00_00ff);
+    x = (x | x << 8) & u64x8::splat(0x100f_00f0_0f00_f00f);
+    x = (x | x << 4) & u64x8::splat(0x10c3_0c30_c30c_30c3);
+    x = (x | x << 2) & u64x8::splat(0x1249_2492_4924_9249);
+    x
+}
+
+/// `morton::morton_encode_u64_unorm_x8` on explicit vectors.
+#[inline]
+pub fn morton_encode_u64_unorm_x8(p: &[DVec3; LANES]) -> [u64; LANES] {
+    let scale = f64x8::splat((1u32 << MORTON_U64_BITS) as f64);
+    let max = f64x8::splat(((1u32 << MORTON_U64_BITS) - 1) as f64);
+    let axis = |f: fn(&DVec3) -> f64| {
+        let v = f64x8::from_array(array::from_fn(|i| f(&p[i])));
+        // NaN casts to 0 like the scalar `as u32`
+        split_by_3_x8((v * scale).simd_clamp(f64x8::splat(0.0), max).cast::<u64>())
+    };
+    (axis(|p| p.x) | axis(|p| p.y) << 1 | axis(|p| p.z) << 2).to_array()
+}
+
+/// `morton::morton_encode_u64_unorm_batch` with `morton_encode_u64_unorm_x8` from this module.
+pub fn morton_encode_u64_unorm_batch(points: &[DVec3], codes: &mut [u64]) {
+    assert_eq!(points.len(), codes.len());
+    let mut point_chunks = points.chunks_exact(LANES);
+    let mut code_chunks = codes.chunks_exact_mut(LANES);
+    for (p, c) in (&mut point_chunks).zip(&mut code_chunks) {
+        c.copy_from_slice(&morton_encode_u64_unorm_x8(p.try_into().unwrap()));
+    }
+    for (p, c) in point_chunks
+        .remainder()
+        .iter()
+        .zip(code_chunks.into_remainder())
+    {
+        *c = morton_encode_u64_unorm(*p);
+    }
+}
+
+/// Radix digit counts of `keys` at `level`, like the counting pass of the sorts. Digits are extracted 8
+/// keys at a time and counted into separate tables per lane, so consecutive equal digits don't wait on
+/// each other's increments.
+pub fn count_digits_u64(keys: &[u64], level: usize) -> [usize; 256] {
+    let shift = u64x8::splat(level as u64 * 8);
+    let mask = u64x8::splat(0xff);
+    let mut lane_counts = [[0u32; 256]; LANES];
+    let chunks = keys.chunks_exact(LANES);
+    let rem = chunks.remainder();
+    for chunk in chunks {
+        let digits = (u64x8::from_slice(chunk) >> shift) & mask;
+        for (counts, digit) in lane_counts.iter_mut().zip(digits.to_array()) {
+            counts[digit as usize] += 1;
+        }
+    }
+    let mut counts = [0usize; 256];
+    for key in rem {
+        counts[key.get_level(level) as usize] += 1;
+    }
+    for lane in &lane_counts {
+        for (count, lane_count) in counts.iter_mut().zip(lane) {
+            *count += *lane_count as usize;
+        }
+    }
+    counts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::reference::{random_rays, Rng};
+    use glam::Vec3A;
+
+    #[test]
+    fn test_simd_matches_scalar() {
+        let mut rng = Rng::new(71);
+        let aabbs: Vec<Aabb> = (0..21)
+            .map(|_| {
+                let p = rng.next_vec3a();
+                Aabb::new(p, p + rng.next_vec3a() * 0.3)
+            })
+            .collect();
+        let mut out = vec![0.0; aabbs.len()];
+        for ray in random_rays(&mut rng, 64) {
+            intersect_aabbs(&ray, &aabbs, &mut out);
+            for (aabb, t) in aabbs.iter().zip(&out) {
+                let expected = aabb.intersect_ray(&ray);
+                assert!(*t == expected || (t.is_infinite() && expected >= ray.tmax));
+            }
+        }
+
+        let points: Vec<DVec3> = (0..19)
+            .map(|_| (rng.next_vec3a() * 1.2 - Vec3A::splat(0.1)).as_dvec3())
+            .collect();
+        let mut codes = vec![0; points.len()];
+        morton_encode_u64_unorm_batch(&points, &mut codes);
+        let expected: Vec<u64> = points.iter().map(|p| morton_encode_u64_unorm(*p)).collect();
+        assert_eq!(codes, expected);
+
+        let keys: Vec<u64> = (0..1003).map(|_| rng.next_u64()).collect();
+        for level in [0, 3, 7] {
+            let mut expected = [0usize; 256];
+            for key in &keys {
+                expected[key.get_level(level) as usize] += 1;
+            }
+            assert_eq!(count_digits_u64(&keys, level), expected);
+        }
+    }
+}
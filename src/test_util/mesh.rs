@@ -0,0 +1,135 @@
+//! Mesh loading for benchmarks and examples on real assets. Use the `obj` feature for `load_obj` and the
+//! `gltf` feature for `load_gltf`.
+
+use std::{io, path::Path};
+
+use glam::Vec3A;
+use obvhs::triangle::Triangle;
+
+/// An indexed triangle mesh, all meshes in a file are merged into one.
+#[derive(Clone, Default, Debug)]
+pub struct IndexedMesh {
+    pub positions: Vec<Vec3A>,
+    pub indices: Vec<[u32; 3]>,
+}
+
+impl IndexedMesh {
+    pub fn triangles(&self) -> Vec<Triangle> {
+        self.indices
+            .iter()
+            .map(|[a, b, c]| Triangle {
+                v0: self.positions[*a as usize],
+                v1: self.positions[*b as usize],
+                v2: self.positions[*c as usize],
+            })
+            .collect()
+    }
+
+    fn extend(&mut self, positions: impl Iterator<Item = Vec3A>, indices: &[u32]) {
+        let base = self.positions.len() as u32;
+        self.positions.extend(positions);
+        self.indices.extend(
+            indices
+                .chunks_exact(3)
+                .map(|t| [base + t[0], base + t[1], base + t[2]]),
+        );
+    }
+}
+
+/// Load every mesh in an OBJ file, triangulating polygons.
+#[cfg(feature = "obj")]
+pub fn load_obj_indexed<P: AsRef<Path>>(path: P) -> io::Result<IndexedMesh> {
+    crate::scope!("load_obj");
+    let (models, _materials) = tobj::load_obj(path.as_ref(), &tobj::GPU_LOAD_OPTIONS)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    let mut mesh = IndexedMesh::default();
+    for model in &models {
+        let positions = model
+            .mesh
+            .positions
+            .chunks_exact(3)
+            .map(|p| Vec3A::new(p[0], p[1], p[2]));
+        mesh.extend(positions, &model.mesh.indices);
+    }
+    Ok(mesh)
+}
+
+#[cfg(feature = "obj")]
+pub fn load_obj<P: AsRef<Path>>(path: P) -> io::Result<Vec<Triangle>> {
+    Ok(load_obj_indexed(path)?.triangles())
+}
+
+/// Load every triangle mesh in the default scene of a glTF file (or the first scene if there is no
+/// default), with node transforms applied. Non triangle primitives are skipped.
+#[cfg(feature = "gltf")]
+pub fn load_gltf_indexed<P: AsRef<Path>>(path: P) -> io::Result<IndexedMesh> {
+    crate::scope!("load_gltf");
+    let (document, buffers, _images) = gltf::import(path).map_err(|e| match e {
+        gltf::Error::Io(e) => e,
+        e => io::Error::new(io::ErrorKind::InvalidData, e),
+    })?;
+
+    fn visit(
+        node: gltf::Node,
+        parent: glam::Mat4,
+        buffers: &[gltf::buffer::Data],
+        mesh: &mut IndexedMesh,
+    ) {
+        let transform = parent * glam::Mat4::from_cols_array_2d(&node.transform().matrix());
+        if let Some(node_mesh) = node.mesh() {
+            for primitive in node_mesh.primitives() {
+                if primitive.mode() != gltf::mesh::Mode::Triangles {
+                    continue;
+                }
+                let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+                let Some(positions) = reader.read_positions() else {
+                    continue;
+                };
+                let positions: Vec<Vec3A> = positions
+                    .map(|p| transform.transform_point3a(Vec3A::from_array(p)))
+                    .collect();
+                let indices: Vec<u32> = match reader.read_indices() {
+                    Some(indices) => indices.into_u32().collect(),
+                    None => (0..positions.len() as u32).collect(),
+                };
+                mesh.extend(positions.into_iter(), &indices);
+            }
+        }
+        for child in node.children() {
+            visit(child, transform, buffers, mesh);
+        }
+    }
+
+    let mut mesh = IndexedMesh::default();
+    if let Some(scene) = document
+        .default_scene()
+        .or_else(|| document.scenes().next())
+    {
+        for node in scene.nodes() {
+            visit(node, glam::Mat4::IDENTITY, &buffers, &mut mesh);
+        }
+    }
+    Ok(mesh)
+}
+
+#[cfg(feature = "gltf")]
+pub fn load_gltf<P: AsRef<Path>>(path: P) -> io::Result<Vec<Triangle>> {
+    Ok(load_gltf_indexed(path)?.triangles())
+}
+
+#[cfg(all(test, feature = "obj"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_obj_quad() {
+        let path = std::env::temp_dir().join("pool_racing_test_load_obj_quad.obj");
+        std::fs::write(&path, "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nf 1 2 3 4\n").unwrap();
+        let mesh = load_obj_indexed(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(mesh.positions.len(), 4);
+        assert_eq!(mesh.indices.len(), 2);
+        let tris = mesh.triangles();
+        assert_eq!(tris[0].v1, Vec3A::new(1.0, 0.0, 0.0));
+    }
+}
@@ -0,0 +1,14 @@
+//! Helpers for testing and benchmarking: a brute force reference intersector with a traversal fuzz
+//! harness, build reproducibility checks, standard benchmark scenes, ray generation, and mesh loaders
+//! behind the `obj` and `gltf` features.
+
+#[cfg(any(feature = "obj", feature = "gltf"))]
+pub mod mesh;
+pub mod rays;
+pub mod reference;
+pub mod reproducibility;
+pub mod scenes;
+
+#[cfg(any(feature = "obj", feature = "gltf"))]
+pub use mesh::*;
+pub use reference::random_bvh;
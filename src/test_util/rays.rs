@@ -0,0 +1,181 @@
+//! Ray generation for tests, benchmarks and examples: pinhole camera rays from view and projection
+//! matrices, cosine weighted hemisphere rays for ambient occlusion, and shadow rays towards lights.
+
+use glam::{uvec2, vec4, Mat4, Vec2, Vec3A, Vec4Swizzles};
+use obvhs::ray::Ray;
+
+use super::reference::Rng;
+
+/// Ray through pixel `index` of a `width` x `height` target, numbered row by row from the top left.
+/// `view_inv` and `proj_inv` are the inverse view and projection matrices, the projection can be reverse
+/// z like `Mat4::perspective_infinite_reverse_rh`. Misses leave `tmax` at `f32::MAX`.
+#[inline(always)]
+pub fn camera_ray(
+    index: usize,
+    width: usize,
+    height: usize,
+    view_inv: &Mat4,
+    proj_inv: &Mat4,
+) -> Ray {
+    let target_size = Vec2::new(width as f32, height as f32);
+    let frag_coord = uvec2((index % width) as u32, (index / width) as u32);
+    let mut screen_uv = frag_coord.as_vec2() / target_size;
+    screen_uv.y = 1.0 - screen_uv.y;
+    let ndc = screen_uv * 2.0 - Vec2::ONE;
+    let clip_pos = vec4(ndc.x, ndc.y, 1.0, 1.0);
+
+    let mut vs_pos = *proj_inv * clip_pos;
+    vs_pos /= vs_pos.w;
+    let eye = Vec3A::from(view_inv.w_axis.xyz());
+    let direction = (Vec3A::from((*view_inv * vs_pos).xyz()) - eye).normalize();
+    Ray::new(eye, direction, 0.0, f32::MAX)
+}
+
+/// `camera_ray` for every pixel, row by row from the top left.
+pub fn camera_rays(width: usize, height: usize, view_inv: &Mat4, proj_inv: &Mat4) -> Vec<Ray> {
+    (0..width * height)
+        .map(|i| camera_ray(i, width, height, view_inv, proj_inv))
+        .collect()
+}
+
+/// Camera rays grouped into `tile_size` x `tile_size` screen tiles, for coherent packets. Tiles are row by
+/// row from the top left, edge tiles are smaller when the target isn't a multiple of the tile size.
+pub fn camera_ray_packets(
+    width: usize,
+    height: usize,
+    tile_size: usize,
+    view_inv: &Mat4,
+    proj_inv: &Mat4,
+) -> Vec<Vec<Ray>> {
+    let mut packets = Vec::new();
+    for tile_y in (0..height).step_by(tile_size) {
+        for tile_x in (0..width).step_by(tile_size) {
+            let mut packet = Vec::with_capacity(tile_size * tile_size);
+            for y in tile_y..(tile_y + tile_size).min(height) {
+                for x in tile_x..(tile_x + tile_size).min(width) {
+                    packet.push(camera_ray(y * width + x, width, height, view_inv, proj_inv));
+                }
+            }
+            packets.push(packet);
+        }
+    }
+    packets
+}
+
+/// Two unit vectors forming an orthonormal basis with the unit vector `n`.
+fn orthonormal(n: Vec3A) -> (Vec3A, Vec3A) {
+    // Building an Orthonormal Basis, Revisited (Duff et al. 2017)
+    let sign = 1.0f32.copysign(n.z);
+    let a = -1.0 / (sign + n.z);
+    let b = n.x * n.y * a;
+    (
+        Vec3A::new(1.0 + sign * n.x * n.x * a, sign * b, -sign * n.x),
+        Vec3A::new(b, sign + n.y * n.y * a, -n.y),
+    )
+}
+
+/// `count` cosine weighted rays over the hemisphere around `normal`, for ambient occlusion. Rays start
+/// `bias` along the normal to avoid hitting the surface they leave from, and end at `max_distance`.
+pub fn cosine_hemisphere_rays(
+    rng: &mut Rng,
+    origin: Vec3A,
+    normal: Vec3A,
+    count: usize,
+    bias: f32,
+    max_distance: f32,
+) -> Vec<Ray> {
+    let (tangent, bitangent) = orthonormal(normal);
+    let origin = origin + normal * bias;
+    (0..count)
+        .map(|_| {
+            // Uniform on the disk projected up to the hemisphere, by rejection so it doesn't depend on
+            // the platform's `sin` and `cos`
+            let (x, y) = loop {
+                let x = rng.next_f32() * 2.0 - 1.0;
+                let y = rng.next_f32() * 2.0 - 1.0;
+                if x * x + y * y <= 1.0 {
+                    break (x, y);
+                }
+            };
+            let z = (1.0 - x * x - y * y).max(0.0).sqrt();
+            let direction = (tangent * x + bitangent * y + normal * z).normalize();
+            Ray::new(origin, direction, 0.0, max_distance)
+        })
+        .collect()
+}
+
+/// Rays from each of `points` towards a point light at `light`, ending just before reaching it. Rays
+/// start `bias` towards the light to avoid hitting the surface they leave from.
+pub fn point_light_shadow_rays(points: &[Vec3A], light: Vec3A, bias: f32) -> Vec<Ray> {
+    points
+        .iter()
+        .map(|&p| {
+            let to_light = light - p;
+            let distance = to_light.length();
+            let direction = to_light / distance;
+            Ray::new(p, direction, bias, (distance - bias).max(bias))
+        })
+        .collect()
+}
+
+/// Rays from each of `points` against `light_direction`, the direction the light travels in, like for
+/// the sun.
+pub fn directional_light_shadow_rays(
+    points: &[Vec3A],
+    light_direction: Vec3A,
+    bias: f32,
+) -> Vec<Ray> {
+    let direction = -light_direction.normalize();
+    points
+        .iter()
+        .map(|&p| Ray::new(p, direction, bias, f32::INFINITY))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glam::{vec3, Vec3};
+
+    #[test]
+    fn test_camera_rays() {
+        let eye = vec3(0.0, 1.0, 2.0);
+        let proj_inv =
+            Mat4::perspective_infinite_reverse_rh(90f32.to_radians(), 2.0, 0.01).inverse();
+        let view_inv = Mat4::look_at_rh(eye, Vec3::Y, Vec3::Y).inverse();
+        let rays = camera_rays(8, 4, &view_inv, &proj_inv);
+        assert_eq!(rays.len(), 32);
+        // The center pixel looks straight ahead
+        let center = rays[2 * 8 + 4];
+        assert!((center.origin - Vec3A::from(eye)).length() < 1e-5);
+        assert!((center.direction - Vec3A::NEG_Z).length() < 1e-4);
+        // The top left looks up and left
+        assert!(rays[0].direction.x < 0.0 && rays[0].direction.y > 0.0);
+
+        let packets = camera_ray_packets(8, 4, 3, &view_inv, &proj_inv);
+        assert_eq!(packets.len(), 6);
+        assert_eq!(packets.iter().map(Vec::len).sum::<usize>(), 32);
+        assert_eq!(packets[0][4].direction, rays[8 + 1].direction);
+    }
+
+    #[test]
+    fn test_hemisphere_and_shadow_rays() {
+        let mut rng = Rng::new(1);
+        let normal = Vec3A::new(0.0, 0.6, 0.8);
+        let rays = cosine_hemisphere_rays(&mut rng, Vec3A::ZERO, normal, 256, 1e-3, 10.0);
+        assert!(rays.iter().all(|r| r.direction.dot(normal) >= 0.0));
+        assert!(rays
+            .iter()
+            .all(|r| (r.direction.length() - 1.0).abs() < 1e-4));
+        let mean_cos = rays.iter().map(|r| r.direction.dot(normal)).sum::<f32>() / 256.0;
+        // The mean cosine of a cosine weighted hemisphere is 2/3
+        assert!((mean_cos - 2.0 / 3.0).abs() < 0.05, "{mean_cos}");
+
+        let points = [Vec3A::ZERO, Vec3A::X];
+        let shadow = point_light_shadow_rays(&points, Vec3A::new(0.0, 2.0, 0.0), 1e-3);
+        assert_eq!(shadow[0].direction, Vec3A::Y);
+        assert!((shadow[0].tmax - (2.0 - 1e-3)).abs() < 1e-5);
+        let sun = directional_light_shadow_rays(&points, Vec3A::NEG_Y, 1e-3);
+        assert_eq!(sun[1].direction, Vec3A::Y);
+    }
+}
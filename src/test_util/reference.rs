@@ -0,0 +1,294 @@
+//! Brute force reference intersection, and a fuzz harness comparing bvh traversal against it on random
+//! scenes for every scheduler and space filling curve.
+
+use std::fmt;
+
+use glam::Vec3A;
+use obvhs::{ray::Ray, triangle::Triangle};
+
+use crate::{
+    bvh::Bvh2,
+    morton::SpaceFillingCurve,
+    par::{Scheduler, SchedulerGuard},
+    ploc::{set_ploc_curve, set_ploc_scheduler, PlocBuilder},
+    radix::set_radix_scheduler,
+};
+
+/// The closest hit of `ray` by testing every triangle, as (primitive id, t).
+pub fn brute_force_closest_hit(triangles: &[Triangle], ray: &Ray) -> Option<(u32, f32)> {
+    let mut ray = *ray;
+    let mut hit = None;
+    for (i, tri) in triangles.iter().enumerate() {
+        let t = tri.intersect(&ray);
+        if t < ray.tmax {
+            ray.tmax = t;
+            hit = Some((i as u32, t));
+        }
+    }
+    hit
+}
+
+/// The closest hit of `ray` through the bvh, as (primitive id, t).
+pub fn bvh_closest_hit(bvh: &Bvh2, triangles: &[Triangle], ray: &Ray) -> Option<(u32, f32)> {
+    if bvh.nodes.is_empty() {
+        return None;
+    }
+    let mut ray = *ray;
+    let mut id = u32::MAX;
+    bvh.traverse(&mut ray, &mut id, |ray, i| triangles[i].intersect(ray));
+    (id != u32::MAX).then_some((id, ray.tmax))
+}
+
+/// Small xorshift generator so fuzz runs are reproducible from their seed. The output only depends on
+/// integer operations, so a seed gives the same sequence on every platform.
+#[derive(Clone, Debug)]
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        // Zero is a fixed point of xorshift
+        Self(seed ^ 0x9e37_79b9_7f4a_7c15)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    /// Uniform in 0..1
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    pub fn next_vec3a(&mut self) -> Vec3A {
+        Vec3A::new(self.next_f32(), self.next_f32(), self.next_f32())
+    }
+}
+
+/// Cube root from exactly rounded float operations only, unlike `f32::cbrt` which can differ between
+/// platform math libraries. Within a few ulp of the true root for positive `x`.
+pub fn portable_cbrt(x: f32) -> f32 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    // Newton's method from a guess above the root, which converges from above
+    let mut y = x.max(1.0);
+    for _ in 0..100 {
+        let next = (2.0 * y + x / (y * y)) / 3.0;
+        if next >= y {
+            break;
+        }
+        y = next;
+    }
+    y
+}
+
+/// Uniformly distributed unit vector, by rejection from the unit ball so it doesn't depend on the
+/// platform's `sin` and `cos`.
+pub fn random_unit_vector(rng: &mut Rng) -> Vec3A {
+    loop {
+        let v = rng.next_vec3a() * 2.0 - 1.0;
+        let len_sq = v.length_squared();
+        if len_sq > 1e-6 && len_sq <= 1.0 {
+            return v / len_sq.sqrt();
+        }
+    }
+}
+
+/// `count` random triangles in the unit cube, with edges up to `max_size` long.
+pub fn random_triangles(rng: &mut Rng, count: usize, max_size: f32) -> Vec<Triangle> {
+    (0..count)
+        .map(|_| {
+            let v0 = rng.next_vec3a();
+            Triangle {
+                v0,
+                v1: v0 + (rng.next_vec3a() - 0.5) * max_size,
+                v2: v0 + (rng.next_vec3a() - 0.5) * max_size,
+            }
+        })
+        .collect()
+}
+
+/// `n` random triangles from `seed` with edges up to 0.2 long, and a bvh over them. Built on a
+/// sequential builder with its own scheduler, so the global schedulers are neither read nor changed.
+pub fn random_bvh(seed: u64, n: usize) -> (Vec<Triangle>, Bvh2) {
+    let triangles = random_triangles(&mut Rng::new(seed), n, 0.2);
+    let aabbs: Vec<_> = triangles.iter().map(|t| t.aabb()).collect();
+    let bvh = PlocBuilder::with_scheduler(Scheduler::SequentialOptimized, n).build_ploc(&aabbs);
+    (triangles, bvh)
+}
+
+/// `count` rays from random points around the unit cube towards random points inside it. Some start
+/// inside the cube.
+pub fn random_rays(rng: &mut Rng, count: usize) -> Vec<Ray> {
+    (0..count)
+        .map(|_| {
+            let origin = (rng.next_vec3a() - 0.5) * 4.0 + 0.5;
+            let target = rng.next_vec3a();
+            Ray::new_inf(origin, (target - origin).normalize())
+        })
+        .collect()
+}
+
+#[derive(Clone, Debug)]
+pub struct FuzzConfig {
+    pub seed: u64,
+    pub scenes: usize,
+    /// Scenes have between 1 and this many triangles
+    pub max_triangles: usize,
+    pub rays_per_scene: usize,
+    /// Relative tolerance for hit distances
+    pub tolerance: f32,
+}
+
+impl Default for FuzzConfig {
+    fn default() -> Self {
+        Self {
+            seed: 0,
+            scenes: 16,
+            max_triangles: 2000,
+            rays_per_scene: 512,
+            tolerance: 1e-4,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct FuzzMismatch {
+    pub scheduler: Scheduler,
+    pub curve: SpaceFillingCurve,
+    pub scene: usize,
+    pub triangle_count: usize,
+    pub ray: Ray,
+    pub expected: Option<(u32, f32)>,
+    pub got: Option<(u32, f32)>,
+}
+
+impl fmt::Display for FuzzMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {:?} scene {} ({} triangles): ray {:?} {:?} expected {:?} got {:?}",
+            self.scheduler.name(),
+            self.curve,
+            self.scene,
+            self.triangle_count,
+            self.ray.origin,
+            self.ray.direction,
+            self.expected,
+            self.got
+        )
+    }
+}
+
+fn hits_match(
+    triangles: &[Triangle],
+    ray: &Ray,
+    expected: Option<(u32, f32)>,
+    got: Option<(u32, f32)>,
+    tolerance: f32,
+) -> bool {
+    match (expected, got) {
+        (None, None) => true,
+        (Some((expected_id, expected_t)), Some((got_id, got_t))) => {
+            let close = |a: f32, b: f32| (a - b).abs() <= tolerance * a.abs().max(1.0);
+            // Another triangle at the same distance is just as close, like on shared edges
+            close(expected_t, got_t)
+                && (expected_id == got_id
+                    || close(triangles[got_id as usize].intersect(ray), expected_t))
+        }
+        _ => false,
+    }
+}
+
+/// Build random scenes with ploc for every scheduler and space filling curve, and check traversal finds
+/// the same closest hits as brute force. Returns the number of rays checked, or the first mismatch.
+///
+/// Each builder is given its scheduler and curve directly. The global ploc and radix schedulers and the
+/// ploc curve are also set to them for the duration, under a `SchedulerGuard` that restores them
+/// afterwards.
+pub fn fuzz_traversal(config: &FuzzConfig) -> Result<usize, FuzzMismatch> {
+    let _guard = SchedulerGuard::new();
+    fuzz_traversal_inner(config)
+}
+
+fn fuzz_traversal_inner(config: &FuzzConfig) -> Result<usize, FuzzMismatch> {
+    let mut checked = 0;
+    for scene in 0..config.scenes {
+        let mut rng = Rng::new(config.seed.wrapping_add(scene as u64));
+        let triangle_count = 1 + (rng.next_u64() as usize % config.max_triangles.max(1));
+        let max_size = 0.02 + rng.next_f32() * 0.3;
+        let triangles = random_triangles(&mut rng, triangle_count, max_size);
+        let rays = random_rays(&mut rng, config.rays_per_scene);
+        let expected: Vec<_> = rays
+            .iter()
+            .map(|ray| brute_force_closest_hit(&triangles, ray))
+            .collect();
+        let aabbs: Vec<_> = triangles.iter().map(|t| t.aabb()).collect();
+
+        for scheduler in Scheduler::ALL {
+            set_ploc_scheduler(scheduler);
+            set_radix_scheduler(scheduler);
+            for curve in SpaceFillingCurve::ALL {
+                set_ploc_curve(curve);
+                let mut builder = PlocBuilder::with_scheduler(scheduler, aabbs.len());
+                builder.curve = curve;
+                let bvh = builder.build_ploc(&aabbs);
+                for (ray, expected) in rays.iter().zip(&expected) {
+                    let got = bvh_closest_hit(&bvh, &triangles, ray);
+                    if !hits_match(&triangles, ray, *expected, got, config.tolerance) {
+                        return Err(FuzzMismatch {
+                            scheduler,
+                            curve,
+                            scene,
+                            triangle_count,
+                            ray: *ray,
+                            expected: *expected,
+                            got,
+                        });
+                    }
+                    checked += 1;
+                }
+            }
+        }
+    }
+    Ok(checked)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fuzz_traversal() {
+        let config = FuzzConfig {
+            scenes: 3,
+            max_triangles: 300,
+            rays_per_scene: 64,
+            ..Default::default()
+        };
+        match fuzz_traversal(&config) {
+            Ok(checked) => assert_eq!(
+                checked,
+                3 * 64 * Scheduler::ALL.len() * SpaceFillingCurve::ALL.len()
+            ),
+            Err(mismatch) => panic!("{mismatch}"),
+        }
+    }
+
+    #[test]
+    fn test_portable_generators() {
+        for x in [1e-3f32, 0.5, 1.0, 8.0, 10_000.0, 1e6] {
+            let y = portable_cbrt(x);
+            assert!((y * y * y - x).abs() <= x * 1e-5, "{x}: {y}");
+        }
+        let mut rng = Rng::new(2);
+        assert!((0..100).all(|_| (random_unit_vector(&mut rng).length() - 1.0).abs() < 1e-5));
+        // Same seed, same sequence
+        let a = random_triangles(&mut Rng::new(9), 50, 0.1);
+        let b = random_triangles(&mut Rng::new(9), 50, 0.1);
+        assert!(a.iter().zip(&b).all(|(a, b)| a.v0 == b.v0 && a.v1 == b.v1));
+    }
+}
@@ -0,0 +1,109 @@
+//! Checks that building the same scene repeatedly gives identical bvhs, across runs and schedulers.
+//! Used to verify deterministic mode and to catch races in new parallel code.
+
+use std::fmt;
+
+use obvhs::aabb::Aabb;
+
+use crate::{
+    par::{Scheduler, SchedulerGuard},
+    ploc::{set_ploc_scheduler, PlocBuilder},
+    radix::set_radix_scheduler,
+};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SchedulerHashes {
+    pub scheduler: Scheduler,
+    /// `Bvh2::content_hash` of each run
+    pub hashes: Vec<u64>,
+}
+
+impl SchedulerHashes {
+    /// Every run of this scheduler built the same bvh.
+    pub fn is_consistent(&self) -> bool {
+        self.hashes.windows(2).all(|w| w[0] == w[1])
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ReproducibilityReport {
+    pub schedulers: Vec<SchedulerHashes>,
+}
+
+impl ReproducibilityReport {
+    /// Every run of every scheduler built the same bvh.
+    pub fn is_identical(&self) -> bool {
+        let mut hashes = self.schedulers.iter().flat_map(|s| &s.hashes);
+        match hashes.next() {
+            Some(first) => hashes.all(|h| h == first),
+            None => true,
+        }
+    }
+}
+
+impl fmt::Display for ReproducibilityReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let reference = self.schedulers.first().and_then(|s| s.hashes.first());
+        for s in &self.schedulers {
+            let status = if !s.is_consistent() {
+                "differs between runs"
+            } else if s.hashes.first() != reference {
+                "differs from first scheduler"
+            } else {
+                "identical"
+            };
+            let hash = s.hashes.first().copied().unwrap_or_default();
+            writeln!(f, "{:>10} {hash:016x} {status}", s.scheduler.name())?;
+        }
+        Ok(())
+    }
+}
+
+/// Build `aabbs` with ploc `runs` times on each of `schedulers`, recording the content hash of each
+/// build. The builder is reused between runs like `rebuild_ploc` would be.
+///
+/// The builder is given each scheduler directly. The global ploc and radix schedulers are also set to it
+/// for the duration, under a `SchedulerGuard` that restores them afterwards.
+pub fn check_reproducibility(
+    aabbs: &[Aabb],
+    runs: usize,
+    schedulers: &[Scheduler],
+) -> ReproducibilityReport {
+    let _guard = SchedulerGuard::new();
+    let mut builder = PlocBuilder::preallocate_builder(aabbs.len());
+    let mut bvh = Default::default();
+    ReproducibilityReport {
+        schedulers: schedulers
+            .iter()
+            .map(|&scheduler| {
+                set_ploc_scheduler(scheduler);
+                set_radix_scheduler(scheduler);
+                builder.scheduler = Some(scheduler);
+                SchedulerHashes {
+                    scheduler,
+                    hashes: (0..runs)
+                        .map(|_| {
+                            builder.rebuild_ploc(aabbs, &mut bvh);
+                            bvh.content_hash()
+                        })
+                        .collect(),
+                }
+            })
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::reference::{random_triangles, Rng};
+
+    #[test]
+    fn test_check_reproducibility() {
+        let triangles = random_triangles(&mut Rng::new(7), 500, 0.1);
+        let aabbs: Vec<_> = triangles.iter().map(|t| t.aabb()).collect();
+        let report = check_reproducibility(&aabbs, 3, &[Scheduler::Sequential]);
+        assert_eq!(report.schedulers[0].hashes.len(), 3);
+        assert!(report.is_identical(), "{report}");
+    }
+}
@@ -0,0 +1,252 @@
+//! Standard procedural benchmark scenes at fixed sizes, so build and traversal results can be compared
+//! between users and versions. Scenes are looked up by name, like `"hair_ball"`, and generated from an
+//! explicit seed, [`DEFAULT_SEED`] for the standard scenes. Generation only uses exactly rounded float
+//! operations so a seed gives the same triangles on every platform.
+
+use std::str::FromStr;
+
+use glam::{Mat4, Vec3, Vec3A};
+use obvhs::{
+    test_util::geometry::{demoscene, CUBE},
+    triangle::Triangle,
+    Transformable,
+};
+
+use super::reference::{portable_cbrt, random_triangles, random_unit_vector, Rng};
+
+/// Seed of the standard benchmark scenes.
+pub const DEFAULT_SEED: u64 = 0x5eed;
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum BenchScene {
+    /// Triangles spread uniformly through the unit cube
+    UniformSoup,
+    /// Triangles bunched into a few dense clusters with empty space between
+    ClusteredSoup,
+    /// Long thin triangles through a sphere, with lots of overlap
+    HairBall,
+    /// A grid of boxes of random heights on a ground plane
+    CityGrid,
+    /// The obvhs demoscene terrain
+    Demoscene,
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum SceneSize {
+    /// About 10k triangles
+    Small,
+    /// About 100k triangles
+    Medium,
+    /// About 1M triangles
+    Large,
+}
+
+impl SceneSize {
+    pub const ALL: [SceneSize; 3] = [SceneSize::Small, SceneSize::Medium, SceneSize::Large];
+
+    pub fn triangle_count(self) -> usize {
+        match self {
+            SceneSize::Small => 10_000,
+            SceneSize::Medium => 100_000,
+            SceneSize::Large => 1_000_000,
+        }
+    }
+
+    /// Name as accepted by `from_str`
+    pub fn name(self) -> &'static str {
+        match self {
+            SceneSize::Small => "small",
+            SceneSize::Medium => "medium",
+            SceneSize::Large => "large",
+        }
+    }
+}
+
+impl FromStr for SceneSize {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "small" => Ok(Self::Small),
+            "medium" => Ok(Self::Medium),
+            "large" => Ok(Self::Large),
+            _ => Err(format!(
+                "Unknown scene size: '{s}', valid sizes: 'small', 'medium', 'large'"
+            )),
+        }
+    }
+}
+
+impl FromStr for BenchScene {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "uniform_soup" => Ok(Self::UniformSoup),
+            "clustered_soup" => Ok(Self::ClusteredSoup),
+            "hair_ball" => Ok(Self::HairBall),
+            "city_grid" => Ok(Self::CityGrid),
+            "demoscene" => Ok(Self::Demoscene),
+            _ => Err(format!(
+                "Unknown scene: '{s}', valid scenes: 'uniform_soup', 'clustered_soup', 'hair_ball', 'city_grid', 'demoscene'"
+            )),
+        }
+    }
+}
+
+impl BenchScene {
+    pub const ALL: [BenchScene; 5] = [
+        BenchScene::UniformSoup,
+        BenchScene::ClusteredSoup,
+        BenchScene::HairBall,
+        BenchScene::CityGrid,
+        BenchScene::Demoscene,
+    ];
+
+    /// Name as accepted by `from_str`
+    pub fn name(self) -> &'static str {
+        match self {
+            BenchScene::UniformSoup => "uniform_soup",
+            BenchScene::ClusteredSoup => "clustered_soup",
+            BenchScene::HairBall => "hair_ball",
+            BenchScene::CityGrid => "city_grid",
+            BenchScene::Demoscene => "demoscene",
+        }
+    }
+
+    /// Generate the scene from `seed`. The triangle count is close to `size.triangle_count()`, but not
+    /// exact for scenes built from whole objects. The demoscene only uses the low 32 bits of the seed, and
+    /// its output is up to obvhs.
+    pub fn generate(self, size: SceneSize, seed: u64) -> Vec<Triangle> {
+        crate::scope!("generate bench scene");
+        let count = size.triangle_count();
+        let mut rng = Rng::new(seed);
+        match self {
+            BenchScene::UniformSoup => uniform_soup(&mut rng, count),
+            BenchScene::ClusteredSoup => clustered_soup(&mut rng, count),
+            BenchScene::HairBall => hair_ball(&mut rng, count),
+            BenchScene::CityGrid => city_grid(&mut rng, count),
+            // The terrain is a res x res grid of quads
+            BenchScene::Demoscene => demoscene(((count / 2) as f32).sqrt() as usize, seed as u32),
+        }
+    }
+}
+
+/// Generate the scene named `name` at `size` from `seed`, see [`BenchScene::from_str`] for the names.
+pub fn bench_scene(name: &str, size: SceneSize, seed: u64) -> Result<Vec<Triangle>, String> {
+    Ok(name.parse::<BenchScene>()?.generate(size, seed))
+}
+
+fn uniform_soup(rng: &mut Rng, count: usize) -> Vec<Triangle> {
+    // Keep the triangle density roughly constant across sizes
+    let max_size = 4.0 / portable_cbrt(count as f32);
+    random_triangles(rng, count, max_size)
+}
+
+fn clustered_soup(rng: &mut Rng, count: usize) -> Vec<Triangle> {
+    let clusters: Vec<(Vec3A, f32)> = (0..32)
+        .map(|_| (rng.next_vec3a(), 0.01 + rng.next_f32() * 0.05))
+        .collect();
+    let max_size = 1.0 / portable_cbrt(count as f32);
+    (0..count)
+        .map(|_| {
+            let (center, radius) = clusters[rng.next_u64() as usize % clusters.len()];
+            // Sum of uniforms, denser towards the center
+            let offset = (rng.next_vec3a() + rng.next_vec3a() - 1.0) * radius;
+            let v0 = center + offset;
+            Triangle {
+                v0,
+                v1: v0 + (rng.next_vec3a() - 0.5) * max_size,
+                v2: v0 + (rng.next_vec3a() - 0.5) * max_size,
+            }
+        })
+        .collect()
+}
+
+fn hair_ball(rng: &mut Rng, count: usize) -> Vec<Triangle> {
+    let width = 0.2 / (count as f32).sqrt();
+    (0..count)
+        .map(|_| {
+            // Strands between two points on the sphere, so most of them cross through the middle
+            let a = random_unit_vector(rng) * 0.5 + 0.5;
+            let b = random_unit_vector(rng) * 0.5 + 0.5;
+            let side = random_unit_vector(rng) * width;
+            Triangle {
+                v0: a,
+                v1: a + side,
+                v2: b,
+            }
+        })
+        .collect()
+}
+
+fn city_grid(rng: &mut Rng, count: usize) -> Vec<Triangle> {
+    // One box per cell, plus two ground triangles
+    let side = (((count - 2) / CUBE.len()) as f32).sqrt().max(1.0) as usize;
+    let cell = 1.0 / side as f32;
+    let mut tris = Vec::with_capacity(side * side * CUBE.len() + 2);
+    tris.push(Triangle {
+        v0: Vec3A::ZERO,
+        v1: Vec3A::new(1.0, 0.0, 0.0),
+        v2: Vec3A::new(1.0, 0.0, 1.0),
+    });
+    tris.push(Triangle {
+        v0: Vec3A::ZERO,
+        v1: Vec3A::new(1.0, 0.0, 1.0),
+        v2: Vec3A::new(0.0, 0.0, 1.0),
+    });
+    for x in 0..side {
+        for z in 0..side {
+            // Mostly low buildings with the occasional tower
+            let r = rng.next_f32();
+            let height = cell * (0.5 + r * r * r * r * 8.0);
+            let footprint = cell * (0.4 + rng.next_f32() * 0.2);
+            let center = Vec3::new((x as f32 + 0.5) * cell, 0.0, (z as f32 + 0.5) * cell);
+            let mut building = CUBE;
+            // CUBE spans -1..1
+            building.transform(&Mat4::from_scale_rotation_translation(
+                Vec3::new(footprint, height, footprint) * 0.5,
+                Default::default(),
+                center + Vec3::Y * height * 0.5,
+            ));
+            tris.extend(building);
+        }
+    }
+    tris
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bench_scenes() {
+        for scene in BenchScene::ALL {
+            assert_eq!(scene.name().parse::<BenchScene>(), Ok(scene));
+            let tris = scene.generate(SceneSize::Small, DEFAULT_SEED);
+            let count = SceneSize::Small.triangle_count();
+            // The demoscene's triangle count depends on obvhs
+            let expected = if scene == BenchScene::Demoscene {
+                1..usize::MAX
+            } else {
+                count / 2..count * 2
+            };
+            assert!(
+                expected.contains(&tris.len()),
+                "{} has {} triangles",
+                scene.name(),
+                tris.len()
+            );
+            assert!(tris
+                .iter()
+                .all(|t| !t.v0.is_nan() && !t.v1.is_nan() && !t.v2.is_nan()));
+        }
+        // Same seed, same scene
+        let a = bench_scene("clustered_soup", SceneSize::Small, 1).unwrap();
+        let b = bench_scene("clustered_soup", SceneSize::Small, 1).unwrap();
+        assert!(a.iter().zip(&b).all(|(a, b)| a.v0 == b.v0 && a.v2 == b.v2));
+        let c = bench_scene("clustered_soup", SceneSize::Small, 2).unwrap();
+        assert!(a.iter().zip(&c).any(|(a, c)| a.v0 != c.v0));
+        assert!(bench_scene("teapot", SceneSize::Small, 1).is_err());
+    }
+}
@@ -0,0 +1,652 @@
+//! Structured recording of `Timer` scopes, for plotting scheduler comparisons.
+//!
+//! Timers are only created by `scope_print!` and `scope_print_major!` when the `scope_print` or
+//! `scope_print_major` features are enabled. While recording, every finished timer is also stored here
+//! with its start offset, duration, thread and nesting depth, and can be written out as JSON or CSV.
+//!
+//! With `scope_print`, chunks run by the scheduler backends are also recorded (but not reported to the
+//! sink), so `write_chrome_trace` can show how work was spread over threads.
+//!
+//! Finished timers are reported to the current `TimerSink`, which prints to stdout by default. Use
+//! `set_sink` to send them to `log`, `tracing` spans, or nowhere instead.
+
+use std::{
+    cell::Cell,
+    io::{self, Write},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex, OnceLock, RwLock,
+    },
+    time::{Duration, Instant},
+};
+
+static RECORDING: AtomicBool = AtomicBool::new(false);
+static RECORDS: Mutex<Vec<TimerRecord>> = Mutex::new(Vec::new());
+static EPOCH: OnceLock<Instant> = OnceLock::new();
+static SINK: RwLock<Option<Box<dyn TimerSink>>> = RwLock::new(None);
+
+thread_local! {
+    static DEPTH: Cell<u32> = const { Cell::new(0) };
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TimerRecord {
+    pub name: String,
+    /// Time from the first timer created in this process to the start of this one
+    pub start: Duration,
+    pub duration: Duration,
+    /// Thread name, or its id for unnamed threads
+    pub thread: String,
+    /// Number of timers that were already running on this thread when this one started
+    pub depth: u32,
+}
+
+/// Receives timer scopes as they start and finish.
+pub trait TimerSink: Send + Sync {
+    /// Called when a timer starts, `depth` is the number of timers already running on this thread.
+    fn enter(&self, _label: &str, _depth: u32) {}
+    /// Called when a timer finishes, on the same thread it started on.
+    fn exit(&self, label: &str, elapsed: Duration, depth: u32);
+}
+
+/// Prints each finished timer as a line to stdout. This is the default sink.
+pub struct StdoutSink;
+
+impl TimerSink for StdoutSink {
+    fn exit(&self, label: &str, elapsed: Duration, _depth: u32) {
+        println!(
+            "{:>8} {}",
+            format!("{}", obvhs::PrettyDuration(elapsed)),
+            label
+        )
+    }
+}
+
+/// Discards timers. They are still stored while recording.
+pub struct NullSink;
+
+impl TimerSink for NullSink {
+    fn exit(&self, _label: &str, _elapsed: Duration, _depth: u32) {}
+}
+
+/// Logs each finished timer through the `log` crate at `level`, with the `pool_racing::timings` target.
+#[cfg(feature = "log")]
+pub struct LogSink {
+    pub level: log::Level,
+}
+
+#[cfg(feature = "log")]
+impl TimerSink for LogSink {
+    fn exit(&self, label: &str, elapsed: Duration, _depth: u32) {
+        log::log!(
+            target: "pool_racing::timings",
+            self.level,
+            "{:>8} {}",
+            format!("{}", obvhs::PrettyDuration(elapsed)),
+            label
+        );
+    }
+}
+
+#[cfg(feature = "tracing")]
+thread_local! {
+    static SPANS: std::cell::RefCell<Vec<tracing::span::EnteredSpan>> =
+        const { std::cell::RefCell::new(Vec::new()) };
+}
+
+/// Enters a `tracing` span named `scope` for each timer, with the timer label in its `label` field
+/// and the measured time in `elapsed_us` once it finishes.
+#[cfg(feature = "tracing")]
+pub struct TracingSink;
+
+#[cfg(feature = "tracing")]
+impl TimerSink for TracingSink {
+    fn enter(&self, label: &str, _depth: u32) {
+        let span = tracing::info_span!(
+            target: "pool_racing::timings",
+            "scope",
+            label,
+            elapsed_us = tracing::field::Empty
+        );
+        SPANS.with(|s| s.borrow_mut().push(span.entered()));
+    }
+
+    fn exit(&self, _label: &str, elapsed: Duration, _depth: u32) {
+        if let Some(span) = SPANS.with(|s| s.borrow_mut().pop()) {
+            span.record("elapsed_us", elapsed.as_secs_f64() * 1e6);
+        }
+    }
+}
+
+/// Replace the sink timers are reported to. Timers that are running when the sink changes start on
+/// one sink and finish on the other.
+pub fn set_sink<S: TimerSink + 'static>(sink: S) {
+    *SINK.write().unwrap() = Some(Box::new(sink));
+}
+
+/// Go back to printing timers to stdout.
+pub fn reset_sink() {
+    *SINK.write().unwrap() = None;
+}
+
+fn with_sink(f: impl FnOnce(&dyn TimerSink)) {
+    match SINK.read().unwrap().as_deref() {
+        Some(sink) => f(sink),
+        None => f(&StdoutSink),
+    }
+}
+
+/// Start storing finished timers. Records from earlier recordings that weren't taken are kept.
+pub fn start_recording() {
+    RECORDING.store(true, Ordering::Relaxed);
+}
+
+pub fn stop_recording() {
+    RECORDING.store(false, Ordering::Relaxed);
+}
+
+pub fn is_recording() -> bool {
+    RECORDING.load(Ordering::Relaxed)
+}
+
+/// Take all records stored so far, ordered by when each timer finished.
+pub fn take_records() -> Vec<TimerRecord> {
+    std::mem::take(&mut *RECORDS.lock().unwrap())
+}
+
+pub(crate) fn epoch() -> Instant {
+    *EPOCH.get_or_init(Instant::now)
+}
+
+/// Enter a timer scope on this thread, returning its depth.
+pub(crate) fn enter(name: &str) -> u32 {
+    let depth = DEPTH.with(|d| {
+        let depth = d.get();
+        d.set(depth + 1);
+        depth
+    });
+    with_sink(|sink| sink.enter(name, depth));
+    depth
+}
+
+/// Leave a timer scope on this thread, reporting it to the sink and storing it if recording.
+pub(crate) fn exit(name: &str, start: Instant, duration: Duration, depth: u32) {
+    DEPTH.with(|d| d.set(depth));
+    with_sink(|sink| sink.exit(name, duration, depth));
+    if is_recording() {
+        record(name, start, duration, depth);
+    }
+}
+
+/// Stores a scope when dropped if recording was on when it was created, without reporting it to the
+/// sink. Used by `chunk_scope!` for the many short chunk spans.
+#[doc(hidden)]
+pub struct RecordScope {
+    /// None if not recording
+    name: Option<String>,
+    start: Instant,
+    depth: u32,
+}
+
+impl RecordScope {
+    pub fn new(name: impl FnOnce() -> String) -> Self {
+        if !is_recording() {
+            return Self {
+                name: None,
+                start: Instant::now(),
+                depth: 0,
+            };
+        }
+        epoch();
+        let depth = DEPTH.with(|d| {
+            let depth = d.get();
+            d.set(depth + 1);
+            depth
+        });
+        Self {
+            name: Some(name()),
+            start: Instant::now(),
+            depth,
+        }
+    }
+}
+
+impl Drop for RecordScope {
+    fn drop(&mut self) {
+        if let Some(name) = &self.name {
+            let duration = self.start.elapsed();
+            DEPTH.with(|d| d.set(self.depth));
+            record(name, self.start, duration, self.depth);
+        }
+    }
+}
+
+fn record(name: &str, start: Instant, duration: Duration, depth: u32) {
+    let thread = std::thread::current();
+    let thread = match thread.name() {
+        Some(name) => name.to_string(),
+        None => format!("{:?}", thread.id()),
+    };
+    RECORDS.lock().unwrap().push(TimerRecord {
+        name: name.to_string(),
+        start: start.saturating_duration_since(epoch()),
+        duration,
+        thread,
+        depth,
+    });
+}
+
+/// Timings of every recorded instance of one scope.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScopeStats {
+    pub name: String,
+    pub count: usize,
+    pub min: Duration,
+    pub median: Duration,
+    pub mean: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+}
+
+/// The `p` percentile (0 to 1) of `sorted` by nearest rank, or zero if it's empty. `sorted` must be in
+/// ascending order.
+pub fn nearest_rank(sorted: &[Duration], p: f64) -> Duration {
+    let i = ((p * sorted.len() as f64).ceil() as usize).max(1) - 1;
+    sorted.get(i).copied().unwrap_or_default()
+}
+
+/// Accumulates scope timings across many runs, e.g. 1000 rebuilds, to compare their distributions
+/// rather than single samples.
+#[derive(Clone, Debug, Default)]
+pub struct TimingAggregator {
+    /// Scope name and its durations, in the order each scope was first seen
+    scopes: Vec<(String, Vec<Duration>)>,
+}
+
+impl TimingAggregator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, name: &str, duration: Duration) {
+        match self.scopes.iter_mut().find(|(n, _)| n == name) {
+            Some((_, durations)) => durations.push(duration),
+            None => self.scopes.push((name.to_string(), vec![duration])),
+        }
+    }
+
+    pub fn add_records(&mut self, records: &[TimerRecord]) {
+        for r in records {
+            self.add(&r.name, r.duration);
+        }
+    }
+
+    /// Add everything recorded since the last `take_records`, typically called once per run.
+    pub fn take_recorded(&mut self) {
+        self.add_records(&take_records());
+    }
+
+    pub fn clear(&mut self) {
+        self.scopes.clear();
+    }
+
+    /// Min/median/mean/p99/max per scope, in the order each scope was first seen. Percentiles use the
+    /// nearest rank.
+    pub fn stats(&self) -> Vec<ScopeStats> {
+        self.scopes
+            .iter()
+            .map(|(name, durations)| {
+                let mut sorted = durations.clone();
+                sorted.sort_unstable();
+                ScopeStats {
+                    name: name.clone(),
+                    count: sorted.len(),
+                    min: sorted[0],
+                    median: nearest_rank(&sorted, 0.5),
+                    mean: sorted.iter().sum::<Duration>() / sorted.len() as u32,
+                    p99: nearest_rank(&sorted, 0.99),
+                    max: sorted[sorted.len() - 1],
+                }
+            })
+            .collect()
+    }
+
+    /// Print a table of `stats` to stdout.
+    pub fn print(&self) {
+        println!(
+            "{:>8} {:>8} {:>8} {:>8} {:>8} {:>6} scope",
+            "min", "median", "mean", "p99", "max", "count"
+        );
+        for s in self.stats() {
+            let d = |d: Duration| format!("{}", obvhs::PrettyDuration(d));
+            println!(
+                "{:>8} {:>8} {:>8} {:>8} {:>8} {:>6} {}",
+                d(s.min),
+                d(s.median),
+                d(s.mean),
+                d(s.p99),
+                d(s.max),
+                s.count,
+                s.name
+            );
+        }
+    }
+}
+
+pub(crate) fn write_json_string<W: Write>(writer: &mut W, s: &str) -> io::Result<()> {
+    write!(writer, "\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => write!(writer, "\\\"")?,
+            '\\' => write!(writer, "\\\\")?,
+            c if (c as u32) < 0x20 => write!(writer, "\\u{:04x}", c as u32)?,
+            c => write!(writer, "{c}")?,
+        }
+    }
+    write!(writer, "\"")
+}
+
+/// Write `records` as a JSON array of objects with `name`, `start_us`, `duration_us`, `thread` and
+/// `depth` fields.
+pub fn write_json<W: Write>(mut writer: W, records: &[TimerRecord]) -> io::Result<()> {
+    writeln!(writer, "[")?;
+    for (i, r) in records.iter().enumerate() {
+        write!(writer, "  {{\"name\": ")?;
+        write_json_string(&mut writer, &r.name)?;
+        write!(
+            writer,
+            ", \"start_us\": {:.3}, \"duration_us\": {:.3}, \"thread\": ",
+            r.start.as_secs_f64() * 1e6,
+            r.duration.as_secs_f64() * 1e6,
+        )?;
+        write_json_string(&mut writer, &r.thread)?;
+        let separator = if i + 1 < records.len() { "," } else { "" };
+        writeln!(writer, ", \"depth\": {}}}{separator}", r.depth)?;
+    }
+    writeln!(writer, "]")
+}
+
+pub(crate) fn write_csv_field<W: Write>(writer: &mut W, s: &str) -> io::Result<()> {
+    if s.contains([',', '"', '\n', '\r']) {
+        write!(writer, "\"{}\"", s.replace('"', "\"\""))
+    } else {
+        write!(writer, "{s}")
+    }
+}
+
+/// Write `records` as CSV with a `name,start_us,duration_us,thread,depth` header.
+pub fn write_csv<W: Write>(mut writer: W, records: &[TimerRecord]) -> io::Result<()> {
+    writeln!(writer, "name,start_us,duration_us,thread,depth")?;
+    for r in records {
+        write_csv_field(&mut writer, &r.name)?;
+        write!(
+            writer,
+            ",{:.3},{:.3},",
+            r.start.as_secs_f64() * 1e6,
+            r.duration.as_secs_f64() * 1e6,
+        )?;
+        write_csv_field(&mut writer, &r.thread)?;
+        writeln!(writer, ",{}", r.depth)?;
+    }
+    Ok(())
+}
+
+/// Write `records` in the Trace Event Format read by chrome://tracing and Perfetto. Each record becomes
+/// a complete event on its own thread track, with timestamps in microseconds.
+pub fn write_chrome_trace<W: Write>(mut writer: W, records: &[TimerRecord]) -> io::Result<()> {
+    let mut threads: Vec<&str> = Vec::new();
+    for r in records {
+        if !threads.contains(&r.thread.as_str()) {
+            threads.push(&r.thread);
+        }
+    }
+    writeln!(writer, "{{\"traceEvents\": [")?;
+    for (tid, thread) in threads.iter().enumerate() {
+        write!(
+            writer,
+            "  {{\"name\": \"thread_name\", \"ph\": \"M\", \"pid\": 1, \"tid\": {tid}, \"args\": {{\"name\": "
+        )?;
+        write_json_string(&mut writer, thread)?;
+        // Every thread has at least one record after it
+        writeln!(writer, "}}}},")?;
+    }
+    for (i, r) in records.iter().enumerate() {
+        let tid = threads.iter().position(|t| *t == r.thread).unwrap();
+        write!(writer, "  {{\"name\": ")?;
+        write_json_string(&mut writer, &r.name)?;
+        let separator = if i + 1 < records.len() { "," } else { "" };
+        writeln!(
+            writer,
+            ", \"ph\": \"X\", \"ts\": {:.3}, \"dur\": {:.3}, \"pid\": 1, \"tid\": {tid}, \"args\": {{\"depth\": {}}}}}{separator}",
+            r.start.as_secs_f64() * 1e6,
+            r.duration.as_secs_f64() * 1e6,
+            r.depth,
+        )?;
+    }
+    writeln!(writer, "], \"displayTimeUnit\": \"ms\"}}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nearest_rank() {
+        let sorted: Vec<Duration> = (1..=10).map(Duration::from_secs).collect();
+        assert_eq!(nearest_rank(&sorted, 0.0), Duration::from_secs(1));
+        assert_eq!(nearest_rank(&sorted, 0.1), Duration::from_secs(1));
+        assert_eq!(nearest_rank(&sorted, 0.5), Duration::from_secs(5));
+        assert_eq!(nearest_rank(&sorted, 0.91), Duration::from_secs(10));
+        assert_eq!(nearest_rank(&sorted, 1.0), Duration::from_secs(10));
+        assert_eq!(nearest_rank(&[], 0.5), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_aggregate() {
+        let mut agg = TimingAggregator::new();
+        for i in 1..=100 {
+            agg.add("build", Duration::from_micros(i));
+        }
+        agg.add("sort", Duration::from_micros(7));
+        let stats = agg.stats();
+        assert_eq!(stats.len(), 2);
+        assert_eq!(stats[0].name, "build");
+        assert_eq!(stats[0].count, 100);
+        assert_eq!(stats[0].min, Duration::from_micros(1));
+        assert_eq!(stats[0].median, Duration::from_micros(50));
+        assert_eq!(stats[0].mean, Duration::from_nanos(50_500));
+        assert_eq!(stats[0].p99, Duration::from_micros(99));
+        assert_eq!(stats[0].max, Duration::from_micros(100));
+        assert_eq!(stats[1].p99, Duration::from_micros(7));
+    }
+
+    #[test]
+    fn test_write_records() {
+        let records = vec![
+            TimerRecord {
+                name: "sort, \"radix\"".to_string(),
+                start: Duration::from_micros(5),
+                duration: Duration::from_micros(20),
+                thread: "main".to_string(),
+                depth: 1,
+            },
+            TimerRecord {
+                name: "build".to_string(),
+                start: Duration::ZERO,
+                duration: Duration::from_micros(40),
+                thread: "main".to_string(),
+                depth: 0,
+            },
+        ];
+
+        let mut csv = Vec::new();
+        write_csv(&mut csv, &records).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "name,start_us,duration_us,thread,depth\n\
+             \"sort, \"\"radix\"\"\",5.000,20.000,main,1\n\
+             build,0.000,40.000,main,0\n"
+        );
+
+        let mut json = Vec::new();
+        write_json(&mut json, &records).unwrap();
+        let json = String::from_utf8(json).unwrap();
+        assert!(json.contains(r#"{"name": "sort, \"radix\"", "start_us": 5.000"#));
+        assert!(json.contains(r#""thread": "main", "depth": 0}"#));
+        assert!(json.trim_end().ends_with(']'));
+
+        let mut trace = Vec::new();
+        write_chrome_trace(&mut trace, &records).unwrap();
+        let trace = String::from_utf8(trace).unwrap();
+        assert_eq!(trace.matches("\"ph\": \"M\"").count(), 1);
+        assert!(trace.contains(
+            r#"{"name": "build", "ph": "X", "ts": 0.000, "dur": 40.000, "pid": 1, "tid": 0, "args": {"depth": 0}}"#
+        ));
+        assert!(trace.trim_end().ends_with("\"displayTimeUnit\": \"ms\"}"));
+    }
+
+    /// Keeps the labels it's given, ignoring timers from other tests running at the same time.
+    struct CaptureSink(std::sync::Arc<Mutex<Vec<String>>>);
+
+    impl TimerSink for CaptureSink {
+        fn enter(&self, label: &str, depth: u32) {
+            if label.starts_with("sink test") {
+                self.0
+                    .lock()
+                    .unwrap()
+                    .push(format!("enter {label} {depth}"));
+            }
+        }
+
+        fn exit(&self, label: &str, _elapsed: Duration, depth: u32) {
+            if label.starts_with("sink test") {
+                self.0.lock().unwrap().push(format!("exit {label} {depth}"));
+            }
+        }
+    }
+
+    #[test]
+    fn test_set_sink() {
+        let captured = std::sync::Arc::new(Mutex::new(Vec::new()));
+        set_sink(CaptureSink(captured.clone()));
+        let start = Instant::now();
+        let outer = enter("sink test outer");
+        let inner = enter("sink test inner");
+        exit("sink test inner", start, Duration::from_micros(1), inner);
+        exit("sink test outer", start, Duration::from_micros(2), outer);
+        reset_sink();
+        let depth = enter("sink test after reset");
+        exit(
+            "sink test after reset",
+            start,
+            Duration::from_micros(1),
+            depth,
+        );
+        assert_eq!(
+            *captured.lock().unwrap(),
+            [
+                "enter sink test outer 0",
+                "enter sink test inner 1",
+                "exit sink test inner 1",
+                "exit sink test outer 0"
+            ]
+        );
+
+        // The stdout and null sinks only need to not panic
+        StdoutSink.exit("sink test stdout", Duration::from_micros(3), 0);
+        NullSink.exit("sink test null", Duration::from_micros(3), 0);
+    }
+
+    #[cfg(feature = "log")]
+    #[test]
+    fn test_log_sink() {
+        struct CaptureLogger(Mutex<Vec<String>>);
+
+        impl log::Log for CaptureLogger {
+            fn enabled(&self, _: &log::Metadata) -> bool {
+                true
+            }
+
+            fn log(&self, record: &log::Record) {
+                self.0.lock().unwrap().push(format!(
+                    "{} {} {}",
+                    record.level(),
+                    record.target(),
+                    record.args()
+                ));
+            }
+
+            fn flush(&self) {}
+        }
+
+        static LOGGER: CaptureLogger = CaptureLogger(Mutex::new(Vec::new()));
+        log::set_logger(&LOGGER).unwrap();
+        log::set_max_level(log::LevelFilter::Trace);
+        let sink = LogSink {
+            level: log::Level::Debug,
+        };
+        sink.exit("sink test log", Duration::from_micros(3), 0);
+        let logged = LOGGER.0.lock().unwrap();
+        assert_eq!(logged.len(), 1);
+        assert!(logged[0].starts_with("DEBUG pool_racing::timings "));
+        assert!(logged[0].ends_with(" sink test log"));
+    }
+
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn test_tracing_sink() {
+        use std::fmt::Write as _;
+        use tracing::{
+            field::{Field, Visit},
+            span,
+        };
+
+        struct FieldWriter<'a>(&'a mut String);
+
+        impl Visit for FieldWriter<'_> {
+            fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+                write!(self.0, " {}={value:?}", field.name()).unwrap();
+            }
+        }
+
+        /// Writes each new span and recorded value as a line.
+        struct CaptureSubscriber(std::sync::Arc<Mutex<Vec<String>>>);
+
+        impl tracing::Subscriber for CaptureSubscriber {
+            fn enabled(&self, _: &tracing::Metadata<'_>) -> bool {
+                true
+            }
+
+            fn new_span(&self, span: &span::Attributes<'_>) -> span::Id {
+                let mut line = span.metadata().name().to_string();
+                span.record(&mut FieldWriter(&mut line));
+                self.0.lock().unwrap().push(line);
+                span::Id::from_u64(1)
+            }
+
+            fn record(&self, _: &span::Id, values: &span::Record<'_>) {
+                let mut line = "record".to_string();
+                values.record(&mut FieldWriter(&mut line));
+                self.0.lock().unwrap().push(line);
+            }
+
+            fn record_follows_from(&self, _: &span::Id, _: &span::Id) {}
+
+            fn event(&self, _: &tracing::Event<'_>) {}
+
+            fn enter(&self, _: &span::Id) {}
+
+            fn exit(&self, _: &span::Id) {}
+        }
+
+        let captured = std::sync::Arc::new(Mutex::new(Vec::new()));
+        tracing::subscriber::with_default(CaptureSubscriber(captured.clone()), || {
+            TracingSink.enter("sink test tracing", 0);
+            TracingSink.exit("sink test tracing", Duration::from_micros(3), 0);
+        });
+        assert_eq!(
+            *captured.lock().unwrap(),
+            ["scope label=\"sink test tracing\"", "record elapsed_us=3.0"]
+        );
+    }
+}